@@ -0,0 +1,57 @@
+use anyhow::bail;
+#[cfg(target_os = "linux")]
+use anyhow::ensure;
+
+// Restricts the calling thread to the given CPU cores
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_affinity(cores: &[usize]) -> anyhow::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &core in cores {
+            ensure!(core < libc::CPU_SETSIZE as usize, "CPU core {core} is out of range");
+            libc::CPU_SET(core, &mut set);
+        }
+        if libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &raw const set) != 0 {
+            bail!("sched_setaffinity failed: {}", std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn current_thread_affinity() -> anyhow::Result<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &raw mut set) != 0 {
+            bail!("sched_getaffinity failed: {}", std::io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize).filter(|&core| libc::CPU_ISSET(core, &set)).collect())
+    }
+}
+
+// Sets the nice value of the calling thread; lower is higher priority, negative values usually need privileges
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_nice(nice: i32) -> anyhow::Result<()> {
+    unsafe {
+        let thread_id = libc::gettid();
+        if libc::setpriority(libc::PRIO_PROCESS, thread_id as libc::id_t, nice) != 0 {
+            bail!("setpriority failed: {}", std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_affinity(_cores: &[usize]) -> anyhow::Result<()> {
+    bail!("thread affinity is only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_thread_affinity() -> anyhow::Result<Vec<usize>> {
+    bail!("thread affinity is only supported on Linux")
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_nice(_nice: i32) -> anyhow::Result<()> {
+    bail!("thread priority is only supported on Linux")
+}
@@ -1,23 +1,59 @@
 #![allow(clippy::struct_excessive_bools)]
 
-use std::{fmt::Display, fs::File, io::Read, path::Path, sync::LazyLock};
+use std::{collections::BTreeMap, fmt::Display, fs::File, io::Read, path::Path, sync::LazyLock, time::Duration};
 
-use anyhow::{Context, anyhow};
+use anyhow::{Context, bail};
 use num_traits::Num;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 
-use crate::demo::{Ball, Brick};
+use crate::{
+    boundary::{Boundaries, Inflow, Wall, WallBehavior},
+    bvh::AABB,
+    demo::{Ball, Brick, Particle},
+    fluid::Fluid,
+    gamma::ColorMath,
+    locale::Language,
+    material::{CombineRule, Material, MaterialPair},
+    pair_storage::PairStorageKind,
+    physics::{DtSource, DurationStat, GravityZone, PhysicsSettings, SimulationMode},
+    thermostat::Thermostat,
+    units::Units,
+    vector2::Vector2,
+    wind::Wind,
+};
 
 pub static CONFIG: LazyLock<AppConfig> =
     LazyLock::new(|| AppConfig::from_file(Path::new("config.toml")).context("load config").unwrap());
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct AppConfig {
     pub window: WindowConfig,
+    #[serde(default)]
+    pub units: Units,
     pub simulation: SimulationConfig,
     pub demo: DemoConfig,
     pub rendering: RenderConfig,
+    #[serde(default)]
+    pub autosave: AutosaveConfig,
+    #[serde(default)]
+    pub threads: ThreadsConfig,
+    #[serde(default)]
+    pub editor: EditorConfig,
+    #[serde(default)]
+    pub mouse: MouseConfig,
+    #[serde(default)]
+    pub ui: UiConfig,
+    #[serde(default)]
+    pub stats: StatsConfig,
+    // Physics overrides for the bricks and balls with the same name
+    #[serde(default)]
+    pub groups: BTreeMap<String, MaterialConfig>,
+    // Materials referenced by the bricks, balls and particles; groups are materials too
+    #[serde(default)]
+    pub materials: BTreeMap<String, MaterialConfig>,
+    #[serde(default)]
+    pub material_pairs: Vec<MaterialPairConfig>,
 }
 
 impl AppConfig {
@@ -26,128 +62,883 @@ impl AppConfig {
             File::open(config_path).context(format!("open config \"{}\"", config_path.to_string_lossy()))?;
         let mut config_string = String::new();
         config_file.read_to_string(&mut config_string).context("read config")?;
-        let config: AppConfig = toml::from_str(&config_string).context("parse config")?;
-        config.validate().context("validate config")?;
+        Self::parse(&config_string, config_path)
+    }
+
+    fn parse(config_string: &str, config_path: &Path) -> anyhow::Result<AppConfig> {
+        let config: AppConfig = toml::from_str(config_string).context("parse config")?;
+        if let Err(error) = config.validate() {
+            let location = error
+                .downcast_ref::<InvalidField>()
+                .and_then(|field| field_location(config_string, &field.path))
+                .map(|(line, column)| format!(" at {}:{line}:{column}", config_path.display()));
+            return Err(error.context(format!("validate config{}", location.unwrap_or_default())));
+        }
         Ok(config)
     }
 
+    // Every field, including the defaults, in a form that parses back into the same config
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let mut value = toml::Value::try_from(self).context("serialize config")?;
+        shorten_floats(&mut value);
+        toml::to_string_pretty(&value).context("serialize config")
+    }
+
     fn validate(&self) -> anyhow::Result<()> {
         validate_positive(self.window.width, "window.width")?;
         validate_positive(self.window.height, "window.height")?;
+        validate_positive(self.window.background_steps_per_second, "window.background_steps_per_second")?;
+
+        validate_positive(self.units.pixels_per_meter, "units.pixels_per_meter")?;
+        validate_positive(self.units.time_scale, "units.time_scale")?;
+        validate_positive(self.units.mass_scale, "units.mass_scale")?;
 
         if let DtSource::Fixed(dt) = self.simulation.dt {
             validate_positive(dt, "simulation.dt")?;
         }
         validate_positive(self.simulation.speed_factor, "simulation.speed_factor")?;
+        validate_non_negative(self.simulation.speed_ramp_time, "simulation.speed_ramp_time")?;
+        validate_positive(self.simulation.hybrid_max_cluster_size, "simulation.hybrid_max_cluster_size")?;
         if let Some(time_limit) = self.simulation.time_limit {
             validate_positive(time_limit, "simulation.time_limit")?;
         }
+        if let Some(step_limit) = self.simulation.step_limit {
+            validate_positive(step_limit, "simulation.step_limit")?;
+        }
+        if let Some(trajectories) = &self.simulation.trajectories {
+            validate_positive(trajectories.period, "simulation.trajectories.period")?;
+            validate_positive(trajectories.capacity, "simulation.trajectories.capacity")?;
+        }
+        if let Some(capture) = &self.simulation.capture {
+            validate_positive(capture.stride, "simulation.capture.stride")?;
+        }
+        for wall in Wall::ALL {
+            if let WallConfig::Inflow(inflow) = self.simulation.boundaries.wall(wall) {
+                inflow.validate(&format!("simulation.boundaries.{}.inflow", wall.name()))?;
+            }
+        }
+        if let Some(heat_conduction) = self.simulation.heat_conduction {
+            validate_non_negative(heat_conduction, "simulation.heat_conduction")?;
+        }
+        if let Some(pair_cache_margin) = self.simulation.pair_cache_margin {
+            validate_positive(pair_cache_margin, "simulation.pair_cache_margin")?;
+        }
+        if let Some(verlet_skin) = self.simulation.verlet_skin {
+            validate_positive(verlet_skin, "simulation.verlet_skin")?;
+        }
+        if cfg!(not(feature = "scripting")) && self.simulation.script.is_some() {
+            bail!("simulation.script requires the \"scripting\" feature");
+        }
+        validate_positive(self.simulation.auto_gpu_compute_period, "simulation.auto_gpu_compute_period")?;
+        validate_positive(self.simulation.validate_gpu_period, "simulation.validate_gpu_period")?;
+        validate_positive(self.simulation.watchdog_timeout, "simulation.watchdog_timeout")?;
+        validate_positive(self.simulation.gpu_kernel_timeout, "simulation.gpu_kernel_timeout")?;
+        if let Some(collision_budget) = self.simulation.collision_budget {
+            validate_positive(collision_budget, "simulation.collision_budget")?;
+        }
+        validate_positive(
+            self.simulation.angular_momentum_drift_tolerance,
+            "simulation.angular_momentum_drift_tolerance",
+        )?;
         validate_positive(self.simulation.gpu_integration_local_wg_size, "simulation.gpu_integration_local_wg_size")?;
         validate_positive(self.simulation.gpu_bvh_local_wg_size, "simulation.gpu_bvh_local_wg_size")?;
-        validate_restitution_coefficient(
-            self.simulation.restitution_coefficient,
-            "simulation.restitution_coefficient",
+        validate_unit_interval(self.simulation.restitution_coefficient, "simulation.restitution_coefficient")?;
+        validate_non_negative(
+            self.simulation.restitution_velocity_threshold,
+            "simulation.restitution_velocity_threshold",
         )?;
+        validate_non_negative(self.simulation.penetration_slop, "simulation.penetration_slop")?;
+        if let Some(wind) = &self.simulation.wind {
+            validate_non_negative(wind.turbulence_amplitude, "simulation.wind.turbulence_amplitude")?;
+            validate_positive(wind.turbulence_scale, "simulation.wind.turbulence_scale")?;
+            validate_non_negative(wind.turbulence_speed, "simulation.wind.turbulence_speed")?;
+        }
+        if let Some(thermostat) = &self.simulation.thermostat {
+            validate_non_negative(thermostat.temperature, "simulation.thermostat.temperature")?;
+            validate_non_negative(thermostat.kick_rate, "simulation.thermostat.kick_rate")?;
+            if let Some(relaxation_time) = thermostat.relaxation_time {
+                validate_positive(relaxation_time, "simulation.thermostat.relaxation_time")?;
+            }
+        }
+        if let Some(fluid) = &self.simulation.fluid {
+            validate_positive(fluid.rest_density, "simulation.fluid.rest_density")?;
+            validate_positive(fluid.smoothing_radius, "simulation.fluid.smoothing_radius")?;
+            validate_positive(fluid.relaxation, "simulation.fluid.relaxation")?;
+            validate_unit_interval(fluid.relaxation, "simulation.fluid.relaxation")?;
+        }
+        validate_positive(self.simulation.position_correction_factor, "simulation.position_correction_factor")?;
+        validate_unit_interval(self.simulation.position_correction_factor, "simulation.position_correction_factor")?;
+
+        if let Some(interval) = self.autosave.interval {
+            validate_positive(interval, "autosave.interval")?;
+        }
+        validate_positive(self.autosave.keep, "autosave.keep")?;
+
+        if let Some(nice) = self.threads.simulation_nice
+            && !(-20..=19).contains(&nice)
+        {
+            return Err(invalid_field("threads.simulation_nice", "must be in range [-20, 19]"));
+        }
+        for (thread_count, name) in [
+            (self.threads.physics, "threads.physics"),
+            (self.threads.field, "threads.field"),
+            (self.threads.render_scene, "threads.render_scene"),
+        ] {
+            if let Some(thread_count) = thread_count {
+                validate_positive(thread_count, name)?;
+            }
+        }
+
+        validate_non_negative(self.rendering.min_screen_radius, "rendering.min_screen_radius")?;
+        if let Some(overlay_scale) = self.ui.overlay_scale {
+            validate_positive(overlay_scale, "ui.overlay_scale")?;
+        }
+        if let Some(point_sprite_radius) = self.rendering.point_sprite_radius {
+            validate_positive(point_sprite_radius, "rendering.point_sprite_radius")?;
+        }
+        validate_non_negative(self.rendering.planets.glow_radius_factor, "rendering.planets.glow_radius_factor")?;
+        validate_positive(self.rendering.reduced_motion_speed_factor, "rendering.reduced_motion_speed_factor")?;
+        validate_positive(self.rendering.planets.velocity_vector_scale, "rendering.planets.velocity_vector_scale")?;
+        validate_positive(
+            self.rendering.planets.acceleration_vector_scale,
+            "rendering.planets.acceleration_vector_scale",
+        )?;
+        validate_unit_interval(self.rendering.trails.decay, "rendering.trails.decay")?;
+        validate_positive(self.rendering.trails.cell_size, "rendering.trails.cell_size")?;
+        validate_positive(self.rendering.trails.intensity, "rendering.trails.intensity")?;
+        validate_non_negative(self.rendering.auto_camera.margin, "rendering.auto_camera.margin")?;
+        validate_non_negative(self.rendering.auto_camera.smoothing_time, "rendering.auto_camera.smoothing_time")?;
+        if let Some(target_fps) = self.rendering.adaptive_quality.target_fps {
+            validate_positive(target_fps, "rendering.adaptive_quality.target_fps")?;
+        }
+        if self.rendering.adaptive_quality.headroom < 1.0 {
+            return Err(invalid_field("rendering.adaptive_quality.headroom", "must be at least 1.0"));
+        }
 
         validate_positive(self.demo.object_radius, "demo.object_radius")?;
         validate_positive(self.demo.randomize_position_factor, "demo.randomize_position_factor")?;
+        validate_positive(self.demo.relaxation_iterations, "demo.relaxation_iterations")?;
+
+        for (brick_index, brick) in self.demo.bricks.iter().enumerate() {
+            let path = |field| format!("demo.bricks[{brick_index}].{field}");
+            validate_positive(brick.size.x, &path("size.x"))?;
+            validate_positive(brick.size.y, &path("size.y"))?;
+            validate_positive(brick.particle_radius, &path("particle_radius"))?;
+            validate_non_negative(brick.particle_spacing, &path("particle_spacing"))?;
+            validate_positive(brick.particle_mass, &path("particle_mass"))?;
+            validate_unit_interval(brick.alpha, &path("alpha"))?;
+            if let Some(material) = &brick.material {
+                self.validate_material_name(material, &path("material"))?;
+            }
+        }
 
-        for brick in &self.demo.bricks {
-            validate_positive(brick.size.x, "brick width")?;
-            validate_positive(brick.size.y, "brick height")?;
-            validate_positive(brick.particle_radius, "brick particle radius")?;
-            validate_non_negative(brick.particle_spacing, "brick particle spacing")?;
-            validate_positive(brick.particle_mass, "brick particle mass")?;
+        for (ball_index, ball) in self.demo.balls.iter().enumerate() {
+            let path = |field| format!("demo.balls[{ball_index}].{field}");
+            validate_positive(ball.radius, &path("radius"))?;
+            validate_positive(ball.particle_radius, &path("particle_radius"))?;
+            validate_non_negative(ball.particle_spacing, &path("particle_spacing"))?;
+            validate_positive(ball.particle_mass, &path("particle_mass"))?;
+            validate_unit_interval(ball.alpha, &path("alpha"))?;
+            if let Some(material) = &ball.material {
+                self.validate_material_name(material, &path("material"))?;
+            }
         }
 
-        for ball in &self.demo.balls {
-            validate_positive(ball.radius, "ball radius")?;
-            validate_positive(ball.particle_radius, "ball particle radius")?;
-            validate_non_negative(ball.particle_spacing, "ball particle spacing")?;
-            validate_positive(ball.particle_mass, "ball particle mass")?;
+        for (particle_index, particle) in self.demo.particles.iter().enumerate() {
+            let path = |field| format!("demo.particles[{particle_index}].{field}");
+            validate_positive(particle.radius, &path("radius"))?;
+            validate_positive(particle.mass, &path("mass"))?;
+            if let Some(material) = &particle.material {
+                self.validate_material_name(material, &path("material"))?;
+            }
+        }
+
+        validate_positive(self.editor.particle_radius, "editor.particle_radius")?;
+        validate_non_negative(self.editor.particle_spacing, "editor.particle_spacing")?;
+        validate_positive(self.editor.particle_mass, "editor.particle_mass")?;
+        validate_positive(self.editor.undo_limit, "editor.undo_limit")?;
+        validate_positive(self.mouse.spring_stiffness, "mouse.spring_stiffness")?;
+        validate_non_negative(self.mouse.spring_damping, "mouse.spring_damping")?;
+        validate_positive(self.mouse.kick_speed, "mouse.kick_speed")?;
+        validate_positive(self.stats.window_frames, "stats.window_frames")?;
+        validate_positive(self.stats.fps_period_ms, "stats.fps_period_ms")?;
+
+        for (section, materials) in [("groups", &self.groups), ("materials", &self.materials)] {
+            for (name, material) in materials {
+                if name == DEFAULT_MATERIAL {
+                    return Err(invalid_field(&format!("{section}.{name}"), "is reserved for the default material"));
+                }
+                material.validate(&format!("{section}.{name}"))?;
+            }
+        }
+        if let Some(name) = self.groups.keys().find(|name| self.materials.contains_key(*name)) {
+            bail!("\"{name}\" is both a group and a material");
+        }
+        for (pair_index, pair) in self.material_pairs.iter().enumerate() {
+            let path = |field: &str| format!("material_pairs[{pair_index}].{field}");
+            for (name_index, name) in pair.materials.iter().enumerate() {
+                self.validate_material_name(name, &path(&format!("materials[{name_index}]")))?;
+            }
+            if let Some(restitution_coefficient) = pair.restitution_coefficient {
+                validate_unit_interval(restitution_coefficient, &path("restitution_coefficient"))?;
+            }
+            if let Some(friction) = pair.friction {
+                validate_non_negative(friction, &path("friction"))?;
+            }
         }
 
         Ok(())
     }
+
+    // The window, in world coordinates
+    #[must_use]
+    pub fn constraints(&self) -> AABB {
+        AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(self.window.width as f32, self.window.height as f32),
+        }
+    }
+
+    #[must_use]
+    pub fn physics_settings(&self) -> PhysicsSettings {
+        let units = &self.units;
+        PhysicsSettings {
+            dt: match self.simulation.dt {
+                DtSource::Auto => DtSource::Auto,
+                DtSource::Fixed(dt) => DtSource::Fixed(units.time(dt)),
+            },
+            mode: self.simulation.mode,
+            hybrid_max_cluster_size: self.simulation.hybrid_max_cluster_size,
+            constraints: self.constraints(),
+            constraint_bouncing: self.simulation.constraint_bouncing,
+            boundaries: {
+                let wall = |wall| match self.simulation.boundaries.wall(wall) {
+                    WallConfig::Reflect => WallBehavior::Reflect,
+                    WallConfig::Absorb => WallBehavior::Absorb,
+                    WallConfig::Wrap => WallBehavior::Wrap,
+                    WallConfig::Inflow(inflow) => WallBehavior::Inflow(Inflow {
+                        rate: units.rate(inflow.rate),
+                        velocity: units.velocity(Vector2::from(inflow.velocity)),
+                        radius: units.length(inflow.radius),
+                        mass: units.mass(inflow.mass),
+                    }),
+                };
+                Boundaries {
+                    left: wall(Wall::Left),
+                    right: wall(Wall::Right),
+                    top: wall(Wall::Top),
+                    bottom: wall(Wall::Bottom),
+                }
+            },
+            restitution_coefficient: self.simulation.restitution_coefficient,
+            materials: self
+                .groups
+                .values()
+                .chain(self.materials.values())
+                .map(|material| Material {
+                    restitution_coefficient: material
+                        .restitution_coefficient
+                        .unwrap_or(self.simulation.restitution_coefficient),
+                    friction: material.friction.unwrap_or(0.0),
+                    drag: units.rate(material.drag.unwrap_or(0.0)),
+                    gravity_scale: material.gravity_scale.unwrap_or(1.0),
+                    adhesion_speed: units.speed(material.adhesion_speed.unwrap_or(0.0)),
+                    adhesion_force: units.force(material.adhesion_force.unwrap_or(0.0)),
+                })
+                .collect(),
+            restitution_combine: self.simulation.restitution_combine,
+            friction_combine: self.simulation.friction_combine,
+            material_pairs: self
+                .material_pairs
+                .iter()
+                .map(|pair| MaterialPair {
+                    materials: pair.materials.each_ref().map(|name| self.material_index(Some(name))),
+                    restitution_coefficient: pair.restitution_coefficient,
+                    friction: pair.friction,
+                })
+                .collect(),
+            restitution_velocity_threshold: units.speed(self.simulation.restitution_velocity_threshold),
+            penetration_slop: units.length(self.simulation.penetration_slop),
+            position_correction_factor: self.simulation.position_correction_factor,
+            global_gravity: units.acceleration(Vector2::from(self.simulation.global_gravity)),
+            gravity_zones: self
+                .simulation
+                .gravity_zones
+                .iter()
+                .map(|zone| GravityZone {
+                    gravity: units.acceleration(Vector2::from(zone.gravity)),
+                    min_y: units.length(zone.min_y),
+                })
+                .collect(),
+            gravitational_constant: units.gravitational_constant(self.simulation.gravitational_constant),
+            wind: self.simulation.wind.as_ref().map(|wind| Wind {
+                acceleration: units.acceleration(Vector2::from(wind.acceleration)),
+                turbulence_amplitude: units.acceleration_magnitude(wind.turbulence_amplitude),
+                turbulence_scale: units.length(wind.turbulence_scale),
+                turbulence_speed: units.rate(wind.turbulence_speed),
+            }),
+            thermostat: self.simulation.thermostat.as_ref().map(|thermostat| Thermostat {
+                temperature: units.energy(thermostat.temperature),
+                kick_rate: units.rate(thermostat.kick_rate),
+                relaxation_time: thermostat.relaxation_time.map(|relaxation_time| units.time(relaxation_time)),
+            }),
+            fluid: self.simulation.fluid.as_ref().map(|fluid| Fluid {
+                rest_density: units.area_density(fluid.rest_density),
+                smoothing_radius: units.length(fluid.smoothing_radius),
+                relaxation: fluid.relaxation,
+                iterations: fluid.iterations,
+            }),
+            heat_conduction: self.simulation.heat_conduction.map(|heat_conduction| units.rate(heat_conduction)),
+            pair_cache_margin: self.simulation.pair_cache_margin.map(|margin| units.length(margin)),
+            verlet_skin: self.simulation.verlet_skin.map(|skin| units.length(skin)),
+            pair_storage: self.simulation.pair_storage,
+            compensated_summation: self.simulation.compensated_summation,
+            gpu_kernel_timeout: Some(Duration::from_secs_f32(self.simulation.gpu_kernel_timeout)),
+            collision_budget: self.simulation.collision_budget.map(Duration::from_secs_f32),
+            thread_pool: None,
+            seed: None,
+            duration_stat_window: self.stats.window_frames,
+        }
+    }
+
+    // Index of a group or a material in the physics material table. Unnamed objects and the ones without a group get
+    // the default material.
+    #[must_use]
+    pub fn material_index(&self, name: Option<&str>) -> u32 {
+        name.and_then(|name| self.groups.keys().chain(self.materials.keys()).position(|material| material == name))
+            .map_or(0, |material_index| u32::try_from(material_index + 1).unwrap())
+    }
+
+    fn validate_material_name(&self, name: &str, path: &str) -> anyhow::Result<()> {
+        if name == DEFAULT_MATERIAL || self.groups.contains_key(name) || self.materials.contains_key(name) {
+            Ok(())
+        } else {
+            Err(invalid_field(path, format!("names an unknown material \"{name}\"")))
+        }
+    }
+}
+
+// Name of the material made of the coefficients in the `[simulation]` section, e.g. for material pairs
+const DEFAULT_MATERIAL: &str = "default";
+
+// Most of the fields are f32, which would be printed with the digits of their f64 representation, like 0.1 as
+// 0.10000000149011612
+fn shorten_floats(value: &mut toml::Value) {
+    match value {
+        toml::Value::Float(float) => {
+            #[allow(clippy::cast_possible_truncation)]
+            let single = *float as f32;
+            if f64::from(single) == *float {
+                *float = single.to_string().parse().unwrap();
+            }
+        }
+        toml::Value::Array(array) => array.iter_mut().for_each(shorten_floats),
+        toml::Value::Table(table) => table.iter_mut().for_each(|(_, value)| shorten_floats(value)),
+        _ => {}
+    }
+}
+
+// A value that failed validation, with the path to it in the config, like `demo.bricks[3].size.x`
+#[derive(Debug)]
+struct InvalidField {
+    path: String,
+    problem: String,
+}
+
+impl Display for InvalidField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.path, self.problem)
+    }
+}
+
+impl std::error::Error for InvalidField {}
+
+fn invalid_field(path: &str, problem: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(InvalidField {
+        path: path.to_string(),
+        problem: problem.into(),
+    })
+}
+
+// Line and column, both starting at 1, of the value at the path, or of the closest enclosing one that is in the
+// config text rather than filled in by default
+fn field_location(config_string: &str, path: &str) -> Option<(usize, usize)> {
+    let document = toml_edit::ImDocument::parse(config_string).ok()?;
+    let mut item = document.as_item();
+    let mut span = None;
+    'path: for segment in path.split('.') {
+        let mut parts = segment.split('[');
+        let key = parts.next()?;
+        let indices = parts.map(|index| index.trim_end_matches(']').parse::<usize>());
+        let Some(next) = item.get(key) else { break };
+        item = next;
+        span = item.span().or(span);
+        for index in indices {
+            let Some(next) = index.ok().and_then(|index| item.get(index)) else {
+                break 'path;
+            };
+            item = next;
+            span = item.span().or(span);
+        }
+    }
+    let offset = span?.start;
+    let line_start = config_string[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    Some((config_string[..offset].matches('\n').count() + 1, config_string[line_start..offset].chars().count() + 1))
 }
 
-fn validate_positive<T: Num + PartialOrd>(value: T, name: &'static str) -> anyhow::Result<()> {
+fn validate_positive<T: Num + PartialOrd>(value: T, path: &str) -> anyhow::Result<()> {
     if value > T::zero() {
         Ok(())
     } else {
-        Err(anyhow!("{} must be positive", name))
+        Err(invalid_field(path, "must be positive"))
     }
 }
 
-fn validate_non_negative<T: Num + PartialOrd>(value: T, name: &'static str) -> anyhow::Result<()> {
+fn validate_non_negative<T: Num + PartialOrd>(value: T, path: &str) -> anyhow::Result<()> {
     if value >= T::zero() {
         Ok(())
     } else {
-        Err(anyhow!("{} must be positive", name))
+        Err(invalid_field(path, "must not be negative"))
     }
 }
 
-fn validate_restitution_coefficient(value: f32, name: &'static str) -> anyhow::Result<()> {
+fn validate_unit_interval(value: f32, path: &str) -> anyhow::Result<()> {
     if (0.0..=1.0).contains(&value) {
         Ok(())
     } else {
-        Err(anyhow!("{} must be in range [0.0, 1.0]", name))
+        Err(invalid_field(path, "must be in range [0.0, 1.0]"))
     }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+// Unset coefficients are taken from the `[simulation]` section or are neutral: no friction, no drag, normal gravity,
+// no adhesion
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialConfig {
+    pub restitution_coefficient: Option<f32>,
+    pub friction: Option<f32>,
+    // Exponential velocity decay rate, per second
+    pub drag: Option<f32>,
+    pub gravity_scale: Option<f32>,
+    // Contacts separating slower than this, in meters per second, stick together if both materials are sticky
+    pub adhesion_speed: Option<f32>,
+    // Force in newtons that breaks a sticking contact
+    pub adhesion_force: Option<f32>,
+}
+
+impl MaterialConfig {
+    fn validate(&self, path: &str) -> anyhow::Result<()> {
+        if let Some(restitution_coefficient) = self.restitution_coefficient {
+            validate_unit_interval(restitution_coefficient, &format!("{path}.restitution_coefficient"))?;
+        }
+        if let Some(friction) = self.friction {
+            validate_non_negative(friction, &format!("{path}.friction"))?;
+        }
+        if let Some(drag) = self.drag {
+            validate_non_negative(drag, &format!("{path}.drag"))?;
+        }
+        if let Some(adhesion_speed) = self.adhesion_speed {
+            validate_non_negative(adhesion_speed, &format!("{path}.adhesion_speed"))?;
+        }
+        if let Some(adhesion_force) = self.adhesion_force {
+            validate_non_negative(adhesion_force, &format!("{path}.adhesion_force"))?;
+        }
+        Ok(())
+    }
+}
+
+// Coefficients of the collisions between two materials, in place of the combined ones
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialPairConfig {
+    pub materials: [String; 2],
+    pub restitution_coefficient: Option<f32>,
+    pub friction: Option<f32>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct WindowConfig {
     pub width: u32,
     pub height: u32,
+    // The package name and version by default
+    pub title: Option<String>,
+    // Application ID on Wayland and class on X11, which desktop files and window rules match
+    #[serde(default = "default_window_app_id")]
+    pub app_id: String,
+    // Generated particle cluster
+    #[serde(default = "default_window_icon")]
+    pub icon: bool,
+    // Open the stats window at startup; it's toggled with D either way
+    #[serde(default)]
+    pub stats_window: bool,
+    // What the simulation does while the window is unfocused or minimized
+    #[serde(default)]
+    pub background: Background,
+    // Upper limit of the step rate in the background when throttled
+    #[serde(default = "default_background_steps_per_second")]
+    pub background_steps_per_second: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Background {
+    #[default]
+    #[serde(rename = "run")]
+    Run,
+
+    #[serde(rename = "pause")]
+    Pause,
+
+    #[serde(rename = "throttle")]
+    Throttle,
 }
 
-#[derive(Deserialize, Clone, Copy)]
+fn default_background_steps_per_second() -> f32 {
+    10.0
+}
+
+fn default_window_app_id() -> String {
+    env!("CARGO_PKG_NAME").to_string()
+}
+
+fn default_window_icon() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct SimulationConfig {
     #[serde(default)]
     pub auto_start: bool,
     #[serde(default)]
     pub dt: DtSource,
+    #[serde(default)]
+    pub mode: SimulationMode,
+    #[serde(default = "default_hybrid_max_cluster_size")]
+    pub hybrid_max_cluster_size: usize,
     #[serde(default = "default_speed_factor")]
     pub speed_factor: f32,
+    // Time it takes the speed multiplier to change, in seconds of real time
+    #[serde(default = "default_speed_ramp_time")]
+    pub speed_ramp_time: f32,
     #[serde(default)]
     pub gpu_integration: bool,
     #[serde(default)]
     pub gpu_bvh: bool,
+    #[serde(default)]
+    pub auto_gpu_compute: bool,
+    #[serde(default = "default_auto_gpu_compute_period")]
+    pub auto_gpu_compute_period: usize,
+    #[serde(default)]
+    pub validate_gpu: bool,
+    #[serde(default = "default_validate_gpu_period")]
+    pub validate_gpu_period: usize,
+    // Seconds without a simulation loop iteration after which the simulation is reported as stalled
+    #[serde(default = "default_watchdog_timeout")]
+    pub watchdog_timeout: f32,
+    // Seconds a GPU kernel may run before the simulation falls back to the CPU, instead of hanging on a driver reset
+    #[serde(default = "default_gpu_kernel_timeout")]
+    pub gpu_kernel_timeout: f32,
+    // Seconds a step may take before the remaining collisions are deferred to the next step
+    #[serde(default)]
+    pub collision_budget: Option<f32>,
+    #[serde(default = "default_angular_momentum_drift_tolerance")]
+    pub angular_momentum_drift_tolerance: f64,
     #[serde(default = "default_wg_size")]
     pub gpu_integration_local_wg_size: usize,
     #[serde(default = "default_wg_size")]
     pub gpu_bvh_local_wg_size: usize,
     pub restitution_coefficient: f32,
+    // How the coefficients of two colliding materials are combined
+    #[serde(default = "default_restitution_combine")]
+    pub restitution_combine: CombineRule,
+    #[serde(default = "default_friction_combine")]
+    pub friction_combine: CombineRule,
+    #[serde(default)]
+    pub restitution_velocity_threshold: f32,
+    #[serde(default)]
+    pub penetration_slop: f32,
+    #[serde(default = "default_position_correction_factor")]
+    pub position_correction_factor: f32,
+    #[serde(default = "default_constraint_bouncing")]
+    pub constraint_bouncing: bool,
+    #[serde(default)]
+    pub boundaries: BoundariesConfig,
     #[serde(default)]
     pub global_gravity: (f32, f32),
+    #[serde(default)]
+    pub gravity_zones: Vec<GravityZoneConfig>,
     pub gravitational_constant: f32,
+    pub wind: Option<WindConfig>,
+    pub thermostat: Option<ThermostatConfig>,
+    pub fluid: Option<FluidConfig>,
+    // Rate at which touching particles even out their heat, per second; heat is only tracked if set
+    pub heat_conduction: Option<f32>,
+    #[serde(default)]
+    pub compensated_summation: bool,
+    pub pair_cache_margin: Option<f32>,
+    // Skin distance of the Verlet lists, which replace the pair cache
+    pub verlet_skin: Option<f32>,
+    #[serde(default)]
+    pub pair_storage: PairStorageKind,
+    // Invalid floating-point operations on the simulation thread stop the process
+    #[serde(default)]
+    pub trap_fp_exceptions: bool,
+    // The traps are disabled during GPU driver calls, since some drivers raise benign exceptions
+    #[serde(default = "default_guard_gpu_fp_exceptions")]
+    pub guard_gpu_fp_exceptions: bool,
     pub time_limit: Option<f32>,
     #[serde(default)]
     pub time_limit_action: TimeLimitAction,
+    pub step_limit: Option<usize>,
+    #[serde(default)]
+    pub step_limit_action: TimeLimitAction,
+    pub script: Option<String>,
+    pub trajectories: Option<TrajectoriesConfig>,
+    pub capture: Option<CaptureConfig>,
+}
+
+fn default_constraint_bouncing() -> bool {
+    true
+}
+
+fn default_guard_gpu_fp_exceptions() -> bool {
+    true
 }
 
 fn default_speed_factor() -> f32 {
     1.0
 }
 
+fn default_watchdog_timeout() -> f32 {
+    5.0
+}
+
+fn default_gpu_kernel_timeout() -> f32 {
+    2.0
+}
+
+fn default_speed_ramp_time() -> f32 {
+    0.5
+}
+
+fn default_restitution_combine() -> CombineRule {
+    CombineRule::Max
+}
+
+fn default_friction_combine() -> CombineRule {
+    CombineRule::GeometricMean
+}
+
+fn default_position_correction_factor() -> f32 {
+    1.0
+}
+
+fn default_auto_gpu_compute_period() -> usize {
+    100
+}
+
+fn default_hybrid_max_cluster_size() -> usize {
+    16
+}
+
+fn default_validate_gpu_period() -> usize {
+    10
+}
+
+fn default_angular_momentum_drift_tolerance() -> f64 {
+    1e-3
+}
+
 fn default_wg_size() -> usize {
     64
 }
 
-#[derive(Deserialize, Clone, Copy, Default)]
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct WindConfig {
+    #[serde(default)]
+    pub acceleration: (f32, f32),
+    #[serde(default)]
+    pub turbulence_amplitude: f32,
+    // Size of the turbulence swirls
+    #[serde(default = "default_wind_turbulence_scale")]
+    pub turbulence_scale: f32,
+    // Turbulence changes per second
+    #[serde(default = "default_wind_turbulence_speed")]
+    pub turbulence_speed: f32,
+}
+
+// Replaces the global gravity below the height, in meters from the top, down to the next zone
+#[derive(Deserialize, Serialize, Clone, Copy)]
 #[serde(deny_unknown_fields)]
-pub enum DtSource {
+pub struct GravityZoneConfig {
+    pub min_y: f32,
+    pub gravity: (f32, f32),
+}
+
+// What happens to the particles reaching each wall, not in event-driven mode
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub struct BoundariesConfig {
+    #[serde(default)]
+    pub left: WallConfig,
+    #[serde(default)]
+    pub right: WallConfig,
+    #[serde(default)]
+    pub top: WallConfig,
+    #[serde(default)]
+    pub bottom: WallConfig,
+}
+
+impl BoundariesConfig {
+    #[must_use]
+    pub fn wall(&self, wall: Wall) -> WallConfig {
+        match wall {
+            Wall::Left => self.left,
+            Wall::Right => self.right,
+            Wall::Top => self.top,
+            Wall::Bottom => self.bottom,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub enum WallConfig {
     #[default]
-    #[serde(rename = "auto")]
-    Auto,
+    #[serde(rename = "reflect")]
+    Reflect,
+
+    #[serde(rename = "absorb")]
+    Absorb,
+
+    #[serde(rename = "wrap")]
+    Wrap,
+
+    #[serde(rename = "inflow")]
+    Inflow(InflowConfig),
+}
+
+// Particles added at random positions along a wall
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct InflowConfig {
+    // Particles per second
+    pub rate: f32,
+    // Meters per second
+    #[serde(default)]
+    pub velocity: (f32, f32),
+    // Meters
+    pub radius: f32,
+    // Kilograms
+    pub mass: f32,
+}
+
+impl InflowConfig {
+    fn validate(&self, path: &str) -> anyhow::Result<()> {
+        validate_non_negative(self.rate, &format!("{path}.rate"))?;
+        validate_positive(self.radius, &format!("{path}.radius"))?;
+        validate_positive(self.mass, &format!("{path}.mass"))?;
+        Ok(())
+    }
+}
+
+// Recorded positions and velocities of the selected objects, written to a CSV file on exit
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TrajectoriesConfig {
+    // Object indices
+    pub objects: Vec<usize>,
+    // Steps between the samples
+    #[serde(default = "default_trajectory_period")]
+    pub period: usize,
+    // Latest samples kept per object
+    #[serde(default = "default_trajectory_capacity")]
+    pub capacity: usize,
+    #[serde(default = "default_trajectory_path")]
+    pub path: String,
+}
+
+fn default_trajectory_period() -> usize {
+    1
+}
+
+fn default_trajectory_capacity() -> usize {
+    10000
+}
+
+fn default_trajectory_path() -> String {
+    "trajectories.csv".to_string()
+}
+
+// Objects of every `stride`-th step, streamed to a file for collision-render
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureConfig {
+    #[serde(default = "default_capture_stride")]
+    pub stride: usize,
+    #[serde(default = "default_capture_path")]
+    pub path: String,
+}
+
+fn default_capture_stride() -> usize {
+    1
+}
+
+fn default_capture_path() -> String {
+    "capture.bin".to_string()
+}
+
+// Brownian agitation of the particles
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct ThermostatConfig {
+    // Mean kinetic energy per degree of freedom, in joules
+    pub temperature: f32,
+    // Strength of the random velocity kicks, per second
+    #[serde(default = "default_thermostat_kick_rate")]
+    pub kick_rate: f32,
+    // The velocities are rescaled towards the temperature with this time constant, in seconds, if set
+    pub relaxation_time: Option<f32>,
+}
+
+fn default_thermostat_kick_rate() -> f32 {
+    1.0
+}
+
+// Density constraint that makes the particles behave like a liquid
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct FluidConfig {
+    // Kilograms per square meter
+    pub rest_density: f32,
+    // Distance between the particle centers within which they contribute to each other's density, in meters
+    pub smoothing_radius: f32,
+    // Fraction of the density error corrected per iteration
+    #[serde(default = "default_fluid_relaxation")]
+    pub relaxation: f32,
+    #[serde(default = "default_fluid_iterations")]
+    pub iterations: usize,
+}
+
+fn default_fluid_relaxation() -> f32 {
+    0.5
+}
+
+fn default_fluid_iterations() -> usize {
+    2
+}
+
+fn default_wind_turbulence_scale() -> f32 {
+    100.0
+}
 
-    #[serde(rename = "fixed")]
-    Fixed(f32),
+fn default_wind_turbulence_speed() -> f32 {
+    0.5
 }
 
-#[derive(Deserialize, Clone, Copy, Default)]
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
 pub enum TimeLimitAction {
     #[default]
     #[serde(rename = "exit")]
@@ -166,7 +957,7 @@ impl Display for TimeLimitAction {
     }
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct DemoConfig {
     pub object_radius: f32,
@@ -182,17 +973,33 @@ pub struct DemoConfig {
     pub randomize_radii: bool,
     #[serde(default)]
     pub randomize_radius_factor: f32,
+    // Push the overlapping objects of the demo apart before the simulation starts
+    #[serde(default)]
+    pub relax_overlaps: bool,
+    #[serde(default = "default_demo_relaxation_iterations")]
+    pub relaxation_iterations: usize,
 
     #[serde(default)]
     pub bricks: Vec<Brick>,
 
     #[serde(default)]
     pub balls: Vec<Ball>,
+
+    #[serde(default)]
+    pub particles: Vec<Particle>,
+
+    // Scene file with more bricks, balls and particles, e.g. saved by the editor
+    pub scene: Option<String>,
+}
+
+fn default_demo_relaxation_iterations() -> usize {
+    200
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Deserialize, Serialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct RenderConfig {
+    // Runs without a window, like --headless
     #[serde(default = "default_rendering_enabled")]
     pub enabled: bool,
 
@@ -201,6 +1008,473 @@ pub struct RenderConfig {
 
     #[serde(default)]
     pub show_edf: bool,
+
+    #[serde(default)]
+    pub show_wind: bool,
+
+    // Axis rulers in meters along the bottom and the right edges, toggled with U
+    #[serde(default)]
+    pub show_rulers: bool,
+
+    // Particle colors add up where particles overlap, so that dense hot regions glow; slower, toggled with X
+    #[serde(default)]
+    pub additive_blending: bool,
+
+    // Space where particle colors are interpolated and trails are accumulated
+    #[serde(default)]
+    pub color_math: ColorMath,
+
+    #[serde(default)]
+    pub planets: PlanetLayerConfig,
+
+    #[serde(default)]
+    pub trails: TrailsConfig,
+
+    #[serde(default)]
+    pub adaptive_quality: AdaptiveQualityConfig,
+
+    #[serde(default)]
+    pub auto_camera: AutoCameraConfig,
+
+    // Velocities are drawn relative to the object followed by the camera
+    #[serde(default)]
+    pub follow_relative_velocity: bool,
+
+    // Objects are never drawn smaller than this on screen, in pixels
+    #[serde(default = "default_min_screen_radius")]
+    pub min_screen_radius: f32,
+    // Objects smaller than this on screen are drawn as squares, which are cheaper to fill
+    pub point_sprite_radius: Option<f32>,
+    // Objects smaller than a pixel on screen are drawn as faint squares, as opaque as much of them the object covers,
+    // instead of being enlarged to the minimum radius, so that sparse and dense regions look as they are
+    #[serde(default)]
+    pub subpixel_splats: bool,
+
+    #[serde(default)]
+    pub depth_sort: DepthSort,
+    // Only used when depth sorting is enabled
+    #[serde(default = "default_larger_on_top")]
+    pub larger_on_top: bool,
+
+    // Larger minimum radii, outlined particles and bold overlay text, toggled with H
+    #[serde(default)]
+    pub high_contrast: bool,
+    // Slower simulation and no flashing color sources, toggled with M
+    #[serde(default)]
+    pub reduced_motion: bool,
+    // Multiplies the speed factor in reduced-motion mode
+    #[serde(default = "default_reduced_motion_speed_factor")]
+    pub reduced_motion_speed_factor: f32,
+}
+
+fn default_reduced_motion_speed_factor() -> f32 {
+    0.5
+}
+
+fn default_larger_on_top() -> bool {
+    true
+}
+
+// Draw order of particles; by default they are drawn in index order
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
+pub enum DepthSort {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+
+    #[serde(rename = "radius")]
+    Radius,
+
+    #[serde(rename = "mass")]
+    Mass,
+}
+
+fn default_min_screen_radius() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlanetLayerConfig {
+    // Radius of the glow relative to the planet radius, no glow if not greater than 1
+    #[serde(default = "default_planet_glow_radius_factor")]
+    pub glow_radius_factor: f32,
+    #[serde(default = "default_planet_outline")]
+    pub outline: bool,
+    // Labels for planets, in the order they are added
+    #[serde(default)]
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub show_mass: bool,
+    #[serde(default)]
+    pub show_velocity: bool,
+    // Velocity and acceleration arrows, toggled with V
+    #[serde(default)]
+    pub show_vectors: bool,
+    // Arrow length per unit of velocity, i.e. how far the planet gets in that time
+    #[serde(default = "default_planet_velocity_vector_scale")]
+    pub velocity_vector_scale: f32,
+    // Arrow length per unit of acceleration
+    #[serde(default = "default_planet_acceleration_vector_scale")]
+    pub acceleration_vector_scale: f32,
+}
+
+impl Default for PlanetLayerConfig {
+    fn default() -> Self {
+        Self {
+            glow_radius_factor: default_planet_glow_radius_factor(),
+            outline: default_planet_outline(),
+            names: Vec::new(),
+            show_mass: false,
+            show_velocity: false,
+            show_vectors: false,
+            velocity_vector_scale: default_planet_velocity_vector_scale(),
+            acceleration_vector_scale: default_planet_acceleration_vector_scale(),
+        }
+    }
+}
+
+fn default_planet_glow_radius_factor() -> f32 {
+    3.0
+}
+
+fn default_planet_outline() -> bool {
+    true
+}
+
+fn default_planet_velocity_vector_scale() -> f32 {
+    0.5
+}
+
+fn default_planet_acceleration_vector_scale() -> f32 {
+    0.05
+}
+
+// Camera that follows the bounding box of all objects, toggled with C; panning or zooming turns it off
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct AutoCameraConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Space around the objects on every side, relative to the size of their bounding box
+    #[serde(default = "default_auto_camera_margin")]
+    pub margin: f32,
+    // Time constant of the camera motion, in seconds of real time
+    #[serde(default = "default_auto_camera_smoothing_time")]
+    pub smoothing_time: f32,
+}
+
+impl Default for AutoCameraConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            margin: default_auto_camera_margin(),
+            smoothing_time: default_auto_camera_smoothing_time(),
+        }
+    }
+}
+
+fn default_auto_camera_margin() -> f32 {
+    0.05
+}
+
+fn default_auto_camera_smoothing_time() -> f32 {
+    0.5
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TrailsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Fraction of the trail brightness kept every frame
+    #[serde(default = "default_trails_decay")]
+    pub decay: f32,
+    // Size of a trail image pixel, in simulation units
+    #[serde(default = "default_trails_cell_size")]
+    pub cell_size: f32,
+    // Brightness added by a particle every frame
+    #[serde(default = "default_trails_intensity")]
+    pub intensity: f32,
+    // Where the trail images are saved
+    #[serde(default = "default_trails_directory")]
+    pub directory: String,
+}
+
+impl Default for TrailsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            decay: default_trails_decay(),
+            cell_size: default_trails_cell_size(),
+            intensity: default_trails_intensity(),
+            directory: default_trails_directory(),
+        }
+    }
+}
+
+fn default_trails_decay() -> f32 {
+    0.97
+}
+
+fn default_trails_cell_size() -> f32 {
+    1.0
+}
+
+fn default_trails_intensity() -> f32 {
+    0.25
+}
+
+fn default_trails_directory() -> String {
+    ".".to_string()
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveQualityConfig {
+    // Rendering quality is lowered to hold this frame rate; adaptive quality is disabled if not set
+    pub target_fps: Option<usize>,
+    // Quality is restored when the frame rate exceeds the target by this factor
+    #[serde(default = "default_adaptive_quality_headroom")]
+    pub headroom: f32,
+}
+
+impl Default for AdaptiveQualityConfig {
+    fn default() -> Self {
+        Self {
+            target_fps: None,
+            headroom: default_adaptive_quality_headroom(),
+        }
+    }
+}
+
+fn default_adaptive_quality_headroom() -> f32 {
+    1.25
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EditorConfig {
+    // Particles placed by the editor and the particles of the bricks and balls drawn in it
+    #[serde(default = "default_editor_particle_radius")]
+    pub particle_radius: f32,
+    #[serde(default = "default_editor_particle_spacing")]
+    pub particle_spacing: f32,
+    #[serde(default = "default_editor_particle_mass")]
+    pub particle_mass: f32,
+    // Where the edited scene is saved
+    #[serde(default = "default_editor_scene")]
+    pub scene: String,
+    // Number of interactive edits that can be undone
+    #[serde(default = "default_editor_undo_limit")]
+    pub undo_limit: usize,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            particle_radius: default_editor_particle_radius(),
+            particle_spacing: default_editor_particle_spacing(),
+            particle_mass: default_editor_particle_mass(),
+            scene: default_editor_scene(),
+            undo_limit: default_editor_undo_limit(),
+        }
+    }
+}
+
+fn default_editor_particle_radius() -> f32 {
+    2.0
+}
+
+fn default_editor_particle_spacing() -> f32 {
+    0.1
+}
+
+fn default_editor_particle_mass() -> f32 {
+    0.1
+}
+
+fn default_editor_scene() -> String {
+    "scene.toml".to_string()
+}
+
+fn default_editor_undo_limit() -> usize {
+    100
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct UiConfig {
+    #[serde(default)]
+    pub language: Language,
+    // Size of the stats, the color legend and the plots relative to their size on a standard display; the scale factor
+    // of the window if not set
+    pub overlay_scale: Option<f32>,
+    // Where the stats are anchored; the color legend is at the bottom, on the other side if the stats are there too
+    #[serde(default)]
+    pub overlay_corner: OverlayCorner,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverlayCorner {
+    #[default]
+    #[serde(rename = "top_left")]
+    TopLeft,
+
+    #[serde(rename = "top_right")]
+    TopRight,
+
+    #[serde(rename = "bottom_left")]
+    BottomLeft,
+
+    #[serde(rename = "bottom_right")]
+    BottomRight,
+}
+
+impl OverlayCorner {
+    #[must_use]
+    pub fn is_right(self) -> bool {
+        matches!(self, OverlayCorner::TopRight | OverlayCorner::BottomRight)
+    }
+
+    #[must_use]
+    pub fn is_bottom(self) -> bool {
+        matches!(self, OverlayCorner::BottomLeft | OverlayCorner::BottomRight)
+    }
+}
+
+// Averaging windows of the reported stats
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StatsConfig {
+    // Steps averaged by the step durations, and frames by the frame durations
+    #[serde(default = "default_stats_window_frames")]
+    pub window_frames: usize,
+    // Wall time the FPS is averaged over
+    #[serde(default = "default_stats_fps_period_ms")]
+    pub fps_period_ms: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            window_frames: default_stats_window_frames(),
+            fps_period_ms: default_stats_fps_period_ms(),
+        }
+    }
+}
+
+fn default_stats_window_frames() -> usize {
+    DurationStat::DEFAULT_WINDOW
+}
+
+fn default_stats_fps_period_ms() -> u64 {
+    300
+}
+
+// Spring that drags the objects grabbed with the mouse, per unit mass
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MouseConfig {
+    // Per second squared
+    #[serde(default = "default_mouse_spring_stiffness")]
+    pub spring_stiffness: f32,
+    // Per second, critically damped at 2 * sqrt(spring_stiffness)
+    #[serde(default = "default_mouse_spring_damping")]
+    pub spring_damping: f32,
+    // Meters per second, of the objects at the cursor pushed with Ctrl and the right button or pulled with Ctrl and
+    // the left one; the kick fades out towards the edge of the influence radius
+    #[serde(default = "default_mouse_kick_speed")]
+    pub kick_speed: f32,
+}
+
+impl Default for MouseConfig {
+    fn default() -> Self {
+        Self {
+            spring_stiffness: default_mouse_spring_stiffness(),
+            spring_damping: default_mouse_spring_damping(),
+            kick_speed: default_mouse_kick_speed(),
+        }
+    }
+}
+
+fn default_mouse_spring_stiffness() -> f32 {
+    400.0
+}
+
+fn default_mouse_kick_speed() -> f32 {
+    2000.0
+}
+
+fn default_mouse_spring_damping() -> f32 {
+    40.0
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AutosaveConfig {
+    // Wall time between autosaves, in seconds; autosave is disabled if not set
+    pub interval: Option<f32>,
+    #[serde(default = "default_autosave_keep")]
+    pub keep: usize,
+    #[serde(default = "default_autosave_directory")]
+    pub directory: String,
+}
+
+impl Default for AutosaveConfig {
+    fn default() -> Self {
+        Self {
+            interval: None,
+            keep: default_autosave_keep(),
+            directory: default_autosave_directory(),
+        }
+    }
+}
+
+fn default_autosave_keep() -> usize {
+    3
+}
+
+fn default_autosave_directory() -> String {
+    "autosave".to_string()
+}
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThreadsConfig {
+    // CPU cores to pin the simulation thread to; not pinned if empty
+    #[serde(default)]
+    pub simulation_cores: Vec<usize>,
+    // CPU cores to pin the physics worker threads to; not pinned if empty
+    #[serde(default)]
+    pub worker_cores: Vec<usize>,
+    pub simulation_nice: Option<i32>,
+    // Thread counts; a thread per CPU by default. The field thread and the scene build share the physics pool if their
+    // sizes match.
+    pub physics: Option<usize>,
+    pub field: Option<usize>,
+    pub render_scene: Option<usize>,
+}
+
+impl ThreadsConfig {
+    #[must_use]
+    pub fn physics_thread_count(&self) -> usize {
+        self.physics.unwrap_or(if self.worker_cores.is_empty() {
+            num_cpus::get()
+        } else {
+            self.worker_cores.len()
+        })
+    }
+
+    #[must_use]
+    pub fn field_thread_count(&self) -> usize {
+        self.field.unwrap_or_else(num_cpus::get)
+    }
+
+    #[must_use]
+    pub fn render_scene_thread_count(&self) -> usize {
+        self.render_scene.unwrap_or_else(num_cpus::get)
+    }
 }
 
 fn default_rendering_enabled() -> bool {
@@ -211,7 +1485,7 @@ fn default_color_source() -> ColorSource {
     ColorSource::Velocity
 }
 
-#[derive(Debug, Deserialize, Clone, Copy, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default)]
 pub enum ColorSource {
     #[serde(rename = "none")]
     None,
@@ -228,4 +1502,37 @@ pub enum ColorSource {
 
     #[serde(rename = "dark")]
     Dark,
+
+    #[serde(rename = "heat")]
+    Heat,
+}
+
+impl ColorSource {
+    // Colors that change with every collision, so that busy regions flicker
+    #[must_use]
+    pub fn is_flashing(self) -> bool {
+        matches!(self, ColorSource::Velocity | ColorSource::Heat)
+    }
+}
+
+#[test]
+fn printed_config_parses_back() {
+    let config = AppConfig::from_file(Path::new("config.toml")).unwrap();
+    let printed = config.to_toml().unwrap();
+    assert!(printed.contains("particle_spacing = 0.1\n"), "{printed}");
+    let parsed: AppConfig = toml::from_str(&printed).unwrap();
+    parsed.validate().unwrap();
+    assert_eq!(parsed.to_toml().unwrap(), printed);
+}
+
+#[test]
+fn validation_errors_point_at_the_field() {
+    let config = AppConfig::from_file(Path::new("config.toml")).unwrap().to_toml().unwrap();
+    let config = format!("{config}\n[[demo.bricks]]\nposition = [0, 0]\nsize = [10, -1]\nvelocity = [0, 0]\n");
+    let line = config.lines().count() - 1;
+    let error = AppConfig::parse(&config, Path::new("scene.toml")).err().unwrap();
+    assert_eq!(
+        format!("{error:#}"),
+        format!("validate config at scene.toml:{line}:8: demo.bricks[1].size.y must be positive")
+    );
 }
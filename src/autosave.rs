@@ -0,0 +1,73 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::physics::PhysicsEngine;
+
+const PREFIX: &str = "autosave-";
+const EXTENSION: &str = "snapshot";
+
+// Writes a snapshot every `interval` of wall time and keeps only the `keep` latest ones
+pub struct Autosave {
+    directory: PathBuf,
+    interval: Duration,
+    keep: usize,
+    last_save: Instant,
+}
+
+impl Autosave {
+    #[must_use]
+    pub fn new(directory: PathBuf, interval: Duration, keep: usize) -> Self {
+        Self {
+            directory,
+            interval,
+            keep,
+            last_save: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, physics: &PhysicsEngine) -> anyhow::Result<()> {
+        if self.last_save.elapsed() < self.interval {
+            return Ok(());
+        }
+        self.last_save = Instant::now();
+
+        fs::create_dir_all(&self.directory)
+            .with_context(|| format!("create autosave directory \"{}\"", self.directory.display()))?;
+        // Zero-padded so that the lexicographic order of the names is chronological
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let path = self.directory.join(format!("{PREFIX}{timestamp:020}.{EXTENSION}"));
+        physics.save_state(&path)?;
+
+        let autosaves = list(&self.directory)?;
+        for old_autosave in &autosaves[..autosaves.len().saturating_sub(self.keep)] {
+            fs::remove_file(old_autosave).with_context(|| format!("remove \"{}\"", old_autosave.display()))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn latest(directory: &Path) -> anyhow::Result<Option<PathBuf>> {
+    if !directory.exists() {
+        return Ok(None);
+    }
+    Ok(list(directory)?.pop())
+}
+
+fn list(directory: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut autosaves = Vec::new();
+    for entry in fs::read_dir(directory).with_context(|| format!("list \"{}\"", directory.display()))? {
+        let path = entry?.path();
+        let is_autosave = path.extension().is_some_and(|extension| extension == EXTENSION)
+            && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(PREFIX));
+        if is_autosave {
+            autosaves.push(path);
+        }
+    }
+    autosaves.sort();
+    Ok(autosaves)
+}
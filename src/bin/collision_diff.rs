@@ -0,0 +1,80 @@
+// Compares two snapshots object by object, e.g. to check that a refactoring stays within numerical tolerance of the
+// reference implementation. Exits with an error if a difference exceeds the given tolerance.
+//
+// Usage: collision-diff <reference> <snapshot> [--position-tolerance <value>] [--velocity-tolerance <value>]
+
+use std::{env, path::PathBuf, process::ExitCode};
+
+use anyhow::{Context, bail};
+use collision::{snapshot::Snapshot, vector2::Vector2};
+
+fn main() -> anyhow::Result<ExitCode> {
+    let mut paths = Vec::new();
+    let mut position_tolerance = None;
+    let mut velocity_tolerance = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut tolerance = |name: &str| -> anyhow::Result<Option<f32>> {
+            let value = args.next().with_context(|| format!("missing value for {name}"))?;
+            Ok(Some(value.parse().with_context(|| format!("invalid value for {name}"))?))
+        };
+        match arg.as_str() {
+            "--position-tolerance" => position_tolerance = tolerance(&arg)?,
+            "--velocity-tolerance" => velocity_tolerance = tolerance(&arg)?,
+            _ if arg.starts_with("--") => bail!("unknown option \"{arg}\""),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [reference_path, snapshot_path] = paths.as_slice() else {
+        bail!(
+            "usage: collision-diff <reference> <snapshot> [--position-tolerance <value>] [--velocity-tolerance <value>]"
+        );
+    };
+
+    let reference = Snapshot::load(reference_path)?;
+    let snapshot = Snapshot::load(snapshot_path)?;
+    if reference.objects.len() != snapshot.objects.len() {
+        bail!("object count differs: {} vs {}", reference.objects.len(), snapshot.objects.len());
+    }
+
+    println!("time: {} vs {}", reference.time, snapshot.time);
+    println!("objects: {}", reference.objects.len());
+    let position = Difference::new(&reference.objects.positions, &snapshot.objects.positions);
+    let velocity = Difference::new(&reference.objects.velocities, &snapshot.objects.velocities);
+    println!("position difference: max {} (object {}), mean {}", position.max, position.max_index, position.mean);
+    println!("velocity difference: max {} (object {}), mean {}", velocity.max, velocity.max_index, velocity.mean);
+
+    let exceeds = |difference: &Difference, tolerance: Option<f32>| tolerance.is_some_and(|t| difference.max > t);
+    if exceeds(&position, position_tolerance) || exceeds(&velocity, velocity_tolerance) {
+        println!("tolerance exceeded");
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+struct Difference {
+    max: f32,
+    max_index: usize,
+    mean: f32,
+}
+
+impl Difference {
+    fn new(reference: &[Vector2<f32>], values: &[Vector2<f32>]) -> Self {
+        let mut max = 0.0;
+        let mut max_index = 0;
+        let mut sum = 0.0_f64;
+        for (index, (&reference, &value)) in reference.iter().zip(values).enumerate() {
+            let difference = (value - reference).magnitude();
+            sum += f64::from(difference);
+            if difference > max {
+                max = difference;
+                max_index = index;
+            }
+        }
+        Self {
+            max,
+            max_index,
+            mean: (sum / reference.len().max(1) as f64) as f32,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// Runs the deterministic golden scenarios and compares the final state hashes against the stored ones, so that
+// accidental changes of the physics behavior are caught. After an intentional change, `--bless` updates the hashes.
+//
+// Usage: collision-golden [--bless] [--hashes <path>] [scenario...]
+
+use std::{env, path::PathBuf, process::ExitCode};
+
+use anyhow::{Context, bail};
+use collision::golden::{self, SCENARIOS, Scenario};
+
+const DEFAULT_HASHES_PATH: &str = "golden/hashes.txt";
+
+fn main() -> anyhow::Result<ExitCode> {
+    let mut bless = false;
+    let mut hashes_path = PathBuf::from(DEFAULT_HASHES_PATH);
+    let mut scenarios = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--hashes" => hashes_path = args.next().context("missing value for --hashes")?.into(),
+            _ if arg.starts_with("--") => bail!("unknown option \"{arg}\""),
+            name => {
+                scenarios.push(golden::find_scenario(name).with_context(|| format!("unknown scenario \"{name}\""))?)
+            }
+        }
+    }
+    if scenarios.is_empty() {
+        scenarios.extend(SCENARIOS);
+    }
+
+    let mut hashes = golden::read_golden_hashes(&hashes_path)?;
+    let mut failed = false;
+    for scenario in scenarios {
+        let Scenario { name, steps, .. } = *scenario;
+        let hash = golden::run(scenario)?;
+        match hashes.get(name) {
+            _ if bless => println!("{name}: {hash:016x}"),
+            Some(&(golden_steps, golden_hash)) if golden_steps == steps && golden_hash == hash => {
+                println!("{name}: ok");
+            }
+            Some(&(golden_steps, golden_hash)) if golden_steps == steps => {
+                println!("{name}: MISMATCH, expected {golden_hash:016x}, got {hash:016x}");
+                failed = true;
+            }
+            Some(&(golden_steps, _)) => {
+                println!("{name}: STALE, golden hash is for {golden_steps} steps instead of {steps}");
+                failed = true;
+            }
+            None => {
+                println!("{name}: MISSING, got {hash:016x}");
+                failed = true;
+            }
+        }
+        if bless {
+            hashes.insert(name.to_string(), (steps, hash));
+        }
+    }
+
+    if bless {
+        golden::write_golden_hashes(&hashes_path, &hashes)?;
+        println!("Golden hashes written to \"{}\"", hashes_path.display());
+    } else if failed {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
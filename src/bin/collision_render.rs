@@ -0,0 +1,107 @@
+// Renders the frames of a frame capture into numbered PNG images at any resolution, e.g. to encode a video with
+// `ffmpeg -framerate 60 -i frame-%06d.png video.mp4`. Objects are drawn in their own colors, or gray if they have
+// none, or colored by speed relative to the fastest object of the frame. Colors are blended in linear light, or on the
+// sRGB-encoded values with --legacy-colors, as the renderer used to.
+//
+// Usage: collision-render <capture> <output directory> [--width <pixels>] [--height <pixels>] [--velocity-colors]
+//        [--legacy-colors]
+
+use std::{
+    env,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+use collision::{
+    capture::{CaptureReader, CapturedFrame, render_frame},
+    gamma::ColorMath,
+    vector2::Vector2,
+};
+use peniko::{Color, color::palette::css};
+
+fn main() -> anyhow::Result<()> {
+    let mut paths = Vec::new();
+    let mut width = 1920;
+    let mut height = 1080;
+    let mut velocity_colors = false;
+    let mut color_math = ColorMath::Linear;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut pixels = |name: &str| -> anyhow::Result<usize> {
+            let value = args.next().with_context(|| format!("missing value for {name}"))?;
+            let pixels = value.parse().with_context(|| format!("invalid value for {name}"))?;
+            if pixels == 0 {
+                bail!("{name} must be positive");
+            }
+            Ok(pixels)
+        };
+        match arg.as_str() {
+            "--width" => width = pixels(&arg)?,
+            "--height" => height = pixels(&arg)?,
+            "--velocity-colors" => velocity_colors = true,
+            "--legacy-colors" => color_math = ColorMath::Legacy,
+            _ if arg.starts_with("--") => bail!("unknown option \"{arg}\""),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+    let [capture_path, output_directory] = paths.as_slice() else {
+        bail!(
+            "usage: collision-render <capture> <output directory> [--width <pixels>] [--height <pixels>] \
+             [--velocity-colors] [--legacy-colors]"
+        );
+    };
+
+    fs::create_dir_all(output_directory)
+        .with_context(|| format!("create directory \"{}\"", output_directory.display()))?;
+    let mut reader = CaptureReader::open(capture_path)?;
+    let mut frame_index = 0;
+    while let Some(frame) = reader.next_frame()? {
+        let colors = if velocity_colors {
+            speed_colors(&frame, color_math)
+        } else {
+            frame.colors.iter().map(|color| color.unwrap_or(css::GRAY)).collect()
+        };
+        let pixels = render_frame(&frame, width, height, &colors, color_math);
+        let path = output_directory.join(format!("frame-{frame_index:06}.png"));
+        save_png(&path, &pixels, width, height)?;
+        println!("step {}, time {}: \"{}\"", frame.step_count, frame.time, path.display());
+        frame_index += 1;
+    }
+    println!("{frame_index} frames rendered");
+    Ok(())
+}
+
+// From blue for the slowest to red for the fastest object, interpolated in the space of the color math
+fn speed_colors(frame: &CapturedFrame, color_math: ColorMath) -> Vec<Color> {
+    let max_speed = frame.velocities.iter().map(Vector2::magnitude).fold(0.0, f32::max);
+    frame
+        .velocities
+        .iter()
+        .map(|velocity| {
+            let position = if max_speed > 0.0 {
+                velocity.magnitude() / max_speed
+            } else {
+                0.0
+            };
+            let [r, g, b] = [position, 1.0 - (position - 0.5).abs() * 2.0, 1.0 - position]
+                .map(|component| color_math.encode(component));
+            Color::new([r, g, b, 1.0])
+        })
+        .collect()
+}
+
+fn save_png(path: &Path, pixels: &[u8], width: usize, height: usize) -> anyhow::Result<()> {
+    let file = File::create(path).with_context(|| format!("create \"{}\"", path.display()))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        u32::try_from(width).context("image is too wide")?,
+        u32::try_from(height).context("image is too high")?,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().with_context(|| format!("write \"{}\"", path.display()))?;
+    writer.write_image_data(pixels).with_context(|| format!("write \"{}\"", path.display()))?;
+    writer.finish().with_context(|| format!("write \"{}\"", path.display()))
+}
@@ -0,0 +1,45 @@
+// Runs a parameter sweep without the window: every combination of the parameter values in the sweep specification
+// is simulated for a fixed step count. Writes the sampled stats of every run to `run-<index>.csv` and one line per
+// run to `summary.csv` in the output directory.
+//
+// Usage: collision-sweep <spec.toml> [--output <directory>]
+
+use std::{env, fs, path::PathBuf};
+
+use anyhow::{Context, bail};
+use collision::sweep::{self, SweepResult, SweepRun, SweepSpec};
+
+const DEFAULT_OUTPUT_DIRECTORY: &str = "sweep";
+
+fn main() -> anyhow::Result<()> {
+    let mut spec_path = None;
+    let mut output_directory = PathBuf::from(DEFAULT_OUTPUT_DIRECTORY);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output_directory = args.next().context("missing value for --output")?.into(),
+            _ if arg.starts_with("--") => bail!("unknown option \"{arg}\""),
+            _ if spec_path.is_none() => spec_path = Some(PathBuf::from(arg)),
+            _ => bail!("usage: collision-sweep <spec.toml> [--output <directory>]"),
+        }
+    }
+    let spec_path = spec_path.context("usage: collision-sweep <spec.toml> [--output <directory>]")?;
+
+    let spec = SweepSpec::load(&spec_path)?;
+    let runs = spec.runs()?;
+    fs::create_dir_all(&output_directory)
+        .with_context(|| format!("create directory \"{}\"", output_directory.display()))?;
+    let mut summary = format!("run,{},{}\n", SweepRun::CSV_HEADER, SweepResult::CSV_HEADER);
+    for (run_index, run) in runs.iter().enumerate() {
+        println!("run {}/{}: {}", run_index + 1, runs.len(), run.csv());
+        let result = spec.run(run).with_context(|| format!("run {run_index}"))?;
+        let run_path = output_directory.join(format!("run-{run_index:04}.csv"));
+        fs::write(&run_path, sweep::format_samples(&result.samples))
+            .with_context(|| format!("write \"{}\"", run_path.display()))?;
+        summary.push_str(&format!("{run_index},{},{}\n", run.csv(), result.csv()));
+    }
+    let summary_path = output_directory.join("summary.csv");
+    fs::write(&summary_path, summary).with_context(|| format!("write \"{}\"", summary_path.display()))?;
+    println!("{} runs written to \"{}\"", runs.len(), output_directory.display());
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use crate::vector2::Vector2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wall {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Wall {
+    pub const ALL: [Wall; 4] = [Wall::Left, Wall::Right, Wall::Top, Wall::Bottom];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Wall::Left => "left",
+            Wall::Right => "right",
+            Wall::Top => "top",
+            Wall::Bottom => "bottom",
+        }
+    }
+}
+
+// What happens to the objects that reach a wall of the simulation box. Planets are always stopped by the walls.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WallBehavior {
+    // Objects are stopped by the wall and bounce off it, if constraint bouncing is on
+    #[default]
+    Reflect,
+    // Particles are removed
+    Absorb,
+    // Particles reappear at the opposite wall once their centers cross the wall. Collisions across the boundary
+    // aren't detected.
+    Wrap,
+    // Reflects like a solid wall and adds particles next to it
+    Inflow(Inflow),
+}
+
+// Particles added at random positions along a wall
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Inflow {
+    // Particles per unit of time
+    pub rate: f32,
+    pub velocity: Vector2<f32>,
+    pub radius: f32,
+    pub mass: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Boundaries {
+    pub left: WallBehavior,
+    pub right: WallBehavior,
+    pub top: WallBehavior,
+    pub bottom: WallBehavior,
+}
+
+impl Boundaries {
+    #[must_use]
+    pub fn wall(&self, wall: Wall) -> WallBehavior {
+        match wall {
+            Wall::Left => self.left,
+            Wall::Right => self.right,
+            Wall::Top => self.top,
+            Wall::Bottom => self.bottom,
+        }
+    }
+}
+
+// Particles that left or entered the box through a wall since the start, and the net mass leaving it per unit of
+// time during the last step. Wrapped particles leave through one wall and enter through the opposite one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WallFlux {
+    pub outflow_count: usize,
+    pub inflow_count: usize,
+    pub mass_flux: f32,
+}
@@ -0,0 +1,14 @@
+use crate::{bvh::AABB, vector2::Vector2};
+
+// Spatial structure that finds the objects that may be touching. The objects are circles given by the positions and
+// radii, which are passed to every call, since the structure may keep only the indices.
+pub trait BroadPhase: Send {
+    // `bounds` is the region of the world where the objects are expected to be, though they may leave it
+    fn update(&mut self, positions: &[Vector2<f32>], radii: &[f32], bounds: AABB);
+
+    // Calls `f` with every object whose bounding box intersects the AABB
+    fn query_aabb(&self, aabb: AABB, positions: &[Vector2<f32>], radii: &[f32], f: &mut dyn FnMut(usize));
+
+    // Calls `f` once with every pair of overlapping objects, the lower index first
+    fn for_each_pair(&self, positions: &[Vector2<f32>], radii: &[f32], f: &mut dyn FnMut(usize, usize));
+}
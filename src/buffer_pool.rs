@@ -0,0 +1,86 @@
+use std::sync::{
+    Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+
+// Recycles `Vec`s, so that per-frame buffers keep their capacity instead of being reallocated every time
+pub struct BufferPool<T> {
+    free: Mutex<Vec<Vec<T>>>,
+    allocations: AtomicUsize,
+    reuses: AtomicUsize,
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct BufferPoolStats {
+    pub allocations: usize,
+    pub reuses: usize,
+}
+
+impl<T> BufferPool<T> {
+    const MAX_FREE_BUFFERS: usize = 8;
+
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            free: Mutex::new(Vec::new()),
+            allocations: AtomicUsize::new(0),
+            reuses: AtomicUsize::new(0),
+        }
+    }
+
+    // Returns an empty buffer
+    pub fn take(&self) -> Vec<T> {
+        if let Some(buffer) = self.free.lock().unwrap().pop() {
+            self.reuses.fetch_add(1, Ordering::Relaxed);
+            buffer
+        } else {
+            self.allocations.fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        }
+    }
+
+    pub fn take_copy(&self, data: &[T]) -> Vec<T>
+    where
+        T: Copy,
+    {
+        let mut buffer = self.take();
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    pub fn give(&self, mut buffer: Vec<T>) {
+        buffer.clear();
+        let mut free = self.free.lock().unwrap();
+        if free.len() < Self::MAX_FREE_BUFFERS {
+            free.push(buffer);
+        }
+    }
+
+    #[must_use]
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            allocations: self.allocations.load(Ordering::Relaxed),
+            reuses: self.reuses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T> Default for BufferPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn buffer_is_reused() {
+    let pool = BufferPool::new();
+    let mut buffer = pool.take_copy(&[1, 2, 3]);
+    buffer.push(4);
+    let capacity = buffer.capacity();
+    pool.give(buffer);
+    let buffer = pool.take();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.capacity(), capacity);
+    let stats = pool.stats();
+    assert_eq!((stats.allocations, stats.reuses), (1, 1));
+}
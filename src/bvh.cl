@@ -26,10 +26,14 @@ bool intersects(const AABB a, const AABB b) {
          a.topleft.y <= b.bottomright.y && a.bottomright.y >= b.topleft.y;
 }
 
-void print_node(global const Node *node) {
-  printf("[OpenCL node] tag: %d, aabb: %f %f %f %f\n", node->tag,
-         node->aabb.topleft.x, node->aabb.topleft.y, node->aabb.bottomright.x,
-         node->aabb.bottomright.y);
+// Tighter than the AABB test near the corners of the object's bounding box.
+// Node AABBs enclose the objects' circles, so a node can't contain a collision
+// partner unless it's touched by the object's circle.
+bool intersects_circle(const float2 center, const float radius,
+                       const AABB aabb) {
+  const float2 closest = clamp(center, aabb.topleft, aabb.bottomright);
+  const float2 delta = center - closest;
+  return dot(delta, delta) <= radius * radius;
 }
 
 #define MAX_CANDIDATES 16
@@ -41,59 +45,46 @@ kernel void bvh_find_candidates(
     const uint object_count, global uint2 *candidates,
     volatile global uint *candidates_length, volatile global uint *errors) {
   const uint object1_index = get_global_id(0);
-  if (object1_index == 0) {
-    print_node(&nodes[0]);
-    print_node(&nodes[1]);
-    printf("[OpenCL] sizeof(Node): %d\n", sizeof(Node));
-    printf("[OpenCL] sizeof(NodeTag): %d\n", sizeof(NodeTag));
-    printf("[OpenCL] sizeof(NodeData): %d\n", sizeof(NodeData));
-    printf("[OpenCL] sizeof(Tree): %d\n", sizeof(Tree));
-    printf("[OpenCL] sizeof(AABB): %d\n", sizeof(AABB));
-  }
 
-  const AABB object_aabb = nodes[object1_index].aabb;
   const float2 object1_position = positions[object1_index];
   const float object1_radius = radii[object1_index];
+  const AABB object_aabb = {object1_position - object1_radius,
+                            object1_position + object1_radius};
 
   uint stack[STACK_SIZE];
   int sp = 0;
   stack[sp++] = root;
 
-  AABB aabb;
   while (sp > 0) {
     const uint node_id = stack[--sp];
     const Node node = nodes[node_id];
-    if (!intersects(object_aabb, node.aabb)) {
+    if (!intersects(object_aabb, node.aabb) ||
+        !intersects_circle(object1_position, object1_radius, node.aabb)) {
       continue;
     }
 
     if (node.tag == TAG_LEAF) {
       const uint object2_index = node.data.leaf_object_index;
-      if (object2_index != object1_index) {
+      // Both objects of a pair find each other, so only the one with the
+      // lower index emits it, already normalized, and the candidates come out
+      // without duplicates
+      if (object2_index > object1_index) {
         const float2 object2_position = positions[object2_index];
         const float object2_radius = radii[object2_index];
         const float d = distance(object1_position, object2_position);
         const float collision_distance = object1_radius + object2_radius;
-        if (object1_index == 0) {
-          printf("[OpenCL] d: %f, collision_distance: %f\n", d,
-                 collision_distance);
-        }
+        // Only the overlapping pairs are compacted into the front of the
+        // candidates, so the host reads back just candidates_length of them.
+        // The pairs that don't fit are still counted, and the host reports
+        // them as dropped.
         if (d < collision_distance) {
-          const uint min_i = min(object1_index, object2_index);
-          const uint max_i = max(object1_index, object2_index);
           const uint index = atomic_add(candidates_length, 1);
           if (index < object_count * MAX_CANDIDATES) {
-            candidates[index] = (uint2)(min_i, max_i);
-          } else {
-            atomic_add(errors, 1);
+            candidates[index] = (uint2)(object1_index, object2_index);
           }
         }
       }
     } else {
-      if (object1_index == 0) {
-        printf("[OpenCL] left: %d, right: %d\n", node.data.tree.left,
-               node.data.tree.right);
-      }
       if (sp + 2 < STACK_SIZE) {
         stack[sp] = node.data.tree.left;
         stack[sp + 1] = node.data.tree.right;
@@ -104,3 +95,14 @@ kernel void bvh_find_candidates(
     }
   }
 }
+
+// Sizes of the types shared with the host, compared with the host types at
+// startup
+kernel void bvh_layout(global uint *sizes) {
+  sizes[0] = sizeof(AABB);
+  sizes[1] = sizeof(Node);
+  sizes[2] = sizeof(NodeTag);
+  sizes[3] = sizeof(NodeData);
+  sizes[4] = sizeof(float2);
+  sizes[5] = sizeof(uint2);
+}
@@ -1,19 +1,30 @@
-use std::time::Instant;
+use std::{iter::zip, time::Instant};
 
-use crate::{physics::NormalizedCollisionPair, vector2::Vector2};
+use crate::{broad_phase::BroadPhase, physics::NormalizedCollisionPair, vector2::Vector2};
 
 #[derive(Default, Clone)]
 pub struct Bvh {
     nodes: Vec<Node>,
+    morton_bounds: AABB,
+    morton_cells: Vec<[u32; 2]>,
+    morton_codes: Vec<u64>,
+    // Object indices sorted by Morton code, which is the order of the leaves
+    morton_order: Vec<u32>,
+    changed_morton_codes: usize,
 }
 
 impl Bvh {
-    pub fn update(&mut self, positions: &[Vector2<f32>], radii: &[f32]) {
+    // `bounds` is the region of the world that Morton codes are normalized to, objects outside of it are clamped
+    pub fn update(&mut self, positions: &[Vector2<f32>], radii: &[f32], bounds: AABB) {
+        let start = Instant::now();
+        self.update_morton_order(positions, bounds);
+        println!("BVH: sort by Morton code {:?} ({} changed)", start.elapsed(), self.changed_morton_codes);
+
         let start = Instant::now();
         self.nodes.clear();
-        self.nodes.extend((0..positions.len()).map(|object_index| {
-            let position = positions[object_index];
-            let radius = radii[object_index];
+        self.nodes.extend(self.morton_order.iter().map(|&object_index| {
+            let position = positions[object_index as usize];
+            let radius = radii[object_index as usize];
             Node {
                 aabb: AABB {
                     topleft: position - radius,
@@ -21,12 +32,10 @@ impl Bvh {
                 },
                 tag: NodeTag::Leaf,
                 data: NodeData {
-                    leaf_object_index: u32::try_from(object_index).unwrap(),
+                    leaf_object_index: object_index,
                 },
             }
         }));
-
-        // TODO check if sorting by Morton code is worth it
         println!("BVH: fill object nodes {:?}", start.elapsed());
 
         let start = Instant::now();
@@ -61,6 +70,47 @@ impl Bvh {
         println!("BVH: build tree {:?}", start.elapsed());
     }
 
+    // Number of objects whose Morton code changed during the last update
+    #[must_use]
+    pub fn changed_morton_codes(&self) -> usize {
+        self.changed_morton_codes
+    }
+
+    // Only the codes of objects that moved to another cell are recomputed. Objects move only slightly between updates,
+    // so the previous order is nearly sorted, which is the best case for insertion sort.
+    fn update_morton_order(&mut self, positions: &[Vector2<f32>], bounds: AABB) {
+        if self.morton_codes.len() != positions.len() || self.morton_bounds != bounds {
+            self.morton_bounds = bounds;
+            self.morton_cells.clear();
+            self.morton_cells.extend(positions.iter().map(|&position| morton_cell(position, bounds)));
+            self.morton_codes.clear();
+            self.morton_codes.extend(self.morton_cells.iter().map(|&cell| morton_code(cell)));
+            self.morton_order.clear();
+            self.morton_order.extend(0..u32::try_from(positions.len()).unwrap());
+            let codes = &self.morton_codes;
+            self.morton_order.sort_unstable_by_key(|&object_index| codes[object_index as usize]);
+            self.changed_morton_codes = positions.len();
+            return;
+        }
+
+        self.changed_morton_codes = 0;
+        for ((cell, code), &position) in zip(zip(&mut self.morton_cells, &mut self.morton_codes), positions) {
+            let new_cell = morton_cell(position, bounds);
+            if new_cell != *cell {
+                *cell = new_cell;
+                *code = morton_code(new_cell);
+                self.changed_morton_codes += 1;
+            }
+        }
+
+        let codes = &self.morton_codes;
+        let key = |&object_index: &u32| codes[object_index as usize];
+        // Give up on insertion sort if the order turns out to be far from sorted
+        if !insertion_sort_by_key(&mut self.morton_order, key, positions.len() * 8) {
+            self.morton_order.sort_unstable_by_key(key);
+        }
+    }
+
     pub fn nodes(&mut self) -> &mut [Node] {
         &mut self.nodes
     }
@@ -69,15 +119,62 @@ impl Bvh {
         u32::try_from(self.nodes.len()).unwrap().checked_sub(1).unwrap()
     }
 
+    // None for an empty tree
+    #[must_use]
+    pub fn metrics(&self) -> Option<BvhMetrics> {
+        const STACK_SIZE: usize = 64;
+
+        let root_aabb = self.nodes.last()?.aabb;
+        let root_perimeter = root_aabb.perimeter();
+        let mut stack = [(0, 0); STACK_SIZE];
+        stack[0] = (self.root(), 1);
+        let mut sp = 1;
+        let mut metrics = BvhMetrics::default();
+        let mut overlap_sum = 0.0;
+        let mut tree_count = 0;
+        while sp > 0 {
+            sp -= 1;
+            let (node_index, depth) = stack[sp];
+            let node = &self.nodes[node_index as usize];
+            metrics.depth = metrics.depth.max(depth);
+            // A point-sized root makes every node equally likely to be visited
+            metrics.sah_cost += if root_perimeter > 0.0 {
+                node.aabb.perimeter() / root_perimeter
+            } else {
+                1.0
+            };
+            if let NodeTag::Tree = node.tag {
+                let children = unsafe { node.data.tree };
+                let (left, right) = (self.nodes[children.left as usize].aabb, self.nodes[children.right as usize].aabb);
+                let area = node.aabb.area();
+                if area > 0.0 {
+                    overlap_sum += left.intersection(&right).map_or(0.0, |overlap| overlap.area()) / area;
+                }
+                tree_count += 1;
+                assert!(sp + 2 <= STACK_SIZE, "BVH traversal stack overflow");
+                stack[sp] = (children.left, depth + 1);
+                stack[sp + 1] = (children.right, depth + 1);
+                sp += 2;
+            }
+        }
+        #[allow(clippy::cast_precision_loss)]
+        if tree_count > 0 {
+            metrics.overlap = overlap_sum / tree_count as f32;
+        }
+        Some(metrics)
+    }
+
+    // Once the candidates are full, the shallowest penetration is replaced by a deeper one; returns the number of
+    // intersections that didn't fit
     pub fn find_intersections(
         &self,
         object1_index: usize,
         positions: &[Vector2<f32>],
         radii: &[f32],
         candidates: &mut [NormalizedCollisionPair],
-    ) {
+    ) -> usize {
         if self.nodes.is_empty() {
-            return;
+            return 0;
         }
 
         const STACK_SIZE: usize = 64;
@@ -86,10 +183,14 @@ impl Bvh {
         stack[sp] = self.root();
         sp += 1;
 
-        let object1_aabb = self.nodes[object1_index].aabb;
         let object1_position = positions[object1_index];
         let object1_radius = radii[object1_index];
+        let object1_aabb = AABB {
+            topleft: object1_position - object1_radius,
+            bottomright: object1_position + object1_radius,
+        };
         let mut candidate_index = 0;
+        let mut dropped_count = 0;
 
         while sp > 0 {
             sp -= 1;
@@ -114,10 +215,160 @@ impl Bvh {
                     let distance_squared = dx * dx + dy * dy;
                     let collision_distance = object1_radius + object2_radius;
 
-                    if distance_squared < collision_distance * collision_distance && candidate_index < candidates.len()
-                    {
-                        candidates[candidate_index] = NormalizedCollisionPair::new(object1_index, object2_index);
-                        candidate_index += 1;
+                    if distance_squared < collision_distance * collision_distance {
+                        let candidate = NormalizedCollisionPair::new(object1_index, object2_index);
+                        if candidate_index < candidates.len() {
+                            candidates[candidate_index] = candidate;
+                            candidate_index += 1;
+                        } else {
+                            dropped_count += 1;
+                            let penetration_depth = candidate.penetration_depth(positions, radii);
+                            if let Some((shallowest_index, shallowest_depth)) = candidates
+                                .iter()
+                                .map(|candidate| candidate.penetration_depth(positions, radii))
+                                .enumerate()
+                                .min_by(|(_, depth1), (_, depth2)| depth1.total_cmp(depth2))
+                                && penetration_depth > shallowest_depth
+                            {
+                                candidates[shallowest_index] = candidate;
+                            }
+                        }
+                    }
+                }
+                NodeTag::Tree => {
+                    if sp + 2 < STACK_SIZE {
+                        let children = unsafe { self.nodes[node_index].data.tree };
+                        stack[sp] = children.left;
+                        stack[sp + 1] = children.right;
+                        sp += 2;
+                    } else {
+                        panic!("BVH traversal stack overflow");
+                    }
+                }
+            }
+        }
+        dropped_count
+    }
+
+    // Objects whose circle contains the point, as of the last update
+    #[must_use]
+    pub fn query_point(&self, point: Vector2<f32>) -> Vec<usize> {
+        self.query_circle(point, 0.0)
+    }
+
+    // Objects whose circle intersects the given one, as of the last update
+    #[must_use]
+    pub fn query_circle(&self, center: Vector2<f32>, radius: f32) -> Vec<usize> {
+        let aabb = AABB {
+            topleft: center - radius,
+            bottomright: center + radius,
+        };
+        let mut objects = Vec::new();
+        self.for_each_leaf(aabb, |object_index, leaf_aabb| {
+            // Leaves are the bounding squares of the circles
+            let leaf_center = (leaf_aabb.topleft + leaf_aabb.bottomright) * 0.5;
+            let distance = radius + (leaf_aabb.bottomright.x - leaf_aabb.topleft.x) * 0.5;
+            if (leaf_center - center).magnitude_squared() <= distance * distance {
+                objects.push(object_index);
+            }
+        });
+        objects
+    }
+
+    // Objects whose bounding box intersects the AABB, as of the last update
+    #[must_use]
+    pub fn query_aabb(&self, aabb: AABB) -> Vec<usize> {
+        let mut objects = Vec::new();
+        self.for_each_leaf(aabb, |object_index, _| objects.push(object_index));
+        objects
+    }
+
+    // Number of objects in the tree, which may differ from the current number of objects until the next update
+    #[must_use]
+    pub fn object_count(&self) -> usize {
+        self.morton_order.len()
+    }
+
+    fn for_each_leaf(&self, aabb: AABB, mut f: impl FnMut(usize, &AABB)) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        const STACK_SIZE: usize = 64;
+        let mut stack = [0; STACK_SIZE];
+        let mut sp = 0;
+        stack[sp] = self.root();
+        sp += 1;
+
+        while sp > 0 {
+            sp -= 1;
+            let node = &self.nodes[usize::try_from(stack[sp]).unwrap()];
+            if !aabb.intersects(&node.aabb) {
+                continue;
+            }
+
+            match node.tag {
+                NodeTag::Leaf => f(usize::try_from(unsafe { node.data.leaf_object_index }).unwrap(), &node.aabb),
+                NodeTag::Tree => {
+                    if sp + 2 < STACK_SIZE {
+                        let children = unsafe { node.data.tree };
+                        stack[sp] = children.left;
+                        stack[sp + 1] = children.right;
+                        sp += 2;
+                    } else {
+                        panic!("BVH traversal stack overflow");
+                    }
+                }
+            }
+        }
+    }
+
+    // Finds objects that are closer than `margin` to touching the given object
+    pub fn find_neighbors(
+        &self,
+        object1_index: usize,
+        margin: f32,
+        positions: &[Vector2<f32>],
+        radii: &[f32],
+        neighbors: &mut Vec<usize>,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+
+        const STACK_SIZE: usize = 64;
+        let mut stack = [0; STACK_SIZE];
+        let mut sp = 0;
+        stack[sp] = self.root();
+        sp += 1;
+
+        let object1_position = positions[object1_index];
+        let object1_radius = radii[object1_index] + margin;
+        let object1_aabb = AABB {
+            topleft: object1_position - object1_radius,
+            bottomright: object1_position + object1_radius,
+        };
+
+        while sp > 0 {
+            sp -= 1;
+            let node_index = usize::try_from(stack[sp]).unwrap();
+
+            if !object1_aabb.intersects(&self.nodes[node_index].aabb) {
+                continue;
+            }
+
+            match self.nodes[node_index].tag {
+                NodeTag::Leaf => {
+                    let object2_index =
+                        usize::try_from(unsafe { self.nodes[node_index].data.leaf_object_index }).unwrap();
+                    if object2_index == object1_index {
+                        continue;
+                    }
+
+                    let distance_squared = (object1_position - positions[object2_index]).magnitude_squared();
+                    let collision_distance = object1_radius + radii[object2_index];
+                    if distance_squared < collision_distance * collision_distance {
+                        neighbors.push(object2_index);
                     }
                 }
                 NodeTag::Tree => {
@@ -135,6 +386,73 @@ impl Bvh {
     }
 }
 
+impl BroadPhase for Bvh {
+    fn update(&mut self, positions: &[Vector2<f32>], radii: &[f32], bounds: AABB) {
+        Bvh::update(self, positions, radii, bounds);
+    }
+
+    fn query_aabb(&self, aabb: AABB, _positions: &[Vector2<f32>], _radii: &[f32], f: &mut dyn FnMut(usize)) {
+        self.for_each_leaf(aabb, |object_index, _| f(object_index));
+    }
+
+    fn for_each_pair(&self, positions: &[Vector2<f32>], radii: &[f32], f: &mut dyn FnMut(usize, usize)) {
+        let mut neighbors = Vec::new();
+        for object_index in 0..positions.len() {
+            neighbors.clear();
+            self.find_neighbors(object_index, 0.0, positions, radii, &mut neighbors);
+            for &neighbor in &neighbors {
+                if neighbor > object_index {
+                    f(object_index, neighbor);
+                }
+            }
+        }
+    }
+}
+
+// The bounds are divided into 2^32 cells along each axis, so the resolution doesn't depend on the size of the world
+fn morton_cell(position: Vector2<f32>, bounds: AABB) -> [u32; 2] {
+    let quantize = |value: f32, min: f32, max: f32| {
+        let normalized = (f64::from(value) - f64::from(min)) / (f64::from(max) - f64::from(min)).max(f64::MIN_POSITIVE);
+        (normalized.clamp(0.0, 1.0) * f64::from(u32::MAX)) as u32
+    };
+    [
+        quantize(position.x, bounds.topleft.x, bounds.bottomright.x),
+        quantize(position.y, bounds.topleft.y, bounds.bottomright.y),
+    ]
+}
+
+fn morton_code([x, y]: [u32; 2]) -> u64 {
+    fn spread_bits(value: u32) -> u64 {
+        let mut value = u64::from(value);
+        value = (value | (value << 16)) & 0x0000_ffff_0000_ffff;
+        value = (value | (value << 8)) & 0x00ff_00ff_00ff_00ff;
+        value = (value | (value << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        value = (value | (value << 2)) & 0x3333_3333_3333_3333;
+        (value | (value << 1)) & 0x5555_5555_5555_5555
+    }
+    spread_bits(x) | (spread_bits(y) << 1)
+}
+
+// Returns false if it ran out of the `max_shifts` budget, leaving the slice partially sorted
+fn insertion_sort_by_key<T: Copy, K: Ord>(slice: &mut [T], key: impl Fn(&T) -> K, max_shifts: usize) -> bool {
+    let mut shifts = 0;
+    for i in 1..slice.len() {
+        let value = slice[i];
+        let value_key = key(&value);
+        let mut j = i;
+        while j > 0 && key(&slice[j - 1]) > value_key {
+            slice[j] = slice[j - 1];
+            j -= 1;
+        }
+        slice[j] = value;
+        shifts += i - j;
+        if shifts > max_shifts {
+            return false;
+        }
+    }
+    true
+}
+
 #[allow(unused)]
 fn print_node(node: &Node) {
     println!(
@@ -143,21 +461,53 @@ fn print_node(node: &Node) {
     )
 }
 
+// Quality of the tree, to compare the broad phases
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BvhMetrics {
+    // Levels from the root to the deepest leaf, both included
+    pub depth: usize,
+    // Average share of the area of a node where its children overlap
+    pub overlap: f32,
+    // Surface area heuristic with unit costs of a node visit and of an intersection test: the expected number of both
+    // for a random ray crossing the root, in 2D where the chance of crossing a box is proportional to its perimeter
+    pub sah_cost: f32,
+}
+
 #[repr(C)]
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone, Copy, PartialEq)]
 pub struct AABB {
     pub topleft: Vector2<f32>,
     pub bottomright: Vector2<f32>,
 }
 
 impl AABB {
-    fn intersects(&self, other: &AABB) -> bool {
+    pub(crate) fn intersects(&self, other: &AABB) -> bool {
         self.topleft.x <= other.bottomright.x
             && self.bottomright.x >= other.topleft.x
             && self.topleft.y <= other.bottomright.y
             && self.bottomright.y >= other.topleft.y
     }
 
+    fn intersection(&self, other: &AABB) -> Option<AABB> {
+        self.intersects(other).then(|| AABB {
+            topleft: Vector2::new(self.topleft.x.max(other.topleft.x), self.topleft.y.max(other.topleft.y)),
+            bottomright: Vector2::new(
+                self.bottomright.x.min(other.bottomright.x),
+                self.bottomright.y.min(other.bottomright.y),
+            ),
+        })
+    }
+
+    fn area(&self) -> f32 {
+        let size = self.bottomright - self.topleft;
+        size.x * size.y
+    }
+
+    fn perimeter(&self) -> f32 {
+        let size = self.bottomright - self.topleft;
+        2.0 * (size.x + size.y)
+    }
+
     fn union(&self, other: &AABB) -> AABB {
         AABB {
             topleft: Vector2::new(self.topleft.x.min(other.topleft.x), self.topleft.y.min(other.topleft.y)),
@@ -201,3 +551,111 @@ pub struct Tree {
     left: u32,
     right: u32,
 }
+
+// The OpenCL types in bvh.cl are packed; their sizes are also checked against the kernel at startup
+const _: () = assert!(size_of::<AABB>() == 16 && size_of::<Node>() == 28 && size_of::<NodeData>() == 8);
+
+#[test]
+fn incremental_morton_order_matches_full_sort() {
+    let mut positions =
+        (0..1000).map(|i| Vector2::new((i * 37 % 101) as f32, (i * 53 % 89) as f32)).collect::<Vec<_>>();
+    let radii = vec![0.5; positions.len()];
+    let bounds = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(200.0, 100.0),
+    };
+    let mut bvh = Bvh::default();
+    bvh.update(&positions, &radii, bounds);
+    for step in 0..10 {
+        for position in positions.iter_mut().skip(step).step_by(97) {
+            position.x += 1.5;
+        }
+        bvh.update(&positions, &radii, bounds);
+        assert!(bvh.changed_morton_codes() > 0);
+
+        let mut full = Bvh::default();
+        full.update(&positions, &radii, bounds);
+        let codes = |bvh: &Bvh| bvh.morton_order.iter().map(|&i| bvh.morton_codes[i as usize]).collect::<Vec<_>>();
+        assert_eq!(codes(&bvh), codes(&full));
+    }
+}
+
+#[test]
+fn full_candidates_keep_deepest_penetrations() {
+    // Neighbors on a circle around the first object, closer ones penetrating deeper
+    #[allow(clippy::cast_precision_loss)]
+    let positions = std::iter::once(Vector2::new(50.0, 50.0))
+        .chain((1..=20).map(|i| {
+            let angle = i as f32 * 0.3;
+            Vector2::new(50.0, 50.0) + Vector2::new(angle.cos(), angle.sin()) * (0.5 + i as f32 * 0.05)
+        }))
+        .collect::<Vec<_>>();
+    let radii = vec![1.0; positions.len()];
+    let mut bvh = Bvh::default();
+    bvh.update(
+        &positions,
+        &radii,
+        AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(100.0, 100.0),
+        },
+    );
+    let mut candidates = [NormalizedCollisionPair::new(0, 0); 4];
+    assert_eq!(bvh.find_intersections(0, &positions, &radii, &mut candidates), 16);
+    let mut found = candidates.map(|pair| pair.object2_index);
+    found.sort_unstable();
+    assert_eq!(found, [1, 2, 3, 4]);
+}
+
+#[test]
+fn bvh_metrics_of_separate_and_coincident_objects() {
+    let bounds = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(100.0, 100.0),
+    };
+    let mut bvh = Bvh::default();
+    assert!(bvh.metrics().is_none());
+
+    let positions = [10.0, 30.0, 50.0, 70.0].map(|x| Vector2::new(x, 50.0));
+    bvh.update(&positions, &[1.0; 4], bounds);
+    let metrics = bvh.metrics().unwrap();
+    assert_eq!(metrics.depth, 3);
+    assert_eq!(metrics.overlap, 0.0);
+    // The root, two nodes of perimeter 48 and four leaves of perimeter 8, relative to the root perimeter of 128
+    assert!((metrics.sah_cost - 2.0).abs() < 1e-6, "{}", metrics.sah_cost);
+
+    bvh.update(&[Vector2::new(50.0, 50.0); 2], &[1.0; 2], bounds);
+    let metrics = bvh.metrics().unwrap();
+    assert_eq!(metrics.depth, 2);
+    assert_eq!(metrics.overlap, 1.0);
+}
+
+#[test]
+fn spatial_queries_find_overlapping_objects() {
+    let positions = [10.0, 20.0, 30.0, 40.0].map(|x| Vector2::new(x, 50.0));
+    let radii = [1.0, 2.0, 1.0, 4.0];
+    let mut bvh = Bvh::default();
+    bvh.update(
+        &positions,
+        &radii,
+        AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(100.0, 100.0),
+        },
+    );
+    let sorted = |mut objects: Vec<usize>| {
+        objects.sort_unstable();
+        objects
+    };
+    assert_eq!(bvh.query_point(Vector2::new(21.5, 50.0)), [1]);
+    // Inside the bounding square of the circle, but not the circle
+    assert!(bvh.query_point(Vector2::new(21.8, 51.8)).is_empty());
+    assert_eq!(sorted(bvh.query_circle(Vector2::new(25.0, 50.0), 4.0)), [1, 2]);
+    assert_eq!(sorted(bvh.query_circle(Vector2::new(35.0, 50.0), 1.0)), [3]);
+    let aabb = AABB {
+        topleft: Vector2::new(0.0, 40.0),
+        bottomright: Vector2::new(19.0, 49.0),
+    };
+    assert_eq!(sorted(bvh.query_aabb(aabb)), [0, 1]);
+    assert_eq!(bvh.object_count(), 4);
+}
@@ -0,0 +1,98 @@
+use crate::{bvh::AABB, vector2::Vector2};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Vector2<f32>,
+    pub zoom: f32,
+    pub viewport_size: Vector2<f32>,
+}
+
+impl Camera {
+    const MIN_ZOOM: f32 = 0.01;
+    const MAX_ZOOM: f32 = 100.0;
+
+    #[must_use]
+    pub fn new(viewport_size: Vector2<f32>) -> Self {
+        Self {
+            position: Vector2::new(0.0, 0.0),
+            zoom: 1.0,
+            viewport_size,
+        }
+    }
+
+    #[must_use]
+    pub fn world_to_screen(&self, point: Vector2<f32>) -> Vector2<f32> {
+        (point - self.position) * self.zoom
+    }
+
+    #[must_use]
+    pub fn screen_to_world(&self, point: Vector2<f32>) -> Vector2<f32> {
+        point / self.zoom + self.position
+    }
+
+    #[must_use]
+    pub fn visible_region(&self) -> AABB {
+        AABB {
+            topleft: self.position,
+            bottomright: self.screen_to_world(self.viewport_size),
+        }
+    }
+
+    pub fn zoom_at(&mut self, screen_point: Vector2<f32>, factor: f32) {
+        let anchor = self.screen_to_world(screen_point);
+        self.zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.position = anchor - screen_point / self.zoom;
+    }
+
+    pub fn pan(&mut self, screen_delta: Vector2<f32>) {
+        self.position -= screen_delta / self.zoom;
+    }
+
+    // Moves the camera towards the one that fits the region into the viewport, with a margin relative to the region
+    // size on every side. Blend is the fraction of the way to go, 1 jumps right there. The zoom is blended
+    // geometrically, so that zooming in and out look the same.
+    pub fn approach_framing(&mut self, region: AABB, margin: f32, blend: f32) {
+        let size = (region.bottomright - region.topleft) * (1.0 + 2.0 * margin);
+        let target_zoom = (self.viewport_size.x / size.x.max(f32::MIN_POSITIVE))
+            .min(self.viewport_size.y / size.y.max(f32::MIN_POSITIVE))
+            .clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        let target_center = (region.topleft + region.bottomright) / 2.0;
+        let center = self.screen_to_world(self.viewport_size / 2.0);
+        self.zoom *= (target_zoom / self.zoom).powf(blend);
+        self.position = center + (target_center - center) * blend - self.viewport_size / 2.0 / self.zoom;
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Vector2::default())
+    }
+}
+
+#[test]
+fn zoom_keeps_anchor() {
+    let mut camera = Camera::new(Vector2::new(800.0, 600.0));
+    let anchor = Vector2::new(200.0, 100.0);
+    let world_before = camera.screen_to_world(anchor);
+    camera.zoom_at(anchor, 4.0);
+    let world_after = camera.screen_to_world(anchor);
+    assert!((world_before - world_after).magnitude() < 1e-4);
+    assert!((camera.world_to_screen(world_after) - anchor).magnitude() < 1e-3);
+}
+
+#[test]
+fn framing_fits_region() {
+    let mut camera = Camera::new(Vector2::new(800.0, 600.0));
+    let region = AABB {
+        topleft: Vector2::new(-1000.0, 200.0),
+        bottomright: Vector2::new(3000.0, 400.0),
+    };
+    camera.approach_framing(region, 0.0, 0.5);
+    // Halfway there, in terms of the zoom factor
+    assert!((camera.zoom - 0.2_f32.sqrt()).abs() < 1e-4);
+
+    camera.approach_framing(region, 0.0, 1.0);
+    assert!((camera.zoom - 0.2).abs() < 1e-4);
+    assert!((camera.world_to_screen(Vector2::new(1000.0, 300.0)) - Vector2::new(400.0, 300.0)).magnitude() < 1e-3);
+    assert!((camera.world_to_screen(region.topleft).x).abs() < 1e-3);
+}
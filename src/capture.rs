@@ -0,0 +1,270 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use peniko::Color;
+
+use crate::{
+    bvh::AABB,
+    container::{self, ContainerReader, ContainerWriter, Field, Header, Layout},
+    gamma::ColorMath,
+    object::ObjectSoa,
+    vector2::Vector2,
+};
+
+const MAGIC: &[u8; 4] = b"CCAP";
+// Version 1 wasn't a container
+const VERSION: u32 = 2;
+
+// Streams the objects of every `stride`-th step to a container, for rendering offline at any resolution. A frame is
+// the step count, the time and the constraints; a record is an object: position, velocity, radius and an RGBA8
+// color, transparent if the object has none.
+pub struct CaptureWriter<W: Write> {
+    container: ContainerWriter<W>,
+    stride: usize,
+    records: Vec<u8>,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    pub fn create(path: &Path, stride: usize) -> anyhow::Result<Self> {
+        let file = File::create(path).with_context(|| format!("create \"{}\"", path.display()))?;
+        Self::new(BufWriter::new(file), stride)
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    pub fn new(writer: W, stride: usize) -> anyhow::Result<Self> {
+        let header = Header {
+            frame: Layout::new(vec![
+                Field::new("step_count", 8),
+                Field::new("time", 4),
+                Field::new("constraints", 16),
+            ]),
+            record: Layout::new(vec![
+                Field::new("position", 8),
+                Field::new("velocity", 8),
+                Field::new("radius", 4),
+                Field::new("color", 4),
+            ]),
+        };
+        Ok(Self {
+            container: ContainerWriter::new(writer, MAGIC, VERSION, header)?,
+            stride: stride.max(1),
+            records: Vec::new(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        step_count: usize,
+        time: f32,
+        objects: &ObjectSoa,
+        constraints: AABB,
+    ) -> anyhow::Result<()> {
+        if !step_count.is_multiple_of(self.stride) {
+            return Ok(());
+        }
+        let mut frame = Vec::with_capacity(28);
+        frame.extend_from_slice(&u64::try_from(step_count)?.to_le_bytes());
+        frame.extend_from_slice(&time.to_le_bytes());
+        let (topleft, bottomright) = (constraints.topleft, constraints.bottomright);
+        for value in [topleft.x, topleft.y, bottomright.x, bottomright.y] {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+        self.records.clear();
+        for object_index in 0..objects.len() {
+            let position = objects.positions[object_index];
+            let velocity = objects.velocities[object_index];
+            for value in [
+                position.x,
+                position.y,
+                velocity.x,
+                velocity.y,
+                objects.radii[object_index],
+            ] {
+                self.records.extend_from_slice(&value.to_le_bytes());
+            }
+            let color = objects.colors[object_index].map_or([0; 4], |color| color.to_rgba8().to_u8_array());
+            self.records.extend_from_slice(&color);
+        }
+        self.container.write_frame(&frame, &self.records)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.container.into_inner()
+    }
+}
+
+pub struct CapturedFrame {
+    pub step_count: usize,
+    pub time: f32,
+    pub constraints: AABB,
+    pub positions: Vec<Vector2<f32>>,
+    pub velocities: Vec<Vector2<f32>>,
+    pub radii: Vec<f32>,
+    pub colors: Vec<Option<Color>>,
+}
+
+pub struct CaptureReader<R: Read> {
+    container: ContainerReader<R>,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("open \"{}\"", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("read capture \"{}\"", path.display()))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let version = container::read_version(&mut reader, MAGIC, "frame capture")?;
+        if version != VERSION {
+            bail!("unsupported frame capture version {version}");
+        }
+        Ok(Self {
+            container: ContainerReader::new(reader)?,
+        })
+    }
+
+    // None at the end of the capture
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<CapturedFrame>> {
+        let Some(frame) = self.container.next_frame()? else {
+            return Ok(None);
+        };
+        let values = self.container.frame_values(&frame);
+        let step_count = usize::try_from(u64::from_le_bytes(values.require("step_count")?))?;
+        let read_frame = || -> anyhow::Result<CapturedFrame> {
+            let constraints = container::f32s::<4>(&values.require::<16>("constraints")?);
+            let mut captured_frame = CapturedFrame {
+                step_count,
+                time: f32::from_le_bytes(values.require("time")?),
+                constraints: AABB {
+                    topleft: Vector2::new(constraints[0], constraints[1]),
+                    bottomright: Vector2::new(constraints[2], constraints[3]),
+                },
+                positions: Vec::with_capacity(frame.record_count),
+                velocities: Vec::with_capacity(frame.record_count),
+                radii: Vec::with_capacity(frame.record_count),
+                colors: Vec::with_capacity(frame.record_count),
+            };
+            for record in self.container.records(&frame) {
+                let [x, y] = container::f32s(&record.require::<8>("position")?);
+                captured_frame.positions.push(Vector2::new(x, y));
+                let [x, y] = container::f32s(&record.require::<8>("velocity")?);
+                captured_frame.velocities.push(Vector2::new(x, y));
+                captured_frame.radii.push(f32::from_le_bytes(record.require("radius")?));
+                let [r, g, b, a] = record.get("color")?.unwrap_or([0; 4]);
+                captured_frame.colors.push((a != 0).then(|| Color::from_rgba8(r, g, b, a)));
+            }
+            Ok(captured_frame)
+        };
+        read_frame().with_context(|| format!("read frame of step {step_count}")).map(Some)
+    }
+}
+
+// Draws the objects of the frame in the given colors, one per object, as sRGB-encoded RGBA8 pixels over black, blended
+// in the space of `color_math`. The constraints are fitted into the image.
+#[must_use]
+pub fn render_frame(
+    frame: &CapturedFrame,
+    width: usize,
+    height: usize,
+    colors: &[Color],
+    color_math: ColorMath,
+) -> Vec<u8> {
+    // Objects are never drawn smaller than this, in pixels
+    const MIN_RADIUS: f32 = 0.5;
+
+    let mut pixels = vec![0; width * height * 4];
+    for pixel in pixels.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    let size = frame.constraints.bottomright - frame.constraints.topleft;
+    #[allow(clippy::cast_precision_loss)]
+    let (width_f32, height_f32) = (width as f32, height as f32);
+    let scale = (width_f32 / size.x).min(height_f32 / size.y);
+    // Centered along the axis with room to spare
+    let offset = (Vector2::new(width_f32, height_f32) - size * scale) / 2.0;
+    for (object_index, &position) in frame.positions.iter().enumerate() {
+        let center = (position - frame.constraints.topleft) * scale + offset;
+        let radius = (frame.radii[object_index] * scale).max(MIN_RADIUS);
+        let [r, g, b, a] = colors[object_index].components;
+        let color = [r, g, b].map(|component| color_math.decode(component));
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let pixel_range = |center: f32, limit: usize| {
+            let min = (center - radius - 1.0).floor().max(0.0) as usize;
+            let max = ((center + radius + 1.0).ceil().max(0.0) as usize).min(limit);
+            min..max
+        };
+        for y in pixel_range(center.y, height) {
+            for x in pixel_range(center.x, width) {
+                #[allow(clippy::cast_precision_loss)]
+                let distance = (Vector2::new(x as f32 + 0.5, y as f32 + 0.5) - center).magnitude();
+                // Antialiased over a pixel at the edge
+                let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0) * a;
+                if coverage == 0.0 {
+                    continue;
+                }
+                let pixel = &mut pixels[(y * width + x) * 4..][..3];
+                for (channel, component) in pixel.iter_mut().zip(color) {
+                    let background = color_math.decode(f32::from(*channel) / 255.0);
+                    let blended = color_math.encode(background * (1.0 - coverage) + component * coverage);
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    let blended = (blended * 255.0).round() as u8;
+                    *channel = blended;
+                }
+            }
+        }
+    }
+    pixels
+}
+
+#[test]
+fn capture_roundtrip_and_render() {
+    use crate::object::ObjectPrototype;
+
+    let constraints = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(20.0, 10.0),
+    };
+    let mut objects = ObjectSoa::default();
+    objects.add(ObjectPrototype {
+        radius: 2.0,
+        color: Some(Color::from_rgba8(255, 0, 0, 255)),
+        ..ObjectPrototype::new(Vector2::new(5.0, 5.0))
+    });
+    objects.add(ObjectPrototype {
+        velocity: Vector2::new(1.0, -2.0),
+        ..ObjectPrototype::new(Vector2::new(15.0, 5.0))
+    });
+
+    let mut writer = CaptureWriter::new(Vec::new(), 2).unwrap();
+    for step_count in 1..=4 {
+        #[allow(clippy::cast_precision_loss)]
+        writer.record(step_count, step_count as f32 * 0.5, &objects, constraints).unwrap();
+    }
+    let buffer = writer.into_inner();
+    let mut reader = CaptureReader::new(buffer.as_slice()).unwrap();
+    let frame = reader.next_frame().unwrap().unwrap();
+    assert_eq!((frame.step_count, frame.time), (2, 1.0));
+    assert!(frame.positions == objects.positions && frame.velocities == objects.velocities);
+    assert_eq!(frame.radii, objects.radii);
+    assert_eq!(frame.colors, objects.colors);
+    assert_eq!(reader.next_frame().unwrap().unwrap().step_count, 4);
+    assert!(reader.next_frame().unwrap().is_none());
+    let mut truncated = CaptureReader::new(&buffer[..buffer.len() - 1]).unwrap();
+    truncated.next_frame().unwrap();
+    assert!(truncated.next_frame().is_err());
+
+    // Fitted into 40x20 pixels, 2 pixels per unit
+    let colors = frame.colors.iter().map(|color| color.unwrap_or(Color::WHITE)).collect::<Vec<_>>();
+    let pixels = render_frame(&frame, 40, 20, &colors, ColorMath::Linear);
+    let pixel = |x: usize, y: usize| &pixels[(y * 40 + x) * 4..][..4];
+    assert_eq!(pixel(10, 10), [255, 0, 0, 255]);
+    assert_eq!(pixel(0, 0), [0, 0, 0, 255]);
+    assert_eq!(pixel(30, 10)[..3], [255, 255, 255]);
+}
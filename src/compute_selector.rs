@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use crate::physics::GpuComputeOptions;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ComputeTimings {
+    pub integration_cpu: Duration,
+    pub integration_gpu: Duration,
+    pub bvh_cpu: Duration,
+    pub bvh_gpu: Duration,
+}
+
+// Picks CPU or GPU for each phase based on periodic calibration measurements. The other side has to be faster by
+// a margin for several calibrations in a row before the choice flips, so noisy timings don't cause flapping.
+pub struct GpuComputeSelector {
+    integration: PhaseSelection,
+    bvh: PhaseSelection,
+}
+
+impl GpuComputeSelector {
+    #[must_use]
+    pub fn new(initial: GpuComputeOptions) -> Self {
+        Self {
+            integration: PhaseSelection::new(initial.integration),
+            bvh: PhaseSelection::new(initial.bvh),
+        }
+    }
+
+    #[must_use]
+    pub fn options(&self) -> GpuComputeOptions {
+        GpuComputeOptions {
+            integration: self.integration.use_gpu,
+            bvh: self.bvh.use_gpu,
+        }
+    }
+
+    pub fn update(&mut self, timings: ComputeTimings) -> GpuComputeOptions {
+        self.integration.update(timings.integration_cpu, timings.integration_gpu);
+        self.bvh.update(timings.bvh_cpu, timings.bvh_gpu);
+        self.options()
+    }
+}
+
+struct PhaseSelection {
+    use_gpu: bool,
+    cpu_average: Option<f32>,
+    gpu_average: Option<f32>,
+    streak: u32,
+}
+
+impl PhaseSelection {
+    const SMOOTHING: f32 = 0.3;
+    const HYSTERESIS: f32 = 0.1;
+    const STREAK_TO_SWITCH: u32 = 3;
+
+    fn new(use_gpu: bool) -> Self {
+        Self {
+            use_gpu,
+            cpu_average: None,
+            gpu_average: None,
+            streak: 0,
+        }
+    }
+
+    fn update(&mut self, cpu: Duration, gpu: Duration) {
+        let smooth = |average: Option<f32>, sample: Duration| {
+            let sample = sample.as_secs_f32();
+            Some(average.map_or(sample, |average| average + (sample - average) * Self::SMOOTHING))
+        };
+        self.cpu_average = smooth(self.cpu_average, cpu);
+        self.gpu_average = smooth(self.gpu_average, gpu);
+
+        let (Some(cpu), Some(gpu)) = (self.cpu_average, self.gpu_average) else {
+            return;
+        };
+        let (current, other) = if self.use_gpu { (gpu, cpu) } else { (cpu, gpu) };
+        if other < current * (1.0 - Self::HYSTERESIS) {
+            self.streak += 1;
+            if self.streak >= Self::STREAK_TO_SWITCH {
+                self.use_gpu = !self.use_gpu;
+                self.streak = 0;
+            }
+        } else {
+            self.streak = 0;
+        }
+    }
+}
+
+#[test]
+fn switches_only_when_persistently_faster() {
+    let millis = Duration::from_millis;
+    let mut selector = GpuComputeSelector::new(GpuComputeOptions::default());
+    let gpu_faster = ComputeTimings {
+        integration_cpu: millis(10),
+        integration_gpu: millis(2),
+        bvh_cpu: millis(5),
+        bvh_gpu: millis(5),
+    };
+    assert!(!selector.update(gpu_faster).integration);
+    assert!(!selector.update(gpu_faster).integration);
+    let options = selector.update(gpu_faster);
+    assert!(options.integration);
+    assert!(!options.bvh);
+}
@@ -0,0 +1,337 @@
+use std::io::{Read, Write};
+
+use anyhow::{Context, bail, ensure};
+
+// Binary container of the snapshots and the frame captures, in little endian: a magic and a version of the file kind,
+// then blocks of their length, their contents and a CRC-32 of the contents, so that corruption is detected on load.
+// The first block is the header, which lists the named fields of the frames and of their records with their sizes.
+// Every other block is a frame: the frame fields, the record count and the records. Readers look the fields up by
+// name, so fields can be added, removed or reordered in later versions without breaking older files.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub size: usize,
+}
+
+impl Field {
+    #[must_use]
+    pub fn new(name: &str, size: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            size,
+        }
+    }
+}
+
+// Fields laid out one after another
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    fields: Vec<Field>,
+    offsets: Vec<usize>,
+    size: usize,
+}
+
+impl Layout {
+    #[must_use]
+    pub fn new(fields: Vec<Field>) -> Self {
+        let offsets = fields
+            .iter()
+            .scan(0, |offset, field| {
+                let field_offset = *offset;
+                *offset += field.size;
+                Some(field_offset)
+            })
+            .collect();
+        let size = fields.iter().map(|field| field.size).sum();
+        Self { fields, offsets, size }
+    }
+
+    #[must_use]
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    #[must_use]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[must_use]
+    pub fn values<'a>(&'a self, bytes: &'a [u8]) -> Values<'a> {
+        Values { layout: self, bytes }
+    }
+}
+
+// The fields of a frame or of a record
+pub struct Values<'a> {
+    layout: &'a Layout,
+    bytes: &'a [u8],
+}
+
+impl Values<'_> {
+    // None if the file has no such field
+    pub fn get<const N: usize>(&self, name: &str) -> anyhow::Result<Option<[u8; N]>> {
+        let Some(field_index) = self.layout.fields.iter().position(|field| field.name == name) else {
+            return Ok(None);
+        };
+        let size = self.layout.fields[field_index].size;
+        ensure!(size == N, "field \"{name}\" has {size} bytes instead of {N}");
+        let offset = self.layout.offsets[field_index];
+        Ok(Some(self.bytes[offset..offset + N].try_into()?))
+    }
+
+    pub fn require<const N: usize>(&self, name: &str) -> anyhow::Result<[u8; N]> {
+        self.get(name)?.with_context(|| format!("missing field \"{name}\""))
+    }
+}
+
+pub struct Header {
+    pub frame: Layout,
+    pub record: Layout,
+}
+
+pub struct ContainerWriter<W: Write> {
+    writer: W,
+    header: Header,
+    block: Vec<u8>,
+}
+
+impl<W: Write> ContainerWriter<W> {
+    pub fn new(mut writer: W, magic: &[u8; 4], version: u32, header: Header) -> anyhow::Result<Self> {
+        writer.write_all(magic)?;
+        writer.write_all(&version.to_le_bytes())?;
+        let mut block = Vec::new();
+        for layout in [&header.frame, &header.record] {
+            block.extend_from_slice(&u32::try_from(layout.fields.len())?.to_le_bytes());
+            for field in &layout.fields {
+                block.push(u8::try_from(field.name.len()).context("field name is too long")?);
+                block.extend_from_slice(field.name.as_bytes());
+                block.extend_from_slice(&u32::try_from(field.size)?.to_le_bytes());
+            }
+        }
+        write_block(&mut writer, &block)?;
+        Ok(Self { writer, header, block })
+    }
+
+    // The frame fields and the records, laid out as in the header
+    pub fn write_frame(&mut self, frame: &[u8], records: &[u8]) -> anyhow::Result<()> {
+        let record_size = self.header.record.size;
+        ensure!(
+            frame.len() == self.header.frame.size,
+            "frame has {} bytes instead of {}",
+            frame.len(),
+            self.header.frame.size
+        );
+        ensure!(
+            records.len().is_multiple_of(record_size.max(1)),
+            "records have {} bytes, not a multiple of {record_size}",
+            records.len()
+        );
+        let record_count = records.len().checked_div(record_size).unwrap_or(0);
+        self.block.clear();
+        self.block.extend_from_slice(frame);
+        self.block.extend_from_slice(&u64::try_from(record_count)?.to_le_bytes());
+        self.block.extend_from_slice(records);
+        write_block(&mut self.writer, &self.block)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+pub struct Frame {
+    pub record_count: usize,
+    bytes: Vec<u8>,
+}
+
+pub struct ContainerReader<R: Read> {
+    reader: R,
+    header: Header,
+}
+
+impl<R: Read> ContainerReader<R> {
+    // Follows `read_version`
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let block = read_block(&mut reader)?.context("missing header")?;
+        let mut bytes = block.as_slice();
+        let mut read_layout = || -> anyhow::Result<Layout> {
+            let field_count = u32::from_le_bytes(take(&mut bytes)?);
+            let fields = (0..field_count)
+                .map(|_| {
+                    let [name_len] = take(&mut bytes)?;
+                    let name = String::from_utf8(take_slice(&mut bytes, usize::from(name_len))?.to_vec())?;
+                    let size = usize::try_from(u32::from_le_bytes(take(&mut bytes)?))?;
+                    Ok(Field { name, size })
+                })
+                .collect::<anyhow::Result<_>>()?;
+            Ok(Layout::new(fields))
+        };
+        let header = Header {
+            frame: read_layout().context("read frame fields")?,
+            record: read_layout().context("read record fields")?,
+        };
+        Ok(Self { reader, header })
+    }
+
+    #[must_use]
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    // None at the end of the container
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let Some(bytes) = read_block(&mut self.reader)? else {
+            return Ok(None);
+        };
+        let frame_size = self.header.frame.size;
+        let mut record_count = bytes.get(frame_size..frame_size + 8).context("frame is too short")?;
+        let record_count = usize::try_from(u64::from_le_bytes(take(&mut record_count)?))?;
+        let records_len = record_count.checked_mul(self.header.record.size).context("too many records")?;
+        ensure!(bytes.len() == frame_size + 8 + records_len, "frame length doesn't match its {record_count} records");
+        Ok(Some(Frame { record_count, bytes }))
+    }
+
+    #[must_use]
+    pub fn frame_values<'a>(&'a self, frame: &'a Frame) -> Values<'a> {
+        self.header.frame.values(&frame.bytes[..self.header.frame.size])
+    }
+
+    pub fn records<'a>(&'a self, frame: &'a Frame) -> impl Iterator<Item = Values<'a>> {
+        let records = &frame.bytes[self.header.frame.size + 8..];
+        let record_size = self.header.record.size;
+        (0..frame.record_count)
+            .map(move |record_index| self.header.record.values(&records[record_index * record_size..][..record_size]))
+    }
+}
+
+// The version of the file kind, to pick the reader
+pub fn read_version(reader: &mut impl Read, magic: &[u8; 4], kind: &str) -> anyhow::Result<u32> {
+    let mut file_magic = [0; 4];
+    reader.read_exact(&mut file_magic).context("read magic")?;
+    if &file_magic != magic {
+        bail!("not a {kind}");
+    }
+    Ok(u32::from_le_bytes(take_from(reader)?))
+}
+
+// Little-endian f32s packed into the bytes of a field
+#[must_use]
+pub fn f32s<const N: usize>(bytes: &[u8]) -> [f32; N] {
+    std::array::from_fn(|index| f32::from_le_bytes(bytes[index * 4..][..4].try_into().unwrap()))
+}
+
+fn write_block(writer: &mut impl Write, block: &[u8]) -> anyhow::Result<()> {
+    writer.write_all(&u64::try_from(block.len())?.to_le_bytes())?;
+    writer.write_all(block)?;
+    writer.write_all(&crc32(block).to_le_bytes())?;
+    Ok(())
+}
+
+// None at the end of the input; a block cut short is an error
+fn read_block(reader: &mut impl Read) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut len = [0; 8];
+    if reader.read(&mut len[..1])? == 0 {
+        return Ok(None);
+    }
+    reader.read_exact(&mut len[1..]).context("read block length")?;
+    let len = u64::from_le_bytes(len);
+    // The block grows with the bytes actually read, so a corrupt length doesn't allocate more than the input holds
+    let mut block = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut block).context("read block")?;
+    ensure!(block.len() as u64 == len, "block length {len} is corrupt or the block is cut short");
+    let crc = u32::from_le_bytes(take_from(reader).context("read block checksum")?);
+    ensure!(crc == crc32(&block), "block checksum mismatch, the file is corrupt");
+    Ok(Some(block))
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    Ok(take_slice(bytes, N)?.try_into()?)
+}
+
+fn take_slice<'a>(bytes: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    ensure!(bytes.len() >= len, "unexpected end of block");
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn take_from<const N: usize>(reader: &mut impl Read) -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// CRC-32 (IEEE 802.3), as in zlib and PNG
+#[must_use]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut index = 0;
+        while index < 256 {
+            #[allow(clippy::cast_possible_truncation)]
+            let mut crc = index as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 0 {
+                    crc >> 1
+                } else {
+                    (crc >> 1) ^ 0xEDB8_8320
+                };
+                bit += 1;
+            }
+            table[index] = crc;
+            index += 1;
+        }
+        table
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let crc = bytes.iter().fold(!0, |crc, &byte| TABLE[usize::from(crc as u8 ^ byte)] ^ (crc >> 8));
+    !crc
+}
+
+#[test]
+fn container_roundtrip_and_corruption() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+
+    let header = Header {
+        frame: Layout::new(vec![Field::new("time", 4)]),
+        record: Layout::new(vec![Field::new("id", 4), Field::new("flag", 1)]),
+    };
+    let mut writer = ContainerWriter::new(Vec::new(), b"TEST", 7, header).unwrap();
+    writer.write_frame(&1.5_f32.to_le_bytes(), &[1, 0, 0, 0, 1, 2, 0, 0, 0, 0]).unwrap();
+    assert!(writer.write_frame(&[0; 4], &[0; 3]).is_err());
+    let bytes = writer.into_inner();
+
+    let mut reader = bytes.as_slice();
+    assert_eq!(read_version(&mut reader, b"TEST", "test").unwrap(), 7);
+    let mut container = ContainerReader::new(reader).unwrap();
+    let frame = container.next_frame().unwrap().unwrap();
+    assert_eq!(container.frame_values(&frame).require("time").unwrap(), 1.5_f32.to_le_bytes());
+    let records = container.records(&frame).collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].require::<4>("id").unwrap(), [2, 0, 0, 0]);
+    assert_eq!(records[0].get::<1>("flag").unwrap(), Some([1]));
+    assert_eq!(records[0].get::<4>("color").unwrap(), None);
+    assert!(records[0].get::<2>("id").is_err());
+    drop(records);
+    assert!(container.next_frame().unwrap().is_none());
+
+    assert!(read_version(&mut bytes.as_slice(), b"ELSE", "test").is_err());
+    let mut corrupt = bytes.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    let mut reader = corrupt.as_slice();
+    read_version(&mut reader, b"TEST", "test").unwrap();
+    assert!(ContainerReader::new(reader).unwrap().next_frame().is_err());
+    let mut reader = &bytes[..bytes.len() - 1];
+    read_version(&mut reader, b"TEST", "test").unwrap();
+    assert!(ContainerReader::new(reader).unwrap().next_frame().is_err());
+
+    // A flipped high bit of a block length
+    let mut block = Vec::new();
+    write_block(&mut block, &[1, 2, 3]).unwrap();
+    block[7] ^= 0x40;
+    assert!(read_block(&mut block.as_slice()).is_err());
+}
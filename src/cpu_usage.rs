@@ -0,0 +1,128 @@
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::bail;
+
+// Share of the wall time spent on a CPU, written by the sampling thread and read by any other. Above 1 for a
+// process whose threads run in parallel.
+pub struct CpuUtilization {
+    // f32 bits, NaN until measured
+    bits: AtomicU32,
+}
+
+impl CpuUtilization {
+    #[must_use]
+    pub fn get(&self) -> Option<f32> {
+        Some(f32::from_bits(self.bits.load(Ordering::Relaxed))).filter(|utilization| !utilization.is_nan())
+    }
+
+    fn set(&self, utilization: f32) {
+        self.bits.store(utilization.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Default for CpuUtilization {
+    fn default() -> Self {
+        Self {
+            bits: AtomicU32::new(f32::NAN.to_bits()),
+        }
+    }
+}
+
+// Measures the CPU time of the calling thread or of the whole process over periods of wall time
+pub struct CpuMeter {
+    cpu_time: fn() -> anyhow::Result<Duration>,
+    period: Duration,
+    // Wall and CPU time at the start of the period
+    period_start: Option<(Instant, Duration)>,
+}
+
+impl CpuMeter {
+    pub const DEFAULT_PERIOD: Duration = Duration::from_millis(500);
+
+    // Must be sampled by the measured thread
+    #[must_use]
+    pub fn thread(period: Duration) -> Self {
+        Self::new(thread_cpu_time, period)
+    }
+
+    #[must_use]
+    pub fn process(period: Duration) -> Self {
+        Self::new(process_cpu_time, period)
+    }
+
+    fn new(cpu_time: fn() -> anyhow::Result<Duration>, period: Duration) -> Self {
+        Self {
+            cpu_time,
+            period,
+            period_start: None,
+        }
+    }
+
+    // Updates the utilization once a period has passed since the last update; cheap enough to call every iteration
+    pub fn sample(&mut self, utilization: &CpuUtilization) {
+        let now = Instant::now();
+        if let Some((wall_start, _)) = self.period_start
+            && now - wall_start < self.period
+        {
+            return;
+        }
+        // Unsupported platforms are never measured
+        let Ok(cpu_time) = (self.cpu_time)() else {
+            return;
+        };
+        if let Some((wall_start, cpu_start)) = self.period_start {
+            utilization.set((cpu_time.saturating_sub(cpu_start)).as_secs_f32() / (now - wall_start).as_secs_f32());
+        }
+        self.period_start = Some((now, cpu_time));
+    }
+}
+
+#[cfg(unix)]
+fn thread_cpu_time() -> anyhow::Result<Duration> {
+    clock_time(libc::CLOCK_THREAD_CPUTIME_ID)
+}
+
+#[cfg(unix)]
+fn process_cpu_time() -> anyhow::Result<Duration> {
+    clock_time(libc::CLOCK_PROCESS_CPUTIME_ID)
+}
+
+#[cfg(unix)]
+fn clock_time(clock: libc::clockid_t) -> anyhow::Result<Duration> {
+    let mut time: libc::timespec = unsafe { std::mem::zeroed() };
+    if unsafe { libc::clock_gettime(clock, &raw mut time) } != 0 {
+        bail!("clock_gettime failed: {}", std::io::Error::last_os_error());
+    }
+    #[allow(clippy::cast_sign_loss)]
+    Ok(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> anyhow::Result<Duration> {
+    bail!("thread CPU time is only supported on Unix")
+}
+
+#[cfg(not(unix))]
+fn process_cpu_time() -> anyhow::Result<Duration> {
+    bail!("process CPU time is only supported on Unix")
+}
+
+#[cfg(unix)]
+#[test]
+fn cpu_meter_measures_busy_thread() {
+    let period = Duration::from_millis(20);
+    let utilization = CpuUtilization::default();
+    let mut meter = CpuMeter::thread(period);
+    meter.sample(&utilization);
+    assert_eq!(utilization.get(), None);
+
+    let start = Instant::now();
+    while start.elapsed() < period * 2 {
+        meter.sample(&utilization);
+    }
+    let utilization = utilization.get().unwrap();
+    assert!(utilization > 0.1 && utilization < 1.5, "{utilization}");
+}
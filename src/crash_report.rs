@@ -0,0 +1,52 @@
+use std::{
+    fs,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use crate::physics::{PhysicsEngine, Stats};
+
+static LAST_STATS: Mutex<Option<Stats>> = Mutex::new(None);
+
+// Keeps the default panic output and additionally writes the latest recorded stats to
+// `<directory>/crash-<timestamp>.txt`
+pub fn install_panic_hook(directory: PathBuf) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        match write_report(&directory, info) {
+            Ok(path) => eprintln!("Crash report written to \"{}\"", path.display()),
+            Err(e) => eprintln!("Failed to write crash report: {e:#}"),
+        }
+    }));
+}
+
+pub fn record_stats(stats: &Stats) {
+    // Poisoning is ignored: the stats are only a plain value, and the panic hook must not panic itself
+    let mut last_stats = LAST_STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    *last_stats = Some(stats.clone());
+}
+
+pub fn save_snapshot(directory: &Path, physics: &PhysicsEngine) -> anyhow::Result<PathBuf> {
+    let path = crash_file_path(directory, "snapshot")?;
+    physics.save_state(&path)?;
+    Ok(path)
+}
+
+fn write_report(directory: &Path, info: &PanicHookInfo) -> anyhow::Result<PathBuf> {
+    let path = crash_file_path(directory, "txt")?;
+    let last_stats = LAST_STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    let report = format!("{info}\n\nlast stats: {:#?}\n", *last_stats);
+    fs::write(&path, report).with_context(|| format!("write \"{}\"", path.display()))?;
+    Ok(path)
+}
+
+fn crash_file_path(directory: &Path, extension: &str) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(directory).with_context(|| format!("create directory \"{}\"", directory.display()))?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    Ok(directory.join(format!("crash-{timestamp:020}.{extension}")))
+}
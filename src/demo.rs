@@ -1,8 +1,11 @@
 #![allow(unused)]
 
+use std::{fs, path::Path};
+
+use anyhow::Context;
 use num_traits::Signed;
 use rand::random;
-use serde_derive::Deserialize;
+use serde_derive::{Deserialize, Serialize};
 use vello::peniko::{
     Color,
     color::{ColorSpace, Hsl, Srgb, palette::css},
@@ -12,27 +15,29 @@ use crate::{
     app_config::CONFIG,
     object::{ObjectPrototype, ObjectSoa},
     physics::PhysicsEngine,
+    placement::relax_overlaps,
     vector2::Vector2,
 };
 
 pub fn create_demo(objects: &mut ObjectSoa) {
+    let units = &CONFIG.units;
     if CONFIG.demo.enable_planets {
         objects.add(ObjectPrototype {
-            velocity: Vector2::new(-700.0, 0.0),
-            radius: CONFIG.demo.object_radius,
-            mass: 10000.0,
+            velocity: units.velocity(Vector2::new(-700.0, 0.0)),
+            radius: units.length(CONFIG.demo.object_radius),
+            mass: units.mass(10000.0),
             // color: Some(css::MAGENTA),
             is_planet: true,
-            ..ObjectPrototype::new(Vector2::new(700.0, 500.0))
+            ..ObjectPrototype::new(units.position(Vector2::new(700.0, 500.0)))
         });
 
         objects.add(ObjectPrototype {
-            velocity: Vector2::new(700.0, 0.0),
-            radius: CONFIG.demo.object_radius,
-            mass: 10000.0,
+            velocity: units.velocity(Vector2::new(700.0, 0.0)),
+            radius: units.length(CONFIG.demo.object_radius),
+            mass: units.mass(10000.0),
             // color: Some(css::YELLOW),
             is_planet: true,
-            ..ObjectPrototype::new(Vector2::new(700.0, 600.0))
+            ..ObjectPrototype::new(units.position(Vector2::new(700.0, 600.0)))
         });
     }
 
@@ -43,11 +48,71 @@ pub fn create_demo(objects: &mut ObjectSoa) {
     for ball in &CONFIG.demo.balls {
         generate_ball(objects, ball);
     }
+
+    for particle in &CONFIG.demo.particles {
+        generate_particle(objects, particle);
+    }
+
+    if let Some(path) = &CONFIG.demo.scene {
+        SceneFile::load(Path::new(path)).unwrap().generate(objects);
+    }
+
+    if CONFIG.demo.relax_overlaps {
+        let overlap_count = relax_overlaps(objects, CONFIG.constraints(), CONFIG.demo.relaxation_iterations);
+        if overlap_count > 0 {
+            eprintln!("{overlap_count} pairs of objects still overlap after relaxation");
+        }
+    }
+}
+
+// Bricks, balls and single particles, in the same form as in the `[demo]` section of the config
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SceneFile {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bricks: Vec<Brick>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub balls: Vec<Ball>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub particles: Vec<Particle>,
+}
+
+impl SceneFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("read scene \"{}\"", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("parse scene \"{}\"", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let text = toml::to_string(self).context("serialize scene")?;
+        fs::write(path, text).with_context(|| format!("write scene \"{}\"", path.display()))
+    }
+
+    pub fn generate(&self, objects: &mut ObjectSoa) {
+        for brick in &self.bricks {
+            generate_brick(objects, brick);
+        }
+        for ball in &self.balls {
+            generate_ball(objects, ball);
+        }
+        for particle in &self.particles {
+            generate_particle(objects, particle);
+        }
+    }
 }
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Brick {
+    // Group of the brick, see `[groups]` in the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Takes precedence over the group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    // Particles of the brick don't collide with each other if false
+    #[serde(default = "default_self_collide", skip_serializing_if = "is_true")]
+    pub self_collide: bool,
     pub position: Vector2<f32>,
     pub size: Vector2<f32>,
     #[serde(default)]
@@ -58,10 +123,29 @@ pub struct Brick {
     pub particle_spacing: f32,
     #[serde(default)]
     pub particle_mass: f32,
+    // Opacity of the particle colors, which accumulate with additive blending
+    #[serde(default = "default_alpha", skip_serializing_if = "is_opaque")]
+    pub alpha: f32,
 }
 
 pub fn generate_brick(objects: &mut ObjectSoa, brick: &Brick) -> Vec<usize> {
+    let units = &CONFIG.units;
+    let brick = &Brick {
+        position: units.position(brick.position),
+        size: units.position(brick.size),
+        velocity: units.velocity(brick.velocity),
+        particle_radius: units.length(brick.particle_radius),
+        particle_spacing: units.length(brick.particle_spacing),
+        particle_mass: units.mass(brick.particle_mass),
+        ..brick.clone()
+    };
     let cell_size = brick.particle_radius * 2.0 + brick.particle_spacing;
+    let material = CONFIG.material_index(brick.material.as_deref().or(brick.name.as_deref()));
+    let collision_group = if brick.self_collide {
+        0
+    } else {
+        objects.new_collision_group()
+    };
     let dims = Vector2::new((brick.size.x / cell_size) as usize, (brick.size.y / cell_size) as usize);
     let mut result = Vec::new();
     for i in 0..dims.x {
@@ -83,7 +167,7 @@ pub fn generate_brick(objects: &mut ObjectSoa, brick: &Brick) -> Vec<usize> {
                 let hue = 300.0 * selection_factor;
                 let hsl = [hue as f32, 100.0, 50.0];
                 let rgb = Hsl::convert::<Srgb>(hsl);
-                Some(Color::new([rgb[0], rgb[1], rgb[2], 1.0]))
+                Some(Color::new([rgb[0], rgb[1], rgb[2], brick.alpha]))
             };
             let radius = brick.particle_radius
                 + if CONFIG.demo.randomize_radii {
@@ -96,6 +180,8 @@ pub fn generate_brick(objects: &mut ObjectSoa, brick: &Brick) -> Vec<usize> {
                 radius,
                 mass: brick.particle_mass,
                 color,
+                material,
+                collision_group,
                 ..ObjectPrototype::new(position)
             });
             result.push(id);
@@ -104,9 +190,36 @@ pub fn generate_brick(objects: &mut ObjectSoa, brick: &Brick) -> Vec<usize> {
     result
 }
 
-#[derive(Deserialize, Clone, Copy)]
+fn default_self_collide() -> bool {
+    true
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn default_alpha() -> f32 {
+    1.0
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_opaque(alpha: &f32) -> bool {
+    *alpha == 1.0
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Ball {
+    // Group of the ball, see `[groups]` in the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Takes precedence over the group
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+    // Particles of the ball don't collide with each other if false
+    #[serde(default = "default_self_collide", skip_serializing_if = "is_true")]
+    pub self_collide: bool,
     pub position: Vector2<f32>,
     pub radius: f32,
     #[serde(default)]
@@ -117,10 +230,29 @@ pub struct Ball {
     pub particle_spacing: f32,
     #[serde(default)]
     pub particle_mass: f32,
+    // Opacity of the particle colors, which accumulate with additive blending
+    #[serde(default = "default_alpha", skip_serializing_if = "is_opaque")]
+    pub alpha: f32,
 }
 
 pub fn generate_ball(objects: &mut ObjectSoa, ball: &Ball) -> Vec<usize> {
+    let units = &CONFIG.units;
+    let ball = &Ball {
+        position: units.position(ball.position),
+        radius: units.length(ball.radius),
+        velocity: units.velocity(ball.velocity),
+        particle_radius: units.length(ball.particle_radius),
+        particle_spacing: units.length(ball.particle_spacing),
+        particle_mass: units.mass(ball.particle_mass),
+        ..ball.clone()
+    };
     let mut result = Vec::new();
+    let material = CONFIG.material_index(ball.material.as_deref().or(ball.name.as_deref()));
+    let collision_group = if ball.self_collide {
+        0
+    } else {
+        objects.new_collision_group()
+    };
     let num_particles = (ball.radius * 2.0 / (ball.particle_radius * 2.0 + ball.particle_spacing)) as usize;
     for i in 0..num_particles {
         for j in 0..num_particles {
@@ -141,7 +273,7 @@ pub fn generate_ball(objects: &mut ObjectSoa, ball: &Ball) -> Vec<usize> {
                     let hue = 300.0 * (position - ball.position).magnitude() / ball.radius;
                     let hsl = [hue as f32, 100.0, 50.0];
                     let rgb = Hsl::convert::<Srgb>(hsl);
-                    Some(Color::new([rgb[0], rgb[1], rgb[2], 1.0]))
+                    Some(Color::new([rgb[0], rgb[1], rgb[2], ball.alpha]))
                 };
                 let radius = ball.particle_radius
                     + if CONFIG.demo.randomize_radii {
@@ -153,6 +285,8 @@ pub fn generate_ball(objects: &mut ObjectSoa, ball: &Ball) -> Vec<usize> {
                     radius,
                     mass: ball.particle_mass,
                     color,
+                    material,
+                    collision_group,
                     ..ObjectPrototype::new(position)
                 };
                 object.velocity = ball.velocity;
@@ -163,3 +297,25 @@ pub fn generate_ball(objects: &mut ObjectSoa, ball: &Ball) -> Vec<usize> {
     }
     result
 }
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Particle {
+    pub position: Vector2<f32>,
+    #[serde(default)]
+    pub velocity: Vector2<f32>,
+    pub radius: f32,
+    pub mass: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<String>,
+}
+
+pub fn generate_particle(objects: &mut ObjectSoa, particle: &Particle) -> usize {
+    objects.add(ObjectPrototype {
+        velocity: CONFIG.units.velocity(particle.velocity),
+        radius: CONFIG.units.length(particle.radius),
+        mass: CONFIG.units.mass(particle.mass),
+        material: CONFIG.material_index(particle.material.as_deref()),
+        ..ObjectPrototype::new(CONFIG.units.position(particle.position))
+    })
+}
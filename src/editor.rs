@@ -0,0 +1,197 @@
+use std::mem;
+
+use crate::{
+    demo::{Ball, Brick, Particle, SceneFile, generate_ball, generate_brick, generate_particle},
+    object::ObjectSoa,
+    units::Units,
+    vector2::Vector2,
+};
+
+#[derive(Clone, Debug)]
+pub enum SceneItem {
+    Particle(Particle),
+    Brick(Brick),
+    Ball(Ball),
+}
+
+impl SceneItem {
+    pub fn generate(&self, objects: &mut ObjectSoa) {
+        match self {
+            SceneItem::Particle(particle) => {
+                generate_particle(objects, particle);
+            }
+            SceneItem::Brick(brick) => {
+                generate_brick(objects, brick);
+            }
+            SceneItem::Ball(ball) => {
+                generate_ball(objects, ball);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditorTool {
+    Particle,
+    // Dragged from corner to corner
+    Brick,
+    // Dragged from the center outwards
+    Ball,
+}
+
+// Builds a scene file out of particles, bricks and balls placed with the mouse. The mouse positions are in world
+// coordinates, the shapes are in physical units like the rest of the scene file.
+pub struct Editor {
+    scene: SceneFile,
+    units: Units,
+    particle_radius: f32,
+    particle_spacing: f32,
+    particle_mass: f32,
+    drag: Option<(EditorTool, Vector2<f32>)>,
+}
+
+impl Editor {
+    #[must_use]
+    pub fn new(
+        scene: SceneFile,
+        units: Units,
+        particle_radius: f32,
+        particle_spacing: f32,
+        particle_mass: f32,
+    ) -> Self {
+        Self {
+            scene,
+            units,
+            particle_radius,
+            particle_spacing,
+            particle_mass,
+            drag: None,
+        }
+    }
+
+    #[must_use]
+    pub fn scene(&self) -> &SceneFile {
+        &self.scene
+    }
+
+    // Returns the previous scene
+    pub fn replace_scene(&mut self, scene: SceneFile) -> SceneFile {
+        mem::replace(&mut self.scene, scene)
+    }
+
+    pub fn press(&mut self, tool: EditorTool, position: Vector2<f32>) {
+        self.drag = Some((tool, self.units.physical_position(position)));
+    }
+
+    // Finishes the drag and adds the resulting item to the scene, unless it's too small to contain a particle
+    pub fn release(&mut self, position: Vector2<f32>) -> Option<SceneItem> {
+        let item = self.preview(position);
+        self.drag = None;
+        match item.clone()? {
+            SceneItem::Particle(particle) => self.scene.particles.push(particle),
+            SceneItem::Brick(brick) => self.scene.bricks.push(brick),
+            SceneItem::Ball(ball) => self.scene.balls.push(ball),
+        }
+        item
+    }
+
+    // The item that would be added if the mouse button was released at `position`
+    #[must_use]
+    pub fn preview(&self, position: Vector2<f32>) -> Option<SceneItem> {
+        let (tool, start) = self.drag?;
+        let position = self.units.physical_position(position);
+        let cell_size = self.particle_radius * 2.0 + self.particle_spacing;
+        match tool {
+            EditorTool::Particle => Some(SceneItem::Particle(Particle {
+                position,
+                velocity: Vector2::default(),
+                radius: self.particle_radius,
+                mass: self.particle_mass,
+                material: None,
+            })),
+            EditorTool::Brick => {
+                let topleft = Vector2::new(start.x.min(position.x), start.y.min(position.y));
+                let size = Vector2::new((position.x - start.x).abs(), (position.y - start.y).abs());
+                (size.x >= cell_size && size.y >= cell_size).then_some(SceneItem::Brick(Brick {
+                    name: None,
+                    material: None,
+                    self_collide: true,
+                    position: topleft,
+                    size,
+                    velocity: Vector2::default(),
+                    particle_radius: self.particle_radius,
+                    particle_spacing: self.particle_spacing,
+                    particle_mass: self.particle_mass,
+                    alpha: 1.0,
+                }))
+            }
+            EditorTool::Ball => {
+                let radius = (position - start).magnitude();
+                (radius >= cell_size).then_some(SceneItem::Ball(Ball {
+                    name: None,
+                    material: None,
+                    self_collide: true,
+                    position: start,
+                    radius,
+                    velocity: Vector2::default(),
+                    particle_radius: self.particle_radius,
+                    particle_spacing: self.particle_spacing,
+                    particle_mass: self.particle_mass,
+                    alpha: 1.0,
+                }))
+            }
+        }
+    }
+
+    // Removes the particles, bricks and balls with the position (or the center) inside the circle
+    pub fn remove(&mut self, position: Vector2<f32>, radius: f32) {
+        let (position, radius) = (self.units.physical_position(position), self.units.physical_length(radius));
+        let is_outside = |point: Vector2<f32>| (point - position).magnitude() >= radius;
+        self.scene.particles.retain(|particle| is_outside(particle.position));
+        self.scene.bricks.retain(|brick| is_outside(brick.position + brick.size / 2.0));
+        self.scene.balls.retain(|ball| is_outside(ball.position));
+    }
+}
+
+#[test]
+fn editor_builds_scene() {
+    let mut editor = Editor::new(SceneFile::default(), Units::default(), 1.0, 0.0, 1.0);
+    editor.press(EditorTool::Brick, Vector2::new(10.0, 20.0));
+    assert!(matches!(
+        editor.preview(Vector2::new(0.0, 25.0)),
+        Some(SceneItem::Brick(Brick { position, size, .. }))
+            if position == Vector2::new(0.0, 20.0) && size == Vector2::new(10.0, 5.0)
+    ));
+    // Too thin for a row of particles
+    assert!(editor.release(Vector2::new(0.0, 21.0)).is_none());
+
+    editor.press(EditorTool::Ball, Vector2::new(50.0, 50.0));
+    assert!(editor.release(Vector2::new(50.0, 60.0)).is_some());
+    editor.press(EditorTool::Particle, Vector2::new(0.0, 0.0));
+    assert!(editor.release(Vector2::new(100.0, 100.0)).is_some());
+    assert_eq!((editor.scene().bricks.len(), editor.scene().balls.len(), editor.scene().particles.len()), (0, 1, 1));
+
+    let scene: SceneFile = toml::from_str(&toml::to_string(editor.scene()).unwrap()).unwrap();
+    assert_eq!(scene.balls[0].radius, 10.0);
+    assert!(scene.particles[0].position == Vector2::new(100.0, 100.0));
+
+    editor.remove(Vector2::new(55.0, 50.0), 10.0);
+    assert_eq!((editor.scene().balls.len(), editor.scene().particles.len()), (0, 1));
+}
+
+#[test]
+fn editor_stores_physical_units() {
+    let units = Units {
+        pixels_per_meter: 10.0,
+        ..Units::default()
+    };
+    let mut editor = Editor::new(SceneFile::default(), units, 1.0, 0.0, 1.0);
+    editor.press(EditorTool::Ball, Vector2::new(100.0, 100.0));
+    assert!(editor.release(Vector2::new(100.0, 150.0)).is_some());
+    let ball = &editor.scene().balls[0];
+    assert!(ball.position == Vector2::new(10.0, 10.0));
+    assert_eq!(ball.radius, 5.0);
+
+    editor.remove(Vector2::new(120.0, 100.0), 30.0);
+    assert!(editor.scene().balls.is_empty());
+}
@@ -0,0 +1,24 @@
+//! Stable entry point for using the simulation as a library.
+//!
+//! Everything the solver needs is re-exported here, so downstream code doesn't have to depend on the internal module
+//! layout, which may change between versions.
+
+pub use crate::{
+    boundary::{Boundaries, Inflow, Wall, WallBehavior, WallFlux},
+    broad_phase::BroadPhase,
+    bvh::{AABB, Bvh},
+    event_driven::EventDrivenStats,
+    fluid::Fluid,
+    grid::Grid,
+    material::{CombineRule, Interaction, Material, MaterialPair, MaterialTable},
+    object::{ObjectPrototype, ObjectSoa},
+    physics::{
+        AngularMomentum, Contact, DtSource, DurationStat, EnergyChanges, GpuComputeOptions, GpuValidation, GravityZone,
+        PhysicsEngine, PhysicsSettings, SimulationMode, Stats, StepObserver, StepReport,
+    },
+    thermostat::Thermostat,
+    trajectory::{TrajectoryRecorder, TrajectorySample},
+    units::Units,
+    vector2::Vector2,
+    wind::Wind,
+};
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crossbeam::queue::{ArrayQueue, SegQueue};
+
+// What a topic does with a message published while the earlier ones haven't been taken yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    // Every message is kept until it's taken, for commands that mustn't be lost
+    Queue,
+    // Only the last message is kept, for data that supersedes the earlier data, like frames to draw
+    Latest,
+}
+
+// Messages of one type between threads, taken without blocking
+pub struct Topic<T> {
+    messages: Messages<T>,
+    dropped_count: AtomicUsize,
+}
+
+enum Messages<T> {
+    Queue(SegQueue<T>),
+    Latest(ArrayQueue<T>),
+}
+
+impl<T> Topic<T> {
+    #[must_use]
+    pub fn new(backpressure: Backpressure) -> Self {
+        Self {
+            messages: match backpressure {
+                Backpressure::Queue => Messages::Queue(SegQueue::new()),
+                Backpressure::Latest => Messages::Latest(ArrayQueue::new(1)),
+            },
+            dropped_count: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn publish(&self, message: T) {
+        match &self.messages {
+            Messages::Queue(queue) => queue.push(message),
+            Messages::Latest(latest) => {
+                if latest.force_push(message).is_some() {
+                    self.dropped_count.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn take(&self) -> Option<T> {
+        match &self.messages {
+            Messages::Queue(queue) => queue.pop(),
+            Messages::Latest(latest) => latest.pop(),
+        }
+    }
+
+    // The messages published so far, in order
+    pub fn drain(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.take())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        match &self.messages {
+            Messages::Queue(queue) => queue.is_empty(),
+            Messages::Latest(latest) => latest.is_empty(),
+        }
+    }
+
+    // Messages replaced by later ones before they were taken
+    #[must_use]
+    pub fn dropped_count(&self) -> usize {
+        self.dropped_count.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn topics_apply_backpressure() {
+    let queue = Topic::new(Backpressure::Queue);
+    let latest = Topic::new(Backpressure::Latest);
+    for message in 0..3 {
+        queue.publish(message);
+        latest.publish(message);
+    }
+    assert_eq!(queue.drain().collect::<Vec<_>>(), [0, 1, 2]);
+    assert!(queue.is_empty());
+    assert_eq!(latest.take(), Some(2));
+    assert_eq!(latest.take(), None);
+    assert_eq!((queue.dropped_count(), latest.dropped_count()), (0, 2));
+}
@@ -0,0 +1,555 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{bvh::AABB, vector2::Vector2};
+
+// Bounds the work per step, e.g. for inelastic collapse in dense piles, when the number of collisions grows without
+// bound. The remaining collisions are left to the next step.
+const MAX_EVENTS_PER_OBJECT: usize = 100;
+
+// Roots closer than this are considered to be the current contact that was just resolved
+const TIME_EPSILON: f64 = 1e-12;
+
+// Advances hard spheres by exactly `dt`, processing every collision at the moment it happens instead of resolving
+// overlaps after the fact. Only the global gravity is taken into account; it cancels out in the relative motion of two
+// objects, so pair collision times are found exactly, while wall collision times are the roots of a quadratic.
+// Frozen objects stay in place and act as obstacles of infinite mass; the gravity doesn't cancel out against them, so
+// the collision times are the roots of a quartic, found numerically.
+pub fn advance(
+    positions: &mut [Vector2<f32>],
+    velocities: &mut [Vector2<f32>],
+    radii: &[f32],
+    masses: &[f32],
+    is_frozen: &[bool],
+    bounds: AABB,
+    global_gravity: Vector2<f32>,
+    restitution_coefficient: f32,
+    restitution_velocity_threshold: f32,
+    dt: f32,
+) -> EventDrivenStats {
+    let mut solver = Solver {
+        positions: positions.iter().map(|&position| to_f64(position)).collect(),
+        velocities: velocities.iter().map(|&velocity| to_f64(velocity)).collect(),
+        times: vec![0.0; positions.len()],
+        collision_counts: vec![0; positions.len()],
+        radii,
+        masses,
+        is_frozen,
+        bounds,
+        gravity: to_f64(global_gravity),
+        restitution_coefficient: f64::from(restitution_coefficient),
+        restitution_velocity_threshold: f64::from(restitution_velocity_threshold),
+        end_time: f64::from(dt),
+        events: BinaryHeap::new(),
+    };
+    for object_index in 0..positions.len() {
+        solver.predict(object_index, object_index + 1);
+    }
+
+    let max_event_count = positions.len() * MAX_EVENTS_PER_OBJECT;
+    let mut event_count = 0;
+    while event_count < max_event_count
+        && let Some(event) = solver.events.pop()
+    {
+        if !solver.is_valid(&event) {
+            continue;
+        }
+        match event.kind {
+            EventKind::Pair(object1_index, object2_index) => {
+                solver.collide_pair(object1_index, object2_index, event.time);
+                solver.predict(object1_index, 0);
+                solver.predict(object2_index, 0);
+            }
+            EventKind::Wall(object_index, wall) => {
+                solver.collide_wall(object_index, wall, event.time);
+                solver.predict(object_index, 0);
+            }
+        }
+        event_count += 1;
+    }
+
+    for object_index in 0..positions.len() {
+        if is_frozen[object_index] {
+            continue;
+        }
+        let (position, velocity) = solver.state_at(object_index, solver.end_time);
+        positions[object_index] = to_f32(position);
+        velocities[object_index] = to_f32(velocity);
+    }
+    EventDrivenStats {
+        object_count: positions.len(),
+        event_count,
+        limit_reached: event_count == max_event_count,
+    }
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EventDrivenStats {
+    pub object_count: usize,
+    pub event_count: usize,
+    pub limit_reached: bool,
+}
+
+// Groups the objects that are closer than `margin` to each other (accounting for their radii) and returns the groups
+// of at most `max_cluster_size` objects. Uses sweep and prune along the X axis.
+#[must_use]
+pub fn sparse_clusters(
+    positions: &[Vector2<f32>],
+    radii: &[f32],
+    margin: f32,
+    max_cluster_size: usize,
+) -> Vec<Vec<usize>> {
+    let mut order = (0..positions.len()).collect::<Vec<_>>();
+    order.sort_unstable_by(|&a, &b| (positions[a].x - radii[a]).total_cmp(&(positions[b].x - radii[b])));
+    let mut parents = (0..positions.len()).collect::<Vec<_>>();
+    for (i, &object1_index) in order.iter().enumerate() {
+        let max_x = positions[object1_index].x + radii[object1_index] + margin;
+        for &object2_index in &order[i + 1..] {
+            if positions[object2_index].x - radii[object2_index] > max_x {
+                break;
+            }
+            let distance = radii[object1_index] + radii[object2_index] + margin;
+            if (positions[object1_index] - positions[object2_index]).magnitude_squared() < distance * distance {
+                let root1 = find_root(&mut parents, object1_index);
+                let root2 = find_root(&mut parents, object2_index);
+                parents[root1] = root2;
+            }
+        }
+    }
+
+    let mut clusters = vec![Vec::new(); positions.len()];
+    for object_index in 0..positions.len() {
+        let root = find_root(&mut parents, object_index);
+        clusters[root].push(object_index);
+    }
+    clusters.retain(|cluster| !cluster.is_empty() && cluster.len() <= max_cluster_size);
+    clusters
+}
+
+fn find_root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        // Path halving
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+struct Solver<'a> {
+    // Every object is stored at its own time, to which it was last advanced
+    positions: Vec<Vector2<f64>>,
+    velocities: Vec<Vector2<f64>>,
+    times: Vec<f64>,
+    // Incremented on every collision of the object, invalidating the events predicted before it
+    collision_counts: Vec<u32>,
+    radii: &'a [f32],
+    masses: &'a [f32],
+    is_frozen: &'a [bool],
+    bounds: AABB,
+    gravity: Vector2<f64>,
+    restitution_coefficient: f64,
+    restitution_velocity_threshold: f64,
+    end_time: f64,
+    events: BinaryHeap<Event>,
+}
+
+impl Solver<'_> {
+    fn state_at(&self, object_index: usize, time: f64) -> (Vector2<f64>, Vector2<f64>) {
+        if self.is_frozen[object_index] {
+            return (self.positions[object_index], Vector2::new(0.0, 0.0));
+        }
+        let t = time - self.times[object_index];
+        let position = self.positions[object_index] + self.velocities[object_index] * t + self.gravity * (0.5 * t * t);
+        let velocity = self.velocities[object_index] + self.gravity * t;
+        (position, velocity)
+    }
+
+    fn advance_object(&mut self, object_index: usize, time: f64) {
+        let (position, velocity) = self.state_at(object_index, time);
+        self.positions[object_index] = position;
+        self.velocities[object_index] = velocity;
+        self.times[object_index] = time;
+    }
+
+    // Predicts the collisions of the object with the walls and with the objects starting from `first_other_index`
+    fn predict(&mut self, object_index: usize, first_other_index: usize) {
+        let now = self.times[object_index];
+        let (position, velocity) = self.state_at(object_index, now);
+        for other_index in first_other_index..self.positions.len() {
+            if other_index == object_index {
+                continue;
+            }
+            let (other_position, other_velocity) = self.state_at(other_index, now);
+            let contact_distance = f64::from(self.radii[object_index] + self.radii[other_index]);
+            let relative_position = position - other_position;
+            let relative_velocity = velocity - other_velocity;
+            let t = if self.is_frozen[object_index] == self.is_frozen[other_index] {
+                pair_collision_time(relative_position, relative_velocity, contact_distance)
+            } else {
+                let relative_acceleration = if self.is_frozen[object_index] {
+                    -self.gravity
+                } else {
+                    self.gravity
+                };
+                accelerated_pair_collision_time(
+                    relative_position,
+                    relative_velocity,
+                    relative_acceleration,
+                    contact_distance,
+                    self.end_time - now,
+                )
+            };
+            if let Some(t) = t {
+                self.push_event(now + t, EventKind::Pair(object_index, other_index));
+            }
+        }
+
+        if self.is_frozen[object_index] {
+            return;
+        }
+        let radius = f64::from(self.radii[object_index]);
+        let topleft = to_f64(self.bounds.topleft);
+        let bottomright = to_f64(self.bounds.bottomright);
+        let walls = [
+            (Wall::Left, position.x - (topleft.x + radius), velocity.x, self.gravity.x, -1.0),
+            (Wall::Right, position.x - (bottomright.x - radius), velocity.x, self.gravity.x, 1.0),
+            (Wall::Top, position.y - (topleft.y + radius), velocity.y, self.gravity.y, -1.0),
+            (Wall::Bottom, position.y - (bottomright.y - radius), velocity.y, self.gravity.y, 1.0),
+        ];
+        for (wall, offset, velocity, acceleration, direction) in walls {
+            if let Some(t) = wall_collision_time(offset * direction, velocity * direction, acceleration * direction) {
+                self.push_event(now + t, EventKind::Wall(object_index, wall));
+            }
+        }
+    }
+
+    fn push_event(&mut self, time: f64, kind: EventKind) {
+        if time > self.end_time {
+            return;
+        }
+        let (object1_index, object2_index) = match kind {
+            EventKind::Pair(object1_index, object2_index) => (object1_index, Some(object2_index)),
+            EventKind::Wall(object_index, _) => (object_index, None),
+        };
+        self.events.push(Event {
+            time,
+            kind,
+            collision_counts: [
+                self.collision_counts[object1_index],
+                object2_index.map_or(0, |object_index| self.collision_counts[object_index]),
+            ],
+        });
+    }
+
+    fn is_valid(&self, event: &Event) -> bool {
+        match event.kind {
+            EventKind::Pair(object1_index, object2_index) => {
+                event.collision_counts
+                    == [
+                        self.collision_counts[object1_index],
+                        self.collision_counts[object2_index],
+                    ]
+            }
+            EventKind::Wall(object_index, _) => event.collision_counts[0] == self.collision_counts[object_index],
+        }
+    }
+
+    fn collide_pair(&mut self, object1_index: usize, object2_index: usize, time: f64) {
+        self.advance_object(object1_index, time);
+        self.advance_object(object2_index, time);
+        let normal = (self.positions[object1_index] - self.positions[object2_index]).normalize();
+        let normal_velocity = (self.velocities[object1_index] - self.velocities[object2_index]).dot(normal);
+        let restitution =
+            restitution(normal_velocity, self.restitution_coefficient, self.restitution_velocity_threshold);
+        let inverse_mass = |object_index: usize| {
+            if self.is_frozen[object_index] {
+                0.0
+            } else {
+                1.0 / f64::from(self.masses[object_index])
+            }
+        };
+        let inverse_mass1 = inverse_mass(object1_index);
+        let inverse_mass2 = inverse_mass(object2_index);
+        let impulse = (1.0 + restitution) * normal_velocity / (inverse_mass1 + inverse_mass2);
+        self.velocities[object1_index] -= normal * (impulse * inverse_mass1);
+        self.velocities[object2_index] += normal * (impulse * inverse_mass2);
+        self.collision_counts[object1_index] += 1;
+        self.collision_counts[object2_index] += 1;
+    }
+
+    fn collide_wall(&mut self, object_index: usize, wall: Wall, time: f64) {
+        self.advance_object(object_index, time);
+        let restitution_velocity_threshold = self.restitution_velocity_threshold;
+        let restitution_coefficient = self.restitution_coefficient;
+        let velocity = &mut self.velocities[object_index];
+        let normal_velocity = match wall {
+            Wall::Left | Wall::Right => &mut velocity.x,
+            Wall::Top | Wall::Bottom => &mut velocity.y,
+        };
+        *normal_velocity *= -restitution(*normal_velocity, restitution_coefficient, restitution_velocity_threshold);
+        self.collision_counts[object_index] += 1;
+    }
+}
+
+// Slow collisions are perfectly inelastic, like in the time-stepped solver
+fn restitution(normal_velocity: f64, restitution_coefficient: f64, restitution_velocity_threshold: f64) -> f64 {
+    if normal_velocity.abs() < restitution_velocity_threshold {
+        0.0
+    } else {
+        restitution_coefficient
+    }
+}
+
+// Time until two objects moving with constant relative velocity come into contact, if they're approaching
+fn pair_collision_time(
+    relative_position: Vector2<f64>,
+    relative_velocity: Vector2<f64>,
+    contact_distance: f64,
+) -> Option<f64> {
+    let b = relative_position.dot(relative_velocity);
+    if b >= 0.0 {
+        return None;
+    }
+    let c = relative_position.magnitude_squared() - contact_distance * contact_distance;
+    if c <= 0.0 {
+        // Already overlapping and approaching
+        return Some(0.0);
+    }
+    let a = relative_velocity.magnitude_squared();
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    // Equivalent to (-b - sqrt(discriminant)) / a, without the cancellation
+    Some(c / (-b + discriminant.sqrt()))
+}
+
+// Time until two objects with a constant relative acceleration come into contact, if that happens within the horizon.
+// The squared distance between them minus the squared contact distance is a quartic in time, whose first root where
+// the objects approach is the contact.
+fn accelerated_pair_collision_time(
+    relative_position: Vector2<f64>,
+    relative_velocity: Vector2<f64>,
+    relative_acceleration: Vector2<f64>,
+    contact_distance: f64,
+    horizon: f64,
+) -> Option<f64> {
+    let (p, v, a) = (relative_position, relative_velocity, relative_acceleration);
+    let c = p.magnitude_squared() - contact_distance * contact_distance;
+    if c <= 0.0 && p.dot(v) < 0.0 {
+        // Already overlapping and approaching
+        return Some(0.0);
+    }
+    // |p + v t + a t^2 / 2|^2 - contact_distance^2, the lowest degree first
+    let quartic = [
+        c,
+        2.0 * p.dot(v),
+        v.magnitude_squared() + p.dot(a),
+        v.dot(a),
+        0.25 * a.magnitude_squared(),
+    ];
+    let derivative = polynomial_derivative(&quartic);
+    polynomial_roots(&quartic, 0.0, horizon)
+        .into_iter()
+        .find(|&t| t > TIME_EPSILON && evaluate_polynomial(&derivative, t) < 0.0)
+}
+
+// Real roots within [lo, hi] in ascending order, by bisection between the roots of the derivative, where the
+// polynomial is monotonic. Double roots that don't change the sign are skipped.
+fn polynomial_roots(coefficients: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let degree = coefficients.iter().rposition(|&coefficient| coefficient != 0.0).unwrap_or(0);
+    if degree == 0 {
+        return Vec::new();
+    }
+    let coefficients = &coefficients[..=degree];
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&polynomial_derivative(coefficients), lo, hi));
+    bounds.push(hi);
+    let mut roots = Vec::new();
+    for bounds in bounds.windows(2) {
+        let (mut lo, mut hi) = (bounds[0], bounds[1]);
+        let lo_is_positive = evaluate_polynomial(coefficients, lo) > 0.0;
+        if lo_is_positive == (evaluate_polynomial(coefficients, hi) > 0.0) {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = 0.5 * (lo + hi);
+            if mid <= lo || mid >= hi {
+                break;
+            }
+            if (evaluate_polynomial(coefficients, mid) > 0.0) == lo_is_positive {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        roots.push(hi);
+    }
+    roots
+}
+
+fn polynomial_derivative(coefficients: &[f64]) -> Vec<f64> {
+    #[allow(clippy::cast_precision_loss)]
+    coefficients.iter().enumerate().skip(1).map(|(power, &coefficient)| coefficient * power as f64).collect()
+}
+
+fn evaluate_polynomial(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().rev().fold(0.0, |value, &coefficient| value * x + coefficient)
+}
+
+// Time until an object reaches a wall, where `offset` is the signed distance past the wall (negative inside the
+// bounds), `velocity` and `acceleration` are the components along the wall normal, pointing out of the bounds
+fn wall_collision_time(offset: f64, velocity: f64, acceleration: f64) -> Option<f64> {
+    if offset >= 0.0 && velocity > 0.0 {
+        return Some(0.0);
+    }
+    // offset + velocity * t + acceleration * t^2 / 2 = 0, crossing outwards
+    let a = 0.5 * acceleration;
+    let roots = if a == 0.0 {
+        if velocity == 0.0 {
+            return None;
+        }
+        [-offset / velocity, f64::NAN]
+    } else {
+        let discriminant = velocity * velocity - 4.0 * a * offset;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let q = -0.5 * (velocity + velocity.signum() * discriminant.sqrt());
+        [q / a, offset / q]
+    };
+    roots.into_iter().filter(|&t| t > TIME_EPSILON && velocity + acceleration * t > 0.0).min_by(f64::total_cmp)
+}
+
+struct Event {
+    time: f64,
+    kind: EventKind,
+    collision_counts: [u32; 2],
+}
+
+#[derive(Clone, Copy)]
+enum EventKind {
+    Pair(usize, usize),
+    Wall(usize, Wall),
+}
+
+#[derive(Clone, Copy)]
+enum Wall {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+// Reversed, so that the max-heap pops the earliest event first
+impl Ord for Event {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.time.total_cmp(&self.time)
+    }
+}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+fn to_f64(vector: Vector2<f32>) -> Vector2<f64> {
+    Vector2::new(f64::from(vector.x), f64::from(vector.y))
+}
+
+fn to_f32(vector: Vector2<f64>) -> Vector2<f32> {
+    Vector2::new(vector.x as f32, vector.y as f32)
+}
+
+#[test]
+fn head_on_collision_is_exact() {
+    let mut positions = [Vector2::new(10.0, 50.0), Vector2::new(30.0, 50.0)];
+    let mut velocities = [Vector2::new(5.0, 0.0), Vector2::new(-5.0, 0.0)];
+    let bounds = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(100.0, 100.0),
+    };
+    // The objects touch at t = 1 and then move apart for another second with swapped velocities
+    let stats = advance(
+        &mut positions,
+        &mut velocities,
+        &[5.0, 5.0],
+        &[1.0, 1.0],
+        &[false, false],
+        bounds,
+        Vector2::default(),
+        1.0,
+        0.0,
+        2.0,
+    );
+    assert_eq!(stats.event_count, 1);
+    assert_eq!(positions, [Vector2::new(10.0, 50.0), Vector2::new(30.0, 50.0)]);
+    assert_eq!(velocities, [Vector2::new(-5.0, 0.0), Vector2::new(5.0, 0.0)]);
+
+    // A frozen object reflects the other one, however light it is
+    let mut positions = [Vector2::new(20.0, 50.0), Vector2::new(30.0, 50.0)];
+    let mut velocities = [Vector2::new(0.0, 0.0), Vector2::new(-10.0, 0.0)];
+    advance(
+        &mut positions,
+        &mut velocities,
+        &[2.5, 2.5],
+        &[1.0, 1000.0],
+        &[true, false],
+        bounds,
+        Vector2::default(),
+        1.0,
+        0.0,
+        1.0,
+    );
+    assert_eq!(positions, [Vector2::new(20.0, 50.0), Vector2::new(30.0, 50.0)]);
+    assert_eq!(velocities, [Vector2::new(0.0, 0.0), Vector2::new(10.0, 0.0)]);
+}
+
+#[test]
+fn falling_object_bounces_off_frozen_one() {
+    // Starts at rest right above the frozen object, so only the gravity brings them together, after 10 / 100 * 2 =
+    // 0.2 seconds squared, and an elastic bounce brings the falling object back to the start in as much time
+    let mut positions = [Vector2::new(50.0, 50.0), Vector2::new(50.0, 30.0)];
+    let mut velocities = [Vector2::new(0.0, 0.0); 2];
+    let stats = advance(
+        &mut positions,
+        &mut velocities,
+        &[5.0, 5.0],
+        &[1.0, 1.0],
+        &[true, false],
+        AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(100.0, 100.0),
+        },
+        Vector2::new(0.0, 100.0),
+        1.0,
+        0.0,
+        2.0 * 0.2f32.sqrt(),
+    );
+    assert_eq!(stats.event_count, 1);
+    assert_eq!(positions[0], Vector2::new(50.0, 50.0));
+    assert!((positions[1] - Vector2::new(50.0, 30.0)).magnitude() < 1e-3, "{:?}", positions[1]);
+    assert!(velocities[1].magnitude() < 1e-3, "{:?}", velocities[1]);
+}
+
+#[test]
+fn sparse_clusters_exclude_dense_regions() {
+    let positions = [
+        Vector2::new(0.0, 0.0),
+        Vector2::new(2.5, 0.0),
+        Vector2::new(5.0, 0.0),
+        Vector2::new(50.0, 0.0),
+        Vector2::new(52.5, 0.0),
+        Vector2::new(100.0, 0.0),
+    ];
+    let clusters = sparse_clusters(&positions, &[1.0; 6], 1.0, 2);
+    assert_eq!(clusters, [vec![3, 4], vec![5]]);
+}
@@ -0,0 +1,231 @@
+use std::f32::consts::PI;
+
+use rayon::{
+    ThreadPool,
+    iter::{IntoParallelIterator, ParallelExtend, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{bvh::Bvh, physics::NormalizedCollisionPair, vector2::Vector2};
+
+// Density constraint of position-based fluids (Macklin and Müller, without the artificial pressure and the vorticity
+// confinement). Particles packed denser than the rest density are pushed apart along the density gradient, which
+// spreads a blob out like a liquid instead of letting it settle into a pile. The density is mass per unit of area.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Fluid {
+    pub rest_density: f32,
+    // Distance between the centers of particles within which they contribute to each other's density
+    pub smoothing_radius: f32,
+    // Fraction of the density error corrected per iteration
+    pub relaxation: f32,
+    pub iterations: usize,
+}
+
+// The fluid settings with the buffers reused between steps
+pub struct FluidSolver {
+    fluid: Fluid,
+    pairs: Vec<NormalizedCollisionPair>,
+    densities: Vec<f32>,
+    lambdas: Vec<f32>,
+    // Sums of the gradients of the density constraint of each particle, and of their squared magnitudes
+    gradient_sums: Vec<(Vector2<f32>, f32)>,
+    corrections: Vec<Vector2<f32>>,
+}
+
+impl FluidSolver {
+    #[must_use]
+    pub fn new(fluid: Fluid) -> Self {
+        Self {
+            fluid,
+            pairs: Vec::new(),
+            densities: Vec::new(),
+            lambdas: Vec::new(),
+            gradient_sums: Vec::new(),
+            corrections: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn fluid(&self) -> Fluid {
+        self.fluid
+    }
+
+    // Neighbors are found with the broad-phase BVH, which has to be up to date. Planets don't take part, frozen
+    // objects contribute to the density but aren't moved. Velocities are changed along with the positions, so the
+    // correction isn't undone by the next integration. Returns the highest relative density excess after the last
+    // iteration.
+    pub fn solve(
+        &mut self,
+        bvh: &Bvh,
+        thread_pool: &ThreadPool,
+        positions: &mut [Vector2<f32>],
+        velocities: &mut [Vector2<f32>],
+        radii: &[f32],
+        masses: &[f32],
+        is_planet: &[bool],
+        is_frozen: &[bool],
+        dt: f32,
+    ) -> f32 {
+        let Fluid {
+            rest_density,
+            smoothing_radius,
+            relaxation,
+            iterations,
+        } = self.fluid;
+
+        self.pairs.clear();
+        thread_pool.install(|| {
+            let pairs = (0..positions.len()).into_par_iter().filter(|&object_index| !is_planet[object_index]).fold(
+                || (Vec::new(), Vec::new()),
+                |(mut pairs, mut neighbors), object_index| {
+                    neighbors.clear();
+                    bvh.find_neighbors(object_index, smoothing_radius, positions, radii, &mut neighbors);
+                    pairs.extend(
+                        neighbors
+                            .iter()
+                            .filter(|&&neighbor| !is_planet[neighbor])
+                            .map(|&neighbor| NormalizedCollisionPair::new(object_index, neighbor)),
+                    );
+                    (pairs, neighbors)
+                },
+            );
+            self.pairs.par_extend(pairs.flat_map_iter(|(pairs, _)| pairs));
+            self.pairs.par_sort_unstable();
+        });
+        self.pairs.dedup();
+
+        let mut max_density_error = 0.0;
+        for _ in 0..iterations {
+            self.densities.clear();
+            self.densities.extend(masses.iter().zip(is_planet).map(|(&mass, &planet)| {
+                if planet {
+                    0.0
+                } else {
+                    mass * poly6(0.0, smoothing_radius)
+                }
+            }));
+            self.gradient_sums.clear();
+            self.gradient_sums.resize(positions.len(), (Vector2::default(), 0.0));
+            for pair in &self.pairs {
+                let (i, j) = (pair.object1_index as usize, pair.object2_index as usize);
+                let offset = positions[i] - positions[j];
+                let distance = offset.magnitude();
+                if distance >= smoothing_radius {
+                    continue;
+                }
+                let weight = poly6(distance, smoothing_radius);
+                self.densities[i] += masses[j] * weight;
+                self.densities[j] += masses[i] * weight;
+                let gradient = spiky_gradient(offset, distance, smoothing_radius) / rest_density;
+                let gradient_i = gradient * masses[j];
+                let gradient_j = gradient * masses[i];
+                self.gradient_sums[i].0 += gradient_i;
+                self.gradient_sums[i].1 += gradient_i.magnitude_squared();
+                self.gradient_sums[j].0 -= gradient_j;
+                self.gradient_sums[j].1 += gradient_j.magnitude_squared();
+            }
+
+            // Only compression is corrected, so the particles at the surface don't clump together
+            max_density_error = 0.0_f32;
+            self.lambdas.clear();
+            self.lambdas.extend(self.densities.iter().zip(&self.gradient_sums).map(
+                |(&density, &(gradient_sum, gradient_squared_sum))| {
+                    let constraint = (density / rest_density - 1.0).max(0.0);
+                    max_density_error = max_density_error.max(constraint);
+                    let denominator = gradient_sum.magnitude_squared() + gradient_squared_sum;
+                    if denominator > 0.0 {
+                        -constraint / denominator
+                    } else {
+                        0.0
+                    }
+                },
+            ));
+
+            self.corrections.clear();
+            self.corrections.resize(positions.len(), Vector2::default());
+            for pair in &self.pairs {
+                let (i, j) = (pair.object1_index as usize, pair.object2_index as usize);
+                let offset = positions[i] - positions[j];
+                let distance = offset.magnitude();
+                if distance >= smoothing_radius {
+                    continue;
+                }
+                let gradient = spiky_gradient(offset, distance, smoothing_radius) / rest_density;
+                let correction = gradient * (self.lambdas[i] * masses[j] + self.lambdas[j] * masses[i]);
+                self.corrections[i] += correction;
+                self.corrections[j] -= correction;
+            }
+
+            for (object_index, &correction) in self.corrections.iter().enumerate() {
+                if !is_planet[object_index] && !is_frozen[object_index] {
+                    let correction = correction * relaxation;
+                    positions[object_index] += correction;
+                    velocities[object_index] += correction / dt;
+                }
+            }
+        }
+        max_density_error
+    }
+}
+
+fn poly6(distance: f32, smoothing_radius: f32) -> f32 {
+    let difference = smoothing_radius * smoothing_radius - distance * distance;
+    4.0 / (PI * smoothing_radius.powi(8)) * difference * difference * difference
+}
+
+// Gradient with respect to the first particle, `offset` is the position of the first particle relative to the second
+fn spiky_gradient(offset: Vector2<f32>, distance: f32, smoothing_radius: f32) -> Vector2<f32> {
+    if distance == 0.0 {
+        return Vector2::default();
+    }
+    let difference = smoothing_radius - distance;
+    offset * (-30.0 / (PI * smoothing_radius.powi(5)) * difference * difference / distance)
+}
+
+#[test]
+fn fluid_relaxes_compressed_blob() {
+    use crate::bvh::AABB;
+
+    #[allow(clippy::cast_precision_loss)]
+    let mut positions = (0..100).map(|i| Vector2::new((i % 10) as f32, (i / 10) as f32)).collect::<Vec<_>>();
+    let mut velocities = vec![Vector2::default(); positions.len()];
+    let radii = vec![0.5; positions.len()];
+    let masses = vec![1.0; positions.len()];
+    let is_planet = vec![false; positions.len()];
+    let mut is_frozen = vec![false; positions.len()];
+    is_frozen[0] = true;
+    let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let bounds = AABB {
+        topleft: Vector2::new(-50.0, -50.0),
+        bottomright: Vector2::new(50.0, 50.0),
+    };
+    let mut bvh = Bvh::default();
+    // The blob is packed twice as densely as the rest density of a unit grid
+    let mut solver = FluidSolver::new(Fluid {
+        rest_density: 0.5,
+        smoothing_radius: 2.5,
+        relaxation: 0.5,
+        iterations: 2,
+    });
+
+    let mut density_errors = Vec::new();
+    for _ in 0..50 {
+        bvh.update(&positions, &radii, bounds);
+        density_errors.push(solver.solve(
+            &bvh,
+            &thread_pool,
+            &mut positions,
+            &mut velocities,
+            &radii,
+            &masses,
+            &is_planet,
+            &is_frozen,
+            0.1,
+        ));
+    }
+    assert!(density_errors[0] > 0.5, "density errors {density_errors:?}");
+    assert!(density_errors.last().unwrap() < &(density_errors[0] * 0.5), "density errors {density_errors:?}");
+    assert!(positions[0] == Vector2::default() && velocities[0] == Vector2::default());
+    // The blob spreads out
+    assert!(velocities[99].x > 0.0 && velocities[99].y > 0.0);
+}
@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::bail;
+
+// Traps on invalid floating-point operations, which stop the process where the first NaN appears instead of letting it
+// spread through the simulation. The traps are a part of the floating-point environment of a thread, so they only
+// apply to the thread that enables them. Only supported on Linux with glibc.
+
+// FE_INVALID, which is the same on x86 and ARM
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+const FE_INVALID: i32 = 1;
+
+static GUARDS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+unsafe extern "C" {
+    fn feenableexcept(excepts: i32) -> i32;
+    fn fedisableexcept(excepts: i32) -> i32;
+    fn fegetexcept() -> i32;
+    fn feclearexcept(excepts: i32) -> i32;
+}
+
+// Enables the traps of the calling thread
+pub fn enable_traps() -> anyhow::Result<()> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    {
+        if unsafe { feenableexcept(FE_INVALID) } == -1 {
+            bail!("feenableexcept failed");
+        }
+        Ok(())
+    }
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    bail!("floating-point exception traps are only supported on Linux with glibc")
+}
+
+// Whether `FpExceptionGuard` disables the traps, which is the default
+pub fn set_guards_enabled(enabled: bool) {
+    GUARDS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// Disables the traps of the calling thread until dropped, around calls into code that raises benign exceptions, like
+// some GPU drivers do. The exceptions raised meanwhile are cleared, so that they don't trap once the traps are back.
+pub struct FpExceptionGuard {
+    // Traps to enable again
+    disabled: i32,
+}
+
+impl FpExceptionGuard {
+    #[must_use]
+    pub fn new() -> Self {
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        if GUARDS_ENABLED.load(Ordering::Relaxed) {
+            let enabled = unsafe { fegetexcept() };
+            if enabled > 0 {
+                unsafe { fedisableexcept(enabled) };
+                return Self { disabled: enabled };
+            }
+        }
+        Self { disabled: 0 }
+    }
+}
+
+impl Default for FpExceptionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FpExceptionGuard {
+    fn drop(&mut self) {
+        #[cfg(all(target_os = "linux", target_env = "gnu"))]
+        if self.disabled > 0 {
+            unsafe {
+                feclearexcept(self.disabled);
+                feenableexcept(self.disabled);
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+#[test]
+fn guard_disables_traps_in_scope() {
+    std::thread::spawn(|| {
+        enable_traps().unwrap();
+        {
+            let _guard = FpExceptionGuard::new();
+            assert_eq!(unsafe { fegetexcept() }, 0);
+            // Would trap without the guard
+            let zero = std::hint::black_box(0.0_f32);
+            assert!((zero / zero).is_nan());
+            let _nested_guard = FpExceptionGuard::new();
+        }
+        assert_eq!(unsafe { fegetexcept() }, FE_INVALID);
+        set_guards_enabled(false);
+        assert_eq!(FpExceptionGuard::new().disabled, 0);
+        set_guards_enabled(true);
+        unsafe { fedisableexcept(FE_INVALID) };
+    })
+    .join()
+    .unwrap();
+}
@@ -2,36 +2,43 @@ use core::option::{
     Option,
     Option::{None, Some},
 };
-use std::{collections::VecDeque, time::Instant};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
 
 pub struct FpsCalculator {
+    // Frames are counted over this period, measured MEASURES_PER_PERIOD times per period
+    averaging_period: Duration,
     last_measure_time: Instant,
     frame_count_queue: VecDeque<(Instant, usize)>,
 }
 
 impl FpsCalculator {
-    pub fn update(&mut self, frame_count: usize) -> Option<usize> {
-        const FRAMES_MEASURE_PERIOD_MILLIS: usize = 100;
-        const {
-            assert!(FRAMES_MEASURE_PERIOD_MILLIS > 0);
+    const MEASURES_PER_PERIOD: u32 = 3;
+
+    #[must_use]
+    pub fn new(averaging_period: Duration) -> Self {
+        assert!(!averaging_period.is_zero(), "FPS averaging period must be positive");
+        Self {
+            averaging_period,
+            last_measure_time: Instant::now(),
+            frame_count_queue: VecDeque::new(),
         }
+    }
 
+    pub fn update(&mut self, frame_count: usize) -> Option<usize> {
         let now = Instant::now();
-        if (now - self.last_measure_time).as_millis() >= FRAMES_MEASURE_PERIOD_MILLIS as u128 {
+        if now - self.last_measure_time >= self.averaging_period / Self::MEASURES_PER_PERIOD {
             self.frame_count_queue.push_back((now, frame_count));
             self.last_measure_time = now;
         }
 
         if let Some((measure_start, start_frame_count)) = self.frame_count_queue.front().copied() {
-            const STATS_AVERAGING_PERIOD_MILLIS: usize = 300;
-            const {
-                assert!(STATS_AVERAGING_PERIOD_MILLIS > 0);
-            }
-
             let period_millis = (now - measure_start).as_millis();
-            if period_millis > STATS_AVERAGING_PERIOD_MILLIS as u128 {
+            if period_millis > self.averaging_period.as_millis() {
                 while let Some((measure_start, _)) = self.frame_count_queue.front().copied() {
-                    if (now - measure_start).as_millis() > STATS_AVERAGING_PERIOD_MILLIS as u128 {
+                    if now - measure_start > self.averaging_period {
                         self.frame_count_queue.pop_front();
                     } else {
                         break;
@@ -47,12 +54,3 @@ impl FpsCalculator {
         None
     }
 }
-
-impl Default for FpsCalculator {
-    fn default() -> Self {
-        Self {
-            last_measure_time: Instant::now(),
-            frame_count_queue: VecDeque::new(),
-        }
-    }
-}
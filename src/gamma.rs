@@ -0,0 +1,66 @@
+use serde_derive::{Deserialize, Serialize};
+
+// sRGB-encoded component to linear light, both in [0, 1]
+#[must_use]
+pub fn srgb_to_linear(component: f32) -> f32 {
+    if component <= 0.040_45 {
+        component / 12.92
+    } else {
+        ((component + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// Linear light to sRGB-encoded component, both in [0, 1]
+#[must_use]
+pub fn linear_to_srgb(component: f32) -> f32 {
+    if component <= 0.003_130_8 {
+        component * 12.92
+    } else {
+        1.055 * component.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Where colors are mixed, scaled and interpolated. Surfaces and images take sRGB-encoded colors, so in linear space the
+// colors are decoded before the math and encoded once for the output. The legacy mode does the math on the encoded
+// components, which darkens blends and washes out gradients, as the renderer used to.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorMath {
+    #[default]
+    #[serde(rename = "linear")]
+    Linear,
+
+    #[serde(rename = "legacy")]
+    Legacy,
+}
+
+impl ColorMath {
+    // sRGB-encoded component to the space of the color math
+    #[must_use]
+    pub fn decode(self, component: f32) -> f32 {
+        match self {
+            ColorMath::Linear => srgb_to_linear(component.clamp(0.0, 1.0)),
+            ColorMath::Legacy => component,
+        }
+    }
+
+    // Component in the space of the color math to sRGB-encoded
+    #[must_use]
+    pub fn encode(self, component: f32) -> f32 {
+        match self {
+            ColorMath::Linear => linear_to_srgb(component.clamp(0.0, 1.0)),
+            ColorMath::Legacy => component,
+        }
+    }
+}
+
+#[test]
+fn srgb_conversions_roundtrip() {
+    for index in 0..=255 {
+        let component = f32::from(u8::try_from(index).unwrap()) / 255.0;
+        assert!((linear_to_srgb(srgb_to_linear(component)) - component).abs() < 1e-5);
+    }
+    assert!((srgb_to_linear(0.5) - 0.214).abs() < 1e-3);
+    assert!((ColorMath::Linear.encode(0.5) - 0.735).abs() < 1e-3);
+    assert_eq!(ColorMath::Legacy.encode(0.5), 0.5);
+    assert_eq!(ColorMath::Legacy.decode(0.25), 0.25);
+}
@@ -0,0 +1,225 @@
+use std::{collections::BTreeMap, fmt::Write as _, fs, path::Path};
+
+use anyhow::{Context, bail};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    boundary::Boundaries,
+    bvh::AABB,
+    material::CombineRule,
+    object::{ObjectPrototype, ObjectSoa},
+    pair_storage::PairStorageKind,
+    physics::{DtSource, DurationStat, GpuComputeOptions, PhysicsEngine, PhysicsSettings, SimulationMode},
+    vector2::Vector2,
+};
+
+// A deterministic simulation: the same seed and step count always produce the same final state on the CPU path
+pub struct Scenario {
+    pub name: &'static str,
+    pub seed: u64,
+    pub steps: usize,
+    pub global_gravity: Vector2<f32>,
+    pub gravitational_constant: f32,
+    pub create_objects: fn(&mut ObjectSoa, &mut StdRng),
+}
+
+pub const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "falling_brick",
+        seed: 1,
+        steps: 500,
+        global_gravity: Vector2::new(0.0, 200.0),
+        gravitational_constant: 0.0,
+        create_objects: |objects, rng| {
+            for i in 0..20 {
+                for j in 0..20 {
+                    let position = Vector2::new(300.0 + i as f32 * 8.0, 100.0 + j as f32 * 8.0);
+                    let jitter = Vector2::new(rng.random::<f32>(), rng.random::<f32>());
+                    objects.add(ObjectPrototype {
+                        radius: 3.5,
+                        ..ObjectPrototype::new(position + jitter)
+                    });
+                }
+            }
+        },
+    },
+    Scenario {
+        name: "head_on",
+        seed: 2,
+        steps: 500,
+        global_gravity: Vector2::new(0.0, 0.0),
+        gravitational_constant: 0.0,
+        create_objects: |objects, rng| {
+            for (offset, velocity) in [(100.0, 300.0), (600.0, -300.0)] {
+                for i in 0..15 {
+                    for j in 0..15 {
+                        let position = Vector2::new(offset + i as f32 * 10.0, 300.0 + j as f32 * 10.0);
+                        objects.add(ObjectPrototype {
+                            velocity: Vector2::new(velocity, rng.random_range(-10.0..10.0)),
+                            radius: rng.random_range(3.0..4.5),
+                            mass: rng.random_range(1.0..3.0),
+                            ..ObjectPrototype::new(position)
+                        });
+                    }
+                }
+            }
+        },
+    },
+    Scenario {
+        name: "orbits",
+        seed: 3,
+        steps: 1000,
+        global_gravity: Vector2::new(0.0, 0.0),
+        gravitational_constant: 1.0,
+        create_objects: |objects, rng| {
+            let center = Vector2::new(500.0, 500.0);
+            let planet_mass = 1e7;
+            objects.add(ObjectPrototype {
+                radius: 20.0,
+                mass: planet_mass,
+                is_planet: true,
+                ..ObjectPrototype::new(center)
+            });
+            for _ in 0..200 {
+                let distance = rng.random_range(60.0..400.0_f32);
+                let angle = rng.random_range(0.0..std::f32::consts::TAU);
+                let direction = Vector2::new(angle.cos(), angle.sin());
+                let speed = (planet_mass / distance).sqrt();
+                objects.add(ObjectPrototype {
+                    velocity: Vector2::new(-direction.y, direction.x) * speed,
+                    radius: 2.0,
+                    ..ObjectPrototype::new(center + direction * distance)
+                });
+            }
+        },
+    },
+];
+
+#[must_use]
+pub fn find_scenario(name: &str) -> Option<&'static Scenario> {
+    SCENARIOS.iter().find(|scenario| scenario.name == name)
+}
+
+// Runs the scenario on the CPU with a fixed time step and returns the hash of the final state
+pub fn run(scenario: &Scenario) -> anyhow::Result<u64> {
+    let mut rng = StdRng::seed_from_u64(scenario.seed);
+    let mut objects = ObjectSoa::default();
+    (scenario.create_objects)(&mut objects, &mut rng);
+    let settings = PhysicsSettings {
+        dt: DtSource::Fixed(1.0 / 1000.0),
+        mode: SimulationMode::TimeStepped,
+        hybrid_max_cluster_size: 0,
+        constraints: AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(1000.0, 1000.0),
+        },
+        constraint_bouncing: true,
+        boundaries: Boundaries::default(),
+        restitution_coefficient: 0.9,
+        materials: Vec::new(),
+        restitution_combine: CombineRule::Max,
+        friction_combine: CombineRule::GeometricMean,
+        material_pairs: Vec::new(),
+        restitution_velocity_threshold: 1.0,
+        penetration_slop: 0.01,
+        position_correction_factor: 0.8,
+        global_gravity: scenario.global_gravity,
+        gravity_zones: Vec::new(),
+        gravitational_constant: scenario.gravitational_constant,
+        wind: None,
+        thermostat: None,
+        fluid: None,
+        heat_conduction: None,
+        pair_cache_margin: None,
+        verlet_skin: None,
+        pair_storage: PairStorageKind::Vec,
+        compensated_summation: false,
+        gpu_kernel_timeout: None,
+        collision_budget: None,
+        thread_pool: None,
+        seed: Some(scenario.seed),
+        duration_stat_window: DurationStat::DEFAULT_WINDOW,
+    };
+    let mut physics = PhysicsEngine::new(objects, settings)?;
+    for _ in 0..scenario.steps {
+        physics.advance(1.0, GpuComputeOptions::default());
+    }
+    Ok(state_hash(physics.time(), physics.objects()))
+}
+
+// FNV-1a over the exact bit patterns, so that any change of the simulation result changes the hash
+#[must_use]
+pub fn state_hash(time: f32, objects: &ObjectSoa) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    let mut hash = OFFSET_BASIS;
+    let mut hash_f32 = |value: f32| {
+        for byte in value.to_le_bytes() {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(PRIME);
+        }
+    };
+    hash_f32(time);
+    for object_index in 0..objects.len() {
+        let position = objects.positions[object_index];
+        let velocity = objects.velocities[object_index];
+        for value in [position.x, position.y, velocity.x, velocity.y] {
+            hash_f32(value);
+        }
+        hash_f32(objects.radii[object_index]);
+        hash_f32(objects.masses[object_index]);
+    }
+    hash
+}
+
+// One `<scenario> <steps> <hash>` line per scenario; the step count is stored to detect stale hashes
+pub fn read_golden_hashes(path: &Path) -> anyhow::Result<BTreeMap<String, (usize, u64)>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(path).with_context(|| format!("read \"{}\"", path.display()))?;
+    parse_golden_hashes(&text).with_context(|| format!("parse \"{}\"", path.display()))
+}
+
+pub fn write_golden_hashes(path: &Path, hashes: &BTreeMap<String, (usize, u64)>) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).with_context(|| format!("create directory \"{}\"", directory.display()))?;
+    }
+    fs::write(path, format_golden_hashes(hashes)).with_context(|| format!("write \"{}\"", path.display()))
+}
+
+fn parse_golden_hashes(text: &str) -> anyhow::Result<BTreeMap<String, (usize, u64)>> {
+    let mut hashes = BTreeMap::new();
+    for (line_index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let [name, steps, hash] = fields.as_slice() else {
+            bail!("line {}: expected \"<scenario> <steps> <hash>\"", line_index + 1);
+        };
+        let steps = steps.parse().with_context(|| format!("line {}: invalid step count", line_index + 1))?;
+        let hash = u64::from_str_radix(hash, 16).with_context(|| format!("line {}: invalid hash", line_index + 1))?;
+        hashes.insert((*name).to_string(), (steps, hash));
+    }
+    Ok(hashes)
+}
+
+fn format_golden_hashes(hashes: &BTreeMap<String, (usize, u64)>) -> String {
+    let mut text = String::new();
+    for (name, (steps, hash)) in hashes {
+        writeln!(text, "{name} {steps} {hash:016x}").unwrap();
+    }
+    text
+}
+
+#[test]
+fn golden_hashes_roundtrip() {
+    let hashes = BTreeMap::from([
+        ("a".to_string(), (10, 0x0123_4567_89ab_cdef)),
+        ("b".to_string(), (5, 1)),
+    ]);
+    let text = format_golden_hashes(&hashes);
+    assert_eq!(parse_golden_hashes(&format!("# comment\n\n{text}")).unwrap(), hashes);
+}
@@ -1,28 +1,45 @@
-use std::{path::Path, ptr::null_mut, sync::LazyLock};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    iter::zip,
+    mem::size_of,
+    path::{Path, PathBuf},
+    ptr::null_mut,
+    sync::{Arc, LazyLock, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
-use anyhow::{Context as _, anyhow};
+use anyhow::{Context as _, anyhow, bail, ensure};
 use opencl3::{
     command_queue::CommandQueue,
     context::Context,
     device::{CL_DEVICE_TYPE_GPU, Device},
-    event::Event,
-    kernel::ExecuteKernel,
+    event::{CL_COMPLETE, Event},
+    kernel::{ExecuteKernel, Kernel},
     memory::{Buffer, CL_MEM_READ_ONLY, CL_MEM_READ_WRITE, CL_MEM_USE_HOST_PTR, CL_MEM_WRITE_ONLY},
     platform::get_platforms,
     program::Program,
     types::{CL_FALSE, cl_mem_flags},
 };
 
+use crate::fp_exceptions::FpExceptionGuard;
+
 pub static GPU: LazyLock<Gpu> = LazyLock::new(|| Gpu::first_available().unwrap());
 
 pub struct Gpu {
     context: Context,
     queue: CommandQueue,
+    buffers: Arc<Mutex<BufferRegistry>>,
+    // Hash of the device and driver, since the compiled kernels only work with the ones that compiled them
+    device_fingerprint: u64,
 }
 
+// Driver calls run with the floating-point exception traps disabled, see `FpExceptionGuard`
 // TODO don't return results (there's no point)
 impl Gpu {
     pub fn first_available() -> anyhow::Result<Self> {
+        let _guard = FpExceptionGuard::new();
         let platforms = get_platforms().context("No platforms found")?;
         println!("Available OpenCL platforms ({}):", platforms.len());
         for (i, platform) in platforms.iter().enumerate() {
@@ -46,16 +63,32 @@ impl Gpu {
                 let context = Context::from_device(&device).context("Failed to create context")?;
                 let queue = CommandQueue::create_default_with_properties(&context, 0, 0)
                     .context("Failed to create command queue")?;
-                return Ok(Gpu { context, queue });
+                let device_fingerprint = fnv1a([
+                    platform.version().context("Failed to get platform version")?,
+                    device.name().context("Failed to get device name")?,
+                    device.driver_version().context("Failed to get driver version")?,
+                ]);
+                return Ok(Gpu {
+                    context,
+                    queue,
+                    buffers: Arc::default(),
+                    device_fingerprint,
+                });
             }
         }
         Err(anyhow!("No GPU device found"))
     }
 
+    // Compiled kernels are cached per device, and rebuilt once the source is newer than the cached binary
     pub fn build_program(&self, path: impl AsRef<Path>) -> anyhow::Result<Program> {
-        let binary_path = path.as_ref().with_extension("bin");
-        if let anyhow::Result::Ok(binary_metadata) = std::fs::metadata(&binary_path)
-            && let anyhow::Result::Ok(source_metadata) = std::fs::metadata(&path)
+        let _guard = FpExceptionGuard::new();
+        let binary_path = kernel_cache_directory().map(|directory| {
+            let name = path.as_ref().file_stem().unwrap_or_default().to_string_lossy();
+            directory.join(format!("{name}-{:016x}.bin", self.device_fingerprint))
+        });
+        if let Some(binary_path) = &binary_path
+            && let anyhow::Result::Ok(binary_metadata) = fs::metadata(binary_path)
+            && let anyhow::Result::Ok(source_metadata) = fs::metadata(&path)
         {
             let source_modified = source_metadata.modified().context("Failed to get source modified time")?;
             let binary_modified = binary_metadata.modified().context("Failed to get binary modified time")?;
@@ -64,14 +97,21 @@ impl Gpu {
             }
         }
         println!("Building OpenCL kernel: {}", path.as_ref().display());
-        let source = &std::fs::read_to_string(path).context("Failed to read source: {path:?}")?;
+        let source = &fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read source: {}", path.as_ref().display()))?;
         let program = Program::create_and_build_from_source(&self.context, source, "").map_err(|e| anyhow!("{e}"))?;
-        std::fs::write(binary_path, &program.get_binaries()?[0]).context("Failed to write binary: {binary_path:?}")?;
+        // The kernel is just rebuilt next time if it can't be cached
+        if let Some(binary_path) = binary_path
+            && let Err(error) = write_cached_binary(&binary_path, &program.get_binaries()?[0])
+        {
+            eprintln!("Failed to cache kernel binary \"{}\": {error:#}", binary_path.display());
+        }
         Ok(program)
     }
 
     pub fn load_program_binary(&self, path: impl AsRef<Path>) -> anyhow::Result<Program> {
-        let binary = &std::fs::read(path).unwrap();
+        let _guard = FpExceptionGuard::new();
+        let binary = &fs::read(path).unwrap();
         Program::create_and_build_from_binary(&self.context, &[binary], "").map_err(|e| anyhow!("{e}"))
     }
 
@@ -79,9 +119,11 @@ impl Gpu {
     /// OpenCL is inherently unsafe
     pub unsafe fn create_host_ptr_buffer<T>(
         &self,
+        name: &'static str,
         data: &mut [T],
         access_mode: GpuBufferAccessMode,
     ) -> anyhow::Result<GpuHostPtrBuffer<T>> {
+        let _guard = FpExceptionGuard::new();
         let buffer = unsafe {
             Buffer::create(
                 &self.context,
@@ -94,14 +136,18 @@ impl Gpu {
         Ok(GpuHostPtrBuffer {
             buffer,
             length: data.len(),
+            host_address: data.as_ptr().addr(),
+            _registration: self.register_buffer::<T>(name, GpuBufferKind::HostPtr, access_mode, data.len()),
         })
     }
 
     pub fn create_host_buffer<T>(
         &self,
+        name: &'static str,
         data: Vec<T>,
         access_mode: GpuBufferAccessMode,
     ) -> anyhow::Result<GpuHostBuffer<T>> {
+        let _guard = FpExceptionGuard::new();
         let buffer = unsafe {
             Buffer::create(
                 &self.context,
@@ -111,17 +157,52 @@ impl Gpu {
             )
         }
         .context("Failed to create host buffer")?;
-        Ok(GpuHostBuffer { data, buffer })
+        let registration = self.register_buffer::<T>(name, GpuBufferKind::Host, access_mode, data.len());
+        Ok(GpuHostBuffer {
+            data,
+            buffer,
+            _registration: registration,
+        })
     }
 
     pub fn create_device_buffer<T>(
         &self,
+        name: &'static str,
         length: usize,
         access_mode: GpuBufferAccessMode,
     ) -> anyhow::Result<GpuDeviceBuffer<T>> {
+        let _guard = FpExceptionGuard::new();
         let buffer = unsafe { Buffer::create(&self.context, access_mode.cl_mem_flags(), length, null_mut()) }
             .context("Failed to create device buffer")?;
-        Ok(GpuDeviceBuffer { buffer, length })
+        Ok(GpuDeviceBuffer {
+            buffer,
+            length,
+            _registration: self.register_buffer::<T>(name, GpuBufferKind::Device, access_mode, length),
+        })
+    }
+
+    fn register_buffer<T>(
+        &self,
+        name: &'static str,
+        kind: GpuBufferKind,
+        access_mode: GpuBufferAccessMode,
+        length: usize,
+    ) -> BufferRegistration {
+        BufferRegistry::register(
+            &self.buffers,
+            GpuBufferInfo {
+                name,
+                kind,
+                access_mode,
+                size: length * size_of::<T>(),
+            },
+        )
+    }
+
+    // Buffers that are currently allocated
+    #[must_use]
+    pub fn memory_report(&self) -> GpuMemoryReport {
+        self.buffers.lock().unwrap().report()
     }
 
     pub fn enqueue_write_device_buffer<T>(
@@ -130,6 +211,7 @@ impl Gpu {
         data: &[T],
         offset: usize,
     ) -> anyhow::Result<Event> {
+        let _guard = FpExceptionGuard::new();
         unsafe {
             self.queue
                 .enqueue_write_buffer(&mut buffer.buffer, CL_FALSE, offset, data, &[])
@@ -143,20 +225,123 @@ impl Gpu {
         dst: &mut [T],
         offset: usize,
     ) -> anyhow::Result<Event> {
+        let _guard = FpExceptionGuard::new();
         unsafe { self.queue.enqueue_read_buffer(&buffer.buffer, CL_FALSE, offset, dst, &[]) }
             .context("Failed to read device buffer")
     }
 
+    // Runs a kernel that writes the sizes of the OpenCL types in the order of `host_sizes`, since kernels silently read
+    // garbage if a type shared with the host has a different layout
+    pub fn verify_layout(
+        &self,
+        program: &Program,
+        kernel_name: &str,
+        host_sizes: &[(&str, usize)],
+    ) -> anyhow::Result<()> {
+        let _guard = FpExceptionGuard::new();
+        let kernel = Kernel::create(program, kernel_name).context("Failed to create layout kernel")?;
+        let sizes =
+            self.create_host_buffer("layout sizes", vec![0_u32; host_sizes.len()], GpuBufferAccessMode::WriteOnly)?;
+        let mut execute_kernel = ExecuteKernel::new(&kernel);
+        unsafe { sizes.set_arg(&mut execute_kernel) };
+        execute_kernel.set_global_work_size(1);
+        self.enqueue_execute_kernel(&mut execute_kernel)?;
+        self.wait_for_queue_completion()?;
+        let mismatches = zip(host_sizes, sizes.data())
+            .filter(|&(&(_, host_size), &gpu_size)| host_size != gpu_size as usize)
+            .map(|(&(name, host_size), &gpu_size)| {
+                format!("{name} is {host_size} bytes on the host, {gpu_size} in OpenCL")
+            })
+            .collect::<Vec<_>>();
+        if !mismatches.is_empty() {
+            bail!("Layout mismatch in {kernel_name}: {}", mismatches.join(", "));
+        }
+        Ok(())
+    }
+
     pub fn enqueue_execute_kernel(&self, kernel: &mut ExecuteKernel) -> anyhow::Result<Event> {
+        let _guard = FpExceptionGuard::new();
         unsafe { kernel.enqueue_nd_range(&self.queue) }.context("Failed to enqueue kernel")
     }
 
     pub fn wait_for_queue_completion(&self) -> anyhow::Result<()> {
+        let _guard = FpExceptionGuard::new();
         self.queue.finish().context("Failed to submit queue")
     }
+
+    // Runs the kernel and waits for it to finish. OpenCL waits indefinitely, so with a timeout the kernel status is
+    // polled instead. A kernel that doesn't finish in time can't be stopped, so it may still access its buffers after
+    // the error, and the memory they alias must not be reused. A kernel aborted by a driver reset isn't run again,
+    // since the reset usually leaves the queue and the context unusable.
+    pub fn execute_kernel(&self, kernel: &mut ExecuteKernel, timeout: Option<Duration>) -> anyhow::Result<()> {
+        let _guard = FpExceptionGuard::new();
+        let Some(timeout) = timeout else {
+            self.enqueue_execute_kernel(kernel)?;
+            return self.wait_for_queue_completion();
+        };
+        let event = self.enqueue_execute_kernel(kernel)?;
+        self.queue.flush().context("Failed to flush queue")?;
+        let start = Instant::now();
+        let status = loop {
+            let status = event.command_execution_status().context("Failed to get kernel status")?.0;
+            if status == CL_COMPLETE || status < 0 {
+                break status;
+            }
+            if start.elapsed() > timeout {
+                bail!("Kernel didn't finish in {timeout:?}");
+            }
+            thread::sleep(Duration::from_micros(50));
+        };
+        ensure!(status == CL_COMPLETE, "Kernel terminated abnormally with status {status}");
+        self.wait_for_queue_completion()
+    }
+}
+
+// Platform cache directory for the compiled kernels, if there is one
+#[must_use]
+pub fn kernel_cache_directory() -> Option<PathBuf> {
+    let cache_directory = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|directory| Path::new(directory).is_absolute())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    cache_directory.map(|directory| directory.join("collision").join("kernels"))
+}
+
+pub fn clear_kernel_cache() -> anyhow::Result<()> {
+    if let Some(directory) = kernel_cache_directory()
+        && directory.exists()
+    {
+        fs::remove_dir_all(&directory)
+            .with_context(|| format!("Failed to remove kernel cache \"{}\"", directory.display()))?;
+    }
+    Ok(())
 }
 
-#[derive(Copy, Clone)]
+fn write_cached_binary(path: &Path, binary: &[u8]) -> anyhow::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    fs::write(path, binary)?;
+    Ok(())
+}
+
+fn fnv1a(strings: impl IntoIterator<Item = String>) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    strings.into_iter().fold(OFFSET_BASIS, |hash, string| {
+        // Separated, so that moving a character from one string to the next changes the hash
+        string.bytes().chain([0]).fold(hash, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(PRIME))
+    })
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum GpuBufferAccessMode {
     ReadOnly,
     WriteOnly,
@@ -172,11 +357,109 @@ impl GpuBufferAccessMode {
             GpuBufferAccessMode::ReadWrite => CL_MEM_READ_WRITE,
         }
     }
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            GpuBufferAccessMode::ReadOnly => "read-only",
+            GpuBufferAccessMode::WriteOnly => "write-only",
+            GpuBufferAccessMode::ReadWrite => "read-write",
+        }
+    }
+}
+
+// Host pointer and host buffers are backed by host memory, but the driver may still mirror them in VRAM
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GpuBufferKind {
+    HostPtr,
+    Host,
+    Device,
+}
+
+impl GpuBufferKind {
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            GpuBufferKind::HostPtr => "host ptr",
+            GpuBufferKind::Host => "host",
+            GpuBufferKind::Device => "device",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuBufferInfo {
+    pub name: &'static str,
+    pub kind: GpuBufferKind,
+    pub access_mode: GpuBufferAccessMode,
+    // Bytes
+    pub size: usize,
+}
+
+// Live buffers in the order of creation, and the largest total size of the buffers that were alive at the same time
+#[derive(Clone, Debug, Default)]
+pub struct GpuMemoryReport {
+    pub buffers: Vec<GpuBufferInfo>,
+    pub peak_size: usize,
+}
+
+impl GpuMemoryReport {
+    #[must_use]
+    pub fn total_size(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.size).sum()
+    }
+}
+
+#[derive(Default)]
+struct BufferRegistry {
+    next_id: u64,
+    buffers: BTreeMap<u64, GpuBufferInfo>,
+    size: usize,
+    peak_size: usize,
+}
+
+impl BufferRegistry {
+    fn register(registry: &Arc<Mutex<BufferRegistry>>, info: GpuBufferInfo) -> BufferRegistration {
+        let mut locked = registry.lock().unwrap();
+        let id = locked.next_id;
+        locked.next_id += 1;
+        locked.size += info.size;
+        locked.peak_size = locked.peak_size.max(locked.size);
+        locked.buffers.insert(id, info);
+        BufferRegistration {
+            id,
+            registry: registry.clone(),
+        }
+    }
+
+    fn report(&self) -> GpuMemoryReport {
+        GpuMemoryReport {
+            buffers: self.buffers.values().cloned().collect(),
+            peak_size: self.peak_size,
+        }
+    }
+}
+
+// Keeps a buffer in the registry until the buffer is dropped
+struct BufferRegistration {
+    id: u64,
+    registry: Arc<Mutex<BufferRegistry>>,
+}
+
+impl Drop for BufferRegistration {
+    fn drop(&mut self) {
+        let mut registry = self.registry.lock().unwrap();
+        if let Some(info) = registry.buffers.remove(&self.id) {
+            registry.size -= info.size;
+        }
+    }
 }
 
 pub struct GpuHostPtrBuffer<T> {
     buffer: Buffer<T>,
     length: usize,
+    host_address: usize,
+    _registration: BufferRegistration,
 }
 
 impl<T> GpuHostPtrBuffer<T> {
@@ -191,6 +474,12 @@ impl<T> GpuHostPtrBuffer<T> {
         self.length
     }
 
+    // The buffer must be recreated once the host data is reallocated or resized
+    #[must_use]
+    pub fn is_bound_to(&self, data: &[T]) -> bool {
+        self.host_address == data.as_ptr().addr() && self.length == data.len()
+    }
+
     /// # Safety
     /// OpenCL is inherently unsafe
     pub unsafe fn set_arg(&self, kernel: &mut ExecuteKernel) {
@@ -201,6 +490,7 @@ impl<T> GpuHostPtrBuffer<T> {
 pub struct GpuHostBuffer<T> {
     data: Vec<T>,
     buffer: Buffer<T>,
+    _registration: BufferRegistration,
 }
 
 impl<T> GpuHostBuffer<T> {
@@ -229,6 +519,7 @@ impl<T> GpuHostBuffer<T> {
 pub struct GpuDeviceBuffer<T> {
     buffer: Buffer<T>,
     length: usize,
+    _registration: BufferRegistration,
 }
 
 impl<T> GpuDeviceBuffer<T> {
@@ -249,3 +540,23 @@ impl<T> GpuDeviceBuffer<T> {
         unsafe { kernel.set_arg(&self.buffer) };
     }
 }
+
+#[test]
+fn buffer_registry_tracks_live_buffers() {
+    let registry = Arc::default();
+    let info = |name, size| GpuBufferInfo {
+        name,
+        kind: GpuBufferKind::Device,
+        access_mode: GpuBufferAccessMode::ReadOnly,
+        size,
+    };
+    let nodes = BufferRegistry::register(&registry, info("nodes", 100));
+    let candidates = BufferRegistry::register(&registry, info("candidates", 50));
+    drop(nodes);
+    let _bigger_nodes = BufferRegistry::register(&registry, info("nodes", 200));
+    drop(candidates);
+    let report = registry.lock().unwrap().report();
+    assert_eq!(report.buffers, [info("nodes", 200)]);
+    assert_eq!(report.total_size(), 200);
+    assert_eq!(report.peak_size, 250);
+}
@@ -0,0 +1,137 @@
+use std::mem;
+
+use crate::{broad_phase::BroadPhase, bvh::AABB, vector2::Vector2};
+
+// Uniform grid with cells as large as the largest object, so that an object can only touch the objects in its own
+// cell and the adjacent ones. Objects are binned by their centers and sorted by cell, which makes every cell a
+// contiguous range of entries.
+#[derive(Default, Clone)]
+pub struct Grid {
+    origin: Vector2<f32>,
+    cell_size: f32,
+    max_radius: f32,
+    // Cell keys and object indices, sorted by cell key
+    entries: Vec<(u64, u32)>,
+}
+
+impl Grid {
+    #[allow(clippy::cast_possible_truncation)]
+    fn cell(&self, position: Vector2<f32>) -> [i32; 2] {
+        let cell = (position - self.origin) / self.cell_size;
+        [cell.x.floor() as i32, cell.y.floor() as i32]
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    fn key([x, y]: [i32; 2]) -> u64 {
+        (u64::from(x as u32) << 32) | u64::from(y as u32)
+    }
+
+    fn objects_in_cell(&self, cell: [i32; 2]) -> impl Iterator<Item = usize> {
+        let key = Self::key(cell);
+        let start = self.entries.partition_point(|&(entry_key, _)| entry_key < key);
+        self.entries[start..]
+            .iter()
+            .take_while(move |&&(entry_key, _)| entry_key == key)
+            .map(|&(_, object_index)| object_index as usize)
+    }
+}
+
+impl BroadPhase for Grid {
+    fn update(&mut self, positions: &[Vector2<f32>], radii: &[f32], bounds: AABB) {
+        self.origin = bounds.topleft;
+        self.max_radius = radii.iter().copied().fold(0.0, f32::max);
+        self.cell_size = (self.max_radius * 2.0).max(f32::MIN_POSITIVE);
+        let mut entries = mem::take(&mut self.entries);
+        entries.clear();
+        entries.extend(
+            positions.iter().enumerate().map(|(object_index, &position)| {
+                (Self::key(self.cell(position)), u32::try_from(object_index).unwrap())
+            }),
+        );
+        entries.sort_unstable();
+        self.entries = entries;
+    }
+
+    fn query_aabb(&self, aabb: AABB, positions: &[Vector2<f32>], radii: &[f32], f: &mut dyn FnMut(usize)) {
+        let mut visit = |object_index: usize| {
+            let position = positions[object_index];
+            let radius = radii[object_index];
+            let object_aabb = AABB {
+                topleft: position - radius,
+                bottomright: position + radius,
+            };
+            if aabb.intersects(&object_aabb) {
+                f(object_index);
+            }
+        };
+
+        let [min_x, min_y] = self.cell(aabb.topleft - self.max_radius);
+        let [max_x, max_y] = self.cell(aabb.bottomright + self.max_radius);
+        let cell_count = (i64::from(max_x) - i64::from(min_x) + 1) * (i64::from(max_y) - i64::from(min_y) + 1);
+        // Visiting the cells one by one only pays off for regions with fewer cells than objects
+        if cell_count > i64::try_from(self.entries.len()).unwrap() {
+            for &(_, object_index) in &self.entries {
+                visit(object_index as usize);
+            }
+            return;
+        }
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                for object_index in self.objects_in_cell([x, y]) {
+                    visit(object_index);
+                }
+            }
+        }
+    }
+
+    fn for_each_pair(&self, positions: &[Vector2<f32>], radii: &[f32], f: &mut dyn FnMut(usize, usize)) {
+        for &(_, object1_index) in &self.entries {
+            let object1_index = object1_index as usize;
+            let [x, y] = self.cell(positions[object1_index]);
+            for neighbor_cell in [-1, 0, 1].into_iter().flat_map(|dx| [-1, 0, 1].map(|dy| [x + dx, y + dy])) {
+                for object2_index in self.objects_in_cell(neighbor_cell) {
+                    let collision_distance = radii[object1_index] + radii[object2_index];
+                    if object2_index > object1_index
+                        && (positions[object1_index] - positions[object2_index]).magnitude_squared()
+                            < collision_distance * collision_distance
+                    {
+                        f(object1_index, object2_index);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn grid_matches_bvh() {
+    use crate::bvh::Bvh;
+
+    #[allow(clippy::cast_precision_loss)]
+    let positions = (0..500).map(|i| Vector2::new((i * 37 % 101) as f32, (i * 53 % 89) as f32)).collect::<Vec<_>>();
+    #[allow(clippy::cast_precision_loss)]
+    let radii = (0..positions.len()).map(|i| 0.5 + (i % 5) as f32 * 0.5).collect::<Vec<_>>();
+    let bounds = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(100.0, 100.0),
+    };
+    let mut broad_phases: [Box<dyn BroadPhase>; 2] = [Box::new(Bvh::default()), Box::new(Grid::default())];
+    let [bvh, grid] = broad_phases.each_mut().map(|broad_phase| {
+        broad_phase.update(&positions, &radii, bounds);
+        let mut pairs = Vec::new();
+        broad_phase.for_each_pair(&positions, &radii, &mut |object1_index, object2_index| {
+            pairs.push((object1_index, object2_index));
+        });
+        pairs.sort_unstable();
+        let mut queried = Vec::new();
+        let aabb = AABB {
+            topleft: Vector2::new(20.0, 30.0),
+            bottomright: Vector2::new(40.0, 35.0),
+        };
+        broad_phase.query_aabb(aabb, &positions, &radii, &mut |object_index| queried.push(object_index));
+        queried.sort_unstable();
+        (pairs, queried)
+    });
+    assert!(!bvh.0.is_empty() && !bvh.1.is_empty());
+    assert_eq!(bvh, grid);
+}
@@ -0,0 +1,107 @@
+use std::mem;
+
+use crate::{object::ObjectPrototype, physics::PhysicsEngine};
+
+// Undo and redo stacks of reversible edits. Reverting an edit produces the edit that reverts it back, which is what
+// ends up on the other stack.
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+    capacity: usize,
+}
+
+impl<T> History<T> {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            capacity,
+        }
+    }
+
+    // A new edit makes the undone edits impossible to redo
+    pub fn push(&mut self, edit: T) {
+        self.redo.clear();
+        self.undo.push(edit);
+        if self.undo.len() > self.capacity {
+            self.undo.remove(0);
+        }
+    }
+
+    pub fn undo(&mut self, revert: impl FnOnce(T) -> T) -> bool {
+        Self::transfer(&mut self.undo, &mut self.redo, revert)
+    }
+
+    pub fn redo(&mut self, revert: impl FnOnce(T) -> T) -> bool {
+        Self::transfer(&mut self.redo, &mut self.undo, revert)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    fn transfer(from: &mut Vec<T>, to: &mut Vec<T>, revert: impl FnOnce(T) -> T) -> bool {
+        match from.pop() {
+            Some(edit) => {
+                to.push(revert(edit));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// Objects are listed in ascending index order
+pub enum ObjectEdit {
+    Added(Vec<(usize, ObjectPrototype)>),
+    Removed(Vec<(usize, ObjectPrototype)>),
+    // The states of the objects before the change
+    Changed(Vec<(usize, ObjectPrototype)>),
+}
+
+impl ObjectEdit {
+    #[must_use]
+    pub fn revert(self, physics: &mut PhysicsEngine) -> Self {
+        match self {
+            ObjectEdit::Added(mut objects) => {
+                for (object_index, object) in objects.iter_mut().rev() {
+                    *object = physics.remove(*object_index);
+                }
+                ObjectEdit::Removed(objects)
+            }
+            ObjectEdit::Removed(objects) => {
+                for &(object_index, object) in &objects {
+                    physics.insert(object_index, object);
+                }
+                ObjectEdit::Added(objects)
+            }
+            ObjectEdit::Changed(mut objects) => {
+                for (object_index, object) in &mut objects {
+                    let current = physics.objects().get(*object_index);
+                    physics.objects_mut().set(*object_index, mem::replace(object, current));
+                }
+                ObjectEdit::Changed(objects)
+            }
+        }
+    }
+}
+
+#[test]
+fn history_undo_redo() {
+    let mut history = History::new(2);
+    for edit in 1..=3 {
+        history.push(edit);
+    }
+    // The oldest edit is dropped when the capacity is exceeded
+    assert!(history.undo(|edit| edit * 10));
+    assert!(history.undo(|edit| edit * 10));
+    assert!(!history.undo(|edit| edit));
+    assert_eq!(history.redo, [30, 20]);
+
+    assert!(history.redo(|edit| edit / 10));
+    assert_eq!(history.undo, [2]);
+    history.push(4);
+    assert!(history.redo.is_empty());
+}
@@ -0,0 +1,57 @@
+use crate::vector2::Vector2;
+
+// Particles of the window icon: center and radius relative to the icon size, and color
+const PARTICLES: [(Vector2<f32>, f32, [f32; 3]); 7] = [
+    (Vector2::new(0.5, 0.5), 0.2, [0.95, 0.75, 0.3]),
+    (Vector2::new(0.24, 0.3), 0.14, [0.35, 0.65, 0.95]),
+    (Vector2::new(0.76, 0.28), 0.12, [0.9, 0.35, 0.3]),
+    (Vector2::new(0.8, 0.7), 0.15, [0.4, 0.85, 0.5]),
+    (Vector2::new(0.27, 0.74), 0.13, [0.75, 0.45, 0.9]),
+    (Vector2::new(0.5, 0.14), 0.08, [0.9, 0.9, 0.9]),
+    (Vector2::new(0.52, 0.87), 0.09, [0.35, 0.8, 0.85]),
+];
+
+// A cluster of shaded particles on a transparent background, as RGBA rows. The particles are antialiased by
+// their coverage of the pixel centers, later particles drawn on top.
+#[must_use]
+pub fn render_icon(size: u32) -> Vec<u8> {
+    #[allow(clippy::cast_precision_loss)]
+    let scale = size as f32;
+    let mut rgba = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            #[allow(clippy::cast_precision_loss)]
+            let pixel = Vector2::new(x as f32 + 0.5, y as f32 + 0.5) / scale;
+            let mut color = [0.0_f32; 4];
+            for (center, radius, particle_color) in PARTICLES {
+                let distance = (pixel - center).magnitude();
+                let coverage = ((radius - distance) * scale + 0.5).clamp(0.0, 1.0);
+                if coverage == 0.0 {
+                    continue;
+                }
+                // Lit from the top left
+                let highlight = 1.0 - ((pixel - (center - radius * 0.4)).magnitude() / (radius * 1.6)).min(1.0);
+                for channel in 0..3 {
+                    let shaded = particle_color[channel] * (0.6 + 0.4 * highlight) + 0.3 * highlight * highlight;
+                    color[channel] = color[channel] * (1.0 - coverage) + shaded.min(1.0) * coverage;
+                }
+                color[3] = color[3] * (1.0 - coverage) + coverage;
+            }
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            rgba.extend(color.map(|channel| (channel * 255.0).round() as u8));
+        }
+    }
+    rgba
+}
+
+#[test]
+fn icon_is_a_cluster_on_transparent_background() {
+    const SIZE: u32 = 32;
+
+    let rgba = render_icon(SIZE);
+    assert_eq!(rgba.len(), (SIZE * SIZE * 4) as usize);
+    let alpha = |x: u32, y: u32| rgba[((y * SIZE + x) * 4 + 3) as usize];
+    assert_eq!(alpha(0, 0), 0);
+    assert_eq!(alpha(SIZE - 1, SIZE - 1), 0);
+    assert_eq!(alpha(SIZE / 2, SIZE / 2), 255);
+}
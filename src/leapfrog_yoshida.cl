@@ -6,14 +6,48 @@
 #define D2 (float)(-1.7024143839193162)
 #define D3 (float)(1.351207191959658)
 
+// Same layout as Material on the host
+typedef struct {
+  float restitution_coefficient;
+  float friction;
+  float drag;
+  float gravity_scale;
+  float adhesion_speed;
+  float adhesion_force;
+} Material;
+
+// Same layout as GravityZone on the host
+typedef struct {
+  float gravity[2];
+  float min_y;
+} GravityZone;
+
+// The zones are sorted by height, the last one the position is below wins
+#pragma(inline)
+float2 zone_gravity(const float2 position, const float2 global_gravity,
+                    constant GravityZone *restrict gravity_zones,
+                    const uint gravity_zone_count) {
+  float2 gravity = global_gravity;
+  for (uint zone_index = 0; zone_index < gravity_zone_count; ++zone_index) {
+    const GravityZone zone = gravity_zones[zone_index];
+    if (position.y >= zone.min_y) {
+      gravity = (float2)(zone.gravity[0], zone.gravity[1]);
+    }
+  }
+  return gravity;
+}
+
 #pragma(inline)
 float2 gravity_acceleration(uint object_index, const float2 position,
                             const float2 global_gravity,
+                            constant GravityZone *restrict gravity_zones,
+                            const uint gravity_zone_count,
                             global const float2 *restrict positions,
                             constant float *restrict planet_masses,
                             const uint planet_count,
                             const float gravitational_constant) {
-  float2 gravity = global_gravity;
+  float2 gravity = zone_gravity(position, global_gravity, gravity_zones,
+                                gravity_zone_count);
   for (uint planet_index = 0; planet_index < planet_count; ++planet_index) {
     const float2 planet_position = positions[planet_index];
     const float planet_mass = planet_masses[planet_index];
@@ -32,29 +66,49 @@ float2 gravity_acceleration(uint object_index, const float2 position,
 
 kernel void leapfrog_yoshida(global float2 *restrict positions,
                              global float2 *restrict velocities,
+                             global const uchar *restrict frozen,
+                             global const uint *restrict object_materials,
+                             constant Material *restrict materials,
                              const uint object_count, const float dt,
                              const float2 global_gravity,
+                             constant GravityZone *restrict gravity_zones,
+                             const uint gravity_zone_count,
                              constant float *restrict planet_masses,
                              const uint planet_count,
                              const float gravitational_constant) {
   const uint object_index = get_global_id(0);
+  if (frozen[object_index]) {
+    return;
+  }
+  const Material material = materials[object_materials[object_index]];
   const float2 x0 = positions[object_index];
   const float2 v0 = velocities[object_index];
   const float2 x1 = fma(v0, C1 * dt, x0);
   const float2 a1 =
-      gravity_acceleration(object_index, x1, global_gravity, positions,
-                           planet_masses, planet_count, gravitational_constant);
-  const float2 v1 = fma(a1, D1 * dt, v0);
+      gravity_acceleration(object_index, x1, global_gravity, gravity_zones,
+                           gravity_zone_count, positions, planet_masses,
+                           planet_count, gravitational_constant);
+  const float2 v1 = fma(a1, material.gravity_scale * D1 * dt, v0);
   const float2 x2 = fma(v1, C2 * dt, x1);
   const float2 a2 =
-      gravity_acceleration(object_index, x2, global_gravity, positions,
-                           planet_masses, planet_count, gravitational_constant);
-  const float2 v2 = fma(a2, D2 * dt, v1);
+      gravity_acceleration(object_index, x2, global_gravity, gravity_zones,
+                           gravity_zone_count, positions, planet_masses,
+                           planet_count, gravitational_constant);
+  const float2 v2 = fma(a2, material.gravity_scale * D2 * dt, v1);
   const float2 x3 = fma(v2, C3 * dt, x2);
   const float2 a3 =
-      gravity_acceleration(object_index, x3, global_gravity, positions,
-                           planet_masses, planet_count, gravitational_constant);
-  const float2 v3 = fma(a3, D3 * dt, v0);
+      gravity_acceleration(object_index, x3, global_gravity, gravity_zones,
+                           gravity_zone_count, positions, planet_masses,
+                           planet_count, gravitational_constant);
+  const float2 v3 = fma(a3, material.gravity_scale * D3 * dt, v0);
   positions[object_index] = fma(v3, C4 * dt, x3);
-  velocities[object_index] = v3;
+  velocities[object_index] = v3 * exp(-material.drag * dt);
+}
+
+// Sizes of the types shared with the host, compared with the host types at
+// startup
+kernel void leapfrog_yoshida_layout(global uint *sizes) {
+  sizes[0] = sizeof(Material);
+  sizes[1] = sizeof(GravityZone);
+  sizes[2] = sizeof(float2);
 }
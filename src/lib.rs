@@ -3,15 +3,91 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::missing_panics_doc)]
 
-pub mod app_config;
-pub mod array2;
+pub mod boundary;
+pub mod broad_phase;
 pub mod bvh;
-pub mod demo;
-pub mod fixed_vec;
-pub mod fps;
+pub mod capture;
+#[cfg(feature = "gpu-opencl")]
+pub mod compute_selector;
+pub mod container;
+pub mod engine;
+pub mod event_driven;
+pub mod fluid;
+pub mod fp_exceptions;
+pub mod gamma;
+pub mod golden;
+#[cfg(feature = "gpu-opencl")]
 pub mod gpu;
+pub mod grid;
+pub mod history;
+pub mod material;
+pub mod memory_stats;
 pub mod object;
+pub mod pair_cache;
+pub mod pair_storage;
 pub mod physics;
+pub mod placement;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod snapshot;
+pub mod stress;
+#[cfg(feature = "sweep")]
+pub mod sweep;
+pub mod thermostat;
+pub mod trajectory;
+pub mod units;
+pub mod vector2;
+pub mod verlet;
+pub mod wind;
+
+#[doc(hidden)]
+pub mod array2;
+#[doc(hidden)]
+pub mod buffer_pool;
+#[doc(hidden)]
+pub mod fixed_vec;
+#[doc(hidden)]
 pub mod ring_buffer;
+
+#[cfg(feature = "app")]
+pub mod affinity;
+#[cfg(feature = "app")]
+pub mod app_config;
+#[cfg(feature = "app")]
+pub mod autosave;
+#[cfg(feature = "app")]
+pub mod camera;
+#[cfg(feature = "app")]
+pub mod cpu_usage;
+#[cfg(feature = "app")]
+pub mod crash_report;
+#[cfg(feature = "app")]
+pub mod demo;
+#[cfg(feature = "app")]
+pub mod editor;
+#[cfg(feature = "app")]
+pub mod event_bus;
+#[cfg(feature = "app")]
+pub mod fps;
+#[cfg(feature = "app")]
+pub mod icon;
+#[cfg(feature = "app")]
+pub mod locale;
+#[cfg(feature = "app")]
+pub mod menu;
+#[cfg(feature = "app")]
+pub mod mouse_spring;
+#[cfg(feature = "app")]
+pub mod plot;
+#[cfg(feature = "app")]
+pub mod quality;
+#[cfg(feature = "app")]
+pub mod ruler;
+#[cfg(feature = "render")]
 pub mod simple_text;
-pub mod vector2;
+#[cfg(feature = "app")]
+pub mod speed_ramp;
+#[cfg(feature = "app")]
+pub mod trails;
+#[cfg(feature = "app")]
+pub mod watchdog;
@@ -0,0 +1,244 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{boundary::Wall, menu::MenuItem};
+
+// Language of the overlay text, by language code
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+
+    #[serde(rename = "de")]
+    German,
+}
+
+// Words and phrases of the overlay text; numbers and punctuation are added around them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Message {
+    Fps,
+    Min,
+    Max,
+    Avg,
+    SimTime,
+    Steps,
+    At,
+    Exit,
+    Pause,
+    Speed,
+    GpuCompute,
+    Integration,
+    Bvh,
+    On,
+    Off,
+    Auto,
+    Objects,
+    CollisionEvents,
+    For,
+    LimitReached,
+    MortonCodesChanged,
+    Allocations,
+    Live,
+    BufferPools,
+    Reuses,
+    GpuBuffers,
+    Peak,
+    Collision,
+    Constraints,
+    Total,
+    PairCacheHits,
+    VerletLists,
+    Skin,
+    Rebuilds,
+    Depth,
+    Overlap,
+    SahCost,
+    CandidateFalsePositives,
+    PairStorage,
+    EnergyChange,
+    Collisions,
+    Correction,
+    Temperature,
+    FluidDensityError,
+    Attachments,
+    Deferred,
+    Dropped,
+    LeftWall,
+    RightWall,
+    TopWall,
+    BottomWall,
+    Out,
+    In,
+    MassFlux,
+    AngularMomentum,
+    Drift,
+    Drifting,
+    GpuDivergence,
+    Position,
+    Velocity,
+    Pairs,
+    Missing,
+    Extra,
+    Heat,
+    NotResponding,
+    StallHint,
+    Paused,
+    Resume,
+    ResetScene,
+    SaveSnapshot,
+    LoadSnapshot,
+    ToggleOverlays,
+    Quit,
+    Stats,
+    Histogram,
+    SceneBuild,
+    Render,
+    Present,
+    Cpu,
+    Simulation,
+    Rendering,
+    Field,
+    Process,
+    GoToObject,
+    Object,
+    Radius,
+    Mass,
+    Frozen,
+}
+
+impl Message {
+    #[must_use]
+    pub fn wall(wall: Wall) -> Self {
+        match wall {
+            Wall::Left => Message::LeftWall,
+            Wall::Right => Message::RightWall,
+            Wall::Top => Message::TopWall,
+            Wall::Bottom => Message::BottomWall,
+        }
+    }
+
+    #[must_use]
+    pub fn menu_item(item: MenuItem) -> Self {
+        match item {
+            MenuItem::Resume => Message::Resume,
+            MenuItem::ResetScene => Message::ResetScene,
+            MenuItem::SaveSnapshot => Message::SaveSnapshot,
+            MenuItem::LoadSnapshot => Message::LoadSnapshot,
+            MenuItem::ToggleOverlays => Message::ToggleOverlays,
+            MenuItem::Quit => Message::Quit,
+        }
+    }
+
+    #[must_use]
+    pub fn text(self, language: Language) -> &'static str {
+        // In the order of the languages
+        let translations: [&str; 2] = match self {
+            Message::Fps => ["FPS", "FPS"],
+            Message::Min => ["min", "min"],
+            Message::Max => ["max", "max"],
+            Message::Avg => ["avg", "Mittel"],
+            Message::SimTime => ["sim time", "Simulationszeit"],
+            Message::Steps => ["steps", "Schritte"],
+            Message::At => ["at", "bei"],
+            Message::Exit => ["exit", "beenden"],
+            Message::Pause => ["pause", "pausieren"],
+            Message::Speed => ["speed", "Geschwindigkeit"],
+            Message::GpuCompute => ["gpu compute", "GPU-Berechnung"],
+            Message::Integration => ["integration", "Integration"],
+            Message::Bvh => ["bvh", "BVH"],
+            Message::On => ["on", "an"],
+            Message::Off => ["off", "aus"],
+            Message::Auto => ["auto", "automatisch"],
+            Message::Objects => ["objects", "Objekte"],
+            Message::CollisionEvents => ["collision events", "Kollisionsereignisse"],
+            Message::For => ["for", "für"],
+            Message::LimitReached => ["limit reached", "Limit erreicht"],
+            Message::MortonCodesChanged => ["morton codes changed", "geänderte Morton-Codes"],
+            Message::Allocations => ["allocations", "Allokationen"],
+            Message::Live => ["live", "belegt"],
+            Message::BufferPools => ["buffer pools", "Pufferpools"],
+            Message::Reuses => ["reuses", "Wiederverwendungen"],
+            Message::GpuBuffers => ["gpu buffers", "GPU-Puffer"],
+            Message::Peak => ["peak", "Spitze"],
+            Message::Collision => ["collision", "Kollision"],
+            Message::Constraints => ["constraints", "Randbedingungen"],
+            Message::Total => ["total", "gesamt"],
+            Message::PairCacheHits => ["pair cache hits", "Paar-Cache-Treffer"],
+            Message::EnergyChange => ["energy change", "Energieänderung"],
+            Message::Collisions => ["collisions", "Kollisionen"],
+            Message::Correction => ["correction", "Korrektur"],
+            Message::Temperature => ["temperature", "Temperatur"],
+            Message::FluidDensityError => ["fluid density error", "Dichtefehler der Flüssigkeit"],
+            Message::Attachments => ["attachments", "Haftungen"],
+            Message::Deferred => ["deferred", "aufgeschoben"],
+            Message::Dropped => ["dropped", "verworfen"],
+            Message::LeftWall => ["left wall", "linke Wand"],
+            Message::RightWall => ["right wall", "rechte Wand"],
+            Message::TopWall => ["top wall", "obere Wand"],
+            Message::BottomWall => ["bottom wall", "untere Wand"],
+            Message::Out => ["out", "hinaus"],
+            Message::In => ["in", "herein"],
+            Message::MassFlux => ["mass flux", "Massenfluss"],
+            Message::AngularMomentum => ["angular momentum", "Drehimpuls"],
+            Message::Drift => ["drift", "Drift"],
+            Message::Drifting => ["DRIFTING", "DRIFTET"],
+            Message::GpuDivergence => ["gpu divergence", "GPU-Abweichung"],
+            Message::Position => ["position", "Position"],
+            Message::Velocity => ["velocity", "Geschwindigkeit"],
+            Message::Pairs => ["pairs", "Paare"],
+            Message::Missing => ["missing", "fehlend"],
+            Message::Extra => ["extra", "überzählig"],
+            Message::Heat => ["heat", "Wärme"],
+            Message::NotResponding => ["Simulation not responding for", "Simulation reagiert nicht seit"],
+            Message::StallHint => [
+                "O: turn GPU compute off once it recovers, K: abort",
+                "O: GPU-Berechnung nach der Erholung ausschalten, K: abbrechen",
+            ],
+            Message::Paused => ["Paused", "Pausiert"],
+            Message::Resume => ["Resume", "Fortsetzen"],
+            Message::ResetScene => ["Reset scene", "Szene zurücksetzen"],
+            Message::SaveSnapshot => ["Save snapshot", "Schnappschuss speichern"],
+            Message::LoadSnapshot => ["Load snapshot", "Schnappschuss laden"],
+            Message::ToggleOverlays => ["Toggle overlays", "Einblendungen umschalten"],
+            Message::Quit => ["Quit", "Beenden"],
+            Message::Stats => ["stats", "Statistik"],
+            Message::Histogram => ["histogram", "Histogramm"],
+            Message::SceneBuild => ["scene build", "Szenenaufbau"],
+            Message::Render => ["render", "Rendern"],
+            Message::Present => ["present", "Darstellung"],
+            Message::Cpu => ["cpu", "CPU"],
+            Message::Simulation => ["simulation", "Simulation"],
+            Message::Rendering => ["rendering", "Rendering"],
+            Message::Field => ["field", "Feld"],
+            Message::Process => ["process", "Prozess"],
+            Message::GoToObject => ["go to object", "gehe zu Objekt"],
+            Message::Object => ["object", "Objekt"],
+            Message::Radius => ["radius", "Radius"],
+            Message::Mass => ["mass", "Masse"],
+            Message::Frozen => ["frozen", "eingefroren"],
+            Message::VerletLists => ["verlet lists", "Verlet-Listen"],
+            Message::Skin => ["skin", "Hülle"],
+            Message::Rebuilds => ["rebuilds", "Neuaufbauten"],
+            Message::Depth => ["depth", "Tiefe"],
+            Message::Overlap => ["overlap", "Überlappung"],
+            Message::SahCost => ["SAH cost", "SAH-Kosten"],
+            Message::CandidateFalsePositives => ["candidate false positives", "falsch positive Kandidaten"],
+            Message::PairStorage => ["pair storage", "Paarspeicher"],
+        };
+        translations[language as usize]
+    }
+}
+
+#[test]
+fn language_is_selected_by_code() {
+    #[derive(Deserialize)]
+    struct Ui {
+        language: Language,
+    }
+
+    let ui: Ui = toml::from_str("language = \"de\"").unwrap();
+    assert_eq!(ui.language, Language::German);
+    assert_eq!(Message::wall(Wall::Top).text(ui.language), "obere Wand");
+    assert_eq!(Message::Steps.text(Language::default()), "steps");
+    assert!(toml::from_str::<Ui>("language = \"xx\"").is_err());
+}
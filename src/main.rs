@@ -6,38 +6,82 @@
 // TODO black holes
 
 use std::{
+    array, env,
+    f32::consts::PI,
     fmt::{self, Debug, Write},
-    iter::zip,
+    fs::{self, File},
+    io::BufWriter,
+    iter::{once, zip},
     num::NonZero,
     ops::{Add, Range},
-    sync::{Arc, Barrier, Mutex, mpsc},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Barrier, Mutex},
     thread::{self, yield_now},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow, bail};
+#[cfg(feature = "scripting")]
+use collision::scripting::Script;
 use collision::{
-    app_config::{CONFIG, ColorSource, TimeLimitAction},
+    affinity,
+    app_config::{Background, CONFIG, ColorSource, DepthSort, OverlayCorner, TimeLimitAction},
     array2::Array2,
-    bvh::{AABB, Bvh, Node},
-    demo::create_demo,
+    autosave::{self, Autosave},
+    boundary::Wall,
+    buffer_pool::{BufferPool, BufferPoolStats},
+    bvh::{AABB, Bvh, BvhMetrics, Node},
+    camera::Camera,
+    capture::CaptureWriter,
+    compute_selector::GpuComputeSelector,
+    cpu_usage::{CpuMeter, CpuUtilization},
+    crash_report,
+    demo::{SceneFile, create_demo},
+    editor::{Editor, EditorTool, SceneItem},
+    event_bus::{Backpressure, Topic},
+    event_driven::EventDrivenStats,
+    fp_exceptions,
     fps::FpsCalculator,
+    gpu::{self, GPU, GpuBufferInfo},
+    history::{History, ObjectEdit},
+    icon::render_icon,
+    locale::Message,
+    memory_stats::{CountingAllocator, memory_stats},
+    menu::{MenuItem, PauseMenu},
+    mouse_spring::MouseSpring,
     object::ObjectSoa,
-    physics::{DurationStat, GpuComputeOptions, PhysicsEngine, Stats},
+    physics::{
+        AngularMomentum, DurationStat, GpuComputeOptions, GpuValidation, PhysicsEngine, PhysicsSettings, Stats,
+        VerletListStats,
+    },
+    plot::{Histogram, Series},
+    quality::{Quality, QualityController},
+    ruler,
     simple_text::SimpleText,
+    speed_ramp::SpeedRamp,
+    stress::create_stress_scene,
+    trails::Trails,
+    trajectory::TrajectoryRecorder,
     vector2::Vector2,
+    watchdog::Heartbeat,
+    wind::Wind,
 };
-use crossbeam::queue::{ArrayQueue, SegQueue};
 use itertools::Itertools;
 use pollster::block_on;
 use rayon::{
-    ThreadPoolBuilder,
+    ThreadPool, ThreadPoolBuilder,
     iter::{IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator},
+    slice::ParallelSlice,
 };
 use vello::{
     AaConfig, AaSupport, RenderParams, Renderer, RendererOptions, Scene,
-    kurbo::{Affine, Circle, Rect, Stroke},
-    peniko::{Blob, Color, Fill, Image, ImageFormat, color::palette::css},
+    kurbo::{self, Affine, Circle, Rect, Stroke},
+    peniko::{
+        BlendMode, Blob, Color, Compose, Fill, Gradient, Image, ImageFormat, Mix,
+        color::{Rgba8, palette::css},
+    },
     util::{DeviceHandle, RenderContext, RenderSurface},
     wgpu::{self, Maintain, PresentMode},
 };
@@ -46,16 +90,76 @@ use winit::{
     dpi::PhysicalSize,
     event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
-    keyboard::{Key, NamedKey},
-    window::{Window, WindowId},
+    keyboard::{Key, ModifiersState, NamedKey},
+    window::{Icon, Window, WindowAttributes, WindowId},
 };
 
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+static EDF_VECTOR_BUFFERS: BufferPool<Vector2<f32>> = BufferPool::new();
+static EDF_SCALAR_BUFFERS: BufferPool<f32> = BufferPool::new();
+static DRAW_ORDER_BUFFERS: BufferPool<usize> = BufferPool::new();
+
+// Multipliers of the simulation speed factor selected with [ and ]
+const SPEED_MULTIPLIERS: [f32; 5] = [0.1, 0.5, 1.0, 2.0, 5.0];
+const NORMAL_SPEED_INDEX: usize = 2;
+// Saved and loaded from the pause menu
+const SESSION_SNAPSHOT_PATH: &str = "session.snapshot";
+// The cursor coordinates are drawn inside the rulers
+const HORIZONTAL_RULER_HEIGHT: f64 = 20.0;
+// Wide enough for the labels
+const VERTICAL_RULER_WIDTH: f64 = 48.0;
+// Half the side of the square that objects smaller than a pixel are drawn as, in pixels
+const SPLAT_SCREEN_RADIUS: f32 = 1.0;
+// How often the simulation thread checks for events while paused in the background
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 pub fn main() -> anyhow::Result<()> {
-    // _enable_floating_point_exceptions();
+    let mut resume_last = false;
+    let mut print_config = false;
+    let mut headless = !CONFIG.rendering.enabled;
+    // Particle count of the stress scene, which replaces the demo
+    let mut stress = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--resume-last" => resume_last = true,
+            "--headless" => headless = true,
+            "--print-config" => print_config = true,
+            "--clear-kernel-cache" => gpu::clear_kernel_cache()?,
+            "--stress" => {
+                let count = args.next().context("missing value for --stress")?;
+                stress = Some(count.parse::<usize>().with_context(|| format!("invalid particle count \"{count}\""))?);
+            }
+            _ => bail!("unknown argument \"{arg}\""),
+        }
+    }
+    if print_config {
+        // With the defaults filled in and the command line applied
+        let mut config = CONFIG.clone();
+        config.rendering.enabled = !headless;
+        print!("{}", config.to_toml()?);
+        return Ok(());
+    }
+    let autosave_directory = Path::new(&CONFIG.autosave.directory);
+    crash_report::install_panic_hook(autosave_directory.to_path_buf());
+    let resume_path = if resume_last {
+        let path = autosave::latest(autosave_directory)?.context("no autosave to resume from")?;
+        println!("Resuming from \"{}\"", path.display());
+        Some(path)
+    } else {
+        if let Some(path) = autosave::latest(autosave_directory)? {
+            println!("Found autosave \"{}\", run with --resume-last to continue from it", path.display());
+        }
+        None
+    };
+    if headless {
+        return run_headless(resume_path, stress);
+    }
+
     let event_loop = EventLoop::with_user_event().build()?;
-    let (simulation_event_sender, simulation_event_receiver) = mpsc::channel();
-    let rendering_event_queue = Box::leak(Box::new(SegQueue::new()));
-    let rendering_event_queue = &*rendering_event_queue;
+    let bus = &*Box::leak(Box::new(EventBus::new(event_loop.create_proxy())));
     let sim_total_duration = Arc::new(Mutex::new(Duration::ZERO));
     let ready_to_exit = Arc::new(Barrier::new(3));
     let gpu_compute_options = GpuComputeOptions {
@@ -63,42 +167,38 @@ pub fn main() -> anyhow::Result<()> {
         bvh: CONFIG.simulation.gpu_bvh,
     };
     let rendering_thread_ready = Arc::new(Barrier::new(3));
-    let (rendering_result_sender, rendering_result_receiver) = mpsc::channel();
+    let simulation_heartbeat = Arc::new(Heartbeat::new());
+    let cpu_utilizations = Arc::new(CpuUtilizations::default());
+    {
+        let simulation_heartbeat = simulation_heartbeat.clone();
+        thread::spawn(move || watchdog_thread(&simulation_heartbeat, bus));
+    }
     let simulation_thread = {
+        let simulation_heartbeat = simulation_heartbeat.clone();
+        let cpu_utilizations = cpu_utilizations.clone();
         let sim_total_duration = sim_total_duration.clone();
         let ready_to_exit = ready_to_exit.clone();
-        let app_event_loop_proxy = event_loop.create_proxy();
         let rendering_thread_ready = rendering_thread_ready.clone();
         thread::spawn(move || -> PhysicsEngine {
             simulation_thread(
                 &sim_total_duration,
-                &app_event_loop_proxy,
-                &simulation_event_receiver,
+                bus,
                 &ready_to_exit,
                 gpu_compute_options,
-                rendering_event_queue,
                 &rendering_thread_ready,
-                &rendering_result_receiver,
+                resume_path,
+                stress,
+                &simulation_heartbeat,
+                &cpu_utilizations,
             )
         })
     };
-    let redraw_job_queue = Box::leak(Box::new(ArrayQueue::new(1)));
-    let redraw_job_queue = &*redraw_job_queue;
-    let redraw_result_queue = Box::leak(Box::new(ArrayQueue::new(1)));
-    let redraw_result_queue = &*redraw_result_queue;
     let rendering_thread = {
         let ready_to_exit = ready_to_exit.clone();
+        let cpu_utilizations = cpu_utilizations.clone();
         let rendering_thread_ready = rendering_thread_ready.clone();
-        let app_event_loop_proxy = event_loop.create_proxy();
         thread::spawn(move || {
-            rendering_thread(
-                rendering_event_queue,
-                &ready_to_exit,
-                &rendering_thread_ready,
-                &rendering_result_sender,
-                redraw_job_queue,
-                &app_event_loop_proxy,
-            );
+            rendering_thread(bus, &ready_to_exit, &rendering_thread_ready, &cpu_utilizations);
         })
     };
     let render_context = RenderContext::new();
@@ -109,23 +209,62 @@ pub fn main() -> anyhow::Result<()> {
         renderers,
         state: render_state,
         cached_window: None,
+        stats_window: None,
+        open_stats_window_on_resume: CONFIG.window.stats_window,
+        stats_scene: Scene::new(),
+        stats_plots: StatsPlots::new(),
         scene: Scene::new(),
         simulation_scene: Scene::new(),
         frame_count: 0,
-        fps_calculator: FpsCalculator::default(),
+        fps_calculator: FpsCalculator::new(Duration::from_millis(CONFIG.stats.fps_period_ms)),
+        frame_stats: FrameStats::new(CONFIG.stats.window_frames),
+        cpu_utilizations,
+        process_cpu_meter: CpuMeter::process(CpuMeter::DEFAULT_PERIOD),
         last_fps: 0,
         min_fps: usize::MAX,
         mouse_position: Vector2::new(0.0, 0.0),
         mouse_influence_radius: 50.0,
         text: SimpleText::new(),
-        simulation_event_sender,
-        rendering_event_queue,
+        bus,
         stats: Stats::default(),
         ready_to_exit,
         gpu_compute_options,
-        redraw_job_queue,
-        redraw_result_queue,
-        rendering_enabled: CONFIG.rendering.enabled,
+        rendering_enabled: true,
+        camera: Camera::new(Vector2::new(CONFIG.window.width as f32, CONFIG.window.height as f32)),
+        auto_gpu_compute: CONFIG.simulation.auto_gpu_compute,
+        speed_multiplier_index: NORMAL_SPEED_INDEX,
+        simulation_heartbeat,
+        auto_camera: CONFIG.rendering.auto_camera.enabled,
+        following: false,
+        modifiers: ModifiersState::default(),
+        panning: false,
+        grabbing: false,
+        high_contrast: CONFIG.rendering.high_contrast,
+        paused: !CONFIG.simulation.auto_start,
+        pause_menu: PauseMenu::new(),
+        show_overlays: true,
+        show_rulers: CONFIG.rendering.show_rulers,
+        overlay_scale: overlay_scale(1.0),
+        focused: true,
+        minimized: false,
+        background: false,
+        quality_controller: CONFIG.rendering.adaptive_quality.target_fps.map(|target_fps| {
+            QualityController::new(
+                target_fps,
+                CONFIG.rendering.adaptive_quality.headroom,
+                CONFIG.rendering.point_sprite_radius,
+            )
+        }),
+        edit_mode: false,
+        id_input: None,
+        editor: Editor::new(
+            editor_scene()?,
+            CONFIG.units,
+            CONFIG.editor.particle_radius,
+            CONFIG.editor.particle_spacing,
+            CONFIG.editor.particle_mass,
+        ),
+        history: History::new(CONFIG.editor.undo_limit),
     };
 
     rendering_thread_ready.wait();
@@ -134,77 +273,245 @@ pub fn main() -> anyhow::Result<()> {
 
     rendering_thread.join().expect("failed to join rendering thread");
     let physics = simulation_thread.join().expect("failed to join simulation thread");
-    let mut stats_buffer = String::new();
-    write_stats(&mut stats_buffer, (app.last_fps, app.min_fps), physics.stats(), app.gpu_compute_options)?;
-    print!("{stats_buffer}");
-    let sim_total_duration_guard = sim_total_duration.lock().unwrap();
-    println!("total simulation duration: {:?}", *sim_total_duration_guard);
-    if *sim_total_duration_guard > Duration::ZERO {
-        println!("relative speed: {}", physics.time() / sim_total_duration_guard.as_secs_f32());
-    }
+    print_final_stats(
+        &physics,
+        (app.last_fps, app.min_fps),
+        &app.frame_stats,
+        &app.cpu_utilizations,
+        app.gpu_compute_options,
+        app.auto_gpu_compute,
+        SPEED_MULTIPLIERS[app.speed_multiplier_index],
+        *sim_total_duration.lock().unwrap(),
+    )?;
     println!("Total app running duration: {:?}", start.elapsed());
 
     Ok(())
 }
 
-#[allow(unused)]
-fn enable_floating_point_exceptions() {
-    unsafe extern "C" {
-        fn feenableexcept(excepts: i32) -> i32;
+// Runs the simulation in the main thread without a window, as fast as it goes, until the time or the step limit is
+// reached, whatever the limit action is
+fn run_headless(resume_path: Option<PathBuf>, stress: Option<usize>) -> anyhow::Result<()> {
+    if CONFIG.simulation.time_limit.is_none() && CONFIG.simulation.step_limit.is_none() {
+        bail!("headless mode needs simulation.time_limit or simulation.step_limit");
+    }
+    let start = Instant::now();
+    configure_simulation_thread();
+    let physics_settings = PhysicsSettings {
+        thread_pool: Some(Arc::new(build_physics_thread_pool())),
+        ..CONFIG.physics_settings()
+    };
+    let mut physics = match resume_path {
+        Some(path) => resume_physics(&path, physics_settings)?,
+        None => create_physics(create_scene(stress, physics_settings.constraints), physics_settings),
+    };
+    println!("{} objects", physics.objects().len());
+    #[cfg(feature = "scripting")]
+    let mut script = CONFIG.simulation.script.as_ref().map(|path| Script::load(Path::new(path)).unwrap());
+    let (mut autosave, mut capture) = (create_autosave(), create_capture());
+    let mut gpu_compute_options = GpuComputeOptions {
+        integration: CONFIG.simulation.gpu_integration,
+        bvh: CONFIG.simulation.gpu_bvh,
+    };
+    let mut gpu_compute_selector = GpuComputeSelector::new(gpu_compute_options);
+    let cpu_utilizations = CpuUtilizations::default();
+    let mut cpu_meter = CpuMeter::thread(CpuMeter::DEFAULT_PERIOD);
+    let mut sim_total_duration = Duration::ZERO;
+    loop {
+        if CONFIG.simulation.time_limit.is_some_and(|limit| physics.time() > CONFIG.units.time(limit)) {
+            println!("Time limit reached");
+            break;
+        }
+        if CONFIG.simulation.step_limit.is_some_and(|limit| physics.stats().step_count >= limit) {
+            println!("Step limit reached");
+            break;
+        }
+        if CONFIG.simulation.auto_gpu_compute
+            && physics.stats().step_count.is_multiple_of(CONFIG.simulation.auto_gpu_compute_period)
+            && physics.objects().len() > 0
+            && !physics.gpu_failed()
+            && let Ok(timings) = physics.measure_compute_timings(physics.last_dt())
+        {
+            gpu_compute_options = gpu_compute_selector.update(timings);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script_to_run) = &mut script
+            && let Err(e) = script_to_run.step(&mut physics)
+        {
+            eprintln!("{e:#}, disabling the script");
+            script = None;
+        }
+        let step_start = Instant::now();
+        advance_or_save_crash_snapshot(&mut physics, CONFIG.simulation.speed_factor, gpu_compute_options);
+        sim_total_duration += step_start.elapsed();
+        cpu_meter.sample(&cpu_utilizations.simulation);
+        record_step(&physics, &mut autosave, &mut capture);
+    }
+    print_final_stats(
+        &physics,
+        (0, 0),
+        &FrameStats::new(CONFIG.stats.window_frames),
+        &cpu_utilizations,
+        gpu_compute_options,
+        CONFIG.simulation.auto_gpu_compute,
+        SPEED_MULTIPLIERS[NORMAL_SPEED_INDEX],
+        sim_total_duration,
+    )?;
+    println!("Total app running duration: {:?}", start.elapsed());
+    Ok(())
+}
+
+// Printed on exit, followed by the trajectories if they are recorded
+fn print_final_stats(
+    physics: &PhysicsEngine,
+    fps: (usize, usize),
+    frame_stats: &FrameStats,
+    cpu_utilizations: &CpuUtilizations,
+    gpu_compute_options: GpuComputeOptions,
+    auto_gpu_compute: bool,
+    speed_multiplier: f32,
+    sim_total_duration: Duration,
+) -> anyhow::Result<()> {
+    let mut stats_buffer = String::new();
+    write_stats(
+        &mut stats_buffer,
+        fps,
+        physics.stats(),
+        frame_stats,
+        cpu_utilizations,
+        gpu_compute_options,
+        auto_gpu_compute,
+        speed_multiplier,
+    )?;
+    print!("{stats_buffer}");
+    if let Some(trajectories) = &CONFIG.simulation.trajectories
+        && let Some(trajectory_recorder) = physics.trajectory_recorder()
+    {
+        fs::write(&trajectories.path, trajectory_recorder.to_csv())
+            .with_context(|| format!("write \"{}\"", trajectories.path))?;
+        println!("Trajectories written to \"{}\"", trajectories.path);
     }
-    unsafe {
-        feenableexcept(1);
+    println!("total simulation duration: {sim_total_duration:?}");
+    if sim_total_duration > Duration::ZERO {
+        println!("relative speed: {}", physics.time() / sim_total_duration.as_secs_f32());
     }
+    Ok(())
 }
 
 fn simulation_thread(
     sim_total_duration: &Arc<Mutex<Duration>>,
-    app_event_loop_proxy: &EventLoopProxy<AppEvent>,
-    simulation_event_receiver: &mpsc::Receiver<SimulationThreadEvent>,
+    bus: &EventBus,
     ready_to_exit: &Arc<Barrier>,
     mut gpu_compute_options: GpuComputeOptions,
-    rendering_event_queue: &SegQueue<RenderingThreadEvent>,
     rendering_thread_ready: &Arc<Barrier>,
-    rendering_result_receiver: &mpsc::Receiver<()>,
+    resume_path: Option<PathBuf>,
+    stress: Option<usize>,
+    heartbeat: &Heartbeat,
+    cpu_utilizations: &Arc<CpuUtilizations>,
 ) -> PhysicsEngine {
+    // Size of an EDF cell on screen, in pixels
     const EDF_CELL_SIZE: f32 = 4.0;
     const EDF_SAMPLING_AREA_SIZE: usize = 3;
 
-    let energy_field_job_queue = Box::leak(Box::new(ArrayQueue::new(1)));
-    let edf_job_queue = &*energy_field_job_queue;
-    let edf_result_queue = Box::leak(Box::new(ArrayQueue::new(1)));
-    let edf_result_queue = &*edf_result_queue;
+    // Private to the simulation thread and its field thread
+    let edf_jobs = &*Box::leak(Box::new(Topic::new(Backpressure::Latest)));
+    let edf_results = &*Box::leak(Box::new(Topic::new(Backpressure::Latest)));
     let edf_ready = Arc::new(Barrier::new(2));
 
+    configure_simulation_thread();
+    let physics_thread_pool = Arc::new(build_physics_thread_pool());
+    let field_thread_count = CONFIG.threads.field_thread_count();
+    let field_thread_pool = if field_thread_count == physics_thread_pool.current_num_threads() {
+        physics_thread_pool.clone()
+    } else {
+        Arc::new(ThreadPoolBuilder::new().num_threads(field_thread_count).build().unwrap())
+    };
     {
         let edf_thread_ready = edf_ready.clone();
-        thread::spawn(move || energy_density_field_thread(edf_thread_ready, edf_job_queue, edf_result_queue));
+        let cpu_utilizations = cpu_utilizations.clone();
+        thread::spawn(move || {
+            energy_density_field_thread(
+                edf_thread_ready,
+                edf_jobs,
+                edf_results,
+                &field_thread_pool,
+                &cpu_utilizations.field,
+            );
+        });
     }
+    let render_scene_thread_pool =
+        if CONFIG.threads.render_scene_thread_count() == physics_thread_pool.current_num_threads() {
+            physics_thread_pool.clone()
+        } else {
+            Arc::new(ThreadPoolBuilder::new().num_threads(CONFIG.threads.render_scene_thread_count()).build().unwrap())
+        };
+    bus.render_data.publish(RenderingThreadEvent::SetThreadPool(render_scene_thread_pool));
 
     let mut advance_time = CONFIG.simulation.auto_start;
+    // Restored when the edit mode, which pauses the simulation, is left
+    let mut advance_time_before_editing = advance_time;
+    // Mirrors the history of the app: every interactive edit pushes an entry to both
+    let mut history = History::new(CONFIG.editor.undo_limit);
     let mut time_limit_action_executed = false;
+    let mut step_limit_action_executed = false;
     let mut draw_aabbs = false;
     let mut draw_ids = false;
-    let mut objects = ObjectSoa::default();
-    create_demo(&mut objects);
-    println!("{} objects", objects.len());
-    let mut physics = PhysicsEngine::new(objects).unwrap();
+    let physics_settings = PhysicsSettings {
+        thread_pool: Some(physics_thread_pool),
+        ..CONFIG.physics_settings()
+    };
+    // Also used to reset the scene, from the pause menu
+    let create_scene = || create_scene(stress, physics_settings.constraints);
+    // Also used to replace the scene, from the pause menu
+    let create_physics = |objects: ObjectSoa| create_physics(objects, physics_settings.clone());
+    let resume_physics = |path: &Path| resume_physics(path, physics_settings.clone());
+    let mut physics = match resume_path.map(|path| resume_physics(&path)) {
+        Some(Ok(physics)) => physics,
+        Some(Err(e)) => {
+            eprintln!("Failed to resume: {e:#}");
+            create_physics(create_scene())
+        }
+        None => create_physics(create_scene()),
+    };
+    println!("{} objects", physics.objects().len());
+    #[cfg(feature = "scripting")]
+    let mut script = CONFIG.simulation.script.as_ref().map(|path| Script::load(Path::new(path)).unwrap());
+    let (mut autosave, mut capture) = (create_autosave(), create_capture());
     let mut color_source = CONFIG.rendering.color_source;
     let mut redraw_needed = false;
     let mut last_redraw_instant = Instant::now();
     let mut show_edf = CONFIG.rendering.show_edf;
+    let mut show_wind = CONFIG.rendering.show_wind;
+    let mut additive_blending = CONFIG.rendering.additive_blending;
+    let mut draw_planet_vectors = CONFIG.rendering.planets.show_vectors;
+    let mut high_contrast = CONFIG.rendering.high_contrast;
+    let mut reduced_motion = CONFIG.rendering.reduced_motion;
+    physics.set_track_accelerations(draw_planet_vectors);
+    let mut quality = Quality::new(0, CONFIG.rendering.point_sprite_radius);
     rendering_thread_ready.wait();
     edf_ready.wait();
     let mut first_redraw = true;
-    let mut edf = Array2::default();
+    let mut edf = EnergyDensityField::default();
+    let mut camera = Camera::new(Vector2::new(CONFIG.window.width as f32, CONFIG.window.height as f32));
+    let mut auto_gpu_compute = CONFIG.simulation.auto_gpu_compute;
+    let mut auto_camera = CONFIG.rendering.auto_camera.enabled;
+    let mut speed_ramp = SpeedRamp::new(1.0, Duration::from_secs_f32(CONFIG.simulation.speed_ramp_time));
+    // Object kept in the center of the view
+    let mut followed = None;
+    let mut inspected = None;
+    let mut mouse_spring: Option<MouseSpring> = None;
+    // Engine with a scene that replaces the current one
+    let mut new_scene = None;
+    // Whether the app has been told that the simulation is paused
+    let mut advance_time_reported = advance_time;
+    // The window is unfocused or minimized
+    let mut background = false;
+    let mut gpu_compute_selector = GpuComputeSelector::new(gpu_compute_options);
+    let mut cpu_meter = CpuMeter::thread(CpuMeter::DEFAULT_PERIOD);
     'main_loop: loop {
-        fn send_app_event(event_loop_proxy: &EventLoopProxy<AppEvent>, event: AppEvent) {
-            let _ = event_loop_proxy
-                .send_event(event.clone())
-                .map_err(|e| eprintln!("Failed to send event {event:?}: {}", &e));
-        }
+        cpu_meter.sample(&cpu_utilizations.simulation);
+        heartbeat.beat();
 
-        while let Result::Ok(event) = simulation_event_receiver.try_recv() {
+        while let Some(event) = bus.sim_control.take() {
             match event {
                 SimulationThreadEvent::Exit => {
                     ready_to_exit.wait();
@@ -227,91 +534,578 @@ fn simulation_thread(
                     show_edf = !show_edf;
                     redraw_needed = true;
                 }
-                SimulationThreadEvent::SetGpuComputeOptions(options) => gpu_compute_options = options,
-                SimulationThreadEvent::UnidirectionalKick {
+                SimulationThreadEvent::ToggleDrawWind => {
+                    show_wind = !show_wind;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::ToggleAdditiveBlending => {
+                    additive_blending = !additive_blending;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::ToggleHighContrast => {
+                    high_contrast = !high_contrast;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::ToggleReducedMotion => {
+                    reduced_motion = !reduced_motion;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::ToggleDrawPlanetVectors => {
+                    draw_planet_vectors = !draw_planet_vectors;
+                    physics.set_track_accelerations(draw_planet_vectors);
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::SetQuality(new_quality) => {
+                    quality = new_quality;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::SetGpuComputeOptions(options) => {
+                    gpu_compute_options = options;
+                    auto_gpu_compute = false;
+                }
+                SimulationThreadEvent::SetAutoGpuCompute(enabled) => {
+                    auto_gpu_compute = enabled;
+                    gpu_compute_selector = GpuComputeSelector::new(gpu_compute_options);
+                }
+                SimulationThreadEvent::SetCamera(new_camera) => {
+                    camera = new_camera;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::SetAutoCamera(enabled) => {
+                    auto_camera = enabled;
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::Follow {
+                    mouse_position,
+                    mouse_influence_radius,
+                } => {
+                    followed = nearest_object(&physics, mouse_position, mouse_influence_radius);
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::StopFollowing => followed = None,
+                SimulationThreadEvent::Inspect(object_index) => {
+                    if let Some(object_index) = object_index
+                        && object_index >= physics.objects().len()
+                    {
+                        eprintln!("no object with index {object_index}");
+                    } else {
+                        inspected = object_index;
+                        followed = object_index.or(followed);
+                        redraw_needed = true;
+                    }
+                }
+                SimulationThreadEvent::SetSpeedMultiplier(multiplier) => {
+                    speed_ramp.set_target(multiplier, Instant::now());
+                }
+                SimulationThreadEvent::Grab {
+                    mouse_position,
+                    mouse_influence_radius,
+                    nearest_only,
+                } => {
+                    let units = &CONFIG.units;
+                    let stiffness = units.rate(units.rate(CONFIG.mouse.spring_stiffness));
+                    let damping = units.rate(CONFIG.mouse.spring_damping);
+                    mouse_spring = if nearest_only {
+                        nearest_object(&physics, mouse_position, mouse_influence_radius)
+                            .map(|object_index| MouseSpring::pin(object_index, mouse_position, stiffness, damping))
+                    } else {
+                        Some(MouseSpring::grab(
+                            physics.objects(),
+                            mouse_position,
+                            mouse_influence_radius,
+                            stiffness,
+                            damping,
+                        ))
+                        .filter(|mouse_spring| !mouse_spring.is_empty())
+                    };
+                }
+                SimulationThreadEvent::MoveGrab(mouse_position) => {
+                    if let Some(mouse_spring) = &mut mouse_spring {
+                        mouse_spring.move_anchor(mouse_position);
+                    }
+                }
+                SimulationThreadEvent::Release => mouse_spring = None,
+                SimulationThreadEvent::Kick {
+                    mouse_position,
+                    mouse_influence_radius,
+                    speed,
+                } => {
+                    for object_index in physics.objects_near(mouse_position, mouse_influence_radius) {
+                        let objects = physics.objects();
+                        let offset = objects.positions[object_index] - mouse_position;
+                        let distance = offset.magnitude();
+                        // Nothing to push away from at the cursor itself
+                        if distance < mouse_influence_radius && distance > 0.0 {
+                            let falloff = 1.0 - distance / mouse_influence_radius;
+                            let impulse = offset / distance * (speed * falloff * objects.masses[object_index]);
+                            physics.apply_impulse(object_index, impulse);
+                        }
+                    }
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::Freeze {
                     mouse_position,
                     mouse_influence_radius,
+                    is_frozen,
                 } => {
                     let objects = physics.objects_mut();
-                    for (&position, velocity) in zip(&objects.positions, &mut objects.velocities) {
-                        let from_mouse_to_object = position - mouse_position;
-                        if (from_mouse_to_object).magnitude() < mouse_influence_radius {
-                            *velocity += from_mouse_to_object.normalize() * 2000.0;
+                    let mut previous_states = Vec::new();
+                    for object_index in objects.particle_range() {
+                        if (objects.positions[object_index] - mouse_position).magnitude() < mouse_influence_radius {
+                            previous_states.push((object_index, objects.get(object_index)));
+                            objects.set_frozen(object_index, is_frozen);
                         }
                     }
+                    history.push(ObjectEdit::Changed(previous_states));
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::SetEditMode(enabled) => {
+                    if enabled {
+                        advance_time_before_editing = advance_time;
+                        advance_time = false;
+                        mouse_spring = None;
+                    } else {
+                        advance_time = advance_time_before_editing;
+                    }
+                }
+                SimulationThreadEvent::SetBackground(is_background) => {
+                    background = is_background;
+                }
+                SimulationThreadEvent::AddSceneItem(item) => {
+                    let mut objects = ObjectSoa::default();
+                    item.generate(&mut objects);
+                    let added = (0..objects.len())
+                        .map(|object_index| {
+                            let object = objects.get(object_index);
+                            (physics.add(object), object)
+                        })
+                        .collect_vec();
+                    history.push(ObjectEdit::Added(added));
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::RemoveObjects { position, radius } => {
+                    let objects = physics.objects();
+                    let removed = objects
+                        .particle_range()
+                        .filter(|&object_index| (objects.positions[object_index] - position).magnitude() < radius)
+                        .collect_vec();
+                    let mut removed = removed
+                        .into_iter()
+                        .rev()
+                        .map(|object_index| (object_index, physics.remove(object_index)))
+                        .collect_vec();
+                    removed.reverse();
+                    history.push(ObjectEdit::Removed(removed));
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::Undo => {
+                    history.undo(|edit| edit.revert(&mut physics));
+                    redraw_needed = true;
+                }
+                SimulationThreadEvent::Redo => {
+                    history.redo(|edit| edit.revert(&mut physics));
                     redraw_needed = true;
                 }
+                SimulationThreadEvent::ResetScene => new_scene = Some(create_physics(create_scene())),
+                SimulationThreadEvent::SaveSnapshot => {
+                    let path = Path::new(SESSION_SNAPSHOT_PATH);
+                    match physics.save_state(path) {
+                        Ok(()) => println!("Snapshot saved to \"{}\"", path.display()),
+                        Err(e) => eprintln!("Failed to save snapshot: {e:#}"),
+                    }
+                }
+                SimulationThreadEvent::LoadSnapshot => match resume_physics(Path::new(SESSION_SNAPSHOT_PATH)) {
+                    Ok(physics) => new_scene = Some(physics),
+                    Err(e) => eprintln!("Failed to load snapshot: {e:#}"),
+                },
+                SimulationThreadEvent::ToggleConstraintBouncing => {
+                    physics.enable_constraint_bouncing = !physics.enable_constraint_bouncing;
+                    println!(
+                        "Constraint bouncing {}",
+                        if physics.enable_constraint_bouncing {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    );
+                }
             }
         }
 
-        if CONFIG.simulation.time_limit.is_some_and(|limit| physics.time() > limit) && !time_limit_action_executed {
-            println!("Time limit reached");
-            time_limit_action_executed = true;
+        if let Some(new_physics) = new_scene.take() {
+            physics = new_physics;
+            println!("{} objects", physics.objects().len());
+            physics.set_track_accelerations(draw_planet_vectors);
+            history.clear();
+            followed = None;
+            inspected = None;
+            mouse_spring = None;
+            time_limit_action_executed = false;
+            step_limit_action_executed = false;
+            redraw_needed = true;
+        }
 
-            match CONFIG.simulation.time_limit_action {
+        let limit_action =
+            if CONFIG.simulation.time_limit.is_some_and(|limit| physics.time() > CONFIG.units.time(limit))
+                && !time_limit_action_executed
+            {
+                println!("Time limit reached");
+                time_limit_action_executed = true;
+                Some(CONFIG.simulation.time_limit_action)
+            } else if CONFIG.simulation.step_limit.is_some_and(|limit| physics.stats().step_count >= limit)
+                && !step_limit_action_executed
+            {
+                println!("Step limit reached");
+                step_limit_action_executed = true;
+                Some(CONFIG.simulation.step_limit_action)
+            } else {
+                None
+            };
+        if let Some(limit_action) = limit_action {
+            match limit_action {
                 TimeLimitAction::Exit => {
-                    send_app_event(app_event_loop_proxy, AppEvent::Exit);
-                    rendering_event_queue.push(RenderingThreadEvent::Exit);
+                    bus.notify_app(AppEvent::Exit);
+                    bus.render_data.publish(RenderingThreadEvent::Exit);
                     ready_to_exit.wait();
                     break 'main_loop;
                 }
                 TimeLimitAction::Pause => advance_time = false,
             }
         }
+        if advance_time != advance_time_reported {
+            advance_time_reported = advance_time;
+            bus.notify_app(AppEvent::Paused(!advance_time));
+        }
 
-        if let Some(new_edf) = edf_result_queue.pop() {
+        if let Some(new_edf) = edf_results.take() {
             edf = new_edf;
         }
-        if show_edf && edf_job_queue.is_empty() {
-            edf_job_queue
-                .push(EnergyDensityFieldJob {
-                    positions: physics.objects().positions.clone(),
-                    velocities: physics.objects().velocities.clone(),
-                    radii: physics.objects().radii.clone(),
-                    masses: physics.objects().radii.clone(),
-                    cell_size: EDF_CELL_SIZE,
-                    sampling_area_size: EDF_SAMPLING_AREA_SIZE,
-                })
-                .map_err(|_| anyhow!("failed to send edf job"))
-                .unwrap();
+        if show_edf && edf_jobs.is_empty() {
+            edf_jobs.publish(EnergyDensityFieldJob {
+                positions: EDF_VECTOR_BUFFERS.take_copy(&physics.objects().positions),
+                velocities: EDF_VECTOR_BUFFERS.take_copy(&physics.objects().velocities),
+                radii: EDF_SCALAR_BUFFERS.take_copy(&physics.objects().radii),
+                masses: EDF_SCALAR_BUFFERS.take_copy(&physics.objects().masses),
+                region: camera.visible_region(),
+                cell_size: EDF_CELL_SIZE * quality.edf_cell_size_factor / camera.zoom,
+                sampling_area_size: EDF_SAMPLING_AREA_SIZE,
+            });
         }
 
-        if advance_time {
+        let background_policy = if background {
+            CONFIG.window.background
+        } else {
+            Background::Run
+        };
+        if advance_time && background_policy != Background::Pause {
+            if auto_gpu_compute
+                && physics.stats().step_count.is_multiple_of(CONFIG.simulation.auto_gpu_compute_period)
+                && physics.objects().len() > 0
+                && !physics.gpu_failed()
+                && let Ok(timings) = physics.measure_compute_timings(physics.last_dt())
+            {
+                println!("compute timings: {timings:?}");
+                let options = gpu_compute_selector.update(timings);
+                if options != gpu_compute_options {
+                    gpu_compute_options = options;
+                    bus.notify_app(AppEvent::GpuComputeOptionsSelected(options));
+                }
+            }
+            #[cfg(feature = "scripting")]
+            if let Some(script_to_run) = &mut script {
+                let object_count = physics.objects().len();
+                if let Err(e) = script_to_run.step(&mut physics) {
+                    eprintln!("{e:#}, disabling the script");
+                    script = None;
+                }
+                // The object indices in the history are only valid while nothing else adds or removes objects
+                if physics.objects().len() != object_count {
+                    history.clear();
+                }
+            }
+            if CONFIG.simulation.validate_gpu
+                && physics.stats().step_count.is_multiple_of(CONFIG.simulation.validate_gpu_period)
+                && physics.objects().len() > 0
+                && !physics.gpu_failed()
+                && let Ok(validation) = physics.validate_gpu(physics.last_dt())
+            {
+                println!("gpu validation: {validation:?}");
+            }
+            if let Some(mouse_spring) = &mouse_spring {
+                let forces = mouse_spring.forces(physics.objects()).collect_vec();
+                for (object_index, force) in forces {
+                    physics.apply_force(object_index, force);
+                }
+            }
             let start = Instant::now();
-            physics.advance(CONFIG.simulation.speed_factor, gpu_compute_options);
+            advance_or_save_crash_snapshot(
+                &mut physics,
+                CONFIG.simulation.speed_factor
+                    * speed_ramp.multiplier(Instant::now())
+                    * if reduced_motion {
+                        CONFIG.rendering.reduced_motion_speed_factor
+                    } else {
+                        1.0
+                    },
+                gpu_compute_options,
+            );
             *sim_total_duration.lock().unwrap() += start.elapsed();
-            send_app_event(app_event_loop_proxy, AppEvent::StatsUpdated(physics.stats().clone()));
+            if physics.gpu_failed() && gpu_compute_options != GpuComputeOptions::default() {
+                gpu_compute_options = GpuComputeOptions::default();
+                bus.notify_app(AppEvent::GpuComputeOptionsSelected(gpu_compute_options));
+            }
+            bus.notify_app(AppEvent::StatsUpdated(physics.stats().clone()));
+            record_step(&physics, &mut autosave, &mut capture);
         }
 
-        let render_result = rendering_result_receiver.try_recv();
-        if first_redraw || redraw_needed || render_result.is_ok() {
+        let render_result = bus.scene_built.take();
+        if first_redraw || redraw_needed || render_result.is_some() {
             first_redraw = false;
             let previous_redraw_instant = last_redraw_instant;
             last_redraw_instant = Instant::now();
             println!("redraw took {:.2?}", last_redraw_instant - previous_redraw_instant);
-            if rendering_event_queue.is_empty() {
+            // Indices change when objects are removed, e.g. by absorbing walls
+            followed = followed.filter(|&object_index| object_index < physics.objects().len());
+            inspected = inspected.filter(|&object_index| object_index < physics.objects().len());
+            if let Some(object_index) = followed {
+                camera.position = physics.objects().positions[object_index] - camera.viewport_size / 2.0 / camera.zoom;
+                bus.notify_app(AppEvent::CameraMoved(camera));
+            } else if auto_camera && let Some(region) = bounding_box(physics.objects()) {
+                let smoothing_time = CONFIG.rendering.auto_camera.smoothing_time;
+                let blend = if smoothing_time > 0.0 {
+                    1.0 - (-(last_redraw_instant - previous_redraw_instant).as_secs_f32() / smoothing_time).exp()
+                } else {
+                    1.0
+                };
+                camera.approach_framing(region, CONFIG.rendering.auto_camera.margin, blend);
+                bus.notify_app(AppEvent::CameraMoved(camera));
+            }
+            if bus.render_data.is_empty() {
                 redraw_needed = false;
-                rendering_event_queue.push(RenderingThreadEvent::Draw(RenderingData {
+                bus.render_data.publish(RenderingThreadEvent::Draw(RenderingData {
                     positions: physics.objects().positions.clone(),
-                    velocities: physics.objects().velocities.clone(),
+                    velocities: match followed {
+                        Some(object_index) if CONFIG.rendering.follow_relative_velocity => {
+                            let velocities = &physics.objects().velocities;
+                            velocities.iter().map(|&velocity| velocity - velocities[object_index]).collect()
+                        }
+                        _ => physics.objects().velocities.clone(),
+                    },
                     radii: physics.objects().radii.clone(),
-                    colors: physics.objects().colors.clone(),
+                    masses: physics.objects().masses.clone(),
+                    colors: physics.objects().colors.iter().map(|color| color.map(|color| color.to_rgba8())).collect(),
+                    heat: physics.heat().to_vec(),
+                    is_frozen: physics.objects().is_frozen.clone(),
                     particle_range: physics.objects().particle_range(),
                     planet_range: physics.objects().planet_range(),
-                    color_source,
-                    draw_ids,
+                    color_source: if reduced_motion && color_source.is_flashing() {
+                        ColorSource::Default
+                    } else {
+                        color_source
+                    },
+                    draw_ids: draw_ids && quality.draw_ids,
+                    high_contrast,
+                    additive_blending,
                     draw_aabbs,
+                    draw_planet_vectors,
+                    planet_accelerations: physics
+                        .accelerations()
+                        .get(physics.objects().planet_range())
+                        .map(<[_]>::to_vec)
+                        .unwrap_or_default(),
                     constraints: physics.constraints(),
+                    gravity_zone_heights: physics.gravity_zones().iter().map(|zone| zone.min_y).collect(),
                     draw_edf: show_edf,
                     edf: edf.clone(),
-                    edf_cell_size: EDF_CELL_SIZE,
+                    wind: physics.wind().filter(|_| show_wind).map(|wind| (wind, physics.time())),
                     bvh: physics.bvh().clone(),
+                    camera,
+                    quality,
+                    inspected,
                 }));
             }
         }
+
+        match background_policy {
+            Background::Run => {}
+            Background::Pause => thread::sleep(BACKGROUND_POLL_INTERVAL),
+            Background::Throttle => {
+                thread::sleep(Duration::from_secs_f32(1.0 / CONFIG.window.background_steps_per_second));
+            }
+        }
+    }
+
+    physics
+}
+
+fn create_scene(stress: Option<usize>, constraints: AABB) -> ObjectSoa {
+    let mut objects = ObjectSoa::default();
+    match stress {
+        Some(count) => {
+            create_stress_scene(&mut objects, count, constraints, CONFIG.units.length(CONFIG.demo.object_radius))
+                .expect("failed to create stress scene");
+        }
+        None => create_demo(&mut objects),
     }
+    objects
+}
+
+fn create_physics(objects: ObjectSoa, physics_settings: PhysicsSettings) -> PhysicsEngine {
+    let mut physics = PhysicsEngine::new(objects, physics_settings).unwrap();
+    record_trajectories(&mut physics);
+    physics
+}
+
+// Continues the simulation saved by `PhysicsEngine::save_state`, with the settings of this run
+fn resume_physics(path: &Path, physics_settings: PhysicsSettings) -> anyhow::Result<PhysicsEngine> {
+    let mut physics = PhysicsEngine::new(ObjectSoa::default(), physics_settings)?;
+    physics.load_state(path)?;
+    // After loading, since the recorded objects are the loaded ones
+    record_trajectories(&mut physics);
+    Ok(physics)
+}
 
+fn record_trajectories(physics: &mut PhysicsEngine) {
+    physics.set_trajectory_recorder(CONFIG.simulation.trajectories.as_ref().map(|trajectories| {
+        TrajectoryRecorder::new(trajectories.objects.iter().copied(), trajectories.period, trajectories.capacity)
+    }));
+}
+
+fn create_autosave() -> Option<Autosave> {
+    CONFIG.autosave.interval.map(|interval| {
+        Autosave::new(CONFIG.autosave.directory.clone().into(), Duration::from_secs_f32(interval), CONFIG.autosave.keep)
+    })
+}
+
+fn create_capture() -> Option<CaptureWriter<BufWriter<File>>> {
+    CONFIG.simulation.capture.as_ref().and_then(|capture| {
+        CaptureWriter::create(Path::new(&capture.path), capture.stride)
+            .inspect_err(|e| eprintln!("Frame capture disabled: {e:#}"))
+            .ok()
+    })
+}
+
+// The object closest to the position among those under it or within the radius
+fn nearest_object(physics: &PhysicsEngine, position: Vector2<f32>, radius: f32) -> Option<usize> {
+    let objects = physics.objects();
+    let distance = |object_index: usize| (objects.positions[object_index] - position).magnitude();
+    // A superset of the objects within the radius or under the position
     physics
+        .objects_near(position, radius)
+        .into_iter()
+        .filter(|&object_index| distance(object_index) < objects.radii[object_index].max(radius))
+        .min_by(|&object1_index, &object2_index| distance(object1_index).total_cmp(&distance(object2_index)))
+}
+
+// Writes a crash snapshot if the step panics, before passing the panic on
+fn advance_or_save_crash_snapshot(
+    physics: &mut PhysicsEngine,
+    speed_factor: f32,
+    gpu_compute_options: GpuComputeOptions,
+) {
+    let advance_result = panic::catch_unwind(AssertUnwindSafe(|| physics.advance(speed_factor, gpu_compute_options)));
+    if let Err(panic_payload) = advance_result {
+        match crash_report::save_snapshot(Path::new(&CONFIG.autosave.directory), physics) {
+            Ok(path) => eprintln!("Crash snapshot written to \"{}\"", path.display()),
+            Err(e) => eprintln!("Failed to write crash snapshot: {e:#}"),
+        }
+        panic::resume_unwind(panic_payload);
+    }
+    crash_report::record_stats(physics.stats());
+}
+
+// Autosaves and captures the state after a step
+fn record_step(
+    physics: &PhysicsEngine,
+    autosave: &mut Option<Autosave>,
+    capture: &mut Option<CaptureWriter<BufWriter<File>>>,
+) {
+    if let Some(autosave) = autosave
+        && let Err(e) = autosave.update(physics)
+    {
+        eprintln!("Autosave failed: {e:#}");
+    }
+    if let Some(capture_writer) = capture
+        && let Err(e) =
+            capture_writer.record(physics.stats().step_count, physics.time(), physics.objects(), physics.constraints())
+    {
+        eprintln!("Frame capture failed: {e:#}, disabling it");
+        *capture = None;
+    }
+}
+
+// Bounding box of the objects, including their radii
+fn bounding_box(objects: &ObjectSoa) -> Option<AABB> {
+    zip(&objects.positions, &objects.radii)
+        .map(|(&position, &radius)| AABB {
+            topleft: position - radius,
+            bottomright: position + radius,
+        })
+        .reduce(|region, aabb| AABB {
+            topleft: Vector2::new(region.topleft.x.min(aabb.topleft.x), region.topleft.y.min(aabb.topleft.y)),
+            bottomright: Vector2::new(
+                region.bottomright.x.max(aabb.bottomright.x),
+                region.bottomright.y.max(aabb.bottomright.y),
+            ),
+        })
+}
+
+// Reports stalls of the simulation thread, e.g. a GPU hang, and keeps the app redrawing the warning while it lasts,
+// since the stats that normally trigger redraws stop coming
+fn watchdog_thread(simulation_heartbeat: &Heartbeat, bus: &EventBus) {
+    let timeout = Duration::from_secs_f32(CONFIG.simulation.watchdog_timeout);
+    let mut stalled = false;
+    loop {
+        thread::sleep(timeout / 4);
+        let stall = simulation_heartbeat.stall(timeout);
+        match (stall, stalled) {
+            (Some(stall), false) => eprintln!("Simulation thread hasn't responded for {stall:.1?}"),
+            (None, true) => eprintln!("Simulation thread responds again"),
+            _ => {}
+        }
+        stalled = stall.is_some();
+        if stalled {
+            bus.notify_app(AppEvent::RequestRedraw);
+        }
+    }
+}
+
+fn build_physics_thread_pool() -> ThreadPool {
+    let worker_cores = &CONFIG.threads.worker_cores;
+    let mut builder = ThreadPoolBuilder::new().num_threads(CONFIG.threads.physics_thread_count());
+    if !worker_cores.is_empty() {
+        builder = builder.start_handler(|worker_index| {
+            let core = worker_cores[worker_index % worker_cores.len()];
+            if let Err(e) = affinity::set_current_thread_affinity(&[core]) {
+                eprintln!("Failed to pin physics worker {worker_index} to core {core}: {e:#}");
+            }
+        });
+        println!("physics workers pinned to cores {worker_cores:?}");
+    }
+    builder.build().unwrap()
+}
+
+fn configure_simulation_thread() {
+    if !CONFIG.threads.simulation_cores.is_empty()
+        && let Err(e) = affinity::set_current_thread_affinity(&CONFIG.threads.simulation_cores)
+    {
+        eprintln!("Failed to set simulation thread affinity: {e:#}");
+    }
+    if let Some(nice) = CONFIG.threads.simulation_nice
+        && let Err(e) = affinity::set_current_thread_nice(nice)
+    {
+        eprintln!("Failed to set simulation thread priority: {e:#}");
+    }
+    match affinity::current_thread_affinity() {
+        Ok(cores) => println!("simulation thread affinity: {cores:?}"),
+        Err(e) => eprintln!("Failed to get simulation thread affinity: {e:#}"),
+    }
+    if CONFIG.simulation.trap_fp_exceptions {
+        fp_exceptions::set_guards_enabled(CONFIG.simulation.guard_gpu_fp_exceptions);
+        if let Err(e) = fp_exceptions::enable_traps() {
+            eprintln!("Failed to enable floating-point exception traps: {e:#}");
+        }
+    }
 }
 
 struct EnergyDensityFieldJob {
@@ -319,43 +1113,66 @@ struct EnergyDensityFieldJob {
     velocities: Vec<Vector2<f32>>,
     radii: Vec<f32>,
     masses: Vec<f32>,
+    region: AABB,
     cell_size: f32,
     sampling_area_size: usize,
 }
 
+#[derive(Default, Clone)]
+struct EnergyDensityField {
+    values: Array2<f32>,
+    origin: Vector2<f32>,
+    cell_size: f32,
+}
+
 fn energy_density_field_thread(
     edf_thread_ready: Arc<Barrier>,
-    energy_field_jobs: &ArrayQueue<EnergyDensityFieldJob>,
-    energy_field_result: &ArrayQueue<Array2<f32>>,
+    energy_field_jobs: &Topic<EnergyDensityFieldJob>,
+    energy_field_result: &Topic<EnergyDensityField>,
+    thread_pool: &ThreadPool,
+    cpu_utilization: &CpuUtilization,
 ) {
     edf_thread_ready.wait();
     let mut edf = Array2::<f32>::default();
     let mut edf_avg = Array2::<f32>::default();
-    let thread_pool = ThreadPoolBuilder::new().num_threads(num_cpus::get()).build().unwrap();
+    let mut cpu_meter = CpuMeter::thread(CpuMeter::DEFAULT_PERIOD);
     loop {
+        cpu_meter.sample(cpu_utilization);
         if let Some(EnergyDensityFieldJob {
             positions,
             velocities,
             radii,
             masses,
+            region,
             cell_size,
             sampling_area_size,
-        }) = energy_field_jobs.pop()
+        }) = energy_field_jobs.take()
         {
-            assert!(cell_size > 1.0);
+            assert!(cell_size > 0.0);
             let start = Instant::now();
-            let width = (CONFIG.window.width as f32 / cell_size) as usize + 1;
-            let height = (CONFIG.window.height as f32 / cell_size) as usize + 1;
+            let region_size = region.bottomright - region.topleft;
+            let width = (region_size.x / cell_size) as usize + 1;
+            let height = (region_size.y / cell_size) as usize + 1;
             edf.reset((width, height));
             for object_index in 0..positions.len() {
-                let position = positions[object_index];
-                let edf_position = position / cell_size;
+                let edf_position = (positions[object_index] - region.topleft) / cell_size;
+                if edf_position.x < 0.0
+                    || edf_position.y < 0.0
+                    || edf_position.x >= width as f32
+                    || edf_position.y >= height as f32
+                {
+                    continue;
+                }
                 let velocity = velocities[object_index];
                 let radius = radii[object_index];
                 let mass = masses[object_index];
                 let energy_contribution = radius * 0.5 * mass * velocity.magnitude_squared();
                 edf[(edf_position.x as usize, edf_position.y as usize)] += energy_contribution;
             }
+            EDF_VECTOR_BUFFERS.give(positions);
+            EDF_VECTOR_BUFFERS.give(velocities);
+            EDF_SCALAR_BUFFERS.give(radii);
+            EDF_SCALAR_BUFFERS.give(masses);
             let mut sat = edf.clone(); // summed-area table
             for y in 0..height {
                 for x in 0..width {
@@ -403,47 +1220,100 @@ fn energy_density_field_thread(
             });
 
             println!("edf calculation took {:.2?}", start.elapsed());
-            energy_field_result.force_push(edf_avg.clone());
+            energy_field_result.publish(EnergyDensityField {
+                values: edf_avg.clone(),
+                origin: region.topleft,
+                cell_size,
+            });
         }
         yield_now();
     }
 }
 
 fn rendering_thread(
-    rendering_event_queue: &SegQueue<RenderingThreadEvent>,
+    bus: &EventBus,
     ready_to_exit: &Arc<Barrier>,
     rendering_thread_ready: &Arc<Barrier>,
-    rendering_result_queue: &mpsc::Sender<()>,
-    redraw_job_queue: &ArrayQueue<Scene>,
-    app_event_loop_proxy: &EventLoopProxy<AppEvent>,
+    cpu_utilizations: &CpuUtilizations,
 ) {
     let mut rendering_data = RenderingData::default();
     rendering_thread_ready.wait();
-    let mut rendering_enabled = CONFIG.rendering.enabled;
+    let mut rendering_enabled = true;
+    let mut trails_enabled = CONFIG.rendering.trails.enabled;
+    let mut trails: Option<Trails> = None;
+    // Sent by the simulation thread before any data to draw
+    let mut thread_pool: Option<Arc<ThreadPool>> = None;
+    let mut overlay_scale = 1.0;
+    let mut cpu_meter = CpuMeter::thread(CpuMeter::DEFAULT_PERIOD);
     'main_loop: loop {
-        while let Some(event) = rendering_event_queue.pop() {
+        cpu_meter.sample(&cpu_utilizations.rendering);
+        let mut new_data = false;
+        while let Some(event) = bus.render_data.take() {
             match event {
-                RenderingThreadEvent::Draw(data) => rendering_data = data,
+                RenderingThreadEvent::Draw(data) => {
+                    rendering_data = data;
+                    new_data = true;
+                }
                 RenderingThreadEvent::SetRendering(enabled) => rendering_enabled = enabled,
+                RenderingThreadEvent::SetThreadPool(pool) => thread_pool = Some(pool),
+                RenderingThreadEvent::SetOverlayScale(scale) => overlay_scale = scale,
+                RenderingThreadEvent::ToggleTrails => {
+                    trails_enabled = !trails_enabled;
+                    if !trails_enabled {
+                        trails = None;
+                    }
+                }
+                RenderingThreadEvent::SaveTrails => match &trails {
+                    Some(trails) => match save_trails(trails) {
+                        Ok(path) => println!("Trails saved to \"{}\"", path.display()),
+                        Err(e) => eprintln!("Failed to save trails: {e:#}"),
+                    },
+                    None => eprintln!("Trails are disabled, nothing to save"),
+                },
                 RenderingThreadEvent::Exit => {
                     ready_to_exit.wait();
                     break 'main_loop;
                 }
             }
         }
-        if rendering_enabled && !rendering_data.positions.is_empty() && redraw_job_queue.is_empty() {
-            let mut scenes = draw_physics(&rendering_data);
-            let mut scene = scenes.remove(0);
-            for subscene in scenes {
+        // Trails accumulate once per simulation frame, not per redraw, so that pausing doesn't saturate them
+        let trails_visible = trails_enabled && rendering_data.quality.trails;
+        if trails_visible && new_data && !rendering_data.positions.is_empty() {
+            let trails = trails.get_or_insert_with(|| new_trails(rendering_data.constraints));
+            if trails.region() != rendering_data.constraints {
+                *trails = new_trails(rendering_data.constraints);
+            }
+            update_trails(trails, &rendering_data);
+        }
+        if rendering_enabled
+            && !rendering_data.positions.is_empty()
+            && bus.scenes.is_empty()
+            && let Some(thread_pool) = &thread_pool
+        {
+            let start = Instant::now();
+            let transform = camera_transform(&rendering_data.camera);
+            let mut scene = Scene::new();
+            // Trails are drawn below everything else
+            if trails_visible && let Some(trails) = &trails {
+                draw_trails(&mut scene, transform, trails);
+            }
+            for subscene in draw_physics(&rendering_data, thread_pool) {
                 // TODO remove this when rendering scenes separately via render_to_texture() and combining the textures
                 scene.append(&subscene, None);
             }
             if rendering_data.draw_aabbs && !rendering_data.bvh.nodes().is_empty() {
-                draw_aabbs(&mut scene, rendering_data.bvh.nodes());
+                draw_aabbs(&mut scene, transform, rendering_data.bvh.nodes());
             }
-            redraw_job_queue.force_push(scene);
-            let _ = app_event_loop_proxy.send_event(AppEvent::RequestRedraw);
-            rendering_result_queue.send(()).unwrap();
+            draw_gravity_zones(&mut scene, transform, rendering_data.constraints, &rendering_data.gravity_zone_heights);
+            if let Some((wind, time)) = rendering_data.wind {
+                draw_wind(&mut scene, transform, &rendering_data.camera, wind, time);
+            }
+            scene.append(&draw_planets(&rendering_data, transform), None);
+            draw_color_legend(&mut scene, &rendering_data, overlay_scale);
+            draw_inspector(&mut scene, &rendering_data);
+            bus.scenes.publish((scene, start.elapsed()));
+            bus.notify_app(AppEvent::RequestRedraw);
+            bus.scene_built.publish(());
         }
         yield_now();
     }
@@ -454,17 +1324,23 @@ fn draw_physics(
         positions,
         velocities,
         radii,
+        masses,
         colors,
+        heat,
+        is_frozen,
         particle_range,
-        planet_range,
         color_source,
         draw_ids,
+        high_contrast,
+        additive_blending,
         constraints,
         draw_edf,
         edf,
-        edf_cell_size,
+        camera,
+        quality,
         ..
     }: &RenderingData,
+    thread_pool: &ThreadPool,
 ) -> Vec<Scene> {
     fn draw_circle(scene: &mut Scene, transform: Affine, position: Vector2<f32>, radius: f32, color: Color) {
         scene.fill(
@@ -472,67 +1348,125 @@ fn draw_physics(
             transform,
             color,
             None,
-            &Circle::new((f64::from(position.x), f64::from(position.y)), f64::from(radius).max(1.0)),
+            &Circle::new((f64::from(position.x), f64::from(position.y)), f64::from(radius)),
+        );
+    }
+
+    fn draw_outline(scene: &mut Scene, transform: Affine, position: Vector2<f32>, radius: f32, camera: &Camera) {
+        scene.stroke(
+            &Stroke::new(1.0 / f64::from(camera.zoom)),
+            transform,
+            css::WHITE,
+            None,
+            &Circle::new((f64::from(position.x), f64::from(position.y)), f64::from(radius)),
         );
     }
 
+    fn draw_point_sprite(scene: &mut Scene, transform: Affine, position: Vector2<f32>, radius: f32, color: Color) {
+        let size = f64::from(radius) * 2.0;
+        let origin = (f64::from(position.x - radius), f64::from(position.y - radius));
+        scene.fill(Fill::NonZero, transform, color, None, &Rect::from_origin_size(origin, (size, size)));
+    }
+
+    // Layers only blend with what's below them, so every particle is a layer of its own
+    fn push_additive_layer(scene: &mut Scene, transform: Affine, position: Vector2<f32>, radius: f32) {
+        let size = f64::from(radius) * 2.0;
+        let origin = (f64::from(position.x - radius), f64::from(position.y - radius));
+        let blend_mode = BlendMode::new(Mix::Normal, Compose::Plus);
+        scene.push_layer(blend_mode, 1.0, transform, &Rect::from_origin_size(origin, (size, size)));
+    }
+
     fn draw_text(scene: &mut Scene, transform: Affine, text: &mut SimpleText, position: Vector2<f32>, s: &str) {
-        text.add(scene, 10.0, None, Affine::translate((f64::from(position.x), f64::from(position.y))) * transform, s);
+        let screen_position = transform * kurbo::Point::new(f64::from(position.x), f64::from(position.y));
+        text.add(scene, 10.0, None, Affine::translate(screen_position.to_vec2()), s);
     }
 
-    let transform = Affine::IDENTITY;
-    let chunk_size = particle_range.len().div_ceil(16);
-    let chunks = particle_range
-        .clone()
-        .chunks(if chunk_size > 0 { chunk_size } else { positions.len() })
-        .into_iter()
-        .map(Itertools::collect_vec)
-        .collect_vec();
+    const FROZEN_COLOR: Color = Color::new([0.45, 0.55, 0.7, 1.0]);
+
+    let transform = camera_transform(camera);
+    // Chunks are appended to the scene in order, so objects later in the draw order end up on top
+    let mut draw_order = DRAW_ORDER_BUFFERS.take();
+    draw_order.extend(particle_range.clone().step_by(quality.particle_stride));
+    let depth_key = match CONFIG.rendering.depth_sort {
+        DepthSort::None => None,
+        DepthSort::Radius => Some(radii),
+        DepthSort::Mass => Some(masses),
+    };
+    if let Some(depth_key) = depth_key {
+        draw_order.sort_by(|&a, &b| depth_key[a].total_cmp(&depth_key[b]));
+        if !CONFIG.rendering.larger_on_top {
+            draw_order.reverse();
+        }
+    }
+    let chunk_count = scene_chunk_count(draw_order.len(), thread_pool.current_num_threads());
+    let chunk_len = draw_order.len().div_ceil(chunk_count).max(1);
 
     // TODO render via OpenCL into Image
-    let mut scenes = std::thread::scope(|scope| {
-        chunks
-            .iter()
+    let mut scenes = thread_pool.install(|| {
+        draw_order
+            .par_chunks(chunk_len)
             .map(|chunk| {
-                scope.spawn(move || {
-                    let mut scene = Scene::new();
-                    let mut text = SimpleText::new();
-                    for &object_index in chunk {
-                        let particle_position = positions[object_index];
-                        let color = match color_source {
-                            ColorSource::None => None,
-                            ColorSource::Default => Some(css::GRAY),
-                            ColorSource::Demo => colors[object_index],
-                            ColorSource::Velocity => Some(color_from_velocity(velocities, object_index)),
-                            ColorSource::Dark => Some(Color::new([0.2, 0.2, 0.2, 1.0])),
-                        };
-                        if let Some(color) = color {
-                            draw_circle(&mut scene, transform, particle_position, radii[object_index].max(1.0), color);
+                let mut scene = Scene::new();
+                let mut text = SimpleText::new();
+                for &object_index in chunk {
+                    let particle_position = positions[object_index];
+                    let color = match color_source {
+                        ColorSource::None => None,
+                        ColorSource::Default => Some(css::GRAY),
+                        ColorSource::Demo => colors[object_index].map(Color::from),
+                        ColorSource::Velocity => Some(color_from_velocity(velocities, object_index)),
+                        ColorSource::Dark => Some(Color::new([0.2, 0.2, 0.2, 1.0])),
+                        ColorSource::Heat => Some(color_from_heat(heat.get(object_index).copied().unwrap_or(0.0))),
+                    }
+                    .map(|color| if is_frozen[object_index] { FROZEN_COLOR } else { color })
+                    .map(|color| {
+                        color.with_alpha(colors[object_index].map_or(1.0, |color| f32::from(color.a) / 255.0))
+                    });
+                    if let Some(color) = color {
+                        let radius = render_radius(radii[object_index], camera, *high_contrast);
+                        let is_point_sprite = quality
+                            .point_sprite_radius
+                            .is_some_and(|point_sprite_radius| radius * camera.zoom < point_sprite_radius);
+                        let splat = (CONFIG.rendering.subpixel_splats && !*high_contrast)
+                            .then(|| splat_coverage(radii[object_index] * camera.zoom))
+                            .flatten();
+                        let splat_radius = SPLAT_SCREEN_RADIUS / camera.zoom;
+                        if *additive_blending {
+                            let layer_radius = if splat.is_some() { splat_radius } else { radius };
+                            push_additive_layer(&mut scene, transform, particle_position, layer_radius);
                         }
-
-                        if *draw_ids {
-                            draw_text(&mut scene, transform, &mut text, particle_position, &format!("{object_index}"));
+                        if let Some(coverage) = splat {
+                            let color = color.multiply_alpha(coverage);
+                            draw_point_sprite(&mut scene, transform, particle_position, splat_radius, color);
+                        } else if is_point_sprite {
+                            draw_point_sprite(&mut scene, transform, particle_position, radius, color);
+                        } else {
+                            draw_circle(&mut scene, transform, particle_position, radius, color);
+                        }
+                        if *additive_blending {
+                            scene.pop_layer();
                         }
+                        if *high_contrast {
+                            draw_outline(&mut scene, transform, particle_position, radius, camera);
+                        }
+                    }
+
+                    if *draw_ids {
+                        draw_text(&mut scene, transform, &mut text, particle_position, &format!("{object_index}"));
                     }
-                    scene
-                })
+                }
+                scene
             })
-            .map(|handle| handle.join().unwrap())
-            .collect_vec()
+            .collect::<Vec<_>>()
     });
-
-    let scene = scenes.last_mut().unwrap();
-    let mut text = SimpleText::new();
-    for (object_index, ((&planet_position, &planet_radius), color)) in
-        zip(zip(&positions[planet_range.clone()], &radii[planet_range.clone()]), &colors[planet_range.clone()])
-            .enumerate()
-    {
-        draw_circle(scene, transform, planet_position, planet_radius.max(1.0), color.unwrap_or(css::WHITE));
-        if *draw_ids {
-            draw_text(scene, transform, &mut text, planet_position, &format!("{object_index}"));
-        }
+    // Everything else is drawn over the last chunk, so there has to be one
+    if scenes.is_empty() {
+        scenes.push(Scene::new());
     }
 
+    DRAW_ORDER_BUFFERS.give(draw_order);
+
+    let scene = scenes.last_mut().unwrap();
     let topleft = constraints.topleft;
     let bottomright = constraints.bottomright;
     scene.stroke(
@@ -543,6 +1477,11 @@ fn draw_physics(
         &Rect::new(f64::from(topleft.x), f64::from(topleft.y), f64::from(bottomright.x), f64::from(bottomright.y)),
     );
 
+    let EnergyDensityField {
+        values: edf,
+        origin: edf_origin,
+        cell_size: edf_cell_size,
+    } = edf;
     println!("edf size: {}x{}", edf.size().0, edf.size().1);
 
     if *draw_edf && !edf.is_empty() {
@@ -571,23 +1510,339 @@ fn draw_physics(
         let start = Instant::now();
         let blob = Blob::new(Arc::new(image_data));
         let image = Image::new(blob, ImageFormat::Rgba8, u32::try_from(width).unwrap(), u32::try_from(height).unwrap());
-        scene.draw_image(&image, transform.pre_scale(f64::from(*edf_cell_size)));
+        scene.draw_image(
+            &image,
+            transform
+                .pre_translate(kurbo::Vec2::new(f64::from(edf_origin.x), f64::from(edf_origin.y)))
+                .pre_scale(f64::from(*edf_cell_size)),
+        );
         println!("rendering edf took {:.2?}", start.elapsed());
     }
 
     scenes
 }
 
-fn color_from_velocity(velocities: &[Vector2<f32>], object_index: usize) -> Color {
+// Enough chunks to keep every thread busy, but none so small that building and appending its scene costs more than
+// drawing it in parallel saves
+fn scene_chunk_count(object_count: usize, thread_count: usize) -> usize {
+    const MIN_CHUNK_LEN: usize = 1024;
+    const CHUNKS_PER_THREAD: usize = 2;
+
+    object_count.div_ceil(MIN_CHUNK_LEN).clamp(1, thread_count * CHUNKS_PER_THREAD)
+}
+
+fn new_trails(region: AABB) -> Trails {
+    Trails::new(region, CONFIG.rendering.trails.cell_size, CONFIG.rendering.trails.decay, CONFIG.rendering.color_math)
+}
+
+fn update_trails(
+    trails: &mut Trails,
+    RenderingData {
+        positions,
+        velocities,
+        particle_range,
+        ..
+    }: &RenderingData,
+) {
+    trails.fade();
+    let intensity = CONFIG.rendering.trails.intensity;
+    for object_index in particle_range.clone() {
+        let [r, g, b, _] = color_from_velocity(velocities, object_index).components;
+        trails.splat(positions[object_index], [r, g, b], intensity);
+    }
+}
+
+fn draw_trails(scene: &mut Scene, transform: Affine, trails: &Trails) {
+    let (width, height) = trails.size();
+    let blob = Blob::new(Arc::new(trails.to_rgba8()));
+    let image = Image::new(blob, ImageFormat::Rgba8, u32::try_from(width).unwrap(), u32::try_from(height).unwrap());
+    let origin = trails.region().topleft;
+    scene.draw_image(
+        &image,
+        transform
+            .pre_translate(kurbo::Vec2::new(f64::from(origin.x), f64::from(origin.y)))
+            .pre_scale(f64::from(trails.cell_size())),
+    );
+}
+
+fn save_trails(trails: &Trails) -> anyhow::Result<PathBuf> {
+    let directory = Path::new(&CONFIG.rendering.trails.directory);
+    fs::create_dir_all(directory).with_context(|| format!("create directory \"{}\"", directory.display()))?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = directory.join(format!("trails-{timestamp:020}.png"));
+    trails.save_png(&path)?;
+    Ok(path)
+}
+
+// Planets get their own layer on top of everything else, so they aren't lost among the particles
+fn draw_planets(
+    RenderingData {
+        positions,
+        velocities,
+        radii,
+        masses,
+        colors,
+        planet_range,
+        draw_ids,
+        draw_planet_vectors,
+        planet_accelerations,
+        high_contrast,
+        camera,
+        ..
+    }: &RenderingData,
+    transform: Affine,
+) -> Scene {
+    const TEXT_SIZE: f32 = 12.0;
+    const VELOCITY_COLOR: Color = css::LIME;
+    const ACCELERATION_COLOR: Color = css::ORANGE_RED;
+
+    let config = &CONFIG.rendering.planets;
+    let mut scene = Scene::new();
+    let mut text = SimpleText::new();
+    for planet_index in planet_range.clone() {
+        let position = positions[planet_index];
+        let center = kurbo::Point::new(f64::from(position.x), f64::from(position.y));
+        let radius = render_radius(radii[planet_index], camera, *high_contrast);
+        let color = colors[planet_index].map_or(css::WHITE, Color::from);
+
+        if config.glow_radius_factor > 1.0 {
+            let glow_radius = radius * config.glow_radius_factor;
+            let glow =
+                Gradient::new_radial(center, glow_radius).with_stops([color.with_alpha(0.6), color.with_alpha(0.0)]);
+            scene.fill(Fill::NonZero, transform, &glow, None, &Circle::new(center, f64::from(glow_radius)));
+        }
+        scene.fill(Fill::NonZero, transform, color, None, &Circle::new(center, f64::from(radius)));
+        if config.outline {
+            let stroke = Stroke::new(2.0 / f64::from(camera.zoom));
+            scene.stroke(&stroke, transform, css::WHITE, None, &Circle::new(center, f64::from(radius)));
+        }
+        if *draw_planet_vectors {
+            let stroke = Stroke::new(2.0 / f64::from(camera.zoom));
+            let velocity = velocities[planet_index] * config.velocity_vector_scale;
+            scene.stroke(&stroke, transform, VELOCITY_COLOR, None, &arrow_path(position, velocity));
+            if let Some(&acceleration) = planet_accelerations.get(planet_index - planet_range.start) {
+                let acceleration = acceleration * config.acceleration_vector_scale;
+                scene.stroke(&stroke, transform, ACCELERATION_COLOR, None, &arrow_path(position, acceleration));
+            }
+        }
+
+        let mut labels = Vec::new();
+        if *draw_ids {
+            labels.push(format!("{planet_index}"));
+        }
+        if let Some(name) = config.names.get(planet_index) {
+            labels.push(name.clone());
+        }
+        if config.show_mass {
+            labels.push(format!("m = {}", masses[planet_index]));
+        }
+        if config.show_velocity {
+            labels.push(format!("v = {:.1}", velocities[planet_index].magnitude()));
+        }
+        let label_position = transform * kurbo::Point::new(center.x, center.y + f64::from(radius));
+        for (line, label) in labels.iter().enumerate() {
+            let offset = kurbo::Vec2::new(0.0, f64::from(TEXT_SIZE) * (line + 1) as f64);
+            text.add(&mut scene, TEXT_SIZE, None, Affine::translate(label_position.to_vec2() + offset), label);
+        }
+    }
+    scene
+}
+
+// Applies the minimum on-screen radius, so the result depends on zoom
+fn render_radius(radius: f32, camera: &Camera, high_contrast: bool) -> f32 {
+    const HIGH_CONTRAST_MIN_SCREEN_RADIUS: f32 = 3.0;
+
+    let min_screen_radius = if high_contrast {
+        CONFIG.rendering.min_screen_radius.max(HIGH_CONTRAST_MIN_SCREEN_RADIUS)
+    } else {
+        CONFIG.rendering.min_screen_radius
+    };
+    radius.max(min_screen_radius / camera.zoom)
+}
+
+// Share of a splat covered by an object of the given radius on screen, if the object is smaller than a pixel. A splat
+// is a square as large as a circle of a pixel radius, so the total intensity doesn't jump once the object is larger.
+fn splat_coverage(screen_radius: f32) -> Option<f32> {
+    (screen_radius < SPLAT_SCREEN_RADIUS)
+        .then(|| PI * screen_radius * screen_radius / (4.0 * SPLAT_SCREEN_RADIUS * SPLAT_SCREEN_RADIUS))
+}
+
+fn camera_transform(camera: &Camera) -> Affine {
+    Affine::scale(f64::from(camera.zoom))
+        * Affine::translate((-f64::from(camera.position.x), -f64::from(camera.position.y)))
+}
+
+fn color_from_velocity(velocities: &[Vector2<f32>], object_index: usize) -> Color {
+    spectrum(velocity_spectrum_position(velocities[object_index].magnitude()), 1.0)
+}
+
+fn color_from_heat(heat: f32) -> Color {
+    spectrum(heat_spectrum_position(heat), 1.0)
+}
+
+// Heat is energy per unit of mass, so it's shown on the same scale as the speed with that kinetic energy
+fn heat_spectrum_position(heat: f32) -> f32 {
+    velocity_spectrum_position((2.0 * heat).sqrt())
+}
+
+fn velocity_spectrum_position(speed: f32) -> f32 {
     const SCALE_FACTOR: f32 = 0.0004;
-    let velocity = velocities[object_index];
-    #[allow(clippy::cast_possible_truncation)]
-    let spectrum_position = (velocity.magnitude() * SCALE_FACTOR).powf(0.6).clamp(0.0, 1.0);
-    spectrum(spectrum_position, 1.0)
+    (speed * SCALE_FACTOR).powf(0.6).clamp(0.0, 1.0)
+}
+
+// Color bar for the quantity mapped to colors, spanning its range in the current frame
+fn draw_color_legend(
+    scene: &mut Scene,
+    RenderingData {
+        velocities,
+        heat,
+        particle_range,
+        color_source,
+        camera,
+        ..
+    }: &RenderingData,
+    scale: f64,
+) {
+    const WIDTH: f64 = 200.0;
+    const HEIGHT: f64 = 12.0;
+    const MARGIN: f64 = 16.0;
+    const TEXT_SIZE: f32 = 12.0;
+    const STOP_COUNT: usize = 16;
+
+    fn min_max(values: impl Iterator<Item = f32>) -> (f32, f32) {
+        values.fold((f32::MAX, 0.0_f32), |(min, max), value| (min.min(value), max.max(value)))
+    }
+
+    if particle_range.is_empty() {
+        return;
+    }
+    let (name, spectrum_position, (min_value, max_value)): (_, fn(f32) -> f32, _) = match color_source {
+        ColorSource::Velocity => (
+            Message::Speed.text(CONFIG.ui.language),
+            velocity_spectrum_position,
+            min_max(velocities[particle_range.clone()].iter().map(Vector2::magnitude)),
+        ),
+        ColorSource::Heat if !heat.is_empty() => (
+            Message::Heat.text(CONFIG.ui.language),
+            heat_spectrum_position,
+            min_max(heat[particle_range.clone()].iter().copied()),
+        ),
+        _ => return,
+    };
+    let min_position = spectrum_position(min_value);
+    let max_position = spectrum_position(max_value);
+    let stops = (0..STOP_COUNT)
+        .map(|i| {
+            let t = i as f32 / (STOP_COUNT - 1) as f32;
+            spectrum(min_position + (max_position - min_position) * t, 1.0)
+        })
+        .collect_vec();
+
+    // At the bottom, away from the stats
+    let corner = if CONFIG.ui.overlay_corner == OverlayCorner::BottomLeft {
+        OverlayCorner::BottomRight
+    } else {
+        OverlayCorner::BottomLeft
+    };
+    let size = kurbo::Size::new(WIDTH + MARGIN * 2.0, HEIGHT + MARGIN * 2.0 + f64::from(TEXT_SIZE));
+    let transform = overlay_transform(corner, size, camera.viewport_size, scale);
+    let bar = Rect::from_origin_size((MARGIN, size.height - MARGIN - HEIGHT), (WIDTH, HEIGHT));
+    let gradient = Gradient::new_linear((bar.x0, bar.y0), (bar.x1, bar.y0)).with_stops(stops.as_slice());
+    scene.fill(Fill::NonZero, transform, &gradient, None, &bar);
+    scene.stroke(&Stroke::default(), transform, css::WHITE, None, &bar);
+
+    let mut text = SimpleText::new();
+    let label_y = bar.y0 - 4.0;
+    let min_label_transform = transform * Affine::translate((bar.x0, label_y));
+    text.add(scene, TEXT_SIZE, None, min_label_transform, &format!("{name} {min_value:.1}"));
+    let max_label = format!("{max_value:.1}");
+    let max_label_x = bar.x1 - f64::from(TEXT_SIZE) * 0.6 * max_label.len() as f64;
+    text.add(scene, TEXT_SIZE, None, transform * Affine::translate((max_label_x, label_y)), &max_label);
 }
 
+// From blue to red through green, interpolated in the color math space, so that the middle isn't dimmer than the ends
 fn spectrum(position: f32, alpha: f32) -> Color {
-    Color::new([1.0 - position, (1.0 - (position - 0.5).abs() * 2.0), position, alpha])
+    let color_math = CONFIG.rendering.color_math;
+    let [r, g, b] =
+        [1.0 - position, 1.0 - (position - 0.5).abs() * 2.0, position].map(|value| color_math.encode(value));
+    Color::new([r, g, b, alpha])
+}
+
+// Highlights the inspected object and lists its properties in the top right corner, left of the vertical ruler
+fn draw_inspector(
+    scene: &mut Scene,
+    RenderingData {
+        positions,
+        velocities,
+        radii,
+        masses,
+        heat,
+        is_frozen,
+        high_contrast,
+        camera,
+        inspected,
+        ..
+    }: &RenderingData,
+) {
+    const TEXT_SIZE: f32 = 14.0;
+    const LINE_HEIGHT: f64 = 18.0;
+    const WIDTH: f64 = 260.0;
+    const MARGIN: f64 = 16.0;
+    const MIN_HIGHLIGHT_RADIUS: f32 = 8.0;
+
+    let Some(object_index) = *inspected else {
+        return;
+    };
+    let language = CONFIG.ui.language;
+    let center = camera.world_to_screen(positions[object_index]);
+    let highlight_radius =
+        (render_radius(radii[object_index], camera, *high_contrast) * camera.zoom + 4.0).max(MIN_HIGHLIGHT_RADIUS);
+    scene.stroke(
+        &Stroke::new(2.0),
+        Affine::IDENTITY,
+        css::YELLOW,
+        None,
+        &Circle::new((f64::from(center.x), f64::from(center.y)), f64::from(highlight_radius)),
+    );
+
+    let (position, velocity) = (positions[object_index], velocities[object_index]);
+    let mut lines = vec![
+        format!("{} {object_index}", Message::Object.text(language)),
+        format!("{} {:.3}, {:.3}", Message::Position.text(language), position.x, position.y),
+        format!("{} {:.3}, {:.3}", Message::Velocity.text(language), velocity.x, velocity.y),
+        format!("{} {:.3}", Message::Radius.text(language), radii[object_index]),
+        format!("{} {:.3}", Message::Mass.text(language), masses[object_index]),
+    ];
+    if let Some(heat) = heat.get(object_index) {
+        lines.push(format!("{} {heat:.3}", Message::Heat.text(language)));
+    }
+    if is_frozen[object_index] {
+        lines.push(Message::Frozen.text(language).to_string());
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let panel = Rect::from_origin_size(
+        (f64::from(camera.viewport_size.x) - VERTICAL_RULER_WIDTH - MARGIN - WIDTH, MARGIN),
+        (WIDTH, LINE_HEIGHT * lines.len() as f64 + 8.0),
+    );
+    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::new([0.0, 0.0, 0.0, 0.7]), None, &panel.to_rounded_rect(4.0));
+    let mut text = SimpleText::new();
+    for (line_index, line) in lines.iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let baseline = panel.y0 + LINE_HEIGHT * (line_index + 1) as f64;
+        text.add(scene, TEXT_SIZE, None, Affine::translate((panel.x0 + 8.0, baseline)), line);
+    }
+}
+
+fn draw_id_input(scene: &mut Scene, text: &mut SimpleText, input: &str, viewport_size: Vector2<f32>) {
+    const TEXT_SIZE: f32 = 18.0;
+    const SIZE: (f64, f64) = (320.0, 36.0);
+
+    let origin = (f64::from(viewport_size.x) / 2.0 - SIZE.0 / 2.0, f64::from(viewport_size.y) / 2.0 - SIZE.1 / 2.0);
+    let rect = Rect::from_origin_size(origin, SIZE);
+    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::new([0.15, 0.15, 0.2, 0.95]), None, &rect.to_rounded_rect(4.0));
+    let label = format!("{}: {input}_", Message::GoToObject.text(CONFIG.ui.language));
+    let label_position = (rect.x0 + 12.0, rect.center().y + f64::from(TEXT_SIZE) / 3.0);
+    text.add(scene, TEXT_SIZE, None, Affine::translate(label_position), &label);
 }
 
 fn draw_mouse_influence(scene: &mut Scene, mouse_position: Vector2<f32>, mouse_influence_radius: f32) {
@@ -600,11 +1855,246 @@ fn draw_mouse_influence(scene: &mut Scene, mouse_position: Vector2<f32>, mouse_i
     );
 }
 
-fn draw_aabbs(scene: &mut Scene, nodes: &[Node]) {
+// Along the bottom and the right edges, clear of the stats, in meters
+fn draw_rulers(scene: &mut Scene, text: &mut SimpleText, camera: &Camera) {
+    const TEXT_SIZE: f32 = 11.0;
+    const TICK_LENGTH: f64 = 6.0;
+    // Screen distance between the ticks
+    const TICK_SPACING: f32 = 100.0;
+
+    let units = &CONFIG.units;
+    let (width, height) = (f64::from(camera.viewport_size.x), f64::from(camera.viewport_size.y));
+    let (bottom, right) = (height - HORIZONTAL_RULER_HEIGHT, width - VERTICAL_RULER_WIDTH);
+    let background = Color::new([0.0, 0.0, 0.0, 0.6]);
+    scene.fill(Fill::NonZero, Affine::IDENTITY, background, None, &Rect::new(0.0, bottom, width, height));
+    scene.fill(Fill::NonZero, Affine::IDENTITY, background, None, &Rect::new(right, 0.0, width, bottom));
+
+    let region = camera.visible_region();
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let axis_ticks = |min: f32, max: f32, screen_length: f32| {
+        let (min, max) = (units.physical_length(min), units.physical_length(max));
+        let step = ruler::tick_step(max - min, (screen_length / TICK_SPACING) as usize);
+        (ruler::ticks(min, max, step), ruler::tick_precision(step))
+    };
+    let tick = |scene: &mut Scene, from: (f64, f64), to: (f64, f64)| {
+        scene.stroke(&Stroke::new(1.0), Affine::IDENTITY, css::WHITE, None, &kurbo::Line::new(from, to));
+    };
+    let (x_ticks, precision) = axis_ticks(region.topleft.x, region.bottomright.x, camera.viewport_size.x);
+    for x_tick in x_ticks {
+        let x = f64::from(camera.world_to_screen(Vector2::new(units.length(x_tick), 0.0)).x);
+        if x < right {
+            tick(scene, (x, bottom), (x, bottom + TICK_LENGTH));
+            let label = format!("{x_tick:.precision$}");
+            text.add(scene, TEXT_SIZE, None, Affine::translate((x + 3.0, height - 4.0)), &label);
+        }
+    }
+    let (y_ticks, precision) = axis_ticks(region.topleft.y, region.bottomright.y, camera.viewport_size.y);
+    for y_tick in y_ticks {
+        let y = f64::from(camera.world_to_screen(Vector2::new(0.0, units.length(y_tick))).y);
+        if y < bottom {
+            tick(scene, (right, y), (right + TICK_LENGTH, y));
+            let label = format!("{y_tick:.precision$}");
+            text.add(scene, TEXT_SIZE, None, Affine::translate((right + 3.0, y + f64::from(TEXT_SIZE) + 2.0)), &label);
+        }
+    }
+}
+
+// The world position under the cursor, in meters, to the precision of a pixel
+fn draw_cursor_coordinates(
+    scene: &mut Scene,
+    text: &mut SimpleText,
+    camera: &Camera,
+    mouse_position: Vector2<f32>,
+    inside_rulers: bool,
+) {
+    const TEXT_SIZE: f32 = 14.0;
+
+    let units = &CONFIG.units;
+    let position = camera.screen_to_world(mouse_position);
+    let precision = ruler::tick_precision(units.physical_length(1.0 / camera.zoom));
+    let label = format!(
+        "x {:.precision$} m, y {:.precision$} m",
+        units.physical_length(position.x),
+        units.physical_length(position.y)
+    );
+    // In the bottom right corner, clear of the color legend
+    let (right, bottom) = if inside_rulers {
+        (VERTICAL_RULER_WIDTH, HORIZONTAL_RULER_HEIGHT)
+    } else {
+        (0.0, 0.0)
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let label_width = f64::from(TEXT_SIZE) * 0.6 * label.len() as f64;
+    let position = (
+        f64::from(camera.viewport_size.x) - right - label_width - 8.0,
+        f64::from(camera.viewport_size.y) - bottom - 8.0,
+    );
+    text.add(scene, TEXT_SIZE, None, Affine::translate(position), &label);
+}
+
+fn draw_pause_menu(scene: &mut Scene, text: &mut SimpleText, menu: &PauseMenu, viewport_size: Vector2<f32>) {
+    const TEXT_SIZE: f32 = 18.0;
+    const ITEM_COLOR: Color = Color::new([0.15, 0.15, 0.2, 0.9]);
+    const SELECTED_ITEM_COLOR: Color = Color::new([0.3, 0.4, 0.6, 0.95]);
+
+    let language = CONFIG.ui.language;
+    let viewport = Rect::new(0.0, 0.0, f64::from(viewport_size.x), f64::from(viewport_size.y));
+    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::new([0.0, 0.0, 0.0, 0.5]), None, &viewport);
+    let mut items = PauseMenu::items(viewport_size).peekable();
+    if let Some((_, first)) = items.peek() {
+        let title_position = (f64::from(first.topleft.x), f64::from(first.topleft.y - PauseMenu::ITEM_SPACING * 2.0));
+        text.add(scene, TEXT_SIZE * 1.5, None, Affine::translate(title_position), Message::Paused.text(language));
+    }
+    for (item, aabb) in items {
+        let rect = Rect::new(
+            f64::from(aabb.topleft.x),
+            f64::from(aabb.topleft.y),
+            f64::from(aabb.bottomright.x),
+            f64::from(aabb.bottomright.y),
+        );
+        let color = if item == menu.selected() {
+            SELECTED_ITEM_COLOR
+        } else {
+            ITEM_COLOR
+        };
+        scene.fill(Fill::NonZero, Affine::IDENTITY, color, None, &rect.to_rounded_rect(4.0));
+        let label_position = (rect.x0 + 12.0, rect.center().y + f64::from(TEXT_SIZE) / 3.0);
+        text.add(scene, TEXT_SIZE, None, Affine::translate(label_position), Message::menu_item(item).text(language));
+    }
+}
+
+fn draw_stall_warning(scene: &mut Scene, text: &mut SimpleText, viewport_width: f64, stall: Duration) {
+    const TEXT_SIZE: f32 = 16.0;
+    const HEIGHT: f64 = 32.0;
+
+    scene.fill(Fill::NonZero, Affine::IDENTITY, css::DARK_RED, None, &Rect::new(0.0, 0.0, viewport_width, HEIGHT));
+    let message = format!(
+        "{} {:.0} s. {}",
+        Message::NotResponding.text(CONFIG.ui.language),
+        stall.as_secs_f32(),
+        Message::StallHint.text(CONFIG.ui.language)
+    );
+    text.add(scene, TEXT_SIZE, None, Affine::translate((8.0, HEIGHT / 2.0 + f64::from(TEXT_SIZE) / 3.0)), &message);
+}
+
+fn draw_editor(
+    scene: &mut Scene,
+    text: &mut SimpleText,
+    editor: &Editor,
+    camera: &Camera,
+    mouse_position: Vector2<f32>,
+) {
+    const PREVIEW_COLOR: Color = Color::new([1.0, 1.0, 0.5, 0.8]);
+
+    let transform = camera_transform(camera);
+    let stroke = Stroke::new(1.0 / f64::from(camera.zoom));
+    let circle = |position: Vector2<f32>, radius: f32| {
+        Circle::new((f64::from(position.x), f64::from(position.y)), f64::from(radius))
+    };
+    // The items are in physical units
+    let units = &CONFIG.units;
+    match editor.preview(camera.screen_to_world(mouse_position)) {
+        Some(SceneItem::Particle(particle)) => {
+            let shape = circle(units.position(particle.position), units.length(particle.radius));
+            scene.fill(Fill::NonZero, transform, PREVIEW_COLOR, None, &shape);
+        }
+        Some(SceneItem::Brick(brick)) => {
+            let (position, size) = (units.position(brick.position), units.position(brick.size));
+            let origin = (f64::from(position.x), f64::from(position.y));
+            let size = (f64::from(size.x), f64::from(size.y));
+            scene.stroke(&stroke, transform, PREVIEW_COLOR, None, &Rect::from_origin_size(origin, size));
+        }
+        Some(SceneItem::Ball(ball)) => {
+            let shape = circle(units.position(ball.position), units.length(ball.radius));
+            scene.stroke(&stroke, transform, PREVIEW_COLOR, None, &shape);
+        }
+        None => {}
+    }
+    let label_position = kurbo::Vec2::new(f64::from(CONFIG.window.width) - 360.0, 20.0);
+    text.add(
+        scene,
+        14.0,
+        None,
+        Affine::translate(label_position),
+        "EDIT: click, Shift+drag brick, Ctrl+drag ball, Ctrl+S save",
+    );
+}
+
+fn editor_scene() -> anyhow::Result<SceneFile> {
+    let path = Path::new(&CONFIG.editor.scene);
+    if path.exists() {
+        SceneFile::load(path)
+    } else {
+        Ok(SceneFile::default())
+    }
+}
+
+// Arrows on a screen-space grid, scaled so that the strongest one spans a grid cell
+fn draw_wind(scene: &mut Scene, transform: Affine, camera: &Camera, wind: Wind, time: f32) {
+    const GRID_STEP: f32 = 40.0;
+
+    let region = camera.visible_region();
+    let step = GRID_STEP / camera.zoom;
+    let mut samples = Vec::new();
+    let mut y = region.topleft.y + step / 2.0;
+    while y < region.bottomright.y {
+        let mut x = region.topleft.x + step / 2.0;
+        while x < region.bottomright.x {
+            let position = Vector2::new(x, y);
+            samples.push((position, wind.acceleration_at(position, time)));
+            x += step;
+        }
+        y += step;
+    }
+    let max_magnitude = samples.iter().map(|(_, acceleration)| acceleration.magnitude()).fold(0.0, f32::max);
+    if max_magnitude == 0.0 {
+        return;
+    }
+
+    let stroke = Stroke::new(1.0 / f64::from(camera.zoom));
+    for (position, acceleration) in samples {
+        let arrow = acceleration * (step * 0.9 / max_magnitude);
+        scene.stroke(&stroke, transform, css::LIGHT_SKY_BLUE, None, &arrow_path(position - arrow / 2.0, arrow));
+    }
+}
+
+fn arrow_path(tail: Vector2<f32>, arrow: Vector2<f32>) -> kurbo::BezPath {
+    let point = |position: Vector2<f32>| kurbo::Point::new(f64::from(position.x), f64::from(position.y));
+    let tip = tail + arrow;
+    let head = arrow * 0.3;
+    let head_side = Vector2::new(-head.y, head.x) * 0.5;
+    let mut path = kurbo::BezPath::new();
+    path.move_to(point(tail));
+    path.line_to(point(tip));
+    path.move_to(point(tip - head + head_side));
+    path.line_to(point(tip));
+    path.line_to(point(tip - head - head_side));
+    path
+}
+
+// Faint lines across the world at the tops of the gravity zones
+fn draw_gravity_zones(scene: &mut Scene, transform: Affine, constraints: AABB, heights: &[f32]) {
+    const COLOR: Color = Color::new([1.0, 1.0, 1.0, 0.2]);
+
+    for &height in heights {
+        scene.stroke(
+            &Stroke::default(),
+            transform,
+            COLOR,
+            None,
+            &kurbo::Line::new(
+                (f64::from(constraints.topleft.x), f64::from(height)),
+                (f64::from(constraints.bottomright.x), f64::from(height)),
+            ),
+        );
+    }
+}
+
+fn draw_aabbs(scene: &mut Scene, transform: Affine, nodes: &[Node]) {
     for &Node { aabb, .. } in nodes {
         scene.stroke(
             &Stroke::default(),
-            Affine::IDENTITY,
+            transform,
             css::LIGHT_GRAY,
             None,
             &Rect {
@@ -622,22 +2112,250 @@ fn draw_stats(
     text: &mut SimpleText,
     (fps, min_fps): (usize, usize),
     stats: &Stats,
+    frame_stats: &FrameStats,
+    cpu_utilizations: &CpuUtilizations,
     gpu_compute_options: GpuComputeOptions,
+    auto_gpu_compute: bool,
+    speed_multiplier: f32,
+    high_contrast: bool,
+    viewport_size: Vector2<f32>,
+    scale: f64,
 ) -> anyhow::Result<()> {
     const TEXT_SIZE: f32 = 16.0;
 
     let buffer = &mut String::new();
-    write_stats(buffer, (fps, min_fps), stats, gpu_compute_options)?;
-    text.add(scene, TEXT_SIZE, None, Affine::translate((0.0, f64::from(TEXT_SIZE))), buffer);
+    write_stats(
+        buffer,
+        (fps, min_fps),
+        stats,
+        frame_stats,
+        cpu_utilizations,
+        gpu_compute_options,
+        auto_gpu_compute,
+        speed_multiplier,
+    )?;
+    let (width, height) = text.measure(TEXT_SIZE, buffer);
+    let size = kurbo::Size::new(f64::from(width), f64::from(height));
+    let transform = overlay_transform(CONFIG.ui.overlay_corner, size, viewport_size, scale)
+        * Affine::translate((0.0, f64::from(TEXT_SIZE)));
+    if high_contrast {
+        text.add_bold(scene, TEXT_SIZE, None, transform, buffer);
+    } else {
+        text.add(scene, TEXT_SIZE, None, transform, buffer);
+    }
 
     Ok(())
 }
 
+// From the scale factor of the window, unless set in the config
+fn overlay_scale(scale_factor: f64) -> f64 {
+    CONFIG.ui.overlay_scale.map_or(scale_factor, f64::from)
+}
+
+// Scales an overlay of the given unscaled size and places it into the corner of the viewport
+fn overlay_transform(corner: OverlayCorner, size: kurbo::Size, viewport_size: Vector2<f32>, scale: f64) -> Affine {
+    let x = if corner.is_right() {
+        f64::from(viewport_size.x) - size.width * scale
+    } else {
+        0.0
+    };
+    let y = if corner.is_bottom() {
+        f64::from(viewport_size.y) - size.height * scale
+    } else {
+        0.0
+    };
+    Affine::translate((x, y)) * Affine::scale(scale)
+}
+
+// Sampled by the threads themselves, except for the whole process, which the app samples every frame
+#[derive(Default)]
+struct CpuUtilizations {
+    simulation: CpuUtilization,
+    rendering: CpuUtilization,
+    field: CpuUtilization,
+    process: CpuUtilization,
+}
+
+// Durations of the stages of the frames of the main window
+struct FrameStats {
+    // Of the simulation scene in the rendering thread
+    scene_build_duration: DurationStat,
+    // Encoding and submitting the scene to the GPU
+    render_duration: DurationStat,
+    // Waiting for the surface texture and presenting it
+    present_duration: DurationStat,
+}
+
+impl FrameStats {
+    fn new(window: usize) -> Self {
+        Self {
+            scene_build_duration: DurationStat::new(window),
+            render_duration: DurationStat::new(window),
+            present_duration: DurationStat::new(window),
+        }
+    }
+}
+
+// Recent stats plotted in the stats window
+struct StatsPlots {
+    fps: Series,
+    // In milliseconds, in the order of STEP_DURATION_MESSAGES
+    step_durations: [Series; 5],
+    // In milliseconds, in the order of FRAME_DURATION_MESSAGES
+    frame_durations: [Series; 3],
+    // Of the last pushed stats, which are resent unchanged while paused
+    step_count: Option<usize>,
+}
+
+impl StatsPlots {
+    const CAPACITY: usize = 300;
+    const STEP_DURATION_MESSAGES: [Message; 5] = [
+        Message::Integration,
+        Message::Collision,
+        Message::Bvh,
+        Message::Constraints,
+        Message::Total,
+    ];
+    const FRAME_DURATION_MESSAGES: [Message; 3] = [Message::SceneBuild, Message::Render, Message::Present];
+
+    fn new() -> Self {
+        Self {
+            fps: Series::new(Self::CAPACITY),
+            step_durations: array::from_fn(|_| Series::new(Self::CAPACITY)),
+            frame_durations: array::from_fn(|_| Series::new(Self::CAPACITY)),
+            step_count: None,
+        }
+    }
+
+    fn push_stats(&mut self, stats: &Stats) {
+        if self.step_count == Some(stats.step_count) {
+            return;
+        }
+        self.step_count = Some(stats.step_count);
+        let durations = [
+            &stats.integration_duration,
+            &stats.collisions_duration,
+            &stats.bvh_duration,
+            &stats.constraints_duration,
+            &stats.total_duration,
+        ];
+        for (series, duration) in zip(&mut self.step_durations, durations) {
+            series.push(duration.current.as_secs_f32() * 1000.0);
+        }
+    }
+
+    fn push_frame_stats(&mut self, frame_stats: &FrameStats) {
+        let durations = [
+            &frame_stats.scene_build_duration,
+            &frame_stats.render_duration,
+            &frame_stats.present_duration,
+        ];
+        for (series, duration) in zip(&mut self.frame_durations, durations) {
+            series.push(duration.current.as_secs_f32() * 1000.0);
+        }
+    }
+}
+
+// The stats text on the left, and on the right the plots of the recent stats above a histogram of the recent total
+// step durations
+fn draw_stats_window(
+    scene: &mut Scene,
+    text: &mut SimpleText,
+    plots: &StatsPlots,
+    stats_text: &str,
+    viewport_size: Vector2<f32>,
+    high_contrast: bool,
+) {
+    const TEXT_SIZE: f32 = 14.0;
+    const TEXT_WIDTH: f64 = 440.0;
+    const MARGIN: f64 = 8.0;
+    const HISTOGRAM_BIN_COUNT: usize = 40;
+
+    let t = |message: Message| message.text(CONFIG.ui.language);
+    let (width, height) = (f64::from(viewport_size.x), f64::from(viewport_size.y));
+    scene.fill(Fill::NonZero, Affine::IDENTITY, css::BLACK, None, &Rect::new(0.0, 0.0, width, height));
+    let transform = Affine::translate((MARGIN, f64::from(TEXT_SIZE) + MARGIN));
+    if high_contrast {
+        text.add_bold(scene, TEXT_SIZE, None, transform, stats_text);
+    } else {
+        text.add(scene, TEXT_SIZE, None, transform, stats_text);
+    }
+
+    let durations = zip(StatsPlots::STEP_DURATION_MESSAGES, &plots.step_durations)
+        .chain(zip(StatsPlots::FRAME_DURATION_MESSAGES, &plots.frame_durations));
+    let plotted =
+        once((t(Message::Fps), &plots.fps, "")).chain(durations.map(|(message, series)| (t(message), series, " ms")));
+    let panel_count = plots.step_durations.len() + plots.frame_durations.len() + 2;
+    #[allow(clippy::cast_precision_loss)]
+    let panel_height = (height - MARGIN) / panel_count as f64 - MARGIN;
+    let panel = |index: usize| {
+        #[allow(clippy::cast_precision_loss)]
+        let y0 = MARGIN + index as f64 * (panel_height + MARGIN);
+        Rect::new(TEXT_WIDTH, y0, width - MARGIN, y0 + panel_height)
+    };
+    for (index, (name, series, unit)) in plotted.enumerate() {
+        draw_plot(scene, text, panel(index), name, series, unit);
+    }
+    let total_durations = &plots.step_durations[plots.step_durations.len() - 1];
+    if let Some(histogram) = Histogram::new(total_durations.values().iter().copied(), HISTOGRAM_BIN_COUNT) {
+        let label =
+            format!("{} {}: {:.2}..{:.2} ms", t(Message::Total), t(Message::Histogram), histogram.min, histogram.max);
+        draw_histogram(scene, text, panel(panel_count - 1), &label, &histogram);
+    }
+}
+
+fn draw_plot(scene: &mut Scene, text: &mut SimpleText, rect: Rect, name: &str, series: &Series, unit: &str) {
+    const TEXT_SIZE: f32 = 12.0;
+
+    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::new([0.1, 0.1, 0.12, 1.0]), None, &rect);
+    let (Some(&last), Some(max)) = (series.values().back(), series.max()) else {
+        return;
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let x_step = rect.width() / (series.capacity() - 1).max(1) as f64;
+    let y_scale = rect.height() / f64::from(max.max(f32::EPSILON));
+    let mut path = kurbo::BezPath::new();
+    for (index, &value) in series.values().iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let point = (rect.x0 + index as f64 * x_step, rect.y1 - f64::from(value) * y_scale);
+        if index == 0 {
+            path.move_to(point);
+        } else {
+            path.line_to(point);
+        }
+    }
+    scene.stroke(&Stroke::new(1.5), Affine::IDENTITY, css::LIGHT_GREEN, None, &path);
+    let label = format!("{name}: {last:.2}{unit} ({} {max:.2}{unit})", Message::Max.text(CONFIG.ui.language));
+    text.add(scene, TEXT_SIZE, None, Affine::translate((rect.x0 + 4.0, rect.y0 + f64::from(TEXT_SIZE))), &label);
+}
+
+fn draw_histogram(scene: &mut Scene, text: &mut SimpleText, rect: Rect, label: &str, histogram: &Histogram) {
+    const TEXT_SIZE: f32 = 12.0;
+
+    scene.fill(Fill::NonZero, Affine::IDENTITY, Color::new([0.1, 0.1, 0.12, 1.0]), None, &rect);
+    let highest_count = histogram.counts.iter().copied().max().unwrap_or(0).max(1);
+    #[allow(clippy::cast_precision_loss)]
+    let bar_width = rect.width() / histogram.counts.len() as f64;
+    for (index, &count) in histogram.counts.iter().enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let bar_height = rect.height() * count as f64 / highest_count as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let x0 = rect.x0 + index as f64 * bar_width;
+        let bar = Rect::new(x0 + 1.0, rect.y1 - bar_height, x0 + bar_width - 1.0, rect.y1);
+        scene.fill(Fill::NonZero, Affine::IDENTITY, css::LIGHT_SKY_BLUE, None, &bar);
+    }
+    text.add(scene, TEXT_SIZE, None, Affine::translate((rect.x0 + 4.0, rect.y0 + f64::from(TEXT_SIZE))), label);
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone)]
 enum AppEvent {
     StatsUpdated(Stats),
+    GpuComputeOptionsSelected(GpuComputeOptions),
+    // The automatic camera moved
+    CameraMoved(Camera),
     RequestRedraw,
+    Paused(bool),
     Exit,
 }
 
@@ -646,12 +2364,48 @@ impl Debug for AppEvent {
         write!(f, "AppEvent::")?;
         match self {
             Self::StatsUpdated(_) => write!(f, "StatsUpdated(...)"),
+            Self::GpuComputeOptionsSelected(options) => write!(f, "GpuComputeOptionsSelected({options:?})"),
+            Self::CameraMoved(camera) => write!(f, "CameraMoved({camera:?})"),
             Self::RequestRedraw => f.write_str("RedrawRequest"),
+            Self::Paused(paused) => write!(f, "Paused({paused})"),
             Self::Exit => f.write_str("Exit"),
         }
     }
 }
 
+// Messages between the threads of the app, one topic per kind of message. The app is notified through its event loop,
+// which also wakes it up.
+struct EventBus {
+    // Commands of the app to the simulation thread
+    sim_control: Topic<SimulationThreadEvent>,
+    // Data to draw from the simulation thread and commands of the app to the rendering thread
+    render_data: Topic<RenderingThreadEvent>,
+    // The scene of the simulation and how long it took to build, from the rendering thread to the app
+    scenes: Topic<(Scene, Duration)>,
+    // Tells the simulation thread that a scene was built, so that it sends the next data to draw
+    scene_built: Topic<()>,
+    app_notify: EventLoopProxy<AppEvent>,
+}
+
+impl EventBus {
+    fn new(app_notify: EventLoopProxy<AppEvent>) -> Self {
+        Self {
+            sim_control: Topic::new(Backpressure::Queue),
+            render_data: Topic::new(Backpressure::Queue),
+            scenes: Topic::new(Backpressure::Latest),
+            scene_built: Topic::new(Backpressure::Latest),
+            app_notify,
+        }
+    }
+
+    // Fails only when the app has exited
+    fn notify_app(&self, event: AppEvent) {
+        if let Err(e) = self.app_notify.send_event(event) {
+            eprintln!("Failed to send event {:?}: {e}", e.0);
+        }
+    }
+}
+
 #[derive(Debug)]
 enum SimulationThreadEvent {
     Exit,
@@ -660,16 +2414,70 @@ enum SimulationThreadEvent {
     ToggleDrawAabbs,
     SetColorSource(ColorSource),
     SetGpuComputeOptions(GpuComputeOptions),
-    UnidirectionalKick {
+    SetAutoGpuCompute(bool),
+    SetCamera(Camera),
+    SetAutoCamera(bool),
+    // Follows the object under the mouse with the camera, or stops following if there is none
+    Follow {
+        mouse_position: Vector2<f32>,
+        mouse_influence_radius: f32,
+    },
+    StopFollowing,
+    // Follows the object with the given index and pins its inspector, or unpins it
+    Inspect(Option<usize>),
+    SetSpeedMultiplier(f32),
+    // Objects under the mouse, or only the nearest one pinned to the mouse, are dragged by a spring until released
+    Grab {
+        mouse_position: Vector2<f32>,
+        mouse_influence_radius: f32,
+        nearest_only: bool,
+    },
+    MoveGrab(Vector2<f32>),
+    Release,
+    // Objects within the radius get a radial velocity change, away from the mouse if positive and towards it if
+    // negative, which fades out towards the edge of the radius
+    Kick {
         mouse_position: Vector2<f32>,
         mouse_influence_radius: f32,
+        speed: f32,
     },
     ToggleDrawEdf,
+    ToggleDrawWind,
+    ToggleAdditiveBlending,
+    ToggleDrawPlanetVectors,
+    ToggleHighContrast,
+    ToggleReducedMotion,
+    // Replaces the scene with the demo or the session snapshot
+    ResetScene,
+    SaveSnapshot,
+    LoadSnapshot,
+    SetQuality(Quality),
+    Freeze {
+        mouse_position: Vector2<f32>,
+        mouse_influence_radius: f32,
+        is_frozen: bool,
+    },
+    SetEditMode(bool),
+    // The window is unfocused or minimized
+    SetBackground(bool),
+    AddSceneItem(SceneItem),
+    RemoveObjects {
+        position: Vector2<f32>,
+        radius: f32,
+    },
+    Undo,
+    Redo,
+    ToggleConstraintBouncing,
 }
 
 enum RenderingThreadEvent {
     Draw(RenderingData),
     SetRendering(bool),
+    // The pool building the scene; shared with the physics if the thread counts match
+    SetThreadPool(Arc<ThreadPool>),
+    SetOverlayScale(f64),
+    ToggleTrails,
+    SaveTrails,
     Exit,
 }
 
@@ -678,6 +2486,10 @@ impl Debug for RenderingThreadEvent {
         match self {
             RenderingThreadEvent::Draw(_) => f.write_str("SetData(...)"),
             RenderingThreadEvent::SetRendering(enabled) => write!(f, "EnableRendering({enabled})"),
+            RenderingThreadEvent::SetThreadPool(pool) => write!(f, "SetThreadPool({})", pool.current_num_threads()),
+            RenderingThreadEvent::SetOverlayScale(scale) => write!(f, "SetOverlayScale({scale})"),
+            RenderingThreadEvent::ToggleTrails => f.write_str("ToggleTrails"),
+            RenderingThreadEvent::SaveTrails => f.write_str("SaveTrails"),
             RenderingThreadEvent::Exit => f.write_str("Exit"),
         }
     }
@@ -688,17 +2500,33 @@ struct RenderingData {
     positions: Vec<Vector2<f32>>,
     velocities: Vec<Vector2<f32>>,
     radii: Vec<f32>,
-    colors: Vec<Option<Color>>,
+    masses: Vec<f32>,
+    // Packed, a quarter of the size of the colors of the objects, which the rendering doesn't need the precision of
+    colors: Vec<Option<Rgba8>>,
+    // Empty unless heat is tracked
+    heat: Vec<f32>,
+    is_frozen: Vec<bool>,
     particle_range: Range<usize>,
     planet_range: Range<usize>,
     color_source: ColorSource,
     draw_ids: bool,
     draw_aabbs: bool,
+    draw_planet_vectors: bool,
+    high_contrast: bool,
+    additive_blending: bool,
+    // Empty if the planets haven't been integrated yet, e.g. in event-driven mode
+    planet_accelerations: Vec<Vector2<f32>>,
     constraints: AABB,
+    gravity_zone_heights: Vec<f32>,
     draw_edf: bool,
-    edf: Array2<f32>,
-    edf_cell_size: f32,
+    edf: EnergyDensityField,
+    // The wind and the simulation time, if the wind is shown
+    wind: Option<(Wind, f32)>,
     bvh: Bvh,
+    camera: Camera,
+    quality: Quality,
+    // The object whose inspector is pinned
+    inspected: Option<usize>,
 }
 
 struct VelloApp<'s> {
@@ -708,23 +2536,232 @@ struct VelloApp<'s> {
     // Whilst suspended, we drop `render_state`, but need to keep the same window.
     // If render_state exists, we must store the window in it, to maintain drop order
     cached_window: Option<Arc<Window>>,
+    // Shows the stats and their plots instead of the main window
+    stats_window: Option<RenderState<'s>>,
+    open_stats_window_on_resume: bool,
+    stats_scene: Scene,
+    stats_plots: StatsPlots,
     scene: Scene,
     simulation_scene: Scene,
     frame_count: usize,
     fps_calculator: FpsCalculator,
+    frame_stats: FrameStats,
+    cpu_utilizations: Arc<CpuUtilizations>,
+    process_cpu_meter: CpuMeter,
     last_fps: usize,
     min_fps: usize,
     mouse_position: Vector2<f32>,
     mouse_influence_radius: f32,
     text: SimpleText,
-    simulation_event_sender: mpsc::Sender<SimulationThreadEvent>,
-    rendering_event_queue: &'s SegQueue<RenderingThreadEvent>,
+    bus: &'s EventBus,
     stats: Stats,
     ready_to_exit: Arc<Barrier>,
     gpu_compute_options: GpuComputeOptions,
-    redraw_job_queue: &'s ArrayQueue<Scene>,
-    redraw_result_queue: &'s ArrayQueue<()>,
+    auto_gpu_compute: bool,
+    // Index into SPEED_MULTIPLIERS
+    speed_multiplier_index: usize,
+    simulation_heartbeat: Arc<Heartbeat>,
     rendering_enabled: bool,
+    camera: Camera,
+    // Mirror the automatic and the follow camera of the simulation thread, which sends the camera back every frame
+    auto_camera: bool,
+    following: bool,
+    modifiers: ModifiersState,
+    panning: bool,
+    // Objects are dragged with the left button
+    grabbing: bool,
+    // Mirrors the high-contrast mode of the simulation thread
+    high_contrast: bool,
+    // The pause menu is shown while the simulation is paused, except in the edit mode
+    paused: bool,
+    pause_menu: PauseMenu,
+    // The stats, the mouse influence, the cursor coordinates and the rulers
+    show_overlays: bool,
+    show_rulers: bool,
+    // Of the stats and the color legend in the main window
+    overlay_scale: f64,
+    // Of the main window; it's in the background unless focused and visible
+    focused: bool,
+    minimized: bool,
+    background: bool,
+    quality_controller: Option<QualityController>,
+    edit_mode: bool,
+    editor: Editor,
+    // The object index typed after /, which captures the keyboard until Enter or Escape
+    id_input: Option<String>,
+    // The scene of the editor before every interactive edit, if the edit changed it
+    history: History<Option<SceneFile>>,
+}
+
+impl VelloApp<'_> {
+    fn simulation_stall(&self) -> Option<Duration> {
+        self.simulation_heartbeat.stall(Duration::from_secs_f32(CONFIG.simulation.watchdog_timeout))
+    }
+
+    fn change_speed(&mut self, step: isize) {
+        self.speed_multiplier_index =
+            self.speed_multiplier_index.saturating_add_signed(step).min(SPEED_MULTIPLIERS.len() - 1);
+        self.bus
+            .sim_control
+            .publish(SimulationThreadEvent::SetSpeedMultiplier(SPEED_MULTIPLIERS[self.speed_multiplier_index]));
+        request_redraw(self.state.as_ref());
+    }
+
+    fn exit(&mut self, event_loop: &ActiveEventLoop) {
+        self.bus.sim_control.publish(SimulationThreadEvent::Exit);
+        self.bus.render_data.publish(RenderingThreadEvent::Exit);
+        self.ready_to_exit.wait();
+        event_loop.exit();
+    }
+
+    fn is_pause_menu_visible(&self) -> bool {
+        self.paused && !self.edit_mode
+    }
+
+    fn activate_menu_item(&mut self, item: MenuItem, event_loop: &ActiveEventLoop) {
+        match item {
+            MenuItem::Resume => {
+                self.bus.sim_control.publish(SimulationThreadEvent::ToggleAdvanceTime);
+            }
+            // The edits can't be undone in the new scene
+            MenuItem::ResetScene => {
+                self.history.clear();
+                self.bus.sim_control.publish(SimulationThreadEvent::ResetScene);
+            }
+            MenuItem::SaveSnapshot => self.bus.sim_control.publish(SimulationThreadEvent::SaveSnapshot),
+            MenuItem::LoadSnapshot => {
+                self.history.clear();
+                self.bus.sim_control.publish(SimulationThreadEvent::LoadSnapshot);
+            }
+            MenuItem::ToggleOverlays => self.show_overlays = !self.show_overlays,
+            MenuItem::Quit => self.exit(event_loop),
+        }
+        request_redraw(self.state.as_ref());
+    }
+
+    fn id_input_key(&mut self, key: Key<&str>) {
+        let Some(input) = &mut self.id_input else {
+            return;
+        };
+        match key {
+            Key::Character(digits) if digits.chars().all(|c| c.is_ascii_digit()) => input.push_str(digits),
+            Key::Named(NamedKey::Backspace) => {
+                input.pop();
+            }
+            Key::Named(NamedKey::Escape) => self.id_input = None,
+            // Nothing typed unpins the inspector
+            Key::Named(NamedKey::Enter) if input.is_empty() => {
+                self.id_input = None;
+                self.bus.sim_control.publish(SimulationThreadEvent::Inspect(None));
+            }
+            Key::Named(NamedKey::Enter) => {
+                if let Ok(object_index) = input.parse() {
+                    if self.auto_camera {
+                        self.auto_camera = false;
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetAutoCamera(false));
+                    }
+                    self.following = true;
+                    self.bus.sim_control.publish(SimulationThreadEvent::Inspect(Some(object_index)));
+                }
+                self.id_input = None;
+            }
+            _ => return,
+        }
+        request_redraw(self.state.as_ref());
+    }
+
+    fn toggle_stats_window(&mut self, event_loop: &ActiveEventLoop) {
+        if self.stats_window.take().is_none() {
+            let window = Arc::new(event_loop.create_window(stats_window_attributes()).unwrap());
+            self.stats_window = Some(create_render_state(&mut self.context, &mut self.renderers, window));
+        }
+        request_redraw(self.state.as_ref());
+    }
+
+    fn stats_window_event(&mut self, event: WindowEvent) {
+        match event {
+            WindowEvent::CloseRequested => {
+                self.stats_window = None;
+                request_redraw(self.state.as_ref());
+            }
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && event.logical_key.as_ref() == Key::Character("d") =>
+            {
+                self.stats_window = None;
+                request_redraw(self.state.as_ref());
+            }
+            WindowEvent::Resized(size) => {
+                if let Some(RenderState { surface, window }) = &mut self.stats_window {
+                    self.context.resize_surface(surface, size.width, size.height);
+                    window.request_redraw();
+                }
+            }
+            WindowEvent::RedrawRequested => {
+                if let Some(RenderState { surface, window }) = &self.stats_window {
+                    let mut stats_text = String::new();
+                    write_stats(
+                        &mut stats_text,
+                        (self.last_fps, self.min_fps),
+                        &self.stats,
+                        &self.frame_stats,
+                        &self.cpu_utilizations,
+                        self.gpu_compute_options,
+                        self.auto_gpu_compute,
+                        SPEED_MULTIPLIERS[self.speed_multiplier_index],
+                    )
+                    .expect("failed to write stats");
+                    self.stats_scene.reset();
+                    #[allow(clippy::cast_precision_loss)]
+                    let viewport_size = Vector2::new(surface.config.width as f32, surface.config.height as f32);
+                    // Laid out in unscaled pixels
+                    let scale = overlay_scale(window.scale_factor());
+                    let mut unscaled_scene = Scene::new();
+                    #[allow(clippy::cast_possible_truncation)]
+                    draw_stats_window(
+                        &mut unscaled_scene,
+                        &mut self.text,
+                        &self.stats_plots,
+                        &stats_text,
+                        viewport_size / scale as f32,
+                        self.high_contrast,
+                    );
+                    self.stats_scene.append(&unscaled_scene, Some(Affine::scale(scale)));
+
+                    let renderer = self.renderers[surface.dev_id].as_mut().expect("failed to get renderer");
+                    let device_handle = &self.context.devices[surface.dev_id];
+                    let surface_texture =
+                        surface.surface.get_current_texture().expect("failed to get current surface texture");
+                    render_scene(&self.stats_scene, surface, &surface_texture, renderer, device_handle);
+                    surface_texture.present();
+                    device_handle.device.poll(Maintain::Poll);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn update_background(&mut self) {
+        let background = !self.focused || self.minimized;
+        if background != self.background {
+            self.background = background;
+            self.bus.sim_control.publish(SimulationThreadEvent::SetBackground(background));
+        }
+    }
+
+    fn set_overlay_scale(&mut self, scale: f64) {
+        self.overlay_scale = scale;
+        self.bus.render_data.publish(RenderingThreadEvent::SetOverlayScale(scale));
+    }
+
+    // Panning and zooming by hand turn off the automatic camera
+    fn camera_updated(&mut self) {
+        if self.auto_camera {
+            self.auto_camera = false;
+            self.bus.sim_control.publish(SimulationThreadEvent::SetAutoCamera(false));
+        }
+        self.bus.sim_control.publish(SimulationThreadEvent::SetCamera(self.camera));
+        request_redraw(self.state.as_ref());
+    }
 }
 
 impl ApplicationHandler<AppEvent> for VelloApp<'_> {
@@ -732,45 +2769,23 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
         if self.state.is_some() {
             return;
         }
-        let window = self.cached_window.take().unwrap_or_else(|| {
-            Arc::new(
-                event_loop
-                    .create_window(
-                        Window::default_attributes()
-                            .with_inner_size(PhysicalSize::new(CONFIG.window.width, CONFIG.window.height))
-                            .with_resizable(false)
-                            .with_title(format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
-                    )
-                    .unwrap(),
-            )
-        });
-        let size = window.inner_size();
-        let surface_future =
-            self.context.create_surface(window.clone(), size.width, size.height, PresentMode::AutoVsync);
-        // We need to block here, in case a Suspended event appeared
-        let surface = block_on(surface_future).expect("failed to create surface");
-        self.state = {
-            let render_state = RenderState { surface, window };
-            self.renderers.resize_with(self.context.devices.len(), || None);
-            let id = render_state.surface.dev_id;
-            self.renderers[id].get_or_insert_with(|| {
-                Renderer::new(
-                    &self.context.devices[id].device,
-                    RendererOptions {
-                        surface_format: Some(render_state.surface.format),
-                        use_cpu: false,
-                        antialiasing_support: AaSupport::area_only(),
-                        num_init_threads: NonZero::new(2),
-                    },
-                )
-                .map_err(|e| anyhow!("{e}"))
-                .expect("failed to create renderer")
-            });
-            Some(render_state)
-        };
+        let window = self
+            .cached_window
+            .take()
+            .unwrap_or_else(|| Arc::new(event_loop.create_window(window_attributes()).unwrap()));
+        self.set_overlay_scale(overlay_scale(window.scale_factor()));
+        self.state = Some(create_render_state(&mut self.context, &mut self.renderers, window));
+        if self.open_stats_window_on_resume {
+            self.open_stats_window_on_resume = false;
+            self.toggle_stats_window(event_loop);
+        }
     }
 
     fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
+        if self.stats_window.as_ref().is_some_and(|stats_window| stats_window.window.id() == window_id) {
+            self.stats_window_event(event);
+            return;
+        }
         let Some(render_state) = &mut self.state else {
             return;
         };
@@ -778,80 +2793,176 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
             return;
         }
         match event {
-            WindowEvent::CloseRequested => {
-                self.simulation_event_sender
-                    .send(SimulationThreadEvent::Exit)
-                    .expect("failed to send simulation thread Exit event");
-                self.rendering_event_queue.push(RenderingThreadEvent::Exit);
-                self.ready_to_exit.wait();
-                event_loop.exit();
+            WindowEvent::CloseRequested => self.exit(event_loop),
+            WindowEvent::KeyboardInput { event, .. }
+                if event.state == ElementState::Pressed && self.id_input.is_some() =>
+            {
+                self.id_input_key(event.logical_key.as_ref());
             }
-            WindowEvent::KeyboardInput { event, .. } => {
-                if event.state == ElementState::Pressed {
-                    match event.logical_key.as_ref() {
-                        Key::Named(NamedKey::Escape) => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::Exit)
-                                .expect("failed to send simulation thread Exit event");
-                            self.rendering_event_queue.push(RenderingThreadEvent::Exit);
-                            self.ready_to_exit.wait();
-                            event_loop.exit();
-                        }
-                        Key::Named(NamedKey::Space) => {
-                            self.simulation_event_sender.send(SimulationThreadEvent::ToggleAdvanceTime).unwrap();
-                        }
-                        Key::Character("g") => {
-                            self.simulation_event_sender.send(SimulationThreadEvent::ToggleDrawAabbs).unwrap();
-                        }
-                        Key::Character("i") => {
-                            self.simulation_event_sender.send(SimulationThreadEvent::ToggleDrawIds).unwrap();
-                        }
-                        Key::Character("1") => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetColorSource(ColorSource::None))
-                                .unwrap();
-                        }
-                        Key::Character("2") => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetColorSource(ColorSource::Default))
-                                .unwrap();
-                        }
-                        Key::Character("3") => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetColorSource(ColorSource::Demo))
-                                .unwrap();
-                        }
-                        Key::Character("4") => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetColorSource(ColorSource::Velocity))
-                                .unwrap();
-                        }
-                        Key::Character("5") => {
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetColorSource(ColorSource::Dark))
-                                .unwrap();
-                        }
-                        Key::Character("l") => {
-                            self.gpu_compute_options.integration = !self.gpu_compute_options.integration;
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetGpuComputeOptions(self.gpu_compute_options))
-                                .unwrap();
+            WindowEvent::KeyboardInput { event, .. } if event.state == ElementState::Pressed => {
+                match event.logical_key.as_ref() {
+                    Key::Named(NamedKey::Escape) => self.exit(event_loop),
+                    Key::Named(NamedKey::ArrowUp) if self.is_pause_menu_visible() => {
+                        self.pause_menu.select_previous();
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Named(NamedKey::ArrowDown) if self.is_pause_menu_visible() => {
+                        self.pause_menu.select_next();
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Named(NamedKey::Enter) if self.is_pause_menu_visible() => {
+                        self.activate_menu_item(self.pause_menu.selected(), event_loop);
+                    }
+                    Key::Named(NamedKey::Space) => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleAdvanceTime);
+                    }
+                    Key::Character("g") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleDrawAabbs);
+                    }
+                    Key::Character("i") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleDrawIds);
+                    }
+                    Key::Character("1") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::None));
+                    }
+                    Key::Character("2") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::Default));
+                    }
+                    Key::Character("3") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::Demo));
+                    }
+                    Key::Character("4") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::Velocity));
+                    }
+                    Key::Character("5") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::Dark));
+                    }
+                    Key::Character("6") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetColorSource(ColorSource::Heat));
+                    }
+                    Key::Character("l") => {
+                        self.gpu_compute_options.integration = !self.gpu_compute_options.integration;
+                        self.auto_gpu_compute = false;
+                        self.bus
+                            .sim_control
+                            .publish(SimulationThreadEvent::SetGpuComputeOptions(self.gpu_compute_options));
+                    }
+                    Key::Character("p") => {
+                        self.gpu_compute_options.bvh = !self.gpu_compute_options.bvh;
+                        self.auto_gpu_compute = false;
+                        self.bus
+                            .sim_control
+                            .publish(SimulationThreadEvent::SetGpuComputeOptions(self.gpu_compute_options));
+                    }
+                    Key::Character("r") => {
+                        self.rendering_enabled = !self.rendering_enabled;
+                        self.bus.render_data.publish(RenderingThreadEvent::SetRendering(self.rendering_enabled));
+                    }
+                    Key::Character("a") => {
+                        self.auto_gpu_compute = !self.auto_gpu_compute;
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetAutoGpuCompute(self.auto_gpu_compute));
+                    }
+                    Key::Character("t") => {
+                        self.bus.render_data.publish(RenderingThreadEvent::ToggleTrails);
+                    }
+                    Key::Character("s") if self.edit_mode && self.modifiers.control_key() => {
+                        let path = Path::new(&CONFIG.editor.scene);
+                        match self.editor.scene().save(path) {
+                            Ok(()) => println!("Scene saved to \"{}\"", path.display()),
+                            Err(e) => eprintln!("Failed to save scene: {e:#}"),
                         }
-                        Key::Character("p") => {
-                            self.gpu_compute_options.bvh = !self.gpu_compute_options.bvh;
-                            self.simulation_event_sender
-                                .send(SimulationThreadEvent::SetGpuComputeOptions(self.gpu_compute_options))
-                                .unwrap();
+                    }
+                    Key::Character("s") => {
+                        self.bus.render_data.publish(RenderingThreadEvent::SaveTrails);
+                    }
+                    Key::Character(z) if z.eq_ignore_ascii_case("z") && self.modifiers.control_key() => {
+                        let editor = &mut self.editor;
+                        let revert = |scene: Option<SceneFile>| scene.map(|scene| editor.replace_scene(scene));
+                        let (reverted, event) = if self.modifiers.shift_key() {
+                            (self.history.redo(revert), SimulationThreadEvent::Redo)
+                        } else {
+                            (self.history.undo(revert), SimulationThreadEvent::Undo)
+                        };
+                        if reverted {
+                            self.bus.sim_control.publish(event);
                         }
-                        Key::Character("r") => {
-                            self.rendering_enabled = !self.rendering_enabled;
-                            self.rendering_event_queue.push(RenderingThreadEvent::SetRendering(self.rendering_enabled));
+                    }
+                    Key::Named(NamedKey::Tab) => {
+                        self.edit_mode = !self.edit_mode;
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetEditMode(self.edit_mode));
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Character("e") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleDrawEdf);
+                    }
+                    Key::Character("w") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleDrawWind);
+                    }
+                    Key::Character("x") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleAdditiveBlending);
+                    }
+                    Key::Character("h") => {
+                        self.high_contrast = !self.high_contrast;
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleHighContrast);
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Character("m") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleReducedMotion);
+                    }
+                    Key::Character("/") => {
+                        self.id_input = Some(String::new());
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Character("u") => {
+                        self.show_rulers = !self.show_rulers;
+                        request_redraw(self.state.as_ref());
+                    }
+                    Key::Character("v") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleDrawPlanetVectors);
+                    }
+                    Key::Character("c") => {
+                        self.auto_camera = !self.auto_camera;
+                        if self.following {
+                            self.following = false;
+                            self.bus.sim_control.publish(SimulationThreadEvent::StopFollowing);
                         }
-                        Key::Character("e") => {
-                            self.simulation_event_sender.send(SimulationThreadEvent::ToggleDrawEdf).unwrap();
+                        self.bus.sim_control.publish(SimulationThreadEvent::SetAutoCamera(self.auto_camera));
+                    }
+                    Key::Character("f") => {
+                        if self.auto_camera {
+                            self.auto_camera = false;
+                            self.bus.sim_control.publish(SimulationThreadEvent::SetAutoCamera(false));
                         }
-                        _ => {}
+                        self.following = true;
+                        self.bus.sim_control.publish(SimulationThreadEvent::Follow {
+                            mouse_position: self.camera.screen_to_world(self.mouse_position),
+                            mouse_influence_radius: self.mouse_influence_radius / self.camera.zoom,
+                        });
+                    }
+                    // Takes effect when the simulation thread gets to the event, e.g. after a GPU hang
+                    Key::Character("o") => {
+                        self.gpu_compute_options = GpuComputeOptions::default();
+                        self.auto_gpu_compute = false;
+                        self.bus
+                            .sim_control
+                            .publish(SimulationThreadEvent::SetGpuComputeOptions(self.gpu_compute_options));
                     }
+                    // A kernel can't be interrupted, so a hung simulation thread takes the whole app down
+                    Key::Character("k") if self.simulation_stall().is_some() => {
+                        eprintln!("Aborting the stalled simulation");
+                        process::abort();
+                    }
+                    Key::Character("d") => self.toggle_stats_window(event_loop),
+                    Key::Character("[") => self.change_speed(-1),
+                    Key::Character("]") => self.change_speed(1),
+                    Key::Character("b") => {
+                        self.bus.sim_control.publish(SimulationThreadEvent::ToggleConstraintBouncing);
+                    }
+                    Key::Named(NamedKey::Home) => {
+                        self.camera = Camera::new(self.camera.viewport_size);
+                        self.camera_updated();
+                    }
+                    _ => {}
                 }
             }
             WindowEvent::Resized(size) => {
@@ -859,15 +2970,40 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
                     self.context.resize_surface(surface, size.width, size.height);
                     window.request_redraw();
                 }
+                // Minimized on Windows
+                self.minimized = size.width == 0 || size.height == 0;
+                self.update_background();
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                self.update_background();
+            }
+            WindowEvent::Occluded(occluded) => {
+                self.minimized = occluded;
+                self.update_background();
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.set_overlay_scale(overlay_scale(scale_factor));
+                request_redraw(self.state.as_ref());
             }
             WindowEvent::RedrawRequested => {
                 if let Some(RenderState { surface, .. }) = &self.state {
-                    if let Some(scene) = self.redraw_job_queue.pop() {
+                    self.process_cpu_meter.sample(&self.cpu_utilizations.process);
+                    if let Some((scene, build_duration)) = self.bus.scenes.take() {
                         self.simulation_scene = scene;
+                        self.frame_stats.scene_build_duration.update(build_duration);
                     }
                     if let Some(fps) = self.fps_calculator.update(self.frame_count) {
                         self.last_fps = fps;
                         self.min_fps = self.min_fps.min(fps);
+                        #[allow(clippy::cast_precision_loss)]
+                        self.stats_plots.fps.push(fps as f32);
+                        if let Some(quality_controller) = &mut self.quality_controller
+                            && let Some(quality) = quality_controller.update(fps)
+                        {
+                            println!("quality level: {}", quality.level);
+                            self.bus.sim_control.publish(SimulationThreadEvent::SetQuality(quality));
+                        }
                     }
                     if self.rendering_enabled {
                         self.scene.reset();
@@ -879,54 +3015,200 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
                             &Rect::new(0.0, 0.0, f64::from(CONFIG.window.width), f64::from(CONFIG.window.height)),
                         );
                         self.scene.append(&self.simulation_scene, None);
-                        draw_mouse_influence(&mut self.scene, self.mouse_position, self.mouse_influence_radius);
-                        draw_stats(
-                            &mut self.scene,
-                            &mut self.text,
-                            (self.last_fps, self.min_fps),
-                            &self.stats,
-                            self.gpu_compute_options,
-                        )
-                        .expect("failed to draw stats");
+                        if self.show_overlays {
+                            draw_mouse_influence(&mut self.scene, self.mouse_position, self.mouse_influence_radius);
+                            if self.show_rulers {
+                                draw_rulers(&mut self.scene, &mut self.text, &self.camera);
+                            }
+                            draw_cursor_coordinates(
+                                &mut self.scene,
+                                &mut self.text,
+                                &self.camera,
+                                self.mouse_position,
+                                self.show_rulers,
+                            );
+                        }
+                        if self.edit_mode {
+                            draw_editor(
+                                &mut self.scene,
+                                &mut self.text,
+                                &self.editor,
+                                &self.camera,
+                                self.mouse_position,
+                            );
+                        }
+                        if self.show_overlays && self.stats_window.is_none() {
+                            draw_stats(
+                                &mut self.scene,
+                                &mut self.text,
+                                (self.last_fps, self.min_fps),
+                                &self.stats,
+                                &self.frame_stats,
+                                &self.cpu_utilizations,
+                                self.gpu_compute_options,
+                                self.auto_gpu_compute,
+                                SPEED_MULTIPLIERS[self.speed_multiplier_index],
+                                self.high_contrast,
+                                self.camera.viewport_size,
+                                self.overlay_scale,
+                            )
+                            .expect("failed to draw stats");
+                        }
+                        if self.is_pause_menu_visible() {
+                            draw_pause_menu(
+                                &mut self.scene,
+                                &mut self.text,
+                                &self.pause_menu,
+                                self.camera.viewport_size,
+                            );
+                        }
+                        if let Some(input) = &self.id_input {
+                            draw_id_input(&mut self.scene, &mut self.text, input, self.camera.viewport_size);
+                        }
+                        if let Some(stall) = self.simulation_stall() {
+                            draw_stall_warning(
+                                &mut self.scene,
+                                &mut self.text,
+                                f64::from(self.camera.viewport_size.x),
+                                stall,
+                            );
+                        }
 
                         let renderer = self.renderers[surface.dev_id].as_mut().expect("failed to get renderer");
                         let device_handle = &self.context.devices[surface.dev_id];
+                        let present_start = Instant::now();
                         let surface_texture =
                             surface.surface.get_current_texture().expect("failed to get current surface texture");
+                        let mut present_duration = present_start.elapsed();
+                        let render_start = Instant::now();
                         render_scene(&self.scene, surface, &surface_texture, renderer, device_handle);
+                        self.frame_stats.render_duration.update(render_start.elapsed());
+                        let present_start = Instant::now();
                         surface_texture.present();
                         device_handle.device.poll(Maintain::Poll);
-
-                        self.redraw_result_queue.force_push(());
+                        present_duration += present_start.elapsed();
+                        self.frame_stats.present_duration.update(present_duration);
+                        self.stats_plots.push_frame_stats(&self.frame_stats);
                     }
                     self.frame_count += 1;
                 }
             }
             WindowEvent::CursorMoved { position, .. } => {
                 #[allow(clippy::cast_possible_truncation)]
-                {
-                    self.mouse_position = Vector2::new(position.x as f32, position.y as f32);
+                let mouse_position = Vector2::new(position.x as f32, position.y as f32);
+                let mouse_delta = mouse_position - self.mouse_position;
+                self.mouse_position = mouse_position;
+                if self.is_pause_menu_visible() {
+                    self.pause_menu.hover(self.camera.viewport_size, mouse_position);
+                }
+                if self.panning {
+                    if self.following {
+                        self.following = false;
+                        self.bus.sim_control.publish(SimulationThreadEvent::StopFollowing);
+                    }
+                    self.camera.pan(mouse_delta);
+                    self.camera_updated();
+                }
+                if self.grabbing {
+                    self.bus
+                        .sim_control
+                        .publish(SimulationThreadEvent::MoveGrab(self.camera.screen_to_world(self.mouse_position)));
                 }
                 request_redraw(self.state.as_ref());
             }
-            WindowEvent::MouseInput { state, button, .. } => {
-                if state == ElementState::Pressed
-                    && let MouseButton::Left = button
-                {
-                    self.simulation_event_sender
-                        .send(SimulationThreadEvent::UnidirectionalKick {
-                            mouse_position: self.mouse_position,
-                            mouse_influence_radius: self.mouse_influence_radius,
-                        })
-                        .unwrap();
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = modifiers.state(),
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } if self.is_pause_menu_visible()
+                && let Some(item) = PauseMenu::item_at(self.camera.viewport_size, self.mouse_position) =>
+            {
+                self.activate_menu_item(item, event_loop);
+            }
+            WindowEvent::MouseInput { state, button, .. } if self.edit_mode => {
+                let mouse_position = self.camera.screen_to_world(self.mouse_position);
+                match (button, state) {
+                    (MouseButton::Left, ElementState::Pressed) => {
+                        let tool = if self.modifiers.shift_key() {
+                            EditorTool::Brick
+                        } else if self.modifiers.control_key() {
+                            EditorTool::Ball
+                        } else {
+                            EditorTool::Particle
+                        };
+                        self.editor.press(tool, mouse_position);
+                    }
+                    (MouseButton::Left, ElementState::Released) => {
+                        let scene = self.editor.scene().clone();
+                        if let Some(item) = self.editor.release(mouse_position) {
+                            self.history.push(Some(scene));
+                            self.bus.sim_control.publish(SimulationThreadEvent::AddSceneItem(item));
+                        }
+                    }
+                    // Removes both the objects from the simulation and the items from the edited scene
+                    (MouseButton::Right, ElementState::Pressed) => {
+                        let radius = self.mouse_influence_radius / self.camera.zoom;
+                        self.history.push(Some(self.editor.scene().clone()));
+                        self.editor.remove(mouse_position, radius);
+                        self.bus.sim_control.publish(SimulationThreadEvent::RemoveObjects {
+                            position: mouse_position,
+                            radius,
+                        });
+                    }
+                    (MouseButton::Middle, _) => self.panning = state == ElementState::Pressed,
+                    _ => {}
                 }
+                request_redraw(self.state.as_ref());
             }
+            WindowEvent::MouseInput { state, button, .. } => match button {
+                // Objects under the mouse are pulled in with the left button and pushed away with the right one
+                MouseButton::Left | MouseButton::Right
+                    if state == ElementState::Pressed && self.modifiers.control_key() =>
+                {
+                    let speed = CONFIG.units.speed(CONFIG.mouse.kick_speed);
+                    self.bus.sim_control.publish(SimulationThreadEvent::Kick {
+                        mouse_position: self.camera.screen_to_world(self.mouse_position),
+                        mouse_influence_radius: self.mouse_influence_radius / self.camera.zoom,
+                        speed: if button == MouseButton::Left { -speed } else { speed },
+                    });
+                }
+                // The nearest object is grabbed, or all of them within the influence radius with Shift
+                MouseButton::Left if state == ElementState::Pressed => {
+                    self.grabbing = true;
+                    self.bus.sim_control.publish(SimulationThreadEvent::Grab {
+                        mouse_position: self.camera.screen_to_world(self.mouse_position),
+                        mouse_influence_radius: self.mouse_influence_radius / self.camera.zoom,
+                        nearest_only: !self.modifiers.shift_key(),
+                    });
+                }
+                MouseButton::Left => {
+                    self.grabbing = false;
+                    self.bus.sim_control.publish(SimulationThreadEvent::Release);
+                }
+                // Objects under the mouse are frozen, or unfrozen with Shift
+                MouseButton::Right if state == ElementState::Pressed => {
+                    self.history.push(None);
+                    self.bus.sim_control.publish(SimulationThreadEvent::Freeze {
+                        mouse_position: self.camera.screen_to_world(self.mouse_position),
+                        mouse_influence_radius: self.mouse_influence_radius / self.camera.zoom,
+                        is_frozen: !self.modifiers.shift_key(),
+                    });
+                }
+                MouseButton::Middle => self.panning = state == ElementState::Pressed,
+                _ => {}
+            },
             WindowEvent::MouseWheel {
                 delta: MouseScrollDelta::LineDelta(_, dy),
                 ..
             } => {
-                self.mouse_influence_radius = (self.mouse_influence_radius + dy * 3.0).max(0.0);
-                request_redraw(self.state.as_ref());
+                if self.modifiers.control_key() {
+                    self.camera.zoom_at(self.mouse_position, 1.1_f32.powf(dy));
+                    self.camera_updated();
+                } else {
+                    self.mouse_influence_radius = (self.mouse_influence_radius + dy * 3.0).max(0.0);
+                    request_redraw(self.state.as_ref());
+                }
             }
             _ => {}
         }
@@ -935,10 +3217,27 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
     fn user_event(&mut self, event_loop: &ActiveEventLoop, event: AppEvent) {
         match event {
             AppEvent::StatsUpdated(stats) => {
+                self.stats_plots.push_stats(&stats);
                 self.stats = stats;
                 request_redraw(self.state.as_ref());
+                request_redraw(self.stats_window.as_ref());
+            }
+            AppEvent::GpuComputeOptionsSelected(options) => {
+                self.gpu_compute_options = options;
+                request_redraw(self.state.as_ref());
+                request_redraw(self.stats_window.as_ref());
+            }
+            // Stale if the camera was moved by hand in the meantime
+            AppEvent::CameraMoved(camera) => {
+                if self.auto_camera || self.following {
+                    self.camera = camera;
+                }
             }
             AppEvent::RequestRedraw => request_redraw(self.state.as_ref()),
+            AppEvent::Paused(paused) => {
+                self.paused = paused;
+                request_redraw(self.state.as_ref());
+            }
             AppEvent::Exit => {
                 self.ready_to_exit.wait();
                 event_loop.exit();
@@ -951,6 +3250,8 @@ impl ApplicationHandler<AppEvent> for VelloApp<'_> {
         if let Some(render_state) = self.state.take() {
             self.cached_window = Some(render_state.window);
         }
+        // The stats window is closed instead and reopened on resume
+        self.open_stats_window_on_resume = self.stats_window.take().is_some();
     }
 }
 
@@ -961,6 +3262,33 @@ struct RenderState<'s> {
     window: Arc<Window>,
 }
 
+fn create_render_state<'s>(
+    context: &mut RenderContext,
+    renderers: &mut Vec<Option<Renderer>>,
+    window: Arc<Window>,
+) -> RenderState<'s> {
+    let size = window.inner_size();
+    let surface_future = context.create_surface(window.clone(), size.width, size.height, PresentMode::AutoVsync);
+    // We need to block here, in case a Suspended event appeared
+    let surface = block_on(surface_future).expect("failed to create surface");
+    renderers.resize_with(context.devices.len(), || None);
+    let id = surface.dev_id;
+    renderers[id].get_or_insert_with(|| {
+        Renderer::new(
+            &context.devices[id].device,
+            RendererOptions {
+                surface_format: Some(surface.format),
+                use_cpu: false,
+                antialiasing_support: AaSupport::area_only(),
+                num_init_threads: NonZero::new(2),
+            },
+        )
+        .map_err(|e| anyhow!("{e}"))
+        .expect("failed to create renderer")
+    });
+    RenderState { surface, window }
+}
+
 fn request_redraw(render_state: Option<&RenderState<'_>>) {
     if let Some(render_state) = render_state {
         render_state.window.request_redraw();
@@ -972,36 +3300,270 @@ fn write_stats(
     (fps, min_fps): (usize, usize),
     Stats {
         sim_time,
+        step_count,
         object_count,
+        morton_codes_changed,
+        bvh_metrics,
         integration_duration,
         bvh_duration,
         collisions_duration,
         constraints_duration,
         total_duration,
+        pair_storage_duration,
+        pair_storage_memory,
+        pair_cache_hit_ratio,
+        verlet_lists,
+        candidate_false_positive_rate,
+        gpu_validation,
+        energy_changes,
+        angular_momentum,
+        event_driven,
+        temperature,
+        fluid_density_error,
+        attachment_count,
+        deferred_collision_count,
+        dropped_collision_count,
+        wall_flux,
     }: &Stats,
+    frame_stats: &FrameStats,
+    cpu_utilizations: &CpuUtilizations,
     gpu_compute_options: GpuComputeOptions,
+    auto_gpu_compute: bool,
+    speed_multiplier: f32,
 ) -> anyhow::Result<()> {
-    const FLAG_NAMES: [&str; 2] = ["off", "on"];
+    let t = |message: Message| message.text(CONFIG.ui.language);
+    let flag_names = [t(Message::Off), t(Message::On)];
+    let action_name = |action: TimeLimitAction| match action {
+        TimeLimitAction::Exit => t(Message::Exit),
+        TimeLimitAction::Pause => t(Message::Pause),
+    };
 
-    writeln!(buffer, "FPS: {fps} (min {min_fps})")?;
-    write!(buffer, "sim time: {sim_time}")?;
+    writeln!(buffer, "{} ({} ms): {fps} ({} {min_fps})", t(Message::Fps), CONFIG.stats.fps_period_ms, t(Message::Min))?;
+    write!(buffer, "{}: {}", t(Message::SimTime), CONFIG.units.physical_time(*sim_time))?;
     if let Some(time_limit) = CONFIG.simulation.time_limit {
-        let action = CONFIG.simulation.time_limit_action.to_string();
-        write!(buffer, " ({action} at {time_limit})")?;
+        let action = action_name(CONFIG.simulation.time_limit_action);
+        write!(buffer, " ({action} {} {time_limit})", t(Message::At))?;
+    }
+    writeln!(buffer)?;
+    write!(buffer, "{}: {step_count}", t(Message::Steps))?;
+    if let Some(step_limit) = CONFIG.simulation.step_limit {
+        let action = action_name(CONFIG.simulation.step_limit_action);
+        write!(buffer, " ({action} {} {step_limit})", t(Message::At))?;
     }
     writeln!(buffer)?;
+    writeln!(buffer, "{}: {speed_multiplier}x", t(Message::Speed))?;
+    write!(
+        buffer,
+        "{}: {} {}, {} {}",
+        t(Message::GpuCompute),
+        t(Message::Integration),
+        flag_names[usize::from(gpu_compute_options.integration)],
+        t(Message::Bvh),
+        flag_names[usize::from(gpu_compute_options.bvh)]
+    )?;
+    if auto_gpu_compute {
+        write!(buffer, " ({})", t(Message::Auto))?;
+    }
+    writeln!(buffer)?;
+    writeln!(buffer, "{}: {object_count}", t(Message::Objects))?;
+    if let Some(EventDrivenStats {
+        object_count,
+        event_count,
+        limit_reached,
+    }) = event_driven
+    {
+        write!(
+            buffer,
+            "{}: {event_count} {} {object_count} {}",
+            t(Message::CollisionEvents),
+            t(Message::For),
+            t(Message::Objects)
+        )?;
+        if *limit_reached {
+            write!(buffer, " ({})", t(Message::LimitReached))?;
+        }
+        writeln!(buffer)?;
+    }
+    writeln!(buffer, "{}: {morton_codes_changed}", t(Message::MortonCodesChanged))?;
+    if let Some(BvhMetrics {
+        depth,
+        overlap,
+        sah_cost,
+    }) = bvh_metrics
+    {
+        writeln!(
+            buffer,
+            "{}: {} {depth}, {} {:.1}%, {} {sah_cost:.1}",
+            t(Message::Bvh),
+            t(Message::Depth),
+            t(Message::Overlap),
+            overlap * 100.0,
+            t(Message::SahCost)
+        )?;
+    }
+    let memory_stats = memory_stats();
+    writeln!(
+        buffer,
+        "{}: {}, {} {} KiB",
+        t(Message::Allocations),
+        memory_stats.allocations,
+        t(Message::Live),
+        memory_stats.live_bytes / 1024
+    )?;
+    let pool_stats = [
+        EDF_VECTOR_BUFFERS.stats(),
+        EDF_SCALAR_BUFFERS.stats(),
+        DRAW_ORDER_BUFFERS.stats(),
+    ]
+    .into_iter()
+    .fold(BufferPoolStats::default(), |total, stats| BufferPoolStats {
+        allocations: total.allocations + stats.allocations,
+        reuses: total.reuses + stats.reuses,
+    });
+    writeln!(
+        buffer,
+        "{}: {} {}, {} {}",
+        t(Message::BufferPools),
+        pool_stats.allocations,
+        t(Message::Allocations),
+        pool_stats.reuses,
+        t(Message::Reuses)
+    )?;
+    let gpu_memory = GPU.memory_report();
+    writeln!(
+        buffer,
+        "{}: {}, {} KiB ({} {} KiB)",
+        t(Message::GpuBuffers),
+        gpu_memory.buffers.len(),
+        gpu_memory.total_size() / 1024,
+        t(Message::Peak),
+        gpu_memory.peak_size / 1024
+    )?;
+    for GpuBufferInfo {
+        name,
+        kind,
+        access_mode,
+        size,
+    } in &gpu_memory.buffers
+    {
+        writeln!(buffer, "  {name}: {} KiB, {}, {}", size / 1024, kind.name(), access_mode.name())?;
+    }
+    write_duration_stat(buffer, t(Message::Integration), integration_duration)?;
+    write_duration_stat(buffer, t(Message::Collision), collisions_duration)?;
+    write_duration_stat(buffer, t(Message::Bvh), bvh_duration)?;
+    write_duration_stat(buffer, t(Message::Constraints), constraints_duration)?;
+    write_duration_stat(buffer, t(Message::Total), total_duration)?;
+    write_duration_stat(
+        buffer,
+        &format!(
+            "{} ({}, {} KiB)",
+            t(Message::PairStorage),
+            CONFIG.simulation.pair_storage.name(),
+            pair_storage_memory / 1024
+        ),
+        pair_storage_duration,
+    )?;
+    write_duration_stat(buffer, t(Message::SceneBuild), &frame_stats.scene_build_duration)?;
+    write_duration_stat(buffer, t(Message::Render), &frame_stats.render_duration)?;
+    write_duration_stat(buffer, t(Message::Present), &frame_stats.present_duration)?;
+    let utilizations = [
+        (Message::Simulation, &cpu_utilizations.simulation),
+        (Message::Rendering, &cpu_utilizations.rendering),
+        (Message::Field, &cpu_utilizations.field),
+        (Message::Process, &cpu_utilizations.process),
+    ]
+    .into_iter()
+    .filter_map(|(message, utilization)| Some(format!("{} {:.0}%", t(message), utilization.get()? * 100.0)))
+    .join(", ");
+    if !utilizations.is_empty() {
+        writeln!(buffer, "{}: {utilizations}", t(Message::Cpu))?;
+    }
+    if let Some(pair_cache_hit_ratio) = pair_cache_hit_ratio {
+        writeln!(buffer, "{}: {:.1}%", t(Message::PairCacheHits), pair_cache_hit_ratio * 100.0)?;
+    }
+    if let Some(VerletListStats { skin, rebuild_count }) = verlet_lists {
+        writeln!(
+            buffer,
+            "{}: {} {skin:.2}, {} {rebuild_count} ({:.1}%)",
+            t(Message::VerletLists),
+            t(Message::Skin),
+            t(Message::Rebuilds),
+            *rebuild_count as f32 / (*step_count).max(1) as f32 * 100.0
+        )?;
+    }
+    if let Some(candidate_false_positive_rate) = candidate_false_positive_rate {
+        writeln!(buffer, "{}: {:.1}%", t(Message::CandidateFalsePositives), candidate_false_positive_rate * 100.0)?;
+    }
     writeln!(
         buffer,
-        "gpu compute: integration {}, bvh {}",
-        FLAG_NAMES[usize::from(gpu_compute_options.integration)],
-        FLAG_NAMES[usize::from(gpu_compute_options.bvh)]
+        "{}: {} {:+.3e}, {} {:+.3e}, {} {:+.3e}",
+        t(Message::EnergyChange),
+        t(Message::Collisions),
+        energy_changes.collision_response,
+        t(Message::Correction),
+        energy_changes.position_correction,
+        t(Message::Constraints),
+        energy_changes.constraints
     )?;
-    writeln!(buffer, "objects: {object_count}")?;
-    write_duration_stat(buffer, "integration", integration_duration)?;
-    write_duration_stat(buffer, "collision", collisions_duration)?;
-    write_duration_stat(buffer, "bvh", bvh_duration)?;
-    write_duration_stat(buffer, "constraints", constraints_duration)?;
-    write_duration_stat(buffer, "total", total_duration)?;
+    if let Some(temperature) = temperature {
+        writeln!(buffer, "{}: {temperature:.3e}", t(Message::Temperature))?;
+    }
+    if let Some(fluid_density_error) = fluid_density_error {
+        writeln!(buffer, "{}: {:.2}%", t(Message::FluidDensityError), fluid_density_error * 100.0)?;
+    }
+    if *attachment_count > 0 {
+        writeln!(buffer, "{}: {attachment_count}", t(Message::Attachments))?;
+    }
+    if *deferred_collision_count > 0 || *dropped_collision_count > 0 {
+        writeln!(
+            buffer,
+            "{}: {deferred_collision_count} {}, {dropped_collision_count} {}",
+            t(Message::Collisions),
+            t(Message::Deferred),
+            t(Message::Dropped)
+        )?;
+    }
+    for (wall, flux) in zip(Wall::ALL, wall_flux) {
+        if flux.outflow_count > 0 || flux.inflow_count > 0 {
+            writeln!(
+                buffer,
+                "{}: {} {}, {} {}, {} {:+.3e}",
+                t(Message::wall(wall)),
+                flux.outflow_count,
+                t(Message::Out),
+                flux.inflow_count,
+                t(Message::In),
+                t(Message::MassFlux),
+                flux.mass_flux
+            )?;
+        }
+    }
+    if let Some(AngularMomentum { value, drift }) = angular_momentum {
+        write!(buffer, "{}: {value:.4e} ({} {:+.3}%)", t(Message::AngularMomentum), t(Message::Drift), drift * 100.0)?;
+        if drift.abs() > CONFIG.simulation.angular_momentum_drift_tolerance {
+            write!(buffer, " {}", t(Message::Drifting))?;
+        }
+        writeln!(buffer)?;
+    }
+    if let Some(GpuValidation {
+        max_position_difference,
+        max_velocity_difference,
+        missing_candidates,
+        extra_candidates,
+    }) = gpu_validation
+    {
+        writeln!(
+            buffer,
+            "{}: {} {max_position_difference:.2e}, {} {max_velocity_difference:.2e}, \
+             {} {missing_candidates} {}, {extra_candidates} {}",
+            t(Message::GpuDivergence),
+            t(Message::Position),
+            t(Message::Velocity),
+            t(Message::Pairs),
+            t(Message::Missing),
+            t(Message::Extra)
+        )?;
+    }
     Ok(())
 }
 
@@ -1013,10 +3575,54 @@ fn write_duration_stat(buffer: &mut String, name: &str, stat: &DurationStat) ->
         let count = u32::try_from(stat.average.len()).unwrap();
         sum / count
     };
-    writeln!(buffer, "{}: min {:.2?}, max {:.2?}, avg {:.2?}", name, stat.lowest, stat.highest, average)?;
+    let t = |message: Message| message.text(CONFIG.ui.language);
+    writeln!(
+        buffer,
+        "{}: {} {:.2?}, {} {:.2?}, {} ({}) {:.2?}",
+        name,
+        t(Message::Min),
+        stat.lowest,
+        t(Message::Max),
+        stat.highest,
+        t(Message::Avg),
+        stat.window(),
+        average
+    )?;
     Ok(())
 }
 
+fn window_title() -> String {
+    CONFIG.window.title.clone().unwrap_or_else(|| format!("{} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+}
+
+fn window_attributes() -> WindowAttributes {
+    const ICON_SIZE: u32 = 64;
+
+    let attributes = Window::default_attributes()
+        .with_inner_size(PhysicalSize::new(CONFIG.window.width, CONFIG.window.height))
+        .with_resizable(false)
+        .with_title(window_title())
+        .with_window_icon(
+            CONFIG.window.icon.then(|| Icon::from_rgba(render_icon(ICON_SIZE), ICON_SIZE, ICON_SIZE).unwrap()),
+        );
+    // Wayland and X11 share the name, so either extension sets it for both
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios", target_os = "android"))))]
+    let attributes = winit::platform::x11::WindowAttributesExtX11::with_name(
+        attributes,
+        CONFIG.window.app_id.clone(),
+        CONFIG.window.app_id.clone(),
+    );
+    attributes
+}
+
+fn stats_window_attributes() -> WindowAttributes {
+    Window::default_attributes().with_inner_size(PhysicalSize::new(1000, 720)).with_title(format!(
+        "{} - {}",
+        window_title(),
+        Message::Stats.text(CONFIG.ui.language)
+    ))
+}
+
 // TODO use new Renderer::render_to_texture()
 fn render_scene(
     scene: &Scene,
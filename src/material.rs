@@ -0,0 +1,204 @@
+use anyhow::bail;
+use serde_derive::{Deserialize, Serialize};
+
+// Per-object coefficients. Restitution and friction of a colliding pair are combined from both materials, unless the
+// pair has an override. Drag is the exponential velocity decay rate.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub restitution_coefficient: f32,
+    pub friction: f32,
+    pub drag: f32,
+    pub gravity_scale: f32,
+    // Contacts separating slower than this stick together, no adhesion if zero
+    pub adhesion_speed: f32,
+    // Largest force an attachment withstands before it breaks
+    pub adhesion_force: f32,
+}
+
+impl Material {
+    #[must_use]
+    pub fn new(restitution_coefficient: f32) -> Self {
+        Self {
+            restitution_coefficient,
+            friction: 0.0,
+            drag: 0.0,
+            gravity_scale: 1.0,
+            adhesion_speed: 0.0,
+            adhesion_force: 0.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum CombineRule {
+    #[serde(rename = "average")]
+    Average,
+
+    #[serde(rename = "geometric_mean")]
+    GeometricMean,
+
+    #[serde(rename = "min")]
+    Min,
+
+    #[serde(rename = "max")]
+    Max,
+
+    #[serde(rename = "multiply")]
+    Multiply,
+}
+
+impl CombineRule {
+    #[must_use]
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            CombineRule::Average => (a + b) / 2.0,
+            CombineRule::GeometricMean => (a * b).sqrt(),
+            CombineRule::Min => a.min(b),
+            CombineRule::Max => a.max(b),
+            CombineRule::Multiply => a * b,
+        }
+    }
+}
+
+// Coefficients of a collision between two materials. The objects only stick together if both materials are sticky.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interaction {
+    pub restitution_coefficient: f32,
+    pub friction: f32,
+    pub adhesion_speed: f32,
+    pub adhesion_force: f32,
+}
+
+// Replaces the combined coefficients for a pair of materials, in either order
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialPair {
+    pub materials: [u32; 2],
+    pub restitution_coefficient: Option<f32>,
+    pub friction: Option<f32>,
+}
+
+// Materials referenced by the object material indices, with the interactions of every pair precomputed
+pub struct MaterialTable {
+    materials: Vec<Material>,
+    interactions: Vec<Interaction>,
+}
+
+impl MaterialTable {
+    pub fn new(
+        materials: Vec<Material>,
+        restitution_combine: CombineRule,
+        friction_combine: CombineRule,
+        pairs: &[MaterialPair],
+    ) -> anyhow::Result<Self> {
+        let material_count = materials.len();
+        let mut interactions = Vec::with_capacity(material_count * material_count);
+        for material1 in &materials {
+            for material2 in &materials {
+                interactions.push(Interaction {
+                    restitution_coefficient: restitution_combine
+                        .combine(material1.restitution_coefficient, material2.restitution_coefficient),
+                    friction: friction_combine.combine(material1.friction, material2.friction),
+                    adhesion_speed: material1.adhesion_speed.min(material2.adhesion_speed),
+                    adhesion_force: material1.adhesion_force.min(material2.adhesion_force),
+                });
+            }
+        }
+        for pair in pairs {
+            let [material1, material2] = pair.materials.map(|material| material as usize);
+            if material1 >= material_count || material2 >= material_count {
+                bail!("material pair {:?} refers to an unknown material", pair.materials);
+            }
+            for interaction_index in [
+                material1 * material_count + material2,
+                material2 * material_count + material1,
+            ] {
+                let interaction = &mut interactions[interaction_index];
+                if let Some(restitution_coefficient) = pair.restitution_coefficient {
+                    interaction.restitution_coefficient = restitution_coefficient;
+                }
+                if let Some(friction) = pair.friction {
+                    interaction.friction = friction;
+                }
+            }
+        }
+        Ok(Self {
+            materials,
+            interactions,
+        })
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    #[must_use]
+    pub fn materials(&self) -> &[Material] {
+        &self.materials
+    }
+
+    #[must_use]
+    pub fn material(&self, material: u32) -> &Material {
+        &self.materials[material as usize]
+    }
+
+    #[must_use]
+    pub fn interaction(&self, material1: u32, material2: u32) -> Interaction {
+        self.interactions[material1 as usize * self.materials.len() + material2 as usize]
+    }
+}
+
+#[test]
+fn material_pairs_override_combined_coefficients() {
+    let rubber = Material {
+        friction: 0.8,
+        ..Material::new(0.5)
+    };
+    let ice = Material {
+        friction: 0.02,
+        ..Material::new(0.9)
+    };
+    let pairs = [MaterialPair {
+        materials: [2, 1],
+        restitution_coefficient: None,
+        friction: Some(0.1),
+    }];
+    let table =
+        MaterialTable::new(vec![Material::new(0.9), rubber, ice], CombineRule::Max, CombineRule::Min, &pairs).unwrap();
+    // Combining a material with itself gives its own coefficients
+    assert_eq!(table.interaction(0, 0).restitution_coefficient, 0.9);
+    assert_eq!(table.interaction(1, 1).friction, 0.8);
+    assert_eq!(
+        table.interaction(0, 1),
+        Interaction {
+            restitution_coefficient: 0.9,
+            friction: 0.0,
+            adhesion_speed: 0.0,
+            adhesion_force: 0.0,
+        }
+    );
+    assert_eq!(
+        table.interaction(1, 2),
+        Interaction {
+            restitution_coefficient: 0.9,
+            friction: 0.1,
+            adhesion_speed: 0.0,
+            adhesion_force: 0.0,
+        }
+    );
+    assert_eq!(table.interaction(2, 1), table.interaction(1, 2));
+
+    let pairs = [MaterialPair {
+        materials: [0, 3],
+        restitution_coefficient: Some(0.0),
+        friction: None,
+    }];
+    assert!(MaterialTable::new(vec![Material::new(0.9)], CombineRule::Max, CombineRule::Min, &pairs).is_err());
+}
@@ -0,0 +1,108 @@
+use crate::{bvh::AABB, vector2::Vector2};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuItem {
+    Resume,
+    ResetScene,
+    SaveSnapshot,
+    LoadSnapshot,
+    ToggleOverlays,
+    Quit,
+}
+
+impl MenuItem {
+    pub const ALL: [MenuItem; 6] = [
+        MenuItem::Resume,
+        MenuItem::ResetScene,
+        MenuItem::SaveSnapshot,
+        MenuItem::LoadSnapshot,
+        MenuItem::ToggleOverlays,
+        MenuItem::Quit,
+    ];
+}
+
+// Menu shown while the simulation is paused, used with the arrow keys and Enter or with the mouse
+pub struct PauseMenu {
+    // Index into MenuItem::ALL
+    selected: usize,
+}
+
+impl PauseMenu {
+    pub const ITEM_SIZE: Vector2<f32> = Vector2::new(260.0, 36.0);
+    pub const ITEM_SPACING: f32 = 8.0;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self { selected: 0 }
+    }
+
+    #[must_use]
+    pub fn selected(&self) -> MenuItem {
+        MenuItem::ALL[self.selected]
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1) % MenuItem::ALL.len();
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = (self.selected + MenuItem::ALL.len() - 1) % MenuItem::ALL.len();
+    }
+
+    // Selects the item under the mouse, if any
+    pub fn hover(&mut self, viewport_size: Vector2<f32>, mouse_position: Vector2<f32>) {
+        if let Some(index) = Self::items(viewport_size).position(|(_, aabb)| contains(aabb, mouse_position)) {
+            self.selected = index;
+        }
+    }
+
+    #[must_use]
+    pub fn item_at(viewport_size: Vector2<f32>, mouse_position: Vector2<f32>) -> Option<MenuItem> {
+        Self::items(viewport_size).find(|(_, aabb)| contains(*aabb, mouse_position)).map(|(item, _)| item)
+    }
+
+    // The items stacked in the center of the viewport, in screen coordinates
+    pub fn items(viewport_size: Vector2<f32>) -> impl Iterator<Item = (MenuItem, AABB)> {
+        #[allow(clippy::cast_precision_loss)]
+        let height = MenuItem::ALL.len() as f32 * (Self::ITEM_SIZE.y + Self::ITEM_SPACING) - Self::ITEM_SPACING;
+        let topleft = (viewport_size - Vector2::new(Self::ITEM_SIZE.x, height)) / 2.0;
+        MenuItem::ALL.into_iter().enumerate().map(move |(index, item)| {
+            #[allow(clippy::cast_precision_loss)]
+            let topleft = topleft + Vector2::new(0.0, index as f32 * (Self::ITEM_SIZE.y + Self::ITEM_SPACING));
+            let aabb = AABB {
+                topleft,
+                bottomright: topleft + Self::ITEM_SIZE,
+            };
+            (item, aabb)
+        })
+    }
+}
+
+impl Default for PauseMenu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn contains(aabb: AABB, position: Vector2<f32>) -> bool {
+    (aabb.topleft.x..aabb.bottomright.x).contains(&position.x)
+        && (aabb.topleft.y..aabb.bottomright.y).contains(&position.y)
+}
+
+#[test]
+fn pause_menu_selects_items() {
+    let mut menu = PauseMenu::new();
+    menu.select_previous();
+    assert_eq!(menu.selected(), MenuItem::Quit);
+    menu.select_next();
+    menu.select_next();
+    assert_eq!(menu.selected(), MenuItem::ResetScene);
+
+    let viewport_size = Vector2::new(800.0, 600.0);
+    let (_, load_snapshot) = PauseMenu::items(viewport_size).nth(3).unwrap();
+    let center = (load_snapshot.topleft + load_snapshot.bottomright) / 2.0;
+    assert_eq!(PauseMenu::item_at(viewport_size, center), Some(MenuItem::LoadSnapshot));
+    assert_eq!(PauseMenu::item_at(viewport_size, Vector2::new(0.0, 0.0)), None);
+    menu.hover(viewport_size, center);
+    assert_eq!(menu.selected(), MenuItem::LoadSnapshot);
+}
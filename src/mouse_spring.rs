@@ -0,0 +1,87 @@
+use crate::{object::ObjectSoa, vector2::Vector2};
+
+// Objects grabbed with the mouse, each pulled towards its place relative to the cursor by a damped spring. The
+// stiffness and damping are per unit mass, so that light and heavy objects follow the cursor alike, and the spring
+// never adds more energy than it stores, unlike an instant velocity change.
+pub struct MouseSpring {
+    // Per second squared
+    stiffness: f32,
+    // Per second
+    damping: f32,
+    anchor: Vector2<f32>,
+    // Grabbed objects and their offsets from the anchor
+    attachments: Vec<(usize, Vector2<f32>)>,
+}
+
+impl MouseSpring {
+    // Grabs the objects within the radius of the anchor
+    #[must_use]
+    pub fn grab(objects: &ObjectSoa, anchor: Vector2<f32>, radius: f32, stiffness: f32, damping: f32) -> Self {
+        let attachments = (0..objects.len())
+            .filter(|&object_index| (objects.positions[object_index] - anchor).magnitude() < radius)
+            .map(|object_index| (object_index, objects.positions[object_index] - anchor))
+            .collect();
+        Self {
+            stiffness,
+            damping,
+            anchor,
+            attachments,
+        }
+    }
+
+    // Grabs a single object, which is pulled right to the anchor instead of keeping its offset
+    #[must_use]
+    pub fn pin(object_index: usize, anchor: Vector2<f32>, stiffness: f32, damping: f32) -> Self {
+        Self {
+            stiffness,
+            damping,
+            anchor,
+            attachments: vec![(object_index, Vector2::new(0.0, 0.0))],
+        }
+    }
+
+    pub fn move_anchor(&mut self, anchor: Vector2<f32>) {
+        self.anchor = anchor;
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.attachments.is_empty()
+    }
+
+    // Spring and damping forces on the grabbed objects that still exist
+    pub fn forces<'a>(&'a self, objects: &'a ObjectSoa) -> impl Iterator<Item = (usize, Vector2<f32>)> + 'a {
+        self.attachments.iter().filter(|&&(object_index, _)| object_index < objects.len()).map(
+            |&(object_index, offset)| {
+                let stretch = self.anchor + offset - objects.positions[object_index];
+                let acceleration = stretch * self.stiffness - objects.velocities[object_index] * self.damping;
+                (object_index, acceleration * objects.masses[object_index])
+            },
+        )
+    }
+}
+
+#[test]
+fn mouse_spring_pulls_grabbed_objects() {
+    use crate::object::ObjectPrototype;
+
+    let mut objects = ObjectSoa::default();
+    objects.add(ObjectPrototype {
+        mass: 2.0,
+        ..ObjectPrototype::new(Vector2::new(1.0, 0.0))
+    });
+    objects.add(ObjectPrototype::new(Vector2::new(100.0, 0.0)));
+    let mut spring = MouseSpring::grab(&objects, Vector2::new(0.0, 0.0), 10.0, 4.0, 1.0);
+    assert!(!spring.is_empty());
+
+    // At rest in its place, the object isn't pulled
+    assert_eq!(spring.forces(&objects).collect::<Vec<_>>(), [(0, Vector2::new(0.0, 0.0))]);
+
+    spring.move_anchor(Vector2::new(0.0, 5.0));
+    objects.velocities[0] = Vector2::new(0.0, 2.0);
+    assert_eq!(spring.forces(&objects).collect::<Vec<_>>(), [(0, Vector2::new(0.0, 36.0))]);
+
+    // A pinned object is pulled to the anchor itself
+    let spring = MouseSpring::pin(1, Vector2::new(98.0, 0.0), 4.0, 1.0);
+    assert_eq!(spring.forces(&objects).collect::<Vec<_>>(), [(1, Vector2::new(-8.0, 0.0))]);
+}
@@ -1,6 +1,6 @@
-use std::ops::Range;
+use std::{iter::zip, ops::Range};
 
-use vello::peniko::Color;
+use peniko::Color;
 
 use crate::vector2::Vector2;
 
@@ -12,6 +12,12 @@ pub struct ObjectSoa {
     pub masses: Vec<f32>,
     pub colors: Vec<Option<Color>>,
     pub is_planet: Vec<bool>,
+    // Frozen objects don't move, but other objects collide with them as with obstacles of infinite mass
+    pub is_frozen: Vec<bool>,
+    // Indices into the material table of the physics engine, 0 is the default material
+    pub materials: Vec<u32>,
+    // Objects that share a non-zero collision group don't collide with each other
+    pub collision_groups: Vec<u32>,
     pub planet_count: usize,
 }
 
@@ -34,10 +40,167 @@ impl ObjectSoa {
         self.masses.push(object.mass);
         self.colors.push(object.color);
         self.is_planet.push(object.is_planet);
+        self.is_frozen.push(object.is_frozen);
+        self.materials.push(object.material);
+        self.collision_groups.push(object.collision_group);
         self.planet_count += usize::from(object.is_planet);
         object_index
     }
 
+    // Planets must be inserted among the planets and other objects after them
+    pub fn insert(&mut self, object_index: usize, object: ObjectPrototype) {
+        assert!(
+            if object.is_planet {
+                object_index <= self.planet_count
+            } else {
+                object_index >= self.planet_count
+            },
+            "planets must stay in front of other objects"
+        );
+        self.positions.insert(object_index, object.position);
+        self.velocities.insert(object_index, object.velocity);
+        self.radii.insert(object_index, object.radius);
+        self.masses.insert(object_index, object.mass);
+        self.colors.insert(object_index, object.color);
+        self.is_planet.insert(object_index, object.is_planet);
+        self.is_frozen.insert(object_index, object.is_frozen);
+        self.materials.insert(object_index, object.material);
+        self.collision_groups.insert(object_index, object.collision_group);
+        self.planet_count += usize::from(object.is_planet);
+    }
+
+    #[must_use]
+    pub fn get(&self, object_index: usize) -> ObjectPrototype {
+        ObjectPrototype {
+            position: self.positions[object_index],
+            velocity: self.velocities[object_index],
+            radius: self.radii[object_index],
+            mass: self.masses[object_index],
+            color: self.colors[object_index],
+            is_planet: self.is_planet[object_index],
+            is_frozen: self.is_frozen[object_index],
+            material: self.materials[object_index],
+            collision_group: self.collision_groups[object_index],
+        }
+    }
+
+    // The object stays a planet or a non-planet, so that the planets stay in front
+    pub fn set(&mut self, object_index: usize, object: ObjectPrototype) {
+        assert_eq!(object.is_planet, self.is_planet[object_index], "can't turn a planet into a particle or vice versa");
+        self.positions[object_index] = object.position;
+        self.velocities[object_index] = object.velocity;
+        self.radii[object_index] = object.radius;
+        self.masses[object_index] = object.mass;
+        self.colors[object_index] = object.color;
+        self.is_frozen[object_index] = object.is_frozen;
+        self.materials[object_index] = object.material;
+        self.collision_groups[object_index] = object.collision_group;
+    }
+
+    // Keeps the order of the remaining objects, so planets stay in front
+    pub fn remove(&mut self, object_index: usize) -> ObjectPrototype {
+        let object = ObjectPrototype {
+            position: self.positions.remove(object_index),
+            velocity: self.velocities.remove(object_index),
+            radius: self.radii.remove(object_index),
+            mass: self.masses.remove(object_index),
+            color: self.colors.remove(object_index),
+            is_planet: self.is_planet.remove(object_index),
+            is_frozen: self.is_frozen.remove(object_index),
+            material: self.materials.remove(object_index),
+            collision_group: self.collision_groups.remove(object_index),
+        };
+        self.planet_count -= usize::from(object.is_planet);
+        object
+    }
+
+    // Constant time, but the last object takes the place of the removed one. A removed planet is replaced with the last
+    // planet first, so that planets stay in front.
+    pub fn swap_remove(&mut self, object_index: usize) -> ObjectPrototype {
+        for (object1_index, object2_index) in self.swap_remove_swaps(object_index) {
+            self.swap(object1_index, object2_index);
+        }
+        let object = ObjectPrototype {
+            position: self.positions.pop().unwrap(),
+            velocity: self.velocities.pop().unwrap(),
+            radius: self.radii.pop().unwrap(),
+            mass: self.masses.pop().unwrap(),
+            color: self.colors.pop().unwrap(),
+            is_planet: self.is_planet.pop().unwrap(),
+            is_frozen: self.is_frozen.pop().unwrap(),
+            material: self.materials.pop().unwrap(),
+            collision_group: self.collision_groups.pop().unwrap(),
+        };
+        self.planet_count -= usize::from(object.is_planet);
+        object
+    }
+
+    // The swaps by which `swap_remove` moves the object to the end, so that the state kept for each object elsewhere
+    // can follow the objects
+    #[must_use]
+    pub fn swap_remove_swaps(&self, object_index: usize) -> [(usize, usize); 2] {
+        if self.is_planet[object_index] {
+            [
+                (object_index, self.planet_count - 1),
+                (self.planet_count - 1, self.len() - 1),
+            ]
+        } else {
+            [(object_index, object_index), (object_index, self.len() - 1)]
+        }
+    }
+
+    // Removes the objects rejected by `keep`, which sees each object once, in order. Keeps the order of the remaining
+    // objects, like `remove`. Returns the number of the removed objects.
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &ObjectPrototype) -> bool) -> usize {
+        fn retain_kept<T>(values: &mut Vec<T>, kept: &[bool]) {
+            let mut kept = kept.iter();
+            values.retain(|_| *kept.next().unwrap());
+        }
+
+        let kept = (0..self.len()).map(|object_index| keep(object_index, &self.get(object_index))).collect::<Vec<_>>();
+        let removed_count = kept.iter().filter(|&&is_kept| !is_kept).count();
+        if removed_count == 0 {
+            return 0;
+        }
+        self.planet_count = zip(&kept, &self.is_planet).filter(|&(&is_kept, &is_planet)| is_kept && is_planet).count();
+        retain_kept(&mut self.positions, &kept);
+        retain_kept(&mut self.velocities, &kept);
+        retain_kept(&mut self.radii, &kept);
+        retain_kept(&mut self.masses, &kept);
+        retain_kept(&mut self.colors, &kept);
+        retain_kept(&mut self.is_planet, &kept);
+        retain_kept(&mut self.is_frozen, &kept);
+        retain_kept(&mut self.materials, &kept);
+        retain_kept(&mut self.collision_groups, &kept);
+        removed_count
+    }
+
+    fn swap(&mut self, object1_index: usize, object2_index: usize) {
+        self.positions.swap(object1_index, object2_index);
+        self.velocities.swap(object1_index, object2_index);
+        self.radii.swap(object1_index, object2_index);
+        self.masses.swap(object1_index, object2_index);
+        self.colors.swap(object1_index, object2_index);
+        self.is_planet.swap(object1_index, object2_index);
+        self.is_frozen.swap(object1_index, object2_index);
+        self.materials.swap(object1_index, object2_index);
+        self.collision_groups.swap(object1_index, object2_index);
+    }
+
+    // A frozen object is stopped, so that it doesn't move after it's unfrozen
+    pub fn set_frozen(&mut self, object_index: usize, is_frozen: bool) {
+        self.is_frozen[object_index] = is_frozen;
+        if is_frozen {
+            self.velocities[object_index] = Vector2::new(0.0, 0.0);
+        }
+    }
+
+    // A collision group that no object belongs to yet
+    #[must_use]
+    pub fn new_collision_group(&self) -> u32 {
+        self.collision_groups.iter().max().map_or(1, |&collision_group| collision_group + 1)
+    }
+
     #[must_use]
     pub fn particle_range(&self) -> Range<usize> {
         self.planet_count..self.positions.len()
@@ -57,6 +220,9 @@ pub struct ObjectPrototype {
     pub mass: f32,
     pub color: Option<Color>,
     pub is_planet: bool,
+    pub is_frozen: bool,
+    pub material: u32,
+    pub collision_group: u32,
 }
 
 impl ObjectPrototype {
@@ -69,6 +235,9 @@ impl ObjectPrototype {
             mass: 1.0,
             color: None,
             is_planet: false,
+            is_frozen: false,
+            material: 0,
+            collision_group: 0,
         }
     }
 
@@ -77,3 +246,33 @@ impl ObjectPrototype {
         self.velocity * self.mass
     }
 }
+
+#[test]
+fn removal_keeps_planets_in_front() {
+    let mut objects = ObjectSoa::default();
+    for x in 0..3 {
+        objects.add(ObjectPrototype {
+            is_planet: true,
+            ..ObjectPrototype::new(Vector2::new(x as f32, 0.0))
+        });
+    }
+    for x in 3..8 {
+        objects.add(ObjectPrototype::new(Vector2::new(x as f32, 0.0)));
+    }
+    let xs = |objects: &ObjectSoa| objects.positions.iter().map(|position| position.x).collect::<Vec<_>>();
+
+    assert_eq!(objects.swap_remove(0).position.x, 0.0);
+    assert_eq!(xs(&objects), [2.0, 1.0, 7.0, 3.0, 4.0, 5.0, 6.0]);
+    assert_eq!(objects.planet_count, 2);
+    assert!(objects.is_planet[..2].iter().all(|&is_planet| is_planet));
+    assert!(!objects.is_planet[2]);
+
+    assert_eq!(objects.swap_remove(3).position.x, 3.0);
+    assert_eq!(xs(&objects), [2.0, 1.0, 7.0, 6.0, 4.0, 5.0]);
+
+    assert_eq!(objects.retain(|object_index, object| object_index != 0 && object.position.x != 4.0), 2);
+    assert_eq!(xs(&objects), [1.0, 7.0, 6.0, 5.0]);
+    assert_eq!(objects.planet_count, 1);
+    assert_eq!(objects.particle_range(), 1..4);
+    assert_eq!(objects.retain(|_, _| true), 0);
+}
@@ -0,0 +1,129 @@
+use rayon::{
+    ThreadPool,
+    iter::{IntoParallelRefIterator, ParallelExtend, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{bvh::Bvh, physics::NormalizedCollisionPair, vector2::Vector2};
+
+// Keeps the pairs of objects that are closer than `margin` to touching, and re-queries the BVH only for objects that
+// moved more than a quarter of the margin since their last query. A pair of objects that weren't re-queried can't have
+// come closer than half the margin since the later of their queries, so every touching pair is always in the cache.
+pub struct PairCache {
+    margin: f32,
+    reference_positions: Vec<Vector2<f32>>,
+    moved: Vec<bool>,
+    moved_indices: Vec<usize>,
+    pairs: Vec<NormalizedCollisionPair>,
+}
+
+impl PairCache {
+    #[must_use]
+    pub fn new(margin: f32) -> Self {
+        Self {
+            margin,
+            reference_positions: Vec::new(),
+            moved: Vec::new(),
+            moved_indices: Vec::new(),
+            pairs: Vec::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn pairs(&self) -> &[NormalizedCollisionPair] {
+        &self.pairs
+    }
+
+    pub fn invalidate(&mut self) {
+        self.reference_positions.clear();
+    }
+
+    // Returns the number of objects that had to be re-queried
+    pub fn update(&mut self, bvh: &Bvh, thread_pool: &ThreadPool, positions: &[Vector2<f32>], radii: &[f32]) -> usize {
+        if self.reference_positions.len() != positions.len() {
+            self.reference_positions.clear();
+            self.reference_positions.extend_from_slice(positions);
+            self.moved.clear();
+            self.moved.resize(positions.len(), true);
+            self.pairs.clear();
+        } else {
+            let max_displacement_squared = (self.margin * 0.25) * (self.margin * 0.25);
+            for ((moved, reference_position), &position) in
+                self.moved.iter_mut().zip(&mut self.reference_positions).zip(positions)
+            {
+                *moved = (position - *reference_position).magnitude_squared() > max_displacement_squared;
+                if *moved {
+                    *reference_position = position;
+                }
+            }
+        }
+
+        self.moved_indices.clear();
+        self.moved_indices.extend(self.moved.iter().enumerate().filter_map(|(i, &moved)| moved.then_some(i)));
+        if self.moved_indices.is_empty() {
+            return 0;
+        }
+
+        let moved = &self.moved;
+        self.pairs.retain(|pair| !moved[pair.object1_index as usize] && !moved[pair.object2_index as usize]);
+        let margin = self.margin;
+        thread_pool.install(|| {
+            // Buffers are per rayon job rather than per object, which keeps the allocation count low
+            let new_pairs = self.moved_indices.par_iter().fold(
+                || (Vec::new(), Vec::new()),
+                |(mut pairs, mut neighbors), &object_index| {
+                    neighbors.clear();
+                    bvh.find_neighbors(object_index, margin, positions, radii, &mut neighbors);
+                    pairs
+                        .extend(neighbors.iter().map(|&neighbor| NormalizedCollisionPair::new(object_index, neighbor)));
+                    (pairs, neighbors)
+                },
+            );
+            self.pairs.par_extend(new_pairs.flat_map_iter(|(pairs, _)| pairs));
+            self.pairs.par_sort_unstable();
+        });
+        self.pairs.dedup();
+        self.moved_indices.len()
+    }
+}
+
+#[test]
+fn pair_cache_matches_brute_force() {
+    let mut positions =
+        (0..100).map(|i| Vector2::new((i % 10) as f32 * 3.0, (i / 10) as f32 * 3.0)).collect::<Vec<_>>();
+    let radii = vec![1.4; positions.len()];
+    let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let mut bvh = Bvh::default();
+    let mut cache = PairCache::new(1.0);
+    let touching_pairs = |positions: &[Vector2<f32>]| {
+        let mut pairs = Vec::new();
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                if (positions[i] - positions[j]).magnitude() < radii[i] + radii[j] {
+                    pairs.push(NormalizedCollisionPair::new(i, j));
+                }
+            }
+        }
+        pairs
+    };
+
+    for step in 0..20 {
+        for (i, position) in positions.iter_mut().enumerate() {
+            if i % 7 == step % 7 {
+                position.x += 0.1;
+            }
+        }
+        bvh.update(
+            &positions,
+            &radii,
+            crate::bvh::AABB {
+                topleft: Vector2::new(0.0, 0.0),
+                bottomright: Vector2::new(30.0, 30.0),
+            },
+        );
+        cache.update(&bvh, &thread_pool, &positions, &radii);
+        for pair in touching_pairs(&positions) {
+            assert!(cache.pairs().contains(&pair));
+        }
+    }
+}
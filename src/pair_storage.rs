@@ -0,0 +1,200 @@
+use rayon::{ThreadPool, slice::ParallelSliceMut};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::physics::NormalizedCollisionPair;
+
+// Where the collision candidates are merged and ordered before the solver takes them
+pub trait PairStorage: Send {
+    // Replaces the stored pairs; duplicates are stored once
+    fn store(&mut self, pairs: &[NormalizedCollisionPair], thread_pool: &ThreadPool);
+
+    // Every stored pair once, sorted
+    fn pairs(&self) -> Box<dyn Iterator<Item = NormalizedCollisionPair> + '_>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Bytes allocated, including the spare capacity
+    fn memory_size(&self) -> usize;
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PairStorageKind {
+    #[default]
+    #[serde(rename = "vec")]
+    Vec,
+
+    #[serde(rename = "bitset")]
+    Bitset,
+}
+
+impl PairStorageKind {
+    // As in the config
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            PairStorageKind::Vec => "vec",
+            PairStorageKind::Bitset => "bitset",
+        }
+    }
+
+    #[must_use]
+    pub fn create(self) -> Box<dyn PairStorage> {
+        match self {
+            PairStorageKind::Vec => Box::new(PairVec::default()),
+            PairStorageKind::Bitset => Box::new(PairBitset::default()),
+        }
+    }
+}
+
+// Sorted and deduplicated in place
+#[derive(Default)]
+pub struct PairVec {
+    pairs: Vec<NormalizedCollisionPair>,
+}
+
+impl PairStorage for PairVec {
+    fn store(&mut self, pairs: &[NormalizedCollisionPair], thread_pool: &ThreadPool) {
+        self.pairs.clear();
+        self.pairs.extend_from_slice(pairs);
+        thread_pool.install(|| self.pairs.par_sort_unstable());
+        self.pairs.dedup();
+    }
+
+    fn pairs(&self) -> Box<dyn Iterator<Item = NormalizedCollisionPair> + '_> {
+        Box::new(self.pairs.iter().copied())
+    }
+
+    fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    fn memory_size(&self) -> usize {
+        self.pairs.capacity() * size_of::<NormalizedCollisionPair>()
+    }
+}
+
+const TILE_SIZE: usize = 64;
+
+// Pairs as bits in tiles of 64 × 64 pairs of object indices, so that duplicates merge and the pairs come out sorted
+// without sorting them. A tile takes 512 bytes, as much as 64 pairs in a vector, so this pays off in dense scenes
+// where neighbors are also close in memory, and the tiles are filled.
+#[derive(Default)]
+pub struct PairBitset {
+    // Indexed by the first object index divided by the tile size: the tiles of the row, with the second object index
+    // divided by the tile size
+    rows: Vec<Vec<(u32, usize)>>,
+    // Rows with tiles, sorted once the pairs are stored
+    used_rows: Vec<u32>,
+    // Bits of the second object indices, indexed by the first object index within the tile
+    tiles: Vec<[u64; TILE_SIZE]>,
+    len: usize,
+}
+
+impl PairStorage for PairBitset {
+    fn store(&mut self, pairs: &[NormalizedCollisionPair], _thread_pool: &ThreadPool) {
+        for &row in &self.used_rows {
+            self.rows[row as usize].clear();
+        }
+        self.used_rows.clear();
+        self.tiles.clear();
+        self.len = 0;
+        for pair in pairs {
+            let row = pair.object1_index as usize / TILE_SIZE;
+            let column = pair.object2_index / TILE_SIZE as u32;
+            if row >= self.rows.len() {
+                self.rows.resize_with(row + 1, Vec::new);
+            }
+            let row_tiles = &mut self.rows[row];
+            let tile_index =
+                if let Some(&(_, tile_index)) = row_tiles.iter().find(|(tile_column, _)| *tile_column == column) {
+                    tile_index
+                } else {
+                    if row_tiles.is_empty() {
+                        self.used_rows.push(u32::try_from(row).unwrap());
+                    }
+                    row_tiles.push((column, self.tiles.len()));
+                    self.tiles.push([0; TILE_SIZE]);
+                    self.tiles.len() - 1
+                };
+            let word = &mut self.tiles[tile_index][pair.object1_index as usize % TILE_SIZE];
+            let bit = 1 << (pair.object2_index as usize % TILE_SIZE);
+            if *word & bit == 0 {
+                *word |= bit;
+                self.len += 1;
+            }
+        }
+        self.used_rows.sort_unstable();
+        for &row in &self.used_rows {
+            self.rows[row as usize].sort_unstable();
+        }
+    }
+
+    fn pairs(&self) -> Box<dyn Iterator<Item = NormalizedCollisionPair> + '_> {
+        Box::new(self.used_rows.iter().flat_map(move |&row| {
+            let row_tiles = &self.rows[row as usize];
+            (0..TILE_SIZE).flat_map(move |tile_row| {
+                let object1_index = row * TILE_SIZE as u32 + u32::try_from(tile_row).unwrap();
+                row_tiles.iter().flat_map(move |&(column, tile_index)| {
+                    set_bits(self.tiles[tile_index][tile_row]).map(move |bit| NormalizedCollisionPair {
+                        object1_index,
+                        object2_index: column * TILE_SIZE as u32 + bit,
+                    })
+                })
+            })
+        }))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn memory_size(&self) -> usize {
+        self.tiles.capacity() * size_of::<[u64; TILE_SIZE]>()
+            + self.rows.capacity() * size_of::<Vec<(u32, usize)>>()
+            + self.rows.iter().map(|row_tiles| row_tiles.capacity() * size_of::<(u32, usize)>()).sum::<usize>()
+            + self.used_rows.capacity() * size_of::<u32>()
+    }
+}
+
+// Positions of the set bits, lowest first
+fn set_bits(mut word: u64) -> impl Iterator<Item = u32> {
+    std::iter::from_fn(move || {
+        (word != 0).then(|| {
+            let bit = word.trailing_zeros();
+            word &= word - 1;
+            bit
+        })
+    })
+}
+
+#[test]
+fn pair_storages_agree() {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut pairs = (0..2000)
+        .map(|_| {
+            let object1_index = rng.random_range(0..500);
+            NormalizedCollisionPair::new(object1_index, object1_index + rng.random_range(1..100))
+        })
+        .collect::<Vec<_>>();
+    pairs.extend_from_slice(&pairs.clone()[..500]);
+    let mut vec = PairStorageKind::Vec.create();
+    let mut bitset = PairStorageKind::Bitset.create();
+    for pairs in [&pairs[..], &pairs[..100], &[]] {
+        vec.store(pairs, &thread_pool);
+        bitset.store(pairs, &thread_pool);
+        let mut expected = pairs.to_vec();
+        expected.sort_unstable();
+        expected.dedup();
+        assert_eq!(vec.pairs().collect::<Vec<_>>(), expected);
+        assert_eq!(bitset.pairs().collect::<Vec<_>>(), expected);
+        assert_eq!(bitset.len(), expected.len());
+    }
+    assert!(bitset.is_empty());
+}
@@ -1,118 +1,429 @@
 use std::{
     iter::{once, zip},
+    mem,
+    path::Path,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
-use anyhow::Context;
+use anyhow::bail;
+#[cfg(feature = "gpu-opencl")]
+use anyhow::{Context, ensure};
+#[cfg(feature = "gpu-opencl")]
+use itertools::EitherOrBoth;
 use itertools::Itertools;
+#[cfg(feature = "gpu-opencl")]
 use opencl3::kernel::{ExecuteKernel, Kernel};
-use rand::{rng, seq::SliceRandom};
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
 use rayon::{
     ThreadPool, ThreadPoolBuilder,
     iter::{IndexedParallelIterator, ParallelIterator},
     slice::ParallelSliceMut,
 };
+use serde_derive::{Deserialize, Serialize};
 
 use crate::{
-    app_config::{CONFIG, DtSource},
-    bvh::{AABB, Bvh, Node},
+    boundary::{Boundaries, Wall, WallBehavior, WallFlux},
+    broad_phase::BroadPhase,
+    bvh::{AABB, Bvh, BvhMetrics},
+    event_driven::{self, EventDrivenStats},
+    fluid::{Fluid, FluidSolver},
+    material::{CombineRule, Interaction, Material, MaterialPair, MaterialTable},
+    object::{ObjectPrototype, ObjectSoa},
+    pair_cache::PairCache,
+    pair_storage::{PairStorage, PairStorageKind},
+    ring_buffer::RingBuffer,
+    snapshot::{ObjectState, Snapshot},
+    thermostat::Thermostat,
+    trajectory::TrajectoryRecorder,
+    vector2::Vector2,
+    verlet::VerletLists,
+    wind::Wind,
+};
+#[cfg(feature = "gpu-opencl")]
+use crate::{
+    bvh::{Node, NodeData, NodeTag},
+    compute_selector::ComputeTimings,
+    fp_exceptions::FpExceptionGuard,
     gpu::{
         GPU,
         GpuBufferAccessMode::{ReadOnly, ReadWrite, WriteOnly},
         GpuDeviceBuffer, GpuHostBuffer, GpuHostPtrBuffer,
     },
-    object::{ObjectPrototype, ObjectSoa},
-    ring_buffer::RingBuffer,
-    vector2::Vector2,
 };
 
 pub struct PhysicsEngine {
     pub enable_constraint_bouncing: bool,
+    pub boundaries: Boundaries,
     objects: ObjectSoa,
     bvh: Bvh,
+    // Set while the Verlet lists let the steps skip the BVH updates
+    bvh_stale: bool,
+    // Replaces the BVH in the CPU search for collision candidates
+    broad_phase: Option<Box<dyn BroadPhase>>,
     candidates: Vec<NormalizedCollisionPair>,
+    pair_cache: Option<PairCache>,
+    // Replaces the pair cache
+    verlet_lists: Option<VerletLists>,
+    pair_storage: Box<dyn PairStorage>,
+    contacts: Vec<Contact>,
+    // Pairs of objects stuck together by adhesion
+    attachments: Vec<NormalizedCollisionPair>,
+    // Indices of the particles that hit an absorbing wall during the step, in ascending order
+    absorbed: Vec<usize>,
+    // Real time the collision processing may take from the start of the step before the rest of the candidates is
+    // deferred to the next step
+    collision_budget: Option<Duration>,
+    step_deadline: Option<Instant>,
+    // Candidates left over by the last step, sorted; processed first in the next step
+    deferred_candidates: Vec<NormalizedCollisionPair>,
+    // Net mass that left the box through each wall during the step
+    wall_mass_transfers: [f32; 4],
+    // Fractional particles yet to be added by each inflow wall
+    inflow_accumulators: [f32; 4],
     time: f32,
+    last_dt: f32,
+    dt_source: DtSource,
+    mode: SimulationMode,
+    hybrid_max_cluster_size: usize,
     constraints: AABB,
     stats: Stats,
-    restitution_coefficient: f32,
+    materials: MaterialTable,
+    restitution_velocity_threshold: f32,
+    penetration_slop: f32,
+    position_correction_factor: f32,
     global_gravity: Vector2<f32>,
+    gravity_zones: Vec<GravityZone>,
     gravitational_constant: f32,
+    wind: Option<Wind>,
+    thermostat: Option<Thermostat>,
+    fluid: Option<FluidSolver>,
+    heat_conduction: Option<f32>,
+    // Heat of each object, per unit of mass; empty unless heat is tracked
+    heat: Vec<f32>,
+    compensated_summation: bool,
+    position_compensations: Vec<Vector2<f32>>,
+    velocity_compensations: Vec<Vector2<f32>>,
+    // Accumulated for the next step; empty if there are none
+    external_forces: Vec<Vector2<f32>>,
+    external_impulses: Vec<Vector2<f32>>,
+    // Velocity change of the last integration per unit of time, collisions excluded; empty unless tracked
+    track_accelerations: bool,
+    accelerations: Vec<Vector2<f32>>,
+    rng: StdRng,
+    initial_angular_momentum: Option<f64>,
+    step_observer: Option<StepObserver>,
+    trajectory_recorder: Option<TrajectoryRecorder>,
     gpu_compute_options: GpuComputeOptions,
-    gpu_integration_kernel: Kernel,
-    gpu_object_positions: GpuHostPtrBuffer<Vector2<f32>>,
-    gpu_object_velocities: GpuHostPtrBuffer<Vector2<f32>>,
-    gpu_object_radii: GpuHostPtrBuffer<f32>,
-    gpu_planet_masses: GpuHostBuffer<f32>,
-    thread_pool: ThreadPool,
+    // Set once a kernel fails, after which the engine stays on the CPU
+    gpu_failed: bool,
+    // Real time the kernels may run before the step fails, instead of hanging on a GPU that stopped responding
+    #[cfg_attr(not(feature = "gpu-opencl"), allow(dead_code))]
+    gpu_kernel_timeout: Option<Duration>,
+    // Created on the first GPU computation
+    #[cfg(feature = "gpu-opencl")]
+    gpu: Option<GpuState>,
+    thread_pool: Arc<ThreadPool>,
     max_candidates_per_object: usize,
-    gpu_bvh_kernel: Kernel,
-    gpu_bvh_nodes: GpuDeviceBuffer<Node>,
-    gpu_collision_candidates: GpuHostPtrBuffer<NormalizedCollisionPair>,
-    gpu_collision_candidates_length: GpuHostBuffer<u32>,
-    gpu_errors: GpuHostBuffer<u32>,
 }
 
-const MAX_CANDIDATES_PER_OBJECT: usize = 16; // TODO maybe calculate based on min and max radii
+// Kernels and buffers of the GPU computations. They are created on the first use instead of with the engine, so the
+// engine works without a GPU as long as the computations stay on the CPU.
+#[cfg(feature = "gpu-opencl")]
+struct GpuState {
+    integration_kernel: Kernel,
+    object_positions: GpuHostPtrBuffer<Vector2<f32>>,
+    object_velocities: GpuHostPtrBuffer<Vector2<f32>>,
+    object_radii: GpuHostPtrBuffer<f32>,
+    object_frozen: GpuHostPtrBuffer<bool>,
+    object_materials: GpuHostPtrBuffer<u32>,
+    materials: GpuHostBuffer<Material>,
+    gravity_zones: GpuHostBuffer<GravityZone>,
+    planet_masses: GpuHostBuffer<f32>,
+    bvh_kernel: Kernel,
+    bvh_nodes: GpuDeviceBuffer<Node>,
+    // The kernel appends the pairs through the counter, so only the written prefix is read back
+    collision_candidates: GpuDeviceBuffer<NormalizedCollisionPair>,
+    collision_candidates_length: GpuHostBuffer<u32>,
+    errors: GpuHostBuffer<u32>,
+}
 
-impl PhysicsEngine {
-    pub fn new(mut objects: ObjectSoa) -> anyhow::Result<Self> {
-        let constraints = AABB {
-            topleft: Vector2::new(0.0, 0.0),
-            bottomright: Vector2::new(CONFIG.window.width as f32, CONFIG.window.height as f32),
-        };
-        let thread_pool = ThreadPoolBuilder::new().num_threads(num_cpus::get()).build().unwrap();
-        let mut bvh = Bvh::default();
-        bvh.update(&objects.positions, &objects.radii);
-        let mut candidates = vec![NormalizedCollisionPair::new(0, 0); objects.len() * MAX_CANDIDATES_PER_OBJECT];
+#[cfg(feature = "gpu-opencl")]
+impl GpuState {
+    fn new(
+        objects: &mut ObjectSoa,
+        candidate_capacity: usize,
+        bvh_node_count: usize,
+        materials: &MaterialTable,
+        gravity_zones: &[GravityZone],
+    ) -> anyhow::Result<Self> {
+        let _fp_exception_guard = FpExceptionGuard::new();
         let integration_program = GPU.build_program("src/leapfrog_yoshida.cl")?;
-        let gpu_integration_kernel =
-            Kernel::create(&integration_program, "leapfrog_yoshida").context("Failed to create kernel")?;
+        GPU.verify_layout(
+            &integration_program,
+            "leapfrog_yoshida_layout",
+            &[
+                ("Material", mem::size_of::<Material>()),
+                ("GravityZone", mem::size_of::<GravityZone>()),
+                ("Vector2<f32>", mem::size_of::<Vector2<f32>>()),
+            ],
+        )?;
         let bvh_program = GPU.build_program("src/bvh.cl")?;
-        let gpu_bvh_kernel = Kernel::create(&bvh_program, "bvh_find_candidates").context("Failed to create kernel")?;
-        let gpu_object_positions = unsafe { GPU.create_host_ptr_buffer(&mut objects.positions, ReadWrite) }.unwrap();
-        let gpu_object_velocities = unsafe { GPU.create_host_ptr_buffer(&mut objects.velocities, ReadWrite) }.unwrap();
-        let gpu_object_radii = unsafe { GPU.create_host_ptr_buffer(&mut objects.radii, ReadOnly) }.unwrap();
-        let gpu_planet_masses = GPU
-            .create_host_buffer(
+        GPU.verify_layout(
+            &bvh_program,
+            "bvh_layout",
+            &[
+                ("AABB", mem::size_of::<AABB>()),
+                ("Node", mem::size_of::<Node>()),
+                ("NodeTag", mem::size_of::<NodeTag>()),
+                ("NodeData", mem::size_of::<NodeData>()),
+                ("Vector2<f32>", mem::size_of::<Vector2<f32>>()),
+                ("NormalizedCollisionPair", mem::size_of::<NormalizedCollisionPair>()),
+            ],
+        )?;
+        Ok(Self {
+            integration_kernel: Kernel::create(&integration_program, "leapfrog_yoshida")
+                .context("Failed to create kernel")?,
+            object_positions: unsafe { GPU.create_host_ptr_buffer("positions", &mut objects.positions, ReadWrite) }?,
+            object_velocities: unsafe { GPU.create_host_ptr_buffer("velocities", &mut objects.velocities, ReadWrite) }?,
+            object_radii: unsafe { GPU.create_host_ptr_buffer("radii", &mut objects.radii, ReadOnly) }?,
+            object_frozen: unsafe { GPU.create_host_ptr_buffer("frozen flags", &mut objects.is_frozen, ReadOnly) }?,
+            object_materials: unsafe {
+                GPU.create_host_ptr_buffer("object materials", &mut objects.materials, ReadOnly)
+            }?,
+            materials: GPU.create_host_buffer("materials", materials.materials().to_vec(), ReadOnly)?,
+            // Padded, since OpenCL buffers can't be empty
+            gravity_zones: GPU.create_host_buffer(
+                "gravity zones",
+                gravity_zones
+                    .iter()
+                    .copied()
+                    .chain(once(GravityZone {
+                        gravity: Vector2::default(),
+                        min_y: 0.0,
+                    }))
+                    .collect_vec(),
+                ReadOnly,
+            )?,
+            planet_masses: GPU.create_host_buffer(
+                "planet masses",
                 objects.masses[objects.planet_range()].iter().copied().chain(once(0.0)).collect_vec(),
                 ReadOnly,
-            )
-            .unwrap();
-        let gpu_bvh_nodes = GPU.create_device_buffer(bvh.nodes().len(), ReadOnly).unwrap();
-        let gpu_collision_candidates = unsafe { GPU.create_host_ptr_buffer(&mut candidates, WriteOnly) }.unwrap();
-        let gpu_collision_candidates_length = GPU.create_host_buffer(vec![0_u32], ReadWrite).unwrap();
-        let gpu_errors = GPU.create_host_buffer(vec![0], ReadWrite).unwrap();
+            )?,
+            bvh_kernel: Kernel::create(&bvh_program, "bvh_find_candidates").context("Failed to create kernel")?,
+            bvh_nodes: GPU.create_device_buffer("bvh nodes", bvh_node_count, ReadOnly)?,
+            collision_candidates: GPU.create_device_buffer("collision candidates", candidate_capacity, WriteOnly)?,
+            collision_candidates_length: GPU.create_host_buffer("collision candidate count", vec![0_u32], ReadWrite)?,
+            errors: GPU.create_host_buffer("errors", vec![0], ReadWrite)?,
+        })
+    }
+}
+
+const MAX_CANDIDATES_PER_OBJECT: usize = 16; // TODO maybe calculate based on min and max radii
+// Candidates processed between the checks of the collision budget
+const COLLISION_BUDGET_BATCH_SIZE: usize = 256;
+
+impl PhysicsEngine {
+    pub fn new(objects: ObjectSoa, settings: PhysicsSettings) -> anyhow::Result<Self> {
+        let thread_pool = settings
+            .thread_pool
+            .clone()
+            .unwrap_or_else(|| Arc::new(ThreadPoolBuilder::new().num_threads(num_cpus::get()).build().unwrap()));
+        let mut bvh = Bvh::default();
+        bvh.update(&objects.positions, &objects.radii, settings.constraints);
+        let candidates = vec![NormalizedCollisionPair::new(0, 0); objects.len() * MAX_CANDIDATES_PER_OBJECT];
+        let materials = MaterialTable::new(
+            once(Material::new(settings.restitution_coefficient)).chain(settings.materials.iter().copied()).collect(),
+            settings.restitution_combine,
+            settings.friction_combine,
+            &settings.material_pairs,
+        )?;
+        let material_count = u32::try_from(materials.len()).unwrap();
+        if let Some(object_index) = objects.materials.iter().position(|&material| material >= material_count) {
+            bail!("object {object_index} has unknown material {}", objects.materials[object_index]);
+        }
+        let mut gravity_zones = settings.gravity_zones.clone();
+        gravity_zones.sort_by(|zone1, zone2| zone1.min_y.total_cmp(&zone2.min_y));
         Ok(Self {
-            enable_constraint_bouncing: true,
+            enable_constraint_bouncing: settings.constraint_bouncing,
+            boundaries: settings.boundaries,
             thread_pool,
             objects,
             bvh,
+            bvh_stale: false,
+            broad_phase: None,
             candidates,
+            pair_cache: settings.pair_cache_margin.map(PairCache::new),
+            verlet_lists: settings.verlet_skin.map(VerletLists::new),
+            pair_storage: settings.pair_storage.create(),
+            contacts: Vec::new(),
+            attachments: Vec::new(),
+            absorbed: Vec::new(),
+            collision_budget: settings.collision_budget,
+            step_deadline: None,
+            deferred_candidates: Vec::new(),
+            wall_mass_transfers: [0.0; 4],
+            inflow_accumulators: [0.0; 4],
             time: 0.0,
-            constraints,
-            stats: Stats::default(),
-            restitution_coefficient: CONFIG.simulation.restitution_coefficient,
-            global_gravity: Vector2::from(CONFIG.simulation.global_gravity),
-            gravitational_constant: CONFIG.simulation.gravitational_constant,
+            last_dt: 0.0,
+            dt_source: settings.dt,
+            mode: settings.mode,
+            hybrid_max_cluster_size: settings.hybrid_max_cluster_size,
+            constraints: settings.constraints,
+            stats: Stats {
+                integration_duration: DurationStat::new(settings.duration_stat_window),
+                bvh_duration: DurationStat::new(settings.duration_stat_window),
+                collisions_duration: DurationStat::new(settings.duration_stat_window),
+                constraints_duration: DurationStat::new(settings.duration_stat_window),
+                total_duration: DurationStat::new(settings.duration_stat_window),
+                pair_storage_duration: DurationStat::new(settings.duration_stat_window),
+                ..Stats::default()
+            },
+            materials,
+            restitution_velocity_threshold: settings.restitution_velocity_threshold,
+            penetration_slop: settings.penetration_slop,
+            position_correction_factor: settings.position_correction_factor,
+            global_gravity: settings.global_gravity,
+            gravity_zones,
+            gravitational_constant: settings.gravitational_constant,
+            wind: settings.wind,
+            thermostat: settings.thermostat,
+            fluid: settings.fluid.map(FluidSolver::new),
+            heat_conduction: settings.heat_conduction,
+            heat: Vec::new(),
+            compensated_summation: settings.compensated_summation,
+            position_compensations: Vec::new(),
+            velocity_compensations: Vec::new(),
+            external_forces: Vec::new(),
+            external_impulses: Vec::new(),
+            track_accelerations: false,
+            accelerations: Vec::new(),
+            initial_angular_momentum: None,
+            step_observer: None,
+            trajectory_recorder: None,
+            rng: settings.seed.map_or_else(StdRng::from_os_rng, StdRng::seed_from_u64),
             gpu_compute_options: GpuComputeOptions::default(),
-            gpu_integration_kernel,
-            gpu_object_positions,
-            gpu_object_velocities,
-            gpu_object_radii,
-            gpu_planet_masses,
+            gpu_failed: false,
+            gpu_kernel_timeout: settings.gpu_kernel_timeout,
+            #[cfg(feature = "gpu-opencl")]
+            gpu: None,
             max_candidates_per_object: 0,
-            gpu_bvh_kernel,
-            gpu_bvh_nodes,
-            gpu_collision_candidates,
-            gpu_collision_candidates_length,
-            gpu_errors,
         })
     }
 
     pub fn add(&mut self, object: ObjectPrototype) -> usize {
+        self.initial_angular_momentum = None;
         self.objects.add(object)
     }
 
+    pub fn insert(&mut self, object_index: usize, object: ObjectPrototype) {
+        self.initial_angular_momentum = None;
+        self.attachments.clear();
+        self.deferred_candidates.clear();
+        if object_index <= self.position_compensations.len() {
+            self.position_compensations.insert(object_index, Vector2::default());
+            self.velocity_compensations.insert(object_index, Vector2::default());
+        }
+        if object_index <= self.heat.len() {
+            self.heat.insert(object_index, 0.0);
+        }
+        for external in [
+            &mut self.external_forces,
+            &mut self.external_impulses,
+            &mut self.accelerations,
+        ] {
+            if !external.is_empty() {
+                external.insert(object_index, Vector2::default());
+            }
+        }
+        if let Some(trajectory_recorder) = &mut self.trajectory_recorder {
+            trajectory_recorder.object_inserted(object_index);
+        }
+        self.objects.insert(object_index, object);
+    }
+
+    pub fn remove(&mut self, object_index: usize) -> ObjectPrototype {
+        self.initial_angular_momentum = None;
+        self.attachments.clear();
+        self.deferred_candidates.clear();
+        if object_index < self.position_compensations.len() {
+            self.position_compensations.remove(object_index);
+            self.velocity_compensations.remove(object_index);
+        }
+        if object_index < self.heat.len() {
+            self.heat.remove(object_index);
+        }
+        for external in [
+            &mut self.external_forces,
+            &mut self.external_impulses,
+            &mut self.accelerations,
+        ] {
+            if !external.is_empty() {
+                external.remove(object_index);
+            }
+        }
+        if let Some(trajectory_recorder) = &mut self.trajectory_recorder {
+            trajectory_recorder.object_removed(object_index);
+        }
+        self.objects.remove(object_index)
+    }
+
+    // Constant time, but other objects take the place of the removed one, see `ObjectSoa::swap_remove`. The state kept
+    // for each object follows the objects that moved, except for the attachments and the deferred candidates, which
+    // are dropped like in `remove`.
+    pub fn swap_remove(&mut self, object_index: usize) -> ObjectPrototype {
+        self.objects_reordered(&Reorder::SwapRemove(self.objects.swap_remove_swaps(object_index)));
+        self.objects.swap_remove(object_index)
+    }
+
+    // Removes the objects rejected by `keep`, see `ObjectSoa::retain`, along with the state kept for them
+    pub fn retain(&mut self, mut keep: impl FnMut(usize, &ObjectPrototype) -> bool) -> usize {
+        let kept = (0..self.objects.len())
+            .map(|object_index| keep(object_index, &self.objects.get(object_index)))
+            .collect::<Vec<_>>();
+        self.objects_reordered(&Reorder::Retain(&kept));
+        self.objects.retain(|object_index, _| kept[object_index])
+    }
+
+    // Applies a change of the object order to the state kept for each object, before it's applied to the objects
+    fn objects_reordered(&mut self, reorder: &Reorder) {
+        self.initial_angular_momentum = None;
+        self.attachments.clear();
+        self.deferred_candidates.clear();
+        if let Some(pair_cache) = &mut self.pair_cache {
+            pair_cache.invalidate();
+        }
+        if let Some(verlet_lists) = &mut self.verlet_lists {
+            verlet_lists.invalidate();
+        }
+        let object_count = self.objects.len();
+        for values in [
+            &mut self.position_compensations,
+            &mut self.velocity_compensations,
+            &mut self.external_forces,
+            &mut self.external_impulses,
+            &mut self.accelerations,
+        ] {
+            reorder.apply(values, object_count);
+        }
+        reorder.apply(&mut self.heat, object_count);
+        if let Some(trajectory_recorder) = &mut self.trajectory_recorder {
+            trajectory_recorder.objects_moved(|object_index| reorder.new_index(object_index, object_count));
+        }
+    }
+
+    // Force acting on the object during the next step, in addition to the ones applied before it
+    pub fn apply_force(&mut self, object_index: usize, force: Vector2<f32>) {
+        self.external_forces.resize(self.objects.len(), Vector2::default());
+        self.external_forces[object_index] += force;
+    }
+
+    // Instant change of the object's momentum at the start of the next step
+    pub fn apply_impulse(&mut self, object_index: usize, impulse: Vector2<f32>) {
+        self.external_impulses.resize(self.objects.len(), Vector2::default());
+        self.external_impulses[object_index] += impulse;
+    }
+
     #[must_use]
     pub fn objects(&self) -> &ObjectSoa {
         &self.objects
@@ -137,11 +448,121 @@ impl PhysicsEngine {
         self.time
     }
 
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    // Checkpoints the simulation time, every object and the heat and summation compensations kept for them as a
+    // snapshot, to continue later with `load_state`. The attachments aren't saved: adhesion forms them again on the
+    // next contact.
+    pub fn save_state(&self, path: &Path) -> anyhow::Result<()> {
+        let object_state = ObjectState {
+            heat: self.heat.clone(),
+            position_compensations: self.position_compensations.clone(),
+            velocity_compensations: self.velocity_compensations.clone(),
+        };
+        Snapshot::save(path, self.time, &self.objects, &object_state)
+    }
+
+    // Replaces the objects, the state kept for them and the simulation time with those saved by `save_state`. The
+    // settings aren't a part of the state, so they stay those of this engine.
+    pub fn load_state(&mut self, path: &Path) -> anyhow::Result<()> {
+        let Snapshot {
+            time,
+            objects,
+            object_state,
+        } = Snapshot::load(path)?;
+        self.retain(|_, _| false);
+        self.bvh.update(&objects.positions, &objects.radii, self.constraints);
+        self.bvh_stale = false;
+        self.objects = objects;
+        self.heat = object_state.heat;
+        self.position_compensations = object_state.position_compensations;
+        self.velocity_compensations = object_state.velocity_compensations;
+        self.time = time;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn last_dt(&self) -> f32 {
+        self.last_dt
+    }
+
+    #[must_use]
+    pub fn global_gravity(&self) -> Vector2<f32> {
+        self.global_gravity
+    }
+
+    pub fn set_global_gravity(&mut self, global_gravity: Vector2<f32>) {
+        self.global_gravity = global_gravity;
+    }
+
+    #[must_use]
+    pub fn gravity_zones(&self) -> &[GravityZone] {
+        &self.gravity_zones
+    }
+
+    #[must_use]
+    pub fn wind(&self) -> Option<Wind> {
+        self.wind
+    }
+
+    pub fn set_wind(&mut self, wind: Option<Wind>) {
+        self.wind = wind;
+    }
+
+    #[must_use]
+    pub fn thermostat(&self) -> Option<Thermostat> {
+        self.thermostat
+    }
+
+    pub fn set_thermostat(&mut self, thermostat: Option<Thermostat>) {
+        self.thermostat = thermostat;
+    }
+
+    #[must_use]
+    pub fn fluid(&self) -> Option<Fluid> {
+        self.fluid.as_ref().map(FluidSolver::fluid)
+    }
+
+    pub fn set_fluid(&mut self, fluid: Option<Fluid>) {
+        self.fluid = fluid.map(FluidSolver::new);
+    }
+
+    // Empty if heat isn't tracked
+    #[must_use]
+    pub fn heat(&self) -> &[f32] {
+        &self.heat
+    }
+
+    pub fn set_track_accelerations(&mut self, track_accelerations: bool) {
+        self.track_accelerations = track_accelerations;
+        if !track_accelerations {
+            self.accelerations = Vec::new();
+        }
+    }
+
+    // Empty unless tracked and the objects have been integrated since, which the event-driven mode doesn't do
+    #[must_use]
+    pub fn accelerations(&self) -> &[Vector2<f32>] {
+        &self.accelerations
+    }
+
+    #[must_use]
+    pub fn materials(&self) -> &MaterialTable {
+        &self.materials
+    }
+
     #[must_use]
     pub fn constraints(&self) -> AABB {
         self.constraints
     }
 
+    #[must_use]
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+
     pub fn bvh(&self) -> &Bvh {
         &self.bvh
     }
@@ -150,14 +571,96 @@ impl PhysicsEngine {
         &mut self.bvh
     }
 
+    // Objects whose circle intersects the given one. The BVH of the last step narrows the search, so an object that
+    // moved far since then may be missed; if objects were added or removed since then, or the Verlet lists skipped the
+    // BVH updates, all of them are checked.
+    #[must_use]
+    pub fn objects_near(&self, position: Vector2<f32>, radius: f32) -> Vec<usize> {
+        let objects = &self.objects;
+        let is_near = |object_index: usize| {
+            let distance = radius + objects.radii[object_index];
+            (objects.positions[object_index] - position).magnitude_squared() <= distance * distance
+        };
+        if !self.bvh_stale && self.bvh.object_count() == objects.len() {
+            self.bvh.query_circle(position, radius).into_iter().filter(|&object_index| is_near(object_index)).collect()
+        } else {
+            (0..objects.len()).filter(|&object_index| is_near(object_index)).collect()
+        }
+    }
+
+    // Called after every step, e.g. to log the simulation without polling the stats
+    pub fn set_step_observer(&mut self, observer: StepObserver) {
+        self.step_observer = Some(observer);
+    }
+
+    pub fn take_step_observer(&mut self) -> Option<StepObserver> {
+        self.step_observer.take()
+    }
+
+    pub fn set_trajectory_recorder(&mut self, trajectory_recorder: Option<TrajectoryRecorder>) {
+        self.trajectory_recorder = trajectory_recorder;
+    }
+
+    #[must_use]
+    pub fn trajectory_recorder(&self) -> Option<&TrajectoryRecorder> {
+        self.trajectory_recorder.as_ref()
+    }
+
+    #[must_use]
+    pub fn gpu_failed(&self) -> bool {
+        self.gpu_failed
+    }
+
+    // A kernel that timed out may still be running and accessing the vectors its buffers alias, so these are leaked
+    // along with the GPU state instead of being reused or freed, and the engine continues with copies. The positions
+    // and the velocities, which the kernel may have written to, are replaced with the given ones.
+    #[cfg(feature = "gpu-opencl")]
+    fn fall_back_to_cpu(&mut self, error: &anyhow::Error, positions: Vec<Vector2<f32>>, velocities: Vec<Vector2<f32>>) {
+        fn replace<T>(vector: &mut Vec<T>, copy: Vec<T>) {
+            mem::forget(mem::replace(vector, copy));
+        }
+        fn replace_with_copy<T: Clone>(vector: &mut Vec<T>) {
+            replace(vector, vector.clone());
+        }
+        eprintln!("{error:#}, falling back to the CPU");
+        self.gpu_failed = true;
+        self.gpu_compute_options = GpuComputeOptions::default();
+        mem::forget(self.gpu.take());
+        let objects = &mut self.objects;
+        replace(&mut objects.positions, positions);
+        replace(&mut objects.velocities, velocities);
+        replace_with_copy(&mut objects.radii);
+        replace_with_copy(&mut objects.is_frozen);
+        replace_with_copy(&mut objects.materials);
+    }
+
+    // The BVH is still kept up to date, since the GPU search, the fluid pass and the rendering rely on it
+    pub fn set_broad_phase(&mut self, broad_phase: Option<Box<dyn BroadPhase>>) {
+        self.broad_phase = broad_phase;
+    }
+
     pub fn advance(&mut self, speed_factor: f32, gpu_compute_options: GpuComputeOptions) {
+        let gpu_compute_options = if cfg!(feature = "gpu-opencl") && !self.gpu_failed {
+            gpu_compute_options
+        } else {
+            GpuComputeOptions::default()
+        };
         if gpu_compute_options.integration != self.gpu_compute_options.integration {
-            self.stats.integration_duration = DurationStat::default();
+            self.stats.integration_duration = DurationStat::new(self.stats.integration_duration.window());
         }
         self.gpu_compute_options = gpu_compute_options;
+        // Phases the step skips take no time
+        for stat in [
+            &mut self.stats.integration_duration,
+            &mut self.stats.bvh_duration,
+            &mut self.stats.collisions_duration,
+            &mut self.stats.constraints_duration,
+        ] {
+            stat.current = Duration::ZERO;
+        }
 
         let start = Instant::now();
-        let dt = match CONFIG.simulation.dt {
+        let dt = match self.dt_source {
             DtSource::Auto => {
                 let (max_velocity_squared, min_object_size) =
                     zip(self.objects.velocities.iter(), self.objects.radii.iter()).fold(
@@ -180,9 +683,10 @@ impl PhysicsEngine {
                             object_index,
                             position,
                             &self.objects.positions,
-                            self.global_gravity,
+                            Self::global_gravity_at(self.global_gravity, &self.gravity_zones, position),
                             self.gravitational_constant,
                             &self.objects.masses[self.objects.planet_range()],
+                            false,
                         )
                         .magnitude_squared();
                         max_gravity_squared.max(gravity_squared)
@@ -197,37 +701,492 @@ impl PhysicsEngine {
             DtSource::Fixed(dt) => dt,
         };
         self.time += dt;
+        self.last_dt = dt;
+        self.step_deadline = self.collision_budget.map(|budget| start + budget);
         self.update(dt, gpu_compute_options);
+        // Planets aren't agitated, they would throw the temperature off with their huge masses
+        self.stats.temperature = self.thermostat.map(|thermostat| {
+            let particles = self.objects.particle_range();
+            thermostat.apply(
+                &mut self.objects.velocities[particles.clone()],
+                &self.objects.masses[particles.clone()],
+                &self.objects.is_frozen[particles],
+                dt,
+                &mut self.rng,
+            )
+        });
 
         self.stats.total_duration.update(start.elapsed());
         self.stats.sim_time = self.time;
+        self.stats.step_count += 1;
         self.stats.object_count = self.objects.len();
+        if let Some(trajectory_recorder) = &mut self.trajectory_recorder {
+            trajectory_recorder.record(
+                self.stats.step_count,
+                self.time,
+                &self.objects.positions,
+                &self.objects.velocities,
+            );
+        }
+        self.stats.angular_momentum = (self.objects.planet_count > 0).then(|| {
+            let angular_momentum =
+                angular_momentum(&self.objects.positions, &self.objects.velocities, &self.objects.masses);
+            let initial_angular_momentum = *self.initial_angular_momentum.get_or_insert(angular_momentum);
+            AngularMomentum {
+                value: angular_momentum,
+                drift: (angular_momentum - initial_angular_momentum) / initial_angular_momentum.abs().max(f64::EPSILON),
+            }
+        });
+        if let Some(mut observer) = self.step_observer.take() {
+            observer(&self.step_report(dt));
+            self.step_observer = Some(observer);
+        }
+    }
+
+    fn step_report(&self, dt: f32) -> StepReport {
+        StepReport {
+            step_count: self.stats.step_count,
+            time: self.time,
+            dt,
+            object_count: self.objects.len(),
+            contact_count: self.contacts.len(),
+            integration_duration: self.stats.integration_duration.current,
+            bvh_duration: self.stats.bvh_duration.current,
+            collisions_duration: self.stats.collisions_duration.current,
+            constraints_duration: self.stats.constraints_duration.current,
+            total_duration: self.stats.total_duration.current,
+            kinetic_energy: zip(&self.objects.masses, &self.objects.velocities)
+                .map(|(&mass, &velocity)| f64::from(kinetic_energy(mass, velocity)))
+                .sum(),
+            energy_changes: self.stats.energy_changes,
+        }
+    }
+
+    // Runs both CPU and GPU implementations of integration and broad-phase on the current state, leaving the state
+    // unchanged. A failed kernel makes the engine fall back to the CPU.
+    #[cfg(feature = "gpu-opencl")]
+    pub fn measure_compute_timings(&mut self, dt: f32) -> anyhow::Result<ComputeTimings> {
+        let positions = self.objects.positions.clone();
+        let velocities = self.objects.velocities.clone();
+        // Advanced by the CPU integration with compensated summation
+        let position_compensations = self.position_compensations.clone();
+        let velocity_compensations = self.velocity_compensations.clone();
+        let start = Instant::now();
+        self.integrate_cpu(dt);
+        let integration_cpu = start.elapsed();
+        self.objects.positions.copy_from_slice(&positions);
+        self.objects.velocities.copy_from_slice(&velocities);
+        self.position_compensations = position_compensations;
+        self.velocity_compensations = velocity_compensations;
+        let start = Instant::now();
+        if let Err(e) = self.integrate_gpu(dt) {
+            self.fall_back_to_cpu(&e, positions, velocities);
+            return Err(e);
+        }
+        let integration_gpu = start.elapsed();
+        self.objects.positions.copy_from_slice(&positions);
+        self.objects.velocities.copy_from_slice(&velocities);
+
+        // May be stale with the Verlet lists
+        self.bvh.update(&self.objects.positions, &self.objects.radii, self.constraints);
+        self.bvh_stale = false;
+        self.candidates.clear();
+        self.candidates.resize(self.objects.len() * MAX_CANDIDATES_PER_OBJECT, NormalizedCollisionPair::new(0, 0));
+        let start = Instant::now();
+        Self::find_collision_candidates_cpu(
+            &self.bvh,
+            &self.thread_pool,
+            &mut self.candidates,
+            &self.objects.positions,
+            &self.objects.radii,
+        );
+        let bvh_cpu = start.elapsed();
+        let start = Instant::now();
+        if let Err(e) = self.find_collision_candidates_gpu() {
+            self.fall_back_to_cpu(&e, positions, velocities);
+            return Err(e);
+        }
+        let bvh_gpu = start.elapsed();
+
+        Ok(ComputeTimings {
+            integration_cpu,
+            integration_gpu,
+            bvh_cpu,
+            bvh_gpu,
+        })
+    }
+
+    // Runs both CPU and GPU implementations of integration and broad-phase on the current state and compares the
+    // results, leaving the state unchanged. The result is also recorded in the stats. A failed kernel makes the engine
+    // fall back to the CPU.
+    #[cfg(feature = "gpu-opencl")]
+    pub fn validate_gpu(&mut self, dt: f32) -> anyhow::Result<GpuValidation> {
+        let positions = self.objects.positions.clone();
+        let velocities = self.objects.velocities.clone();
+        // Advanced by the CPU integration with compensated summation
+        let position_compensations = self.position_compensations.clone();
+        let velocity_compensations = self.velocity_compensations.clone();
+        self.integrate_cpu(dt);
+        let cpu_positions = self.objects.positions.clone();
+        let cpu_velocities = self.objects.velocities.clone();
+        self.objects.positions.copy_from_slice(&positions);
+        self.objects.velocities.copy_from_slice(&velocities);
+        self.position_compensations = position_compensations;
+        self.velocity_compensations = velocity_compensations;
+        if let Err(e) = self.integrate_gpu(dt) {
+            self.fall_back_to_cpu(&e, positions, velocities);
+            return Err(e);
+        }
+        let max_difference = |cpu_values: &[Vector2<f32>], gpu_values: &[Vector2<f32>]| {
+            zip(cpu_values, gpu_values).map(|(&cpu, &gpu)| (gpu - cpu).magnitude()).fold(0.0, f32::max)
+        };
+        let max_position_difference = max_difference(&cpu_positions, &self.objects.positions);
+        let max_velocity_difference = max_difference(&cpu_velocities, &self.objects.velocities);
+        self.objects.positions.copy_from_slice(&positions);
+        self.objects.velocities.copy_from_slice(&velocities);
+
+        // May be stale with the Verlet lists
+        self.bvh.update(&self.objects.positions, &self.objects.radii, self.constraints);
+        self.bvh_stale = false;
+        self.candidates.clear();
+        self.candidates.resize(self.objects.len() * MAX_CANDIDATES_PER_OBJECT, NormalizedCollisionPair::new(0, 0));
+        Self::find_collision_candidates_cpu(
+            &self.bvh,
+            &self.thread_pool,
+            &mut self.candidates,
+            &self.objects.positions,
+            &self.objects.radii,
+        );
+        let mut cpu_candidates = self.candidates.clone();
+        cpu_candidates.retain(|pair| pair.object1_index > 0 || pair.object2_index > 0);
+        cpu_candidates.sort_unstable();
+        cpu_candidates.dedup();
+        self.candidates.clear();
+        self.candidates.resize(self.objects.len() * MAX_CANDIDATES_PER_OBJECT, NormalizedCollisionPair::new(0, 0));
+        if let Err(e) = self.find_collision_candidates_gpu() {
+            self.fall_back_to_cpu(&e, positions, velocities);
+            return Err(e);
+        }
+        // Not deduplicated, so that duplicates from the GPU count as extra candidates
+        self.candidates.sort_unstable();
+        let (mut missing_candidates, mut extra_candidates) = (0, 0);
+        for pair in cpu_candidates.iter().merge_join_by(&self.candidates, Ord::cmp) {
+            match pair {
+                EitherOrBoth::Left(_) => missing_candidates += 1,
+                EitherOrBoth::Right(_) => extra_candidates += 1,
+                EitherOrBoth::Both(..) => {}
+            }
+        }
+
+        let validation = GpuValidation {
+            max_position_difference,
+            max_velocity_difference,
+            missing_candidates,
+            extra_candidates,
+        };
+        self.stats.gpu_validation = Some(validation);
+        Ok(validation)
     }
 
     fn update(&mut self, dt: f32, gpu_compute_options: GpuComputeOptions) {
+        self.wall_mass_transfers = [0.0; 4];
+        // Only the time-stepped CPU integration integrates the external forces along with gravity
+        if !matches!(self.mode, SimulationMode::TimeStepped) || gpu_compute_options.integration {
+            self.apply_external_forces_as_impulses(dt);
+        }
+        self.apply_external_impulses();
+        match self.mode {
+            SimulationMode::TimeStepped => {
+                self.update_time_stepped(dt, gpu_compute_options, Duration::ZERO);
+                self.stats.event_driven = None;
+            }
+            SimulationMode::EventDriven => self.update_event_driven(dt),
+            SimulationMode::Hybrid => self.update_hybrid(dt, gpu_compute_options),
+        }
+        self.external_forces.clear();
+        self.remove_absorbed_objects();
+        self.add_inflow(dt);
+        for (wall_flux, &mass_transfer) in zip(&mut self.stats.wall_flux, &self.wall_mass_transfers) {
+            wall_flux.mass_flux = mass_transfer / dt;
+        }
+    }
+
+    fn apply_external_forces_as_impulses(&mut self, dt: f32) {
+        if self.external_forces.is_empty() {
+            return;
+        }
+        self.external_impulses.resize(self.objects.len(), Vector2::default());
+        for (impulse, &force) in zip(&mut self.external_impulses, &self.external_forces) {
+            *impulse += force * dt;
+        }
+        self.external_forces.clear();
+    }
+
+    fn apply_external_impulses(&mut self) {
+        for (object_index, &impulse) in self.external_impulses.iter().enumerate() {
+            if !self.objects.is_frozen[object_index] {
+                self.objects.velocities[object_index] += impulse / self.objects.masses[object_index];
+            }
+        }
+        self.external_impulses.clear();
+    }
+
+    // Done after the whole step, since the hybrid mode refers to the objects by their indices until the end of it
+    fn remove_absorbed_objects(&mut self) {
+        if self.absorbed.is_empty() {
+            return;
+        }
+        let absorbed = mem::take(&mut self.absorbed);
+        for &object_index in absorbed.iter().rev() {
+            self.remove(object_index);
+        }
+        self.contacts.clear();
+        if let Some(pair_cache) = &mut self.pair_cache {
+            pair_cache.invalidate();
+        }
+        if let Some(verlet_lists) = &mut self.verlet_lists {
+            verlet_lists.invalidate();
+        }
+        self.absorbed = absorbed;
+        self.absorbed.clear();
+    }
+
+    // New particles are placed at random along the wall, just inside the box
+    fn add_inflow(&mut self, dt: f32) {
+        let cb = self.constraints;
+        for wall in Wall::ALL {
+            let WallBehavior::Inflow(inflow) = self.boundaries.wall(wall) else {
+                continue;
+            };
+            let accumulator = &mut self.inflow_accumulators[wall as usize];
+            *accumulator += inflow.rate * dt;
+            let count = accumulator.floor();
+            *accumulator -= count;
+            for _ in 0..count as usize {
+                let [min, max] = [cb.topleft + inflow.radius, cb.bottomright - inflow.radius];
+                let along = self.rng.random::<f32>();
+                let position = match wall {
+                    Wall::Left => Vector2::new(min.x, min.y + (max.y - min.y) * along),
+                    Wall::Right => Vector2::new(max.x, min.y + (max.y - min.y) * along),
+                    Wall::Top => Vector2::new(min.x + (max.x - min.x) * along, min.y),
+                    Wall::Bottom => Vector2::new(min.x + (max.x - min.x) * along, max.y),
+                };
+                self.add(ObjectPrototype {
+                    position,
+                    velocity: inflow.velocity,
+                    radius: inflow.radius,
+                    mass: inflow.mass,
+                    color: None,
+                    is_planet: false,
+                    is_frozen: false,
+                    material: 0,
+                    collision_group: 0,
+                });
+                self.stats.wall_flux[wall as usize].inflow_count += 1;
+                self.wall_mass_transfers[wall as usize] -= inflow.mass;
+            }
+        }
+    }
+
+    // The event-driven duration is the time hybrid mode spent on its clusters, counted as part of the collisions
+    fn update_time_stepped(
+        &mut self,
+        dt: f32,
+        gpu_compute_options: GpuComputeOptions,
+        event_driven_duration: Duration,
+    ) {
         let start = Instant::now();
         self.integrate(dt, gpu_compute_options);
         self.stats.integration_duration.update(start.elapsed());
 
         let start = Instant::now();
-        self.bvh.update(&self.objects.positions, &self.objects.radii);
+        // Valid Verlet lists make the BVH unnecessary, unless something else searches it
+        let bvh_needed = gpu_compute_options.bvh
+            || self.broad_phase.is_some()
+            || self.fluid.is_some()
+            || self
+                .verlet_lists
+                .as_ref()
+                .is_none_or(|verlet_lists| verlet_lists.needs_rebuild(&self.objects.positions));
+        if bvh_needed {
+            self.bvh.update(&self.objects.positions, &self.objects.radii, self.constraints);
+            self.stats.bvh_metrics = self.bvh.metrics();
+        }
+        self.bvh_stale = !bvh_needed;
         self.stats.bvh_duration.update(start.elapsed());
+        self.stats.morton_codes_changed = if bvh_needed { self.bvh.changed_morton_codes() } else { 0 };
 
         let start = Instant::now();
         self.process_collisions();
+        self.stats.fluid_density_error = self.fluid.as_mut().map(|fluid| {
+            fluid.solve(
+                &self.bvh,
+                &self.thread_pool,
+                &mut self.objects.positions,
+                &mut self.objects.velocities,
+                &self.objects.radii,
+                &self.objects.masses,
+                &self.objects.is_planet,
+                &self.objects.is_frozen,
+                dt,
+            )
+        });
+        self.process_attachments(dt);
+        self.conduct_heat(dt);
+        self.stats.collisions_duration.update(start.elapsed() + event_driven_duration);
+
+        let start = Instant::now();
+        self.apply_constraints();
+        self.stats.constraints_duration.update(start.elapsed());
+    }
+
+    // Objects in small isolated clusters are advanced event-driven, the rest is time-stepped. The clusters are found
+    // with a margin that no object can travel during the step, so they can't interact with other objects, and they are
+    // frozen for the time-stepped pass. The event-driven solver only knows the global gravity, one restitution
+    // coefficient and reflecting walls, so scenes with planets, wind, gravity zones, a fluid or other walls are always
+    // time-stepped, and so are the clusters with mixed materials, drag, scaled gravity or attachments. The thermostat
+    // and the external forces act outside of both solvers. The walls of the event-driven clusters use the restitution
+    // of their material instead of bouncing elastically.
+    fn update_hybrid(&mut self, dt: f32, gpu_compute_options: GpuComputeOptions) {
+        let walls_reflect = Wall::ALL
+            .iter()
+            .all(|&wall| matches!(self.boundaries.wall(wall), WallBehavior::Reflect | WallBehavior::Inflow(_)));
+        if self.objects.planet_count > 0
+            || self.wind.is_some()
+            || !self.gravity_zones.is_empty()
+            || self.fluid.is_some()
+            || !walls_reflect
+        {
+            self.update_time_stepped(dt, gpu_compute_options, Duration::ZERO);
+            self.stats.event_driven = None;
+            return;
+        }
+
+        let start = Instant::now();
+        let max_speed = self.objects.velocities.iter().map(Vector2::magnitude).fold(0.0, f32::max);
+        let max_travel_distance = max_speed * dt + 0.5 * self.global_gravity.magnitude() * dt * dt;
+        let mut clusters = event_driven::sparse_clusters(
+            &self.objects.positions,
+            &self.objects.radii,
+            2.0 * max_travel_distance,
+            self.hybrid_max_cluster_size,
+        );
+        let mut attached = vec![false; self.objects.len()];
+        for pair in &self.attachments {
+            attached[pair.object1_index as usize] = true;
+            attached[pair.object2_index as usize] = true;
+        }
+        let (objects, materials) = (&self.objects, &self.materials);
+        clusters.retain(|cluster| {
+            let material_index = objects.materials[cluster[0]];
+            let material = materials.material(material_index);
+            material.drag == 0.0
+                && material.gravity_scale == 1.0
+                && cluster
+                    .iter()
+                    .all(|&object_index| objects.materials[object_index] == material_index && !attached[object_index])
+        });
+
+        fn gather<T: Copy>(values: &[T], cluster: &[usize]) -> Vec<T> {
+            cluster.iter().map(|&object_index| values[object_index]).collect()
+        }
+        let mut stats = EventDrivenStats::default();
+        let results = clusters
+            .iter()
+            .map(|cluster| {
+                let (mut positions, mut velocities) =
+                    (gather(&objects.positions, cluster), gather(&objects.velocities, cluster));
+                let material_index = objects.materials[cluster[0]];
+                let cluster_stats = event_driven::advance(
+                    &mut positions,
+                    &mut velocities,
+                    &gather(&objects.radii, cluster),
+                    &gather(&objects.masses, cluster),
+                    &gather(&objects.is_frozen, cluster),
+                    self.constraints,
+                    self.global_gravity,
+                    materials.interaction(material_index, material_index).restitution_coefficient,
+                    self.restitution_velocity_threshold,
+                    dt,
+                );
+                stats.object_count += cluster_stats.object_count;
+                stats.event_count += cluster_stats.event_count;
+                stats.limit_reached |= cluster_stats.limit_reached;
+                (positions, velocities)
+            })
+            .collect_vec();
+        let event_driven_duration = start.elapsed();
+
+        // The time-stepped pass leaves the frozen objects in place
+        let is_frozen = self.objects.is_frozen.clone();
+        for &object_index in clusters.iter().flatten() {
+            self.objects.is_frozen[object_index] = true;
+        }
+        self.update_time_stepped(dt, gpu_compute_options, event_driven_duration);
+        self.objects.is_frozen.copy_from_slice(&is_frozen);
+
+        for (cluster, (positions, velocities)) in zip(&clusters, results) {
+            for (i, &object_index) in cluster.iter().enumerate() {
+                self.objects.positions[object_index] = positions[i];
+                self.objects.velocities[object_index] = velocities[i];
+            }
+        }
+        self.stats.event_driven = Some(stats);
+    }
+
+    fn update_event_driven(&mut self, dt: f32) {
+        let start = Instant::now();
+        let event_driven_stats = event_driven::advance(
+            &mut self.objects.positions,
+            &mut self.objects.velocities,
+            &self.objects.radii,
+            &self.objects.masses,
+            &self.objects.is_frozen,
+            self.constraints,
+            self.global_gravity,
+            self.materials.material(0).restitution_coefficient,
+            self.restitution_velocity_threshold,
+            dt,
+        );
+        self.stats.event_driven = Some(event_driven_stats);
         self.stats.collisions_duration.update(start.elapsed());
+        self.contacts.clear();
 
+        // Objects resting on a wall aren't stopped by collision events, so they are still clamped
         let start = Instant::now();
         self.apply_constraints();
         self.stats.constraints_duration.update(start.elapsed());
+
+        // Kept up to date for the rendering of the BVH and spatial queries
+        let start = Instant::now();
+        self.bvh.update(&self.objects.positions, &self.objects.radii, self.constraints);
+        self.stats.bvh_metrics = self.bvh.metrics();
+        self.stats.bvh_duration.update(start.elapsed());
     }
 
     fn integrate(&mut self, dt: f32, gpu_compute_options: GpuComputeOptions) {
-        if gpu_compute_options.integration {
-            self.integrate_gpu(dt);
+        let initial_velocities = self.track_accelerations.then(|| self.objects.velocities.clone());
+        if gpu_compute_options.integration && !self.gpu_failed {
+            #[cfg(feature = "gpu-opencl")]
+            {
+                // The kernel updates the objects in place and may have been aborted halfway
+                let positions = self.objects.positions.clone();
+                let velocities = self.objects.velocities.clone();
+                if let Err(e) = self.integrate_gpu(dt) {
+                    self.fall_back_to_cpu(&e, positions, velocities);
+                    self.integrate_cpu(dt);
+                }
+            }
         } else {
             self.integrate_cpu(dt);
         }
+        if let Some(initial_velocities) = initial_velocities {
+            self.accelerations.clear();
+            self.accelerations.extend(
+                zip(&self.objects.velocities, &initial_velocities)
+                    .map(|(&velocity, &initial_velocity)| (velocity - initial_velocity) / dt),
+            );
+        }
     }
 
     fn integrate_cpu(&mut self, dt: f32) {
@@ -250,61 +1209,179 @@ impl PhysicsEngine {
         let d1dt = D1 * dt;
         let d2dt = D2 * dt;
         let d3dt = D3 * dt;
+        let compensated = self.compensated_summation;
+        if compensated {
+            self.position_compensations.resize(self.objects.len(), Vector2::default());
+            self.velocity_compensations.resize(self.objects.len(), Vector2::default());
+        }
+        let add: fn(&mut Vector2<f32>, &mut Vector2<f32>, Vector2<f32>) = if compensated {
+            compensated_add
+        } else {
+            |sum, _, value| *sum += value
+        };
+        // Wind doesn't blow planets around
+        let wind = self.wind.map(|wind| (wind, self.time));
+        let planet_count = self.objects.planet_count;
+        let external_forces = &self.external_forces;
+        let masses = &self.objects.masses;
+        let external_acceleration = |object_index: usize, gravity: Vector2<f32>, position, gravity_scale: f32| {
+            let acceleration = gravity * gravity_scale;
+            let acceleration = match wind {
+                Some((wind, time)) if object_index >= planet_count => {
+                    acceleration + wind.acceleration_at(position, time)
+                }
+                _ => acceleration,
+            };
+            match external_forces.get(object_index) {
+                Some(&force) => acceleration + force / masses[object_index],
+                None => acceleration,
+            }
+        };
         for object_index in 0..self.objects.len() {
-            let x0 = self.objects.positions[object_index];
-            let v0 = self.objects.velocities[object_index];
-            let x1 = x0 + v0 * c1dt;
+            if self.objects.is_frozen[object_index] {
+                continue;
+            }
+            let material = *self.materials.material(self.objects.materials[object_index]);
+            let mut x = self.objects.positions[object_index];
+            let mut v = self.objects.velocities[object_index];
+            let (mut x_compensation, mut v_compensation) = if compensated {
+                (self.position_compensations[object_index], self.velocity_compensations[object_index])
+            } else {
+                (Vector2::default(), Vector2::default())
+            };
+            add(&mut x, &mut x_compensation, v * c1dt);
             let a1 = Self::gravity_acceleration(
                 object_index,
-                x1,
+                x,
                 &self.objects.positions,
-                self.global_gravity,
+                Self::global_gravity_at(self.global_gravity, &self.gravity_zones, x),
                 self.gravitational_constant,
                 &self.objects.masses[self.objects.planet_range()],
+                compensated,
             );
-            let v1 = v0 + a1 * d1dt;
-            let x2 = x1 + v1 * c2dt;
+            add(&mut v, &mut v_compensation, external_acceleration(object_index, a1, x, material.gravity_scale) * d1dt);
+            add(&mut x, &mut x_compensation, v * c2dt);
             let a2 = Self::gravity_acceleration(
                 object_index,
-                x2,
+                x,
                 &self.objects.positions,
-                self.global_gravity,
+                Self::global_gravity_at(self.global_gravity, &self.gravity_zones, x),
                 self.gravitational_constant,
                 &self.objects.masses[self.objects.planet_range()],
+                compensated,
             );
-            let v2 = v1 + a2 * d2dt;
-            let x3 = x2 + v2 * c3dt;
+            add(&mut v, &mut v_compensation, external_acceleration(object_index, a2, x, material.gravity_scale) * d2dt);
+            add(&mut x, &mut x_compensation, v * c3dt);
             let a3 = Self::gravity_acceleration(
                 object_index,
-                x3,
+                x,
                 &self.objects.positions,
-                self.global_gravity,
+                Self::global_gravity_at(self.global_gravity, &self.gravity_zones, x),
                 self.gravitational_constant,
                 &self.objects.masses[self.objects.planet_range()],
+                compensated,
             );
-            let v3 = v2 + a3 * d3dt;
-            self.objects.positions[object_index] = x3 + v3 * c4dt;
-            self.objects.velocities[object_index] = v3;
+            add(&mut v, &mut v_compensation, external_acceleration(object_index, a3, x, material.gravity_scale) * d3dt);
+            add(&mut x, &mut x_compensation, v * c4dt);
+            if material.drag > 0.0 {
+                v *= (-material.drag * dt).exp();
+            }
+            self.objects.positions[object_index] = x;
+            self.objects.velocities[object_index] = v;
+            if compensated {
+                self.position_compensations[object_index] = x_compensation;
+                self.velocity_compensations[object_index] = v_compensation;
+            }
         }
     }
 
-    fn integrate_gpu(&mut self, dt: f32) {
-        let mut kernel = ExecuteKernel::new(&self.gpu_integration_kernel);
+    // Host pointer buffers alias the object vectors, so they are recreated after these are reallocated or resized,
+    // e.g. when objects are added or removed
+    #[cfg(feature = "gpu-opencl")]
+    fn sync_gpu_buffers(&mut self) -> anyhow::Result<()> {
+        let bvh_node_count = self.bvh.nodes().len();
+        let candidate_capacity = self.objects.len() * MAX_CANDIDATES_PER_OBJECT;
+        let gpu = match &mut self.gpu {
+            Some(gpu) => gpu,
+            None => self.gpu.insert(GpuState::new(
+                &mut self.objects,
+                candidate_capacity,
+                bvh_node_count,
+                &self.materials,
+                &self.gravity_zones,
+            )?),
+        };
+        if !gpu.object_positions.is_bound_to(&self.objects.positions) {
+            gpu.object_positions =
+                unsafe { GPU.create_host_ptr_buffer("positions", &mut self.objects.positions, ReadWrite) }?;
+        }
+        if !gpu.object_velocities.is_bound_to(&self.objects.velocities) {
+            gpu.object_velocities =
+                unsafe { GPU.create_host_ptr_buffer("velocities", &mut self.objects.velocities, ReadWrite) }?;
+        }
+        if !gpu.object_radii.is_bound_to(&self.objects.radii) {
+            gpu.object_radii = unsafe { GPU.create_host_ptr_buffer("radii", &mut self.objects.radii, ReadOnly) }?;
+        }
+        if !gpu.object_frozen.is_bound_to(&self.objects.is_frozen) {
+            gpu.object_frozen =
+                unsafe { GPU.create_host_ptr_buffer("frozen flags", &mut self.objects.is_frozen, ReadOnly) }?;
+        }
+        if !gpu.object_materials.is_bound_to(&self.objects.materials) {
+            gpu.object_materials =
+                unsafe { GPU.create_host_ptr_buffer("object materials", &mut self.objects.materials, ReadOnly) }?;
+        }
+        let planet_masses = &self.objects.masses[self.objects.planet_range()];
+        if gpu.planet_masses.data()[..gpu.planet_masses.len() - 1] != *planet_masses {
+            gpu.planet_masses = GPU.create_host_buffer(
+                "planet masses",
+                planet_masses.iter().copied().chain(once(0.0)).collect_vec(),
+                ReadOnly,
+            )?;
+        }
+        if gpu.bvh_nodes.len() < bvh_node_count {
+            gpu.bvh_nodes = GPU.create_device_buffer("bvh nodes", bvh_node_count, ReadOnly)?;
+        }
+        if gpu.collision_candidates.len() < candidate_capacity {
+            gpu.collision_candidates =
+                GPU.create_device_buffer("collision candidates", candidate_capacity, WriteOnly)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "gpu-opencl")]
+    fn integrate_gpu(&mut self, dt: f32) -> anyhow::Result<()> {
+        let _fp_exception_guard = FpExceptionGuard::new();
+        self.sync_gpu_buffers()?;
+        let gpu = self.gpu.as_mut().unwrap();
+        let mut kernel = ExecuteKernel::new(&gpu.integration_kernel);
         kernel.set_global_work_size(self.objects.len());
         // kernel.set_local_work_size(CONFIG.simulation.gpu_integration_local_wg_size);
         unsafe {
-            self.gpu_object_positions.set_arg(&mut kernel);
-            self.gpu_object_velocities.set_arg(&mut kernel);
+            gpu.object_positions.set_arg(&mut kernel);
+            gpu.object_velocities.set_arg(&mut kernel);
+            gpu.object_frozen.set_arg(&mut kernel);
+            gpu.object_materials.set_arg(&mut kernel);
+            gpu.materials.set_arg(&mut kernel);
             kernel.set_arg(&u32::try_from(self.objects.len()).unwrap());
             kernel.set_arg(&dt);
             kernel.set_arg(&self.global_gravity);
+            gpu.gravity_zones.set_arg(&mut kernel);
+            kernel.set_arg(&u32::try_from(self.gravity_zones.len()).unwrap());
             // TODO store planet masses and posittions on GPU (used in a loop for every particle)
-            self.gpu_planet_masses.set_arg(&mut kernel);
+            gpu.planet_masses.set_arg(&mut kernel);
             kernel.set_arg(&u32::try_from(self.objects.planet_count).unwrap());
             kernel.set_arg(&self.gravitational_constant);
         }
-        GPU.enqueue_execute_kernel(&mut kernel).context("Failed to execute kernel").unwrap();
-        GPU.wait_for_queue_completion().unwrap();
+        GPU.execute_kernel(&mut kernel, self.gpu_kernel_timeout).context("Failed to execute integration kernel")
+    }
+
+    // The zones are sorted by height, the lowest zone the position is in wins
+    fn global_gravity_at(
+        global_gravity: Vector2<f32>,
+        gravity_zones: &[GravityZone],
+        position: Vector2<f32>,
+    ) -> Vector2<f32> {
+        gravity_zones.iter().rev().find(|zone| position.y >= zone.min_y).map_or(global_gravity, |zone| zone.gravity)
     }
 
     fn gravity_acceleration(
@@ -314,14 +1391,21 @@ impl PhysicsEngine {
         global_gravity: Vector2<f32>,
         gravitational_constant: f32,
         planet_masses: &[f32],
+        compensated: bool,
     ) -> Vector2<f32> {
         let mut gravity = global_gravity;
+        let mut compensation = Vector2::default();
         for planet_index in 0..planet_masses.len() {
             if planet_index != object_index {
                 let to_planet = positions[planet_index] - position;
                 let direction = to_planet.normalize();
-                gravity +=
+                let acceleration =
                     direction * (gravitational_constant * planet_masses[planet_index] / to_planet.magnitude_squared());
+                if compensated {
+                    compensated_add(&mut gravity, &mut compensation, acceleration);
+                } else {
+                    gravity += acceleration;
+                }
             }
         }
         gravity
@@ -331,10 +1415,52 @@ impl PhysicsEngine {
         let start = Instant::now();
         self.candidates.clear();
         self.candidates.resize(self.objects.len() * MAX_CANDIDATES_PER_OBJECT, NormalizedCollisionPair::new(0, 0));
+        self.stats.pair_cache_hit_ratio = None;
+        self.stats.verlet_lists = None;
+        self.stats.dropped_collision_count = 0;
+        #[cfg(feature = "gpu-opencl")]
         if self.gpu_compute_options.bvh {
-            self.find_collision_candidates_gpu();
+            match self.find_collision_candidates_gpu() {
+                Ok(dropped_count) => self.stats.dropped_collision_count = dropped_count,
+                Err(e) => {
+                    // The kernel only reads the positions
+                    self.fall_back_to_cpu(&e, self.objects.positions.clone(), self.objects.velocities.clone());
+                    self.candidates.clear();
+                    self.candidates
+                        .resize(self.objects.len() * MAX_CANDIDATES_PER_OBJECT, NormalizedCollisionPair::new(0, 0));
+                }
+            }
+        }
+        if self.gpu_compute_options.bvh {
+            // Already found on the GPU
+        } else if let Some(broad_phase) = &mut self.broad_phase {
+            broad_phase.update(&self.objects.positions, &self.objects.radii, self.constraints);
+            self.candidates.clear();
+            broad_phase.for_each_pair(
+                &self.objects.positions,
+                &self.objects.radii,
+                &mut |object1_index, object2_index| {
+                    self.candidates.push(NormalizedCollisionPair::new(object1_index, object2_index));
+                },
+            );
+        } else if let Some(verlet_lists) = &mut self.verlet_lists {
+            if verlet_lists.needs_rebuild(&self.objects.positions) {
+                verlet_lists.rebuild(&self.bvh, &self.thread_pool, &self.objects.positions, &self.objects.radii);
+            }
+            self.candidates.clear();
+            self.candidates.extend_from_slice(verlet_lists.pairs());
+            self.stats.verlet_lists = Some(VerletListStats {
+                skin: verlet_lists.skin(),
+                rebuild_count: verlet_lists.rebuild_count(),
+            });
+        } else if let Some(pair_cache) = &mut self.pair_cache {
+            let requeried =
+                pair_cache.update(&self.bvh, &self.thread_pool, &self.objects.positions, &self.objects.radii);
+            self.candidates.clear();
+            self.candidates.extend_from_slice(pair_cache.pairs());
+            self.stats.pair_cache_hit_ratio = Some(1.0 - requeried as f32 / self.objects.len().max(1) as f32);
         } else {
-            Self::find_collision_candidates_cpu(
+            self.stats.dropped_collision_count = Self::find_collision_candidates_cpu(
                 &self.bvh,
                 &self.thread_pool,
                 &mut self.candidates,
@@ -342,111 +1468,303 @@ impl PhysicsEngine {
                 &self.objects.radii,
             );
         };
-        self.candidates.retain(|pair| pair.object1_index > 0 || pair.object2_index > 0);
+        let collision_groups = &self.objects.collision_groups;
+        self.candidates.retain(|pair| {
+            let collision_groups = [
+                collision_groups[pair.object1_index as usize],
+                collision_groups[pair.object2_index as usize],
+            ];
+            (pair.object1_index > 0 || pair.object2_index > 0)
+                && (collision_groups[0] == 0 || collision_groups[0] != collision_groups[1])
+        });
         println!("found {} candidates in {:?}", self.candidates.len(), start.elapsed());
 
-        let start = Instant::now();
-        self.thread_pool.install(|| self.candidates.par_sort_unstable());
-        println!("candidates sort {:?} ", start.elapsed());
-
-        let start = Instant::now();
-        let max_candidates_per_object =
-            self.candidates.chunk_by(|a, b| a.object1_index == b.object1_index).map(<[_]>::len).max().unwrap_or(0);
-        self.max_candidates_per_object = self.max_candidates_per_object.max(max_candidates_per_object);
-        println!("max candidates per object {} {:?}", self.max_candidates_per_object, start.elapsed());
+        // The GPU search emits every pair once
+        if !self.gpu_compute_options.bvh {
+            let start = Instant::now();
+            let previous_length = self.candidates.len();
+            self.pair_storage.store(&self.candidates, &self.thread_pool);
+            self.candidates.clear();
+            self.candidates.extend(self.pair_storage.pairs());
+            self.stats.pair_storage_duration.update(start.elapsed());
+            self.stats.pair_storage_memory = self.pair_storage.memory_size();
+            println!("candidates storage {} -> {} {:?}", previous_length, self.candidates.len(), start.elapsed());
 
-        let start = Instant::now();
-        let previous_length = self.candidates.len();
-        self.candidates.dedup();
-        println!("candidates dedup {} -> {} {:?}", previous_length, self.candidates.len(), start.elapsed());
+            let start = Instant::now();
+            let max_candidates_per_object =
+                self.candidates.chunk_by(|a, b| a.object1_index == b.object1_index).map(<[_]>::len).max().unwrap_or(0);
+            self.max_candidates_per_object = self.max_candidates_per_object.max(max_candidates_per_object);
+            println!("max candidates per object {} {:?}", self.max_candidates_per_object, start.elapsed());
+        }
 
         let start = Instant::now();
-        self.candidates.shuffle(&mut rng());
+        self.candidates.shuffle(&mut self.rng);
         println!("candidates shuffle {:?} ", start.elapsed());
 
+        let deferred_count = self.deferred_candidates.len();
+        if !self.deferred_candidates.is_empty() {
+            let deferred_candidates = mem::take(&mut self.deferred_candidates);
+            self.candidates.retain(|pair| deferred_candidates.binary_search(pair).is_err());
+            self.candidates.splice(0..0, deferred_candidates.iter().copied());
+            self.deferred_candidates = deferred_candidates;
+            self.deferred_candidates.clear();
+        }
+        // After the deferred candidates, the deepest penetrations are resolved first, in case the budget runs out
+        if self.collision_budget.is_some() {
+            let (positions, radii) = (&self.objects.positions, &self.objects.radii);
+            let fresh_candidates = &mut self.candidates[deferred_count..];
+            let mut by_depth =
+                fresh_candidates.iter().map(|pair| (pair.penetration_depth(positions, radii), *pair)).collect_vec();
+            by_depth.sort_by(|(depth1, _), (depth2, _)| depth2.total_cmp(depth1));
+            for (candidate, (_, pair)) in zip(fresh_candidates, by_depth) {
+                *candidate = pair;
+            }
+        }
+
         let start = Instant::now();
-        for &NormalizedCollisionPair {
-            object1_index,
-            object2_index,
-        } in &self.candidates
+        self.contacts.clear();
+        if self.heat_conduction.is_some() {
+            self.heat.resize(self.objects.len(), 0.0);
+        }
+        let mut collision_response_energy = 0.0;
+        let mut position_correction_energy = 0.0;
+        let mut processed_count = self.candidates.len();
+        for (
+            candidate_index,
+            &NormalizedCollisionPair {
+                object1_index,
+                object2_index,
+            },
+        ) in self.candidates.iter().enumerate()
         {
-            Self::process_collision_candidate(
+            if let Some(step_deadline) = self.step_deadline
+                && candidate_index > 0
+                && candidate_index % COLLISION_BUDGET_BATCH_SIZE == 0
+                && Instant::now() > step_deadline
+            {
+                processed_count = candidate_index;
+                break;
+            }
+            let object_indices = [
                 usize::try_from(object1_index).unwrap(),
                 usize::try_from(object2_index).unwrap(),
-                self.restitution_coefficient,
+            ];
+            let positions_before = object_indices.map(|object_index| self.objects.positions[object_index]);
+            let velocities_before = object_indices.map(|object_index| self.objects.velocities[object_index]);
+            let contact = Self::process_collision_candidate(
+                object_indices[0],
+                object_indices[1],
+                self.materials
+                    .interaction(self.objects.materials[object_indices[0]], self.objects.materials[object_indices[1]]),
+                self.restitution_velocity_threshold,
+                self.penetration_slop,
+                self.position_correction_factor,
                 &mut self.objects.positions,
                 &mut self.objects.velocities,
                 &self.objects.radii,
                 &self.objects.masses,
                 &self.objects.is_planet,
+                &self.objects.is_frozen,
             );
+            if contact.is_some() {
+                let mut contact_energy = 0.0;
+                for (object_index, position_before, velocity_before) in
+                    itertools::izip!(object_indices, positions_before, velocities_before)
+                {
+                    let mass = self.objects.masses[object_index];
+                    let kinetic_energy_change = kinetic_energy(mass, self.objects.velocities[object_index])
+                        - kinetic_energy(mass, velocity_before);
+                    collision_response_energy += kinetic_energy_change;
+                    contact_energy += kinetic_energy_change;
+                    position_correction_energy +=
+                        gravity_work(mass, self.global_gravity, self.objects.positions[object_index] - position_before);
+                }
+                // The kinetic energy lost in the collision heats both objects up equally
+                if !self.heat.is_empty() && contact_energy < 0.0 {
+                    let total_mass =
+                        object_indices.map(|object_index| self.objects.masses[object_index]).iter().sum::<f32>();
+                    for object_index in object_indices {
+                        self.heat[object_index] -= contact_energy / total_mass;
+                    }
+                }
+            }
+            self.contacts.extend(contact);
+        }
+        self.stats.energy_changes.collision_response = collision_response_energy;
+        self.stats.energy_changes.position_correction = position_correction_energy;
+        #[allow(clippy::cast_precision_loss)]
+        {
+            self.stats.candidate_false_positive_rate =
+                (processed_count > 0).then(|| 1.0 - self.contacts.len() as f32 / processed_count as f32);
         }
+        self.deferred_candidates.extend_from_slice(&self.candidates[processed_count..]);
+        self.deferred_candidates.sort_unstable();
+        self.stats.deferred_collision_count = self.deferred_candidates.len();
         println!("candidates processed {:?} ", start.elapsed());
     }
 
+    // Contacts between sticky materials that are hardly separating become attachments. An attachment pulls the objects
+    // back together when they drift apart, like a rope as long as the contact distance, and breaks when that takes
+    // more than the adhesion force.
+    fn process_attachments(&mut self, dt: f32) {
+        for contact in &self.contacts {
+            let object_indices = [contact.object1_index as usize, contact.object2_index as usize];
+            let interaction = self
+                .materials
+                .interaction(self.objects.materials[object_indices[0]], self.objects.materials[object_indices[1]]);
+            let separation_speed = (self.objects.velocities[object_indices[0]]
+                - self.objects.velocities[object_indices[1]])
+                .dot(contact.normal);
+            if separation_speed.abs() < interaction.adhesion_speed {
+                self.attachments.push(NormalizedCollisionPair::new(object_indices[0], object_indices[1]));
+            }
+        }
+        self.attachments.sort_unstable();
+        self.attachments.dedup();
+
+        let objects = &mut self.objects;
+        let materials = &self.materials;
+        let position_correction_factor = self.position_correction_factor;
+        self.attachments.retain(|pair| {
+            let (object1_index, object2_index) = (pair.object1_index as usize, pair.object2_index as usize);
+            let from_2_to_1 = objects.positions[object1_index] - objects.positions[object2_index];
+            let distance = from_2_to_1.magnitude();
+            let gap = distance - (objects.radii[object1_index] + objects.radii[object2_index]);
+            let inv_mass1 = if objects.is_frozen[object1_index] {
+                0.0
+            } else {
+                1.0 / objects.masses[object1_index]
+            };
+            let inv_mass2 = if objects.is_frozen[object2_index] {
+                0.0
+            } else {
+                1.0 / objects.masses[object2_index]
+            };
+            let total_inv_mass = inv_mass1 + inv_mass2;
+            if gap <= 0.0 || total_inv_mass == 0.0 {
+                return true;
+            }
+
+            // The impulse stops the separation and closes a part of the gap within the step
+            let normal = from_2_to_1 / distance;
+            let separation_speed = (objects.velocities[object1_index] - objects.velocities[object2_index]).dot(normal);
+            let impulse = (separation_speed + gap * position_correction_factor / dt) / total_inv_mass;
+            if impulse <= 0.0 {
+                return true;
+            }
+            let interaction = materials.interaction(objects.materials[object1_index], objects.materials[object2_index]);
+            if impulse > interaction.adhesion_force * dt {
+                return false;
+            }
+            objects.velocities[object1_index] -= normal * (impulse * inv_mass1);
+            objects.velocities[object2_index] += normal * (impulse * inv_mass2);
+            true
+        });
+        self.stats.attachment_count = self.attachments.len();
+    }
+
+    // Touching objects exchange heat, which moves their heat towards the common equilibrium at the conduction rate
+    fn conduct_heat(&mut self, dt: f32) {
+        let Some(heat_conduction) = self.heat_conduction else {
+            return;
+        };
+        let fraction = (heat_conduction * dt).min(1.0);
+        for contact in &self.contacts {
+            let (object1_index, object2_index) = (contact.object1_index as usize, contact.object2_index as usize);
+            let (mass1, mass2) = (self.objects.masses[object1_index], self.objects.masses[object2_index]);
+            let equilibrium_transfer =
+                (self.heat[object1_index] - self.heat[object2_index]) * (mass1 * mass2 / (mass1 + mass2));
+            let transfer = equilibrium_transfer * fraction;
+            self.heat[object1_index] -= transfer / mass1;
+            self.heat[object2_index] += transfer / mass2;
+        }
+    }
+
+    // Returns the number of candidates that didn't fit the per-object limit
     fn find_collision_candidates_cpu(
         bvh: &Bvh,
         thread_pool: &ThreadPool,
         candidates: &mut [NormalizedCollisionPair],
         positions: &[Vector2<f32>],
         radii: &[f32],
-    ) {
+    ) -> usize {
         let chunk_size = (positions.len()).div_ceil(thread_pool.current_num_threads());
         thread_pool.install(|| {
-            candidates.par_chunks_mut(chunk_size * MAX_CANDIDATES_PER_OBJECT).enumerate().for_each(
-                |(chunk_index, candidates)| {
+            candidates
+                .par_chunks_mut(chunk_size * MAX_CANDIDATES_PER_OBJECT)
+                .enumerate()
+                .map(|(chunk_index, candidates)| {
+                    let mut dropped_count = 0;
                     for (i, candidates) in candidates.chunks_mut(MAX_CANDIDATES_PER_OBJECT).enumerate() {
                         let object_index = chunk_index * chunk_size + i;
-                        bvh.find_intersections(object_index, positions, radii, candidates);
+                        dropped_count += bvh.find_intersections(object_index, positions, radii, candidates);
                     }
-                },
-            );
-        });
+                    dropped_count
+                })
+                .sum()
+        })
     }
 
-    fn find_collision_candidates_gpu(&mut self) {
+    // Returns the number of candidates that didn't fit the candidate buffer, see `find_collision_candidates_cpu`
+    #[cfg(feature = "gpu-opencl")]
+    fn find_collision_candidates_gpu(&mut self) -> anyhow::Result<usize> {
+        let _fp_exception_guard = FpExceptionGuard::new();
+        self.sync_gpu_buffers()?;
+        let gpu = self.gpu.as_mut().unwrap();
         let start = Instant::now();
         let object_count = u32::try_from(self.objects.len()).unwrap();
-        let mut kernel = ExecuteKernel::new(&self.gpu_bvh_kernel);
+        let mut kernel = ExecuteKernel::new(&gpu.bvh_kernel);
         kernel.set_global_work_size(self.objects.len());
         // kernel.set_local_work_size(CONFIG.simulation.gpu_bvh_local_wg_size);
         unsafe {
             kernel.set_arg(&self.bvh.root());
-            self.gpu_bvh_nodes.set_arg(&mut kernel);
-            kernel.set_arg(&u32::try_from(self.gpu_bvh_nodes.len()).unwrap());
-            self.gpu_object_positions.set_arg(&mut kernel);
-            self.gpu_object_radii.set_arg(&mut kernel);
+            gpu.bvh_nodes.set_arg(&mut kernel);
+            kernel.set_arg(&u32::try_from(gpu.bvh_nodes.len()).unwrap());
+            gpu.object_positions.set_arg(&mut kernel);
+            gpu.object_radii.set_arg(&mut kernel);
             kernel.set_arg(&object_count);
-            self.gpu_collision_candidates.set_arg(&mut kernel);
-            self.gpu_collision_candidates_length.set_arg(&mut kernel);
-            self.gpu_errors.set_arg(&mut kernel);
+            gpu.collision_candidates.set_arg(&mut kernel);
+            gpu.collision_candidates_length.set_arg(&mut kernel);
+            gpu.errors.set_arg(&mut kernel);
         }
         println!("GPU BVH: setup {:?}", start.elapsed());
         let start = Instant::now();
-        self.gpu_collision_candidates_length.data_mut()[0] = 0;
-        self.gpu_errors.data_mut()[0] = 0;
-        GPU.enqueue_write_device_buffer(&mut self.gpu_bvh_nodes, self.bvh.nodes(), 0).unwrap().wait().unwrap();
+        gpu.collision_candidates_length.data_mut()[0] = 0;
+        gpu.errors.data_mut()[0] = 0;
+        GPU.enqueue_write_device_buffer(&mut gpu.bvh_nodes, self.bvh.nodes(), 0)?.wait()?;
         println!("GPU BVH: write nodes {:?}", start.elapsed());
         let start = Instant::now();
-        GPU.enqueue_execute_kernel(&mut kernel).context("Failed to execute kernel").unwrap();
-        GPU.wait_for_queue_completion().unwrap();
+        GPU.execute_kernel(&mut kernel, self.gpu_kernel_timeout).context("Failed to execute BVH kernel")?;
         println!("GPU BVH: kernel {:?}", start.elapsed());
-        let candidates_length = self.gpu_collision_candidates_length.data()[0];
-        self.candidates.truncate(usize::try_from(candidates_length).unwrap());
-        let errors_count = self.gpu_errors.data()[0];
-        assert_eq!(errors_count, 0);
+        let errors_count = gpu.errors.data()[0];
+        ensure!(errors_count == 0, "BVH kernel reported {errors_count} errors");
+        let start = Instant::now();
+        // Counts the candidates that didn't fit as well
+        let found_count = usize::try_from(gpu.collision_candidates_length.data()[0]).unwrap();
+        let candidates_length = found_count.min(self.objects.len() * MAX_CANDIDATES_PER_OBJECT);
+        self.candidates.resize(candidates_length, NormalizedCollisionPair::new(0, 0));
+        GPU.enqueue_read_device_buffer(&gpu.collision_candidates, &mut self.candidates, 0)?.wait()?;
+        println!("GPU BVH: read candidates {:?}", start.elapsed());
+        Ok(found_count - candidates_length)
     }
 
     fn process_collision_candidate(
         object1_index: usize,
         object2_index: usize,
-        restitution_coefficient: f32,
+        interaction: Interaction,
+        restitution_velocity_threshold: f32,
+        penetration_slop: f32,
+        position_correction_factor: f32,
         positions: &mut [Vector2<f32>],
         velocities: &mut [Vector2<f32>],
         radii: &[f32],
         masses: &[f32],
         is_planet: &[bool],
-    ) {
+        is_frozen: &[bool],
+    ) -> Option<Contact> {
+        if is_frozen[object1_index] && is_frozen[object2_index] {
+            return None;
+        }
         let object1_position = positions[object1_index];
         let object2_position = positions[object2_index];
         let object1_radius = radii[object1_index];
@@ -454,17 +1772,23 @@ impl PhysicsEngine {
         let distance_squared = (object1_position - object2_position).magnitude_squared();
         let collision_distance = object1_radius + object2_radius;
         if distance_squared < collision_distance * collision_distance {
-            Self::process_object_collision(
+            Some(Self::process_object_collision(
                 object1_index,
                 object2_index,
                 distance_squared,
                 collision_distance,
-                restitution_coefficient,
+                interaction,
+                restitution_velocity_threshold,
+                penetration_slop,
+                position_correction_factor,
                 positions,
                 velocities,
                 masses,
                 is_planet,
-            );
+                is_frozen,
+            ))
+        } else {
+            None
         }
     }
 
@@ -473,12 +1797,16 @@ impl PhysicsEngine {
         object2_index: usize,
         distance_squared: f32,
         collision_distance: f32,
-        restitution_coefficient: f32,
+        interaction: Interaction,
+        restitution_velocity_threshold: f32,
+        penetration_slop: f32,
+        position_correction_factor: f32,
         positions: &mut [Vector2<f32>],
         velocities: &mut [Vector2<f32>],
         masses: &[f32],
         is_planet: &[bool],
-    ) {
+        is_frozen: &[bool],
+    ) -> Contact {
         let from_1_to_2 = positions[object1_index] - positions[object2_index];
         let distance = distance_squared.sqrt();
 
@@ -493,21 +1821,53 @@ impl PhysicsEngine {
         let v1_initial = velocities[object1_index];
         let v2_initial = velocities[object2_index];
 
-        // Compute the impulse scalar using the original velocities.
-        let impulse_scalar = 2.0 * (v1_initial - v2_initial).dot(normal) / total_mass;
+        // Compute the impulse scalar using the original velocities. Slow collisions are perfectly inelastic to avoid
+        // endless micro-bouncing of resting objects.
+        let normal_velocity = (v1_initial - v2_initial).dot(normal);
+        let elasticity = if normal_velocity.abs() < restitution_velocity_threshold {
+            0.0
+        } else {
+            1.0
+        };
+        let impulse_scalar = (1.0 + elasticity) * normal_velocity / total_mass;
 
-        // Update velocities using the impulse
-        let new_v1 = v1_initial - normal * mass2 * impulse_scalar;
-        let new_v2 = v2_initial + normal * mass1 * impulse_scalar;
+        // Update velocities using the impulse. A frozen object has infinite mass, so the other object gets all of it.
+        let frozen = [is_frozen[object1_index], is_frozen[object2_index]];
+        let inv_mass1 = if frozen[0] { 0.0 } else { 1.0 / mass1 };
+        let inv_mass2 = if frozen[1] { 0.0 } else { 1.0 / mass2 };
+        let total_inv_mass = inv_mass1 + inv_mass2;
+        let (mut new_v1, mut new_v2) = match frozen {
+            [false, false] => {
+                (v1_initial - normal * mass2 * impulse_scalar, v2_initial + normal * mass1 * impulse_scalar)
+            }
+            [true, _] => (v1_initial, v2_initial + normal * ((1.0 + elasticity) * normal_velocity)),
+            [false, true] => (v1_initial - normal * ((1.0 + elasticity) * normal_velocity), v2_initial),
+        };
+
+        // Coulomb friction: the tangential impulse stops the sliding, but can't exceed the friction coefficient times
+        // the normal impulse
+        let friction = interaction.friction;
+        if friction > 0.0 && normal_velocity < 0.0 {
+            let relative_velocity = new_v1 - new_v2;
+            let tangent_velocity = relative_velocity - normal * relative_velocity.dot(normal);
+            let sliding_speed = tangent_velocity.magnitude();
+            if sliding_speed > 0.0 {
+                let normal_impulse = -(1.0 + elasticity) * normal_velocity / total_inv_mass;
+                let friction_impulse = (sliding_speed / total_inv_mass).min(friction * normal_impulse);
+                let tangent = tangent_velocity / sliding_speed;
+                new_v1 -= tangent * (friction_impulse * inv_mass1);
+                new_v2 += tangent * (friction_impulse * inv_mass2);
+            }
+        }
 
-        // Apply restitution coefficient if the objects aren't planets.
-        let corrected_v1 = if !is_planet[object1_index] {
-            new_v1 * restitution_coefficient
+        // Apply restitution coefficient if the objects aren't planets or frozen.
+        let corrected_v1 = if !is_planet[object1_index] && !frozen[0] {
+            new_v1 * interaction.restitution_coefficient
         } else {
             new_v1
         };
-        let corrected_v2 = if !is_planet[object2_index] {
-            new_v2 * restitution_coefficient
+        let corrected_v2 = if !is_planet[object2_index] && !frozen[1] {
+            new_v2 * interaction.restitution_coefficient
         } else {
             new_v2
         };
@@ -515,64 +1875,287 @@ impl PhysicsEngine {
         velocities[object1_index] = corrected_v1;
         velocities[object2_index] = corrected_v2;
 
-        // Correct positions based on penetration depth using inverse masses.
+        // Correct positions based on penetration depth using inverse masses. Only a fraction of the penetration beyond
+        // the slop is resolved per step (Baumgarte stabilization), which avoids popping in dense piles.
         let intersection_depth = collision_distance - distance;
-        let inv_mass1 = 1.0 / mass1;
-        let inv_mass2 = 1.0 / mass2;
-        let total_inv_mass = inv_mass1 + inv_mass2;
-        let correction = normal * intersection_depth;
+        let correction = normal * ((intersection_depth - penetration_slop).max(0.0) * position_correction_factor);
         positions[object1_index] += correction * (inv_mass1 / total_inv_mass);
         positions[object2_index] -= correction * (inv_mass2 / total_inv_mass);
+
+        Contact {
+            object1_index: u32::try_from(object1_index).unwrap(),
+            object2_index: u32::try_from(object2_index).unwrap(),
+            normal,
+            penetration_depth: intersection_depth,
+            normal_impulse: -impulse_scalar * mass1 * mass2,
+        }
     }
 
     fn apply_constraints(&mut self) {
         let cb = self.constraints;
-        for ((position, velocity), radius) in
-            zip(zip(&mut self.objects.positions, &mut self.objects.velocities), &self.objects.radii)
-        {
+        let restitution_velocity_threshold = self.restitution_velocity_threshold;
+        let bounce = |v: f32| {
+            if v.abs() < restitution_velocity_threshold {
+                0.0
+            } else {
+                -v
+            }
+        };
+        let mut constraints_energy = 0.0;
+        for (object_index, position, velocity, radius, &mass, &is_planet, &is_frozen, &material) in itertools::izip!(
+            0..,
+            &mut self.objects.positions,
+            &mut self.objects.velocities,
+            &self.objects.radii,
+            &self.objects.masses,
+            &self.objects.is_planet,
+            &self.objects.is_frozen,
+            &self.objects.materials,
+        ) {
+            if is_frozen {
+                continue;
+            }
+            let initial_position = *position;
             let initial_velocity = *velocity;
-            if position.x - radius < cb.topleft.x {
-                position.x = cb.topleft.x + radius;
-                if self.enable_constraint_bouncing {
-                    velocity.x *= -1.0;
-                }
-            } else if position.x + radius > cb.bottomright.x {
-                position.x = cb.bottomright.x - radius;
-                if self.enable_constraint_bouncing {
-                    velocity.x *= -1.0;
+            // Wrapping moves an object without any work done by the wall
+            let mut wrap_offset = Vector2::default();
+            for (position, velocity, wrap_offset, min, max, [min_wall, max_wall]) in [
+                (
+                    &mut position.x,
+                    &mut velocity.x,
+                    &mut wrap_offset.x,
+                    cb.topleft.x,
+                    cb.bottomright.x,
+                    [Wall::Left, Wall::Right],
+                ),
+                (
+                    &mut position.y,
+                    &mut velocity.y,
+                    &mut wrap_offset.y,
+                    cb.topleft.y,
+                    cb.bottomright.y,
+                    [Wall::Top, Wall::Bottom],
+                ),
+            ] {
+                let (wall, limit, opposite_wall) = if *position - radius < min {
+                    (min_wall, min + radius, max_wall)
+                } else if *position + radius > max {
+                    (max_wall, max - radius, min_wall)
+                } else {
+                    continue;
+                };
+                match self.boundaries.wall(wall) {
+                    WallBehavior::Absorb if !is_planet => {
+                        self.absorbed.push(object_index);
+                        self.stats.wall_flux[wall as usize].outflow_count += 1;
+                        self.wall_mass_transfers[wall as usize] += mass;
+                        break;
+                    }
+                    WallBehavior::Wrap if !is_planet => {
+                        // Only once the center is past the wall, so that the object doesn't jump back and forth
+                        if *position < min || *position > max {
+                            *wrap_offset = if wall == min_wall { max - min } else { min - max };
+                            *position += *wrap_offset;
+                            self.stats.wall_flux[wall as usize].outflow_count += 1;
+                            self.stats.wall_flux[opposite_wall as usize].inflow_count += 1;
+                            self.wall_mass_transfers[wall as usize] += mass;
+                            self.wall_mass_transfers[opposite_wall as usize] -= mass;
+                        }
+                    }
+                    _ => {
+                        *position = limit;
+                        if self.enable_constraint_bouncing {
+                            *velocity = bounce(*velocity);
+                        }
+                    }
                 }
             }
+            if self.absorbed.last() == Some(&object_index) {
+                continue;
+            }
 
-            if position.y - radius < cb.topleft.y {
-                position.y = cb.topleft.y + radius;
-                if self.enable_constraint_bouncing {
-                    velocity.y *= -1.0;
-                }
-            } else if position.y + radius > cb.bottomright.y {
-                position.y = cb.bottomright.y - radius;
-                if self.enable_constraint_bouncing {
-                    velocity.y *= -1.0;
+            if *velocity != initial_velocity {
+                *velocity *= self.materials.material(material).restitution_coefficient;
+            }
+            if *position != initial_position + wrap_offset {
+                constraints_energy += kinetic_energy(mass, *velocity) - kinetic_energy(mass, initial_velocity)
+                    + gravity_work(mass, self.global_gravity, *position - initial_position - wrap_offset);
+            }
+        }
+        self.stats.energy_changes.constraints = constraints_energy;
+    }
+}
+
+// Normal points from object 2 to object 1. Normal impulse is the elastic impulse pushing the objects apart, before
+// restitution; it's negative if the objects were already separating.
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    pub object1_index: u32,
+    pub object2_index: u32,
+    pub normal: Vector2<f32>,
+    pub penetration_depth: f32,
+    pub normal_impulse: f32,
+}
+
+#[derive(Clone)]
+pub struct PhysicsSettings {
+    pub dt: DtSource,
+    pub mode: SimulationMode,
+    // Largest cluster of nearby objects that is advanced event-driven in hybrid mode
+    pub hybrid_max_cluster_size: usize,
+    pub constraints: AABB,
+    pub constraint_bouncing: bool,
+    pub boundaries: Boundaries,
+    // Restitution coefficient of the default material
+    pub restitution_coefficient: f32,
+    // Materials referenced by object material indices starting from 1, index 0 is the default material
+    pub materials: Vec<Material>,
+    // How the coefficients of two colliding materials are combined, unless the pair has an override
+    pub restitution_combine: CombineRule,
+    pub friction_combine: CombineRule,
+    pub material_pairs: Vec<MaterialPair>,
+    pub restitution_velocity_threshold: f32,
+    pub penetration_slop: f32,
+    pub position_correction_factor: f32,
+    pub global_gravity: Vector2<f32>,
+    // Replace the global gravity in horizontal layers; not supported by the event-driven solver
+    pub gravity_zones: Vec<GravityZone>,
+    pub gravitational_constant: f32,
+    // Only applied by the CPU integration, so neither by the event-driven solver nor on the GPU
+    pub wind: Option<Wind>,
+    pub thermostat: Option<Thermostat>,
+    // Density constraint over the collision neighbors, applied after the collisions in time-stepped mode
+    pub fluid: Option<Fluid>,
+    // Rate at which touching objects even out their heat; heat is only tracked if set
+    pub heat_conduction: Option<f32>,
+    pub pair_cache_margin: Option<f32>,
+    // Collision candidates come from Verlet lists with this skin distance instead of the pair cache
+    pub verlet_skin: Option<f32>,
+    // How the candidates of the CPU searches are merged and ordered
+    pub pair_storage: PairStorageKind,
+    pub compensated_summation: bool,
+    // Real time a GPU kernel may run before the step fails
+    pub gpu_kernel_timeout: Option<Duration>,
+    // Real time a step may take before the remaining collisions are deferred to the next step
+    pub collision_budget: Option<Duration>,
+    // Worker pool for the parallel parts of the solver; a pool with a thread per CPU is created if not set
+    pub thread_pool: Option<Arc<ThreadPool>>,
+    // Seed for the collision processing order, which is otherwise random; makes CPU runs reproducible
+    pub seed: Option<u64>,
+    // Steps averaged by the duration stats
+    pub duration_stat_window: usize,
+}
+
+// Global gravity below the height `min_y`, down to the next zone; y grows downwards
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GravityZone {
+    pub gravity: Vector2<f32>,
+    pub min_y: f32,
+}
+
+// A change of the object order, to be applied to the state kept for each object
+enum Reorder<'a> {
+    // Swaps that move the removed object to the end
+    SwapRemove([(usize, usize); 2]),
+    // Whether each object is kept
+    Retain(&'a [bool]),
+}
+
+impl Reorder<'_> {
+    fn apply<T: Clone + Default>(&self, values: &mut Vec<T>, object_count: usize) {
+        // Empty unless tracked; objects added since the values were last resized have no state yet
+        if values.is_empty() {
+            return;
+        }
+        values.resize(object_count, T::default());
+        match self {
+            Reorder::SwapRemove(swaps) => {
+                for &(object1_index, object2_index) in swaps {
+                    values.swap(object1_index, object2_index);
                 }
+                values.pop();
+            }
+            Reorder::Retain(kept) => {
+                let mut kept = kept.iter();
+                values.retain(|_| *kept.next().unwrap());
             }
+        }
+    }
 
-            if *velocity != initial_velocity {
-                *velocity *= self.restitution_coefficient;
+    // None for the removed object
+    fn new_index(&self, object_index: usize, object_count: usize) -> Option<usize> {
+        match self {
+            Reorder::SwapRemove(swaps) => {
+                let new_index = swaps.iter().fold(object_index, |index, &(object1_index, object2_index)| {
+                    if index == object1_index {
+                        object2_index
+                    } else if index == object2_index {
+                        object1_index
+                    } else {
+                        index
+                    }
+                });
+                (new_index != object_count - 1).then_some(new_index)
+            }
+            Reorder::Retain(kept) => {
+                kept.get(object_index)?.then(|| kept[..object_index].iter().filter(|&&kept| kept).count())
             }
         }
     }
 }
 
+#[derive(Deserialize, Serialize, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub enum DtSource {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+
+    #[serde(rename = "fixed")]
+    Fixed(f32),
+}
+
+// Time-stepped mode integrates all objects and then resolves overlaps. Event-driven mode treats the objects as hard
+// spheres and processes every collision exactly at the time it happens; it only supports the global gravity and is
+// meant for validation with a small number of objects, since its cost grows quadratically. Hybrid mode uses the
+// event-driven solver for small isolated clusters of objects and time-stepping for the dense regions, see
+// `PhysicsEngine::update_hybrid` for what keeps a cluster time-stepped.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub enum SimulationMode {
+    #[default]
+    #[serde(rename = "time_stepped")]
+    TimeStepped,
+
+    #[serde(rename = "event_driven")]
+    EventDriven,
+
+    #[serde(rename = "hybrid")]
+    Hybrid,
+}
+
 #[derive(Default, Debug, Clone, Copy, PartialEq)]
 pub struct GpuComputeOptions {
     pub integration: bool,
     pub bvh: bool,
 }
 
+// Divergence of the GPU implementations from the CPU reference. Missing candidates are the pairs found only by the CPU
+// broad-phase, extra candidates are the pairs found only by the GPU one.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GpuValidation {
+    pub max_position_difference: f32,
+    pub max_velocity_difference: f32,
+    pub missing_candidates: usize,
+    pub extra_candidates: usize,
+}
+
 #[repr(C)]
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct NormalizedCollisionPair {
-    object1_index: u32,
-    object2_index: u32,
+    pub(crate) object1_index: u32,
+    pub(crate) object2_index: u32,
 }
 
 impl NormalizedCollisionPair {
@@ -584,6 +2167,12 @@ impl NormalizedCollisionPair {
             object2_index: object1_index.max(object2_index),
         }
     }
+
+    // Negative if the objects don't touch
+    pub(crate) fn penetration_depth(self, positions: &[Vector2<f32>], radii: &[f32]) -> f32 {
+        let (object1_index, object2_index) = (self.object1_index as usize, self.object2_index as usize);
+        radii[object1_index] + radii[object2_index] - (positions[object1_index] - positions[object2_index]).magnitude()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -591,10 +2180,28 @@ pub struct DurationStat {
     pub current: Duration,
     pub lowest: Duration,
     pub highest: Duration,
-    pub average: RingBuffer<32, Duration>,
+    pub average: RingBuffer<Duration>,
 }
 
 impl DurationStat {
+    pub const DEFAULT_WINDOW: usize = 32;
+
+    // Averages the last `window` durations
+    #[must_use]
+    pub fn new(window: usize) -> Self {
+        Self {
+            current: Duration::ZERO,
+            lowest: Duration::MAX,
+            highest: Duration::ZERO,
+            average: RingBuffer::new(window),
+        }
+    }
+
+    #[must_use]
+    pub fn window(&self) -> usize {
+        self.average.capacity()
+    }
+
     pub fn update(&mut self, duration: Duration) {
         self.current = duration;
         self.lowest = self.lowest.min(duration);
@@ -605,22 +2212,384 @@ impl DurationStat {
 
 impl Default for DurationStat {
     fn default() -> Self {
-        Self {
-            current: Duration::ZERO,
-            lowest: Duration::MAX,
-            highest: Duration::ZERO,
-            average: RingBuffer::default(),
-        }
+        Self::new(Self::DEFAULT_WINDOW)
     }
 }
 
 #[derive(Default, Clone, Debug)]
 pub struct Stats {
     pub sim_time: f32,
+    pub step_count: usize,
     pub object_count: usize,
+    pub morton_codes_changed: usize,
+    // Of the last BVH update
+    pub bvh_metrics: Option<BvhMetrics>,
     pub integration_duration: DurationStat,
     pub bvh_duration: DurationStat,
     pub collisions_duration: DurationStat,
     pub constraints_duration: DurationStat,
     pub total_duration: DurationStat,
+    // Merging and ordering the candidates of the CPU searches, and the memory it takes
+    pub pair_storage_duration: DurationStat,
+    pub pair_storage_memory: usize,
+    pub pair_cache_hit_ratio: Option<f32>,
+    pub verlet_lists: Option<VerletListStats>,
+    // Share of the processed collision candidates that weren't in contact
+    pub candidate_false_positive_rate: Option<f32>,
+    pub gpu_validation: Option<GpuValidation>,
+    pub energy_changes: EnergyChanges,
+    pub angular_momentum: Option<AngularMomentum>,
+    pub event_driven: Option<EventDrivenStats>,
+    // Temperature of the particles, if the thermostat is on
+    pub temperature: Option<f32>,
+    // Highest relative excess of a particle density over the rest density, if the fluid pass is on
+    pub fluid_density_error: Option<f32>,
+    // Pairs of objects stuck together by adhesion
+    pub attachment_count: usize,
+    // Collision candidates deferred to the next step because the collision budget ran out
+    pub deferred_collision_count: usize,
+    // Candidates that didn't fit the per-object limit of the CPU BVH search, which keeps the deepest penetrations and
+    // may still find a pair from its other object, or the total limit of the GPU search. The pair cache, the Verlet
+    // lists and the broad phase keep every pair, so they drop none.
+    pub dropped_collision_count: usize,
+    // Indexed by `Wall`
+    pub wall_flux: [WallFlux; 4],
+}
+
+pub type StepObserver = Box<dyn FnMut(&StepReport) + Send>;
+
+// Passed to the step observer after every step
+#[derive(Clone, Copy, Debug)]
+pub struct StepReport {
+    pub step_count: usize,
+    pub time: f32,
+    pub dt: f32,
+    pub object_count: usize,
+    pub contact_count: usize,
+    pub integration_duration: Duration,
+    pub bvh_duration: Duration,
+    pub collisions_duration: Duration,
+    pub constraints_duration: Duration,
+    pub total_duration: Duration,
+    pub kinetic_energy: f64,
+    pub energy_changes: EnergyChanges,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VerletListStats {
+    pub skin: f32,
+    pub rebuild_count: usize,
+}
+
+// Total angular momentum about the barycenter, tracked for scenes with planets. Drift is relative to the value after
+// the first step; with a symplectic integrator and no collisions it should stay close to zero.
+#[derive(Clone, Copy, Debug)]
+pub struct AngularMomentum {
+    pub value: f64,
+    pub drift: f64,
+}
+
+// Energy added (positive) or removed (negative) by each part of the solver during the last step. Collision response
+// changes the kinetic energy through restitution and inelastic slow contacts, while position correction and
+// constraints also move objects along the global gravity, changing their potential energy.
+#[derive(Default, Clone, Copy, Debug)]
+pub struct EnergyChanges {
+    pub collision_response: f32,
+    pub position_correction: f32,
+    pub constraints: f32,
+}
+
+fn angular_momentum(positions: &[Vector2<f32>], velocities: &[Vector2<f32>], masses: &[f32]) -> f64 {
+    let (mut total_mass, mut weighted_position, mut momentum) = (0.0, (0.0, 0.0), (0.0, 0.0));
+    for ((position, velocity), &mass) in zip(zip(positions, velocities), masses) {
+        let mass = f64::from(mass);
+        total_mass += mass;
+        weighted_position.0 += mass * f64::from(position.x);
+        weighted_position.1 += mass * f64::from(position.y);
+        momentum.0 += mass * f64::from(velocity.x);
+        momentum.1 += mass * f64::from(velocity.y);
+    }
+    if total_mass == 0.0 {
+        return 0.0;
+    }
+    // L = sum(m * (r - R) x v) = sum(m * r x v) - R x P
+    let barycenter = (weighted_position.0 / total_mass, weighted_position.1 / total_mass);
+    let mut angular_momentum = -(barycenter.0 * momentum.1 - barycenter.1 * momentum.0);
+    for ((position, velocity), &mass) in zip(zip(positions, velocities), masses) {
+        let (x, y) = (f64::from(position.x), f64::from(position.y));
+        angular_momentum += f64::from(mass) * (x * f64::from(velocity.y) - y * f64::from(velocity.x));
+    }
+    angular_momentum
+}
+
+fn kinetic_energy(mass: f32, velocity: Vector2<f32>) -> f32 {
+    0.5 * mass * velocity.magnitude_squared()
+}
+
+// Potential energy gained by moving an object by `displacement` in the global gravity field
+fn gravity_work(mass: f32, global_gravity: Vector2<f32>, displacement: Vector2<f32>) -> f32 {
+    -mass * global_gravity.dot(displacement)
+}
+
+// Kahan summation: `compensation` carries the low-order bits lost when adding `value` to `sum`
+fn compensated_add(sum: &mut Vector2<f32>, compensation: &mut Vector2<f32>, value: Vector2<f32>) {
+    let corrected_value = value - *compensation;
+    let new_sum = *sum + corrected_value;
+    *compensation = (new_sum - *sum) - corrected_value;
+    *sum = new_sum;
+}
+
+#[test]
+fn compensated_summation_reduces_orbit_drift() {
+    const STEPS: usize = 1_000_000;
+    // Two equal planets on a circular orbit around their barycenter with a period of 100
+    const CENTER: Vector2<f32> = Vector2::new(500.0, 500.0);
+    const RADIUS: f32 = 100.0;
+    const ANGULAR_VELOCITY: f32 = std::f32::consts::TAU / 100.0;
+
+    let orbit = |compensated_summation: bool| {
+        let speed = ANGULAR_VELOCITY * RADIUS;
+        // G * m / (2 * r)^2 = speed^2 / r
+        let mass = 4.0 * RADIUS * speed * speed;
+        let mut objects = ObjectSoa::default();
+        for side in [-1.0, 1.0] {
+            objects.add(ObjectPrototype {
+                velocity: Vector2::new(0.0, side * speed),
+                mass,
+                is_planet: true,
+                ..ObjectPrototype::new(CENTER + Vector2::new(side * RADIUS, 0.0))
+            });
+        }
+        let settings = PhysicsSettings {
+            gravitational_constant: 1.0,
+            compensated_summation,
+            ..test_settings()
+        };
+        let mut physics = PhysicsEngine::new(objects, settings).unwrap();
+        for _ in 0..STEPS {
+            physics.advance(1.0, GpuComputeOptions::default());
+        }
+        // Distance from where the exact orbit puts the planets. The engine time is a sum of the steps that drifts
+        // just as much, so the exact time is computed from the step count.
+        let angle = f64::from(ANGULAR_VELOCITY) * STEPS as f64 / 1000.0;
+        zip([-1.0, 1.0], &physics.objects().positions)
+            .map(|(side, position)| {
+                let x = f64::from(CENTER.x) + side * f64::from(RADIUS) * angle.cos();
+                let y = f64::from(CENTER.y) + side * f64::from(RADIUS) * angle.sin();
+                (f64::from(position.x) - x).hypot(f64::from(position.y) - y)
+            })
+            .fold(0.0, f64::max)
+    };
+
+    let naive_drift = orbit(false);
+    let compensated_drift = orbit(true);
+    assert!(compensated_drift * 5.0 < naive_drift, "{compensated_drift} vs {naive_drift}");
+}
+
+#[test]
+fn objects_near_finds_objects_the_verlet_lists_kept_out_of_the_bvh() {
+    let mut objects = ObjectSoa::default();
+    objects.add(ObjectPrototype {
+        velocity: Vector2::new(1000.0, 0.0),
+        ..ObjectPrototype::new(Vector2::new(100.0, 100.0))
+    });
+    objects.add(ObjectPrototype::new(Vector2::new(500.0, 500.0)));
+    let settings = PhysicsSettings {
+        verlet_skin: Some(10.0),
+        ..test_settings()
+    };
+    let mut physics = PhysicsEngine::new(objects, settings).unwrap();
+    // The object moves by 1 per step, and the Verlet lists stay valid until it moves by 5 since the first step
+    for _ in 0..4 {
+        physics.advance(1.0, GpuComputeOptions::default());
+    }
+    let position = physics.objects().positions[0];
+    assert!((position.x - 104.0).abs() < 0.01, "{}", position.x);
+    assert_eq!(physics.objects_near(position, 0.5), [0]);
+}
+
+#[test]
+fn saved_state_continues_like_the_original() {
+    let mut objects = ObjectSoa::default();
+    for side in [-1.0, 1.0] {
+        objects.add(ObjectPrototype {
+            velocity: Vector2::new(0.0, side * 10.0),
+            mass: 1000.0,
+            is_planet: true,
+            ..ObjectPrototype::new(Vector2::new(500.0 + side * 100.0, 500.0))
+        });
+    }
+    for side in [-1.0, 1.0] {
+        objects.add(ObjectPrototype {
+            velocity: Vector2::new(-side * 20.0, 0.0),
+            radius: 5.0,
+            ..ObjectPrototype::new(Vector2::new(500.0 + side * 10.0, 200.0))
+        });
+    }
+    let settings = PhysicsSettings {
+        restitution_coefficient: 0.5,
+        gravitational_constant: 1.0,
+        heat_conduction: Some(0.1),
+        compensated_summation: true,
+        ..test_settings()
+    };
+    let mut physics = PhysicsEngine::new(objects, settings.clone()).unwrap();
+    for _ in 0..500 {
+        physics.advance(1.0, GpuComputeOptions::default());
+    }
+    let path = std::env::temp_dir().join(format!("collision-state-{}.snapshot", std::process::id()));
+    physics.save_state(&path).unwrap();
+    let mut loaded = PhysicsEngine::new(ObjectSoa::default(), settings).unwrap();
+    loaded.load_state(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(physics.heat().iter().any(|&heat| heat > 0.0));
+    for _ in 0..500 {
+        physics.advance(1.0, GpuComputeOptions::default());
+        loaded.advance(1.0, GpuComputeOptions::default());
+    }
+    assert_eq!(loaded.time(), physics.time());
+    assert!(loaded.objects().positions == physics.objects().positions);
+    assert!(loaded.objects().velocities == physics.objects().velocities);
+    assert_eq!(loaded.heat(), physics.heat());
+}
+
+#[test]
+fn angular_momentum_is_about_barycenter() {
+    // Two bodies orbiting their barycenter counterclockwise, the whole system drifting to the right
+    let drift = Vector2::new(3.0, 0.0);
+    let positions = [Vector2::new(9.0, 10.0), Vector2::new(12.0, 10.0)];
+    let velocities = [Vector2::new(0.0, -2.0) + drift, Vector2::new(0.0, 1.0) + drift];
+    let masses = [1.0, 2.0];
+    // Barycenter is at x = 11: 1 * 2 * 2 + 2 * 1 * 1
+    assert!((angular_momentum(&positions, &velocities, &masses) - 6.0).abs() < 1e-9);
+}
+
+#[test]
+fn gravity_zones_replace_global_gravity() {
+    let global_gravity = Vector2::new(0.0, 10.0);
+    let zone = |min_y, gravity_y| GravityZone {
+        gravity: Vector2::new(0.0, gravity_y),
+        min_y,
+    };
+    let zones = [zone(100.0, -5.0), zone(200.0, 20.0)];
+    let gravity_at = |y| PhysicsEngine::global_gravity_at(global_gravity, &zones, Vector2::new(0.0, y)).y;
+    assert_eq!(gravity_at(50.0), 10.0);
+    assert_eq!(gravity_at(100.0), -5.0);
+    assert_eq!(gravity_at(150.0), -5.0);
+    assert_eq!(gravity_at(250.0), 20.0);
+}
+
+// Settings of a CPU-only engine with no forces between the objects, for the tests that drive the whole engine
+#[cfg(test)]
+fn test_settings() -> PhysicsSettings {
+    PhysicsSettings {
+        dt: DtSource::Fixed(1.0 / 1000.0),
+        mode: SimulationMode::TimeStepped,
+        hybrid_max_cluster_size: 0,
+        constraints: AABB {
+            topleft: Vector2::new(0.0, 0.0),
+            bottomright: Vector2::new(1000.0, 1000.0),
+        },
+        constraint_bouncing: true,
+        boundaries: Boundaries::default(),
+        restitution_coefficient: 1.0,
+        materials: Vec::new(),
+        restitution_combine: CombineRule::Max,
+        friction_combine: CombineRule::GeometricMean,
+        material_pairs: Vec::new(),
+        restitution_velocity_threshold: 0.0,
+        penetration_slop: 0.0,
+        position_correction_factor: 1.0,
+        global_gravity: Vector2::default(),
+        gravity_zones: Vec::new(),
+        gravitational_constant: 0.0,
+        wind: None,
+        thermostat: None,
+        fluid: None,
+        heat_conduction: None,
+        pair_cache_margin: None,
+        verlet_skin: None,
+        pair_storage: PairStorageKind::Vec,
+        compensated_summation: false,
+        gpu_kernel_timeout: None,
+        collision_budget: None,
+        thread_pool: None,
+        seed: Some(0),
+        duration_stat_window: DurationStat::DEFAULT_WINDOW,
+    }
+}
+
+#[test]
+fn removal_keeps_per_object_state_in_sync() {
+    let mut objects = ObjectSoa::default();
+    for x in [100.0, 200.0, 300.0, 400.0] {
+        objects.add(ObjectPrototype {
+            is_planet: x == 100.0,
+            ..ObjectPrototype::new(Vector2::new(x, 100.0))
+        });
+    }
+    let mut physics = PhysicsEngine::new(objects, test_settings()).unwrap();
+    physics.set_trajectory_recorder(Some(TrajectoryRecorder::new([2, 3], 1, 10)));
+    physics.apply_impulse(3, Vector2::new(1.0, 0.0));
+    physics.apply_force(2, Vector2::new(0.0, 1.0));
+
+    // The last object takes the place of the removed one, along with its impulse and trajectory
+    physics.swap_remove(1);
+    assert_eq!(physics.objects().positions, [100.0, 400.0, 300.0].map(|x| Vector2::new(x, 100.0)));
+    assert_eq!(physics.trajectory_recorder().unwrap().object_indices().collect::<Vec<_>>(), [1, 2]);
+    physics.advance(1.0, GpuComputeOptions::default());
+    assert_eq!(physics.objects().velocities[1], Vector2::new(1.0, 0.0));
+    assert!(physics.objects().velocities[2].y > 0.0);
+
+    physics.apply_impulse(2, Vector2::new(-1.0, 0.0));
+    assert_eq!(physics.retain(|_, object| !object.is_planet), 1);
+    assert_eq!(physics.objects().planet_count, 0);
+    assert_eq!(physics.trajectory_recorder().unwrap().object_indices().collect::<Vec<_>>(), [0, 1]);
+    physics.advance(1.0, GpuComputeOptions::default());
+    assert_eq!(physics.objects().velocities[1].x, -1.0);
+    assert_eq!(physics.trajectory_recorder().unwrap().trajectory(0).unwrap().len(), 2);
+}
+
+#[test]
+fn hybrid_mode_keeps_forces_the_event_driven_solver_ignores() {
+    let objects = || {
+        let mut objects = ObjectSoa::default();
+        objects.add(ObjectPrototype::new(Vector2::new(500.0, 500.0)));
+        objects
+    };
+    let settings = PhysicsSettings {
+        mode: SimulationMode::Hybrid,
+        hybrid_max_cluster_size: 16,
+        global_gravity: Vector2::new(0.0, 10.0),
+        ..test_settings()
+    };
+
+    // A lone falling particle is advanced event-driven, and only once
+    let mut physics = PhysicsEngine::new(objects(), settings.clone()).unwrap();
+    for _ in 0..100 {
+        physics.advance(1.0, GpuComputeOptions::default());
+    }
+    assert_eq!(physics.stats().event_driven.map(|stats| stats.object_count), Some(1));
+    assert!((physics.objects().velocities[0].y - 1.0).abs() < 1e-4);
+    assert!((physics.objects().positions[0].y - 500.05).abs() < 1e-3);
+
+    let wind = Wind {
+        acceleration: Vector2::new(10.0, 0.0),
+        turbulence_amplitude: 0.0,
+        turbulence_scale: 1.0,
+        turbulence_speed: 0.0,
+    };
+    let mut physics = PhysicsEngine::new(
+        objects(),
+        PhysicsSettings {
+            wind: Some(wind),
+            ..settings
+        },
+    )
+    .unwrap();
+    for _ in 0..100 {
+        physics.advance(1.0, GpuComputeOptions::default());
+    }
+    assert!(physics.stats().event_driven.is_none());
+    assert!((physics.objects().velocities[0].x - 1.0).abs() < 1e-3);
 }
@@ -0,0 +1,105 @@
+use crate::{broad_phase::BroadPhase, bvh::AABB, grid::Grid, object::ObjectSoa, vector2::Vector2};
+
+// Pushes overlapping objects apart, in proportion to their inverse masses, without touching the velocities, so that a
+// generated scene starts at rest instead of exploding on the first step. Frozen objects stay in place, planets and
+// objects that don't collide with each other are left alone, and the objects are kept within the constraints. Every
+// push is applied at once, so that it spreads through a packed cluster within a few iterations. Returns the number of
+// pairs that still overlap.
+pub fn relax_overlaps(objects: &mut ObjectSoa, constraints: AABB, max_iterations: usize) -> usize {
+    // Of the sum of the radii; separation only converges, so a tiny overlap is left
+    const TOLERANCE: f32 = 1e-4;
+
+    let inverse_masses = (0..objects.len())
+        .map(|object_index| {
+            if objects.is_frozen[object_index] {
+                0.0
+            } else {
+                1.0 / objects.masses[object_index]
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut grid = Grid::default();
+    let mut pairs = Vec::new();
+    for iteration in 0..=max_iterations {
+        grid.update(&objects.positions, &objects.radii, constraints);
+        pairs.clear();
+        grid.for_each_pair(&objects.positions, &objects.radii, &mut |object1_index, object2_index| {
+            let collision_group = objects.collision_groups[object1_index];
+            let collide = !objects.is_planet[object1_index]
+                && !objects.is_planet[object2_index]
+                && (collision_group == 0 || collision_group != objects.collision_groups[object2_index]);
+            if collide {
+                pairs.push((object1_index, object2_index));
+            }
+        });
+        let mut overlap_count = 0;
+        for &(object1_index, object2_index) in &pairs {
+            let delta = objects.positions[object2_index] - objects.positions[object1_index];
+            let distance = delta.magnitude();
+            let radius_sum = objects.radii[object1_index] + objects.radii[object2_index];
+            let depth = radius_sum - distance;
+            let inverse_mass_sum = inverse_masses[object1_index] + inverse_masses[object2_index];
+            if depth <= radius_sum * TOLERANCE || inverse_mass_sum == 0.0 {
+                continue;
+            }
+            overlap_count += 1;
+            if iteration == max_iterations {
+                continue;
+            }
+            // Coincident objects are separated along an arbitrary axis
+            let normal = if distance > 0.0 {
+                delta / distance
+            } else {
+                Vector2::new(1.0, 0.0)
+            };
+            for (object_index, push) in [
+                (object1_index, -normal * (depth * inverse_masses[object1_index] / inverse_mass_sum)),
+                (object2_index, normal * (depth * inverse_masses[object2_index] / inverse_mass_sum)),
+            ] {
+                let radius = objects.radii[object_index];
+                let position = &mut objects.positions[object_index];
+                *position += push;
+                let (min, max) = (constraints.topleft + radius, constraints.bottomright - radius);
+                if min.x <= max.x && min.y <= max.y {
+                    position.x = position.x.clamp(min.x, max.x);
+                    position.y = position.y.clamp(min.y, max.y);
+                }
+            }
+        }
+        if overlap_count == 0 {
+            break;
+        }
+        if iteration == max_iterations {
+            return overlap_count;
+        }
+    }
+    0
+}
+
+#[test]
+fn relaxation_separates_overlapping_objects() {
+    use crate::object::ObjectPrototype;
+
+    let constraints = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(100.0, 100.0),
+    };
+    let mut objects = ObjectSoa::default();
+    objects.add(ObjectPrototype {
+        radius: 5.0,
+        is_frozen: true,
+        ..ObjectPrototype::new(Vector2::new(50.0, 50.0))
+    });
+    for x in [54.0, 58.0, 62.0] {
+        objects.add(ObjectPrototype {
+            radius: 5.0,
+            ..ObjectPrototype::new(Vector2::new(x, 50.0))
+        });
+    }
+    assert_eq!(relax_overlaps(&mut objects, constraints, 100), 0);
+    assert_eq!(objects.positions[0], Vector2::new(50.0, 50.0));
+    let mut xs = objects.positions.iter().map(|position| position.x).collect::<Vec<_>>();
+    xs.sort_by(f32::total_cmp);
+    assert!(xs.windows(2).all(|pair| pair[1] - pair[0] > 9.99));
+    assert!(objects.velocities.iter().all(|&velocity| velocity == Vector2::new(0.0, 0.0)));
+}
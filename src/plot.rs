@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+
+// Latest values of a plotted quantity, oldest first
+pub struct Series {
+    capacity: usize,
+    values: VecDeque<f32>,
+}
+
+impl Series {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            values: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    #[must_use]
+    pub fn values(&self) -> &VecDeque<f32> {
+        &self.values
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[must_use]
+    pub fn max(&self) -> Option<f32> {
+        self.values.iter().copied().reduce(f32::max)
+    }
+}
+
+// Counts of the values in equal bins between the smallest and the largest value
+pub struct Histogram {
+    pub min: f32,
+    pub max: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    #[must_use]
+    pub fn new(values: impl Iterator<Item = f32> + Clone, bin_count: usize) -> Option<Self> {
+        let (min, max) = values.clone().fold(None, |range, value| match range {
+            None => Some((value, value)),
+            Some((min, max)) => Some((value.min(min), value.max(max))),
+        })?;
+        let mut counts = vec![0; bin_count.max(1)];
+        let last_bin = counts.len() - 1;
+        for value in values {
+            #[allow(
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss,
+                clippy::cast_precision_loss
+            )]
+            let bin = if max > min {
+                (((value - min) / (max - min) * counts.len() as f32) as usize).min(last_bin)
+            } else {
+                0
+            };
+            counts[bin] += 1;
+        }
+        Some(Self { min, max, counts })
+    }
+}
+
+#[test]
+fn series_and_histogram() {
+    let mut series = Series::new(4);
+    for value in [5.0, 1.0, 2.0, 2.5, 3.0, 4.0] {
+        series.push(value);
+    }
+    assert_eq!(series.values(), &[2.0, 2.5, 3.0, 4.0]);
+    assert_eq!(series.max(), Some(4.0));
+
+    let histogram = Histogram::new(series.values().iter().copied(), 2).unwrap();
+    assert_eq!((histogram.min, histogram.max), (2.0, 4.0));
+    assert_eq!(histogram.counts, [2, 2]);
+    assert!(Histogram::new(Series::new(1).values().iter().copied(), 2).is_none());
+}
@@ -0,0 +1,117 @@
+// Rendering quality knobs, from the cheapest to lose to the most noticeable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+    pub level: usize,
+    pub draw_ids: bool,
+    pub trails: bool,
+    // Multiplies the energy density field cell size
+    pub edf_cell_size_factor: f32,
+    // Objects smaller than this on screen are drawn as squares
+    pub point_sprite_radius: Option<f32>,
+    // Only every Nth particle is drawn
+    pub particle_stride: usize,
+}
+
+impl Quality {
+    pub const MAX_LEVEL: usize = 7;
+    const DEGRADED_POINT_SPRITE_RADIUS: f32 = 2.0;
+
+    // Level 0 is the configured quality, every next level degrades one more knob
+    #[must_use]
+    pub fn new(level: usize, point_sprite_radius: Option<f32>) -> Self {
+        let level = level.min(Self::MAX_LEVEL);
+        Self {
+            level,
+            draw_ids: level < 1,
+            trails: level < 2,
+            edf_cell_size_factor: if level < 3 { 1.0 } else { 2.0 },
+            point_sprite_radius: if level < 4 {
+                point_sprite_radius
+            } else {
+                Some(point_sprite_radius.map_or(Self::DEGRADED_POINT_SPRITE_RADIUS, |radius| {
+                    radius.max(Self::DEGRADED_POINT_SPRITE_RADIUS)
+                }))
+            },
+            particle_stride: 1 << level.saturating_sub(4),
+        }
+    }
+}
+
+impl Default for Quality {
+    fn default() -> Self {
+        Self::new(0, None)
+    }
+}
+
+// Lowers the quality while the frame rate stays below the target and restores it when the frame rate stays above the
+// target by a margin. Restoring takes longer than degrading, so that the quality doesn't flap around the target.
+pub struct QualityController {
+    target_fps: usize,
+    headroom: f32,
+    point_sprite_radius: Option<f32>,
+    level: usize,
+    streak: i32,
+}
+
+impl QualityController {
+    const STREAK_TO_DEGRADE: i32 = 5;
+    const STREAK_TO_RESTORE: i32 = 20;
+
+    #[must_use]
+    pub fn new(target_fps: usize, headroom: f32, point_sprite_radius: Option<f32>) -> Self {
+        Self {
+            target_fps,
+            headroom,
+            point_sprite_radius,
+            level: 0,
+            streak: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn quality(&self) -> Quality {
+        Quality::new(self.level, self.point_sprite_radius)
+    }
+
+    // Called on every FPS measurement, returns the new quality when it changes
+    pub fn update(&mut self, fps: usize) -> Option<Quality> {
+        #[allow(clippy::cast_precision_loss)]
+        let (fps, target_fps) = (fps as f32, self.target_fps as f32);
+        if fps < target_fps {
+            self.streak = self.streak.min(0) - 1;
+        } else if fps > target_fps * self.headroom {
+            self.streak = self.streak.max(0) + 1;
+        } else {
+            self.streak = 0;
+        }
+
+        let level = if self.streak <= -Self::STREAK_TO_DEGRADE {
+            (self.level + 1).min(Quality::MAX_LEVEL)
+        } else if self.streak >= Self::STREAK_TO_RESTORE {
+            self.level.saturating_sub(1)
+        } else {
+            return None;
+        };
+        self.streak = 0;
+        (level != self.level).then(|| {
+            self.level = level;
+            self.quality()
+        })
+    }
+}
+
+#[test]
+fn quality_controller_degrades_and_restores() {
+    let mut controller = QualityController::new(60, 1.25, None);
+    let updates =
+        (0..QualityController::STREAK_TO_DEGRADE * 2).filter_map(|_| controller.update(30)).collect::<Vec<_>>();
+    assert_eq!(updates.iter().map(|quality| quality.level).collect::<Vec<_>>(), [1, 2]);
+    assert!(!updates[1].draw_ids && !updates[1].trails);
+
+    // Within the headroom band the quality stays as it is
+    assert!((0..100).all(|_| controller.update(70).is_none()));
+    let updates =
+        (0..QualityController::STREAK_TO_RESTORE * 3).filter_map(|_| controller.update(100)).collect::<Vec<_>>();
+    assert_eq!(updates.iter().map(|quality| quality.level).collect::<Vec<_>>(), [1, 0]);
+    assert_eq!(controller.quality(), Quality::new(0, None));
+}
@@ -1,13 +1,29 @@
 use std::fmt::Debug;
 
 #[derive(Clone)]
-pub struct RingBuffer<const N: usize, T> {
-    data: [T; N],
+pub struct RingBuffer<T> {
+    data: Vec<T>,
     front: usize,
     length: usize,
 }
 
-impl<const N: usize, T> RingBuffer<N, T> {
+impl<T: Default + Copy> RingBuffer<T> {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "ring buffer capacity must be positive");
+        Self {
+            data: vec![T::default(); capacity],
+            front: 0,
+            length: 0,
+        }
+    }
+}
+
+impl<T> RingBuffer<T> {
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
     pub fn len(&self) -> usize {
         self.length
     }
@@ -17,25 +33,16 @@ impl<const N: usize, T> RingBuffer<N, T> {
     }
 
     pub fn push(&mut self, item: T) {
-        let new_length = (self.length + 1).min(N);
-        let index = (self.front + self.length) % N;
-        self.front = (self.front + (self.length.checked_sub(N).unwrap_or(1) ^ 1)) % N;
+        let n = self.data.len();
+        let new_length = (self.length + 1).min(n);
+        let index = (self.front + self.length) % n;
+        self.front = (self.front + (self.length.checked_sub(n).unwrap_or(1) ^ 1)) % n;
         self.length = new_length;
         self.data[index] = item;
     }
 }
 
-impl<const N: usize, T: Default + Copy> Default for RingBuffer<N, T> {
-    fn default() -> Self {
-        Self {
-            data: [T::default(); N],
-            front: 0,
-            length: 0,
-        }
-    }
-}
-
-impl<const N: usize, T: Copy> Iterator for RingBuffer<N, T> {
+impl<T: Copy> Iterator for RingBuffer<T> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -44,13 +51,13 @@ impl<const N: usize, T: Copy> Iterator for RingBuffer<N, T> {
         } else {
             let item = self.data[self.front];
             self.length -= 1;
-            self.front = (self.front + 1) % N;
+            self.front = (self.front + 1) % self.data.len();
             Some(item)
         }
     }
 }
 
-impl<const N: usize, T: Debug + Copy> Debug for RingBuffer<N, T> {
+impl<T: Debug + Copy> Debug for RingBuffer<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for item in self.clone() {
             Debug::fmt(&item, f)?;
@@ -65,7 +72,7 @@ fn ring_buffer() {
     const EXCESS: usize = 20;
     for front in 0..N {
         let mut buffer = RingBuffer {
-            data: [0; N],
+            data: vec![0; N],
             front,
             length: 0,
         };
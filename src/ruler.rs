@@ -0,0 +1,42 @@
+// Spacing of the ticks of an axis ruler: 1, 2 or 5 times a power of ten, giving about `target_count` ticks over
+// the range
+#[must_use]
+pub fn tick_step(range: f32, target_count: usize) -> f32 {
+    #[allow(clippy::cast_precision_loss)]
+    let raw_step = range / target_count.max(1) as f32;
+    let magnitude = 10_f32.powf(raw_step.log10().floor());
+    let factor = match raw_step / magnitude {
+        ..1.5 => 1.0,
+        ..3.5 => 2.0,
+        ..7.5 => 5.0,
+        _ => 10.0,
+    };
+    factor * magnitude
+}
+
+// Multiples of the step within the range
+pub fn ticks(min: f32, max: f32, step: f32) -> impl Iterator<Item = f32> {
+    #[allow(clippy::cast_possible_truncation)]
+    let (first, last) = ((min / step).ceil() as i64, (max / step).floor() as i64);
+    #[allow(clippy::cast_precision_loss)]
+    (first..=last).map(move |index| index as f32 * step)
+}
+
+// Enough decimal places to tell the ticks apart
+#[must_use]
+pub fn tick_precision(step: f32) -> usize {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let precision = (-step.log10().floor()).max(0.0) as usize;
+    precision
+}
+
+#[test]
+fn ruler_ticks_are_round() {
+    assert_eq!(tick_step(1000.0, 10), 100.0);
+    assert_eq!(tick_step(37.0, 10), 5.0);
+    assert!((tick_step(0.3, 5) - 0.05).abs() < 1e-6);
+    assert_eq!(tick_step(16.0, 10), 2.0);
+    assert_eq!(ticks(-12.0, 31.0, 10.0).collect::<Vec<_>>(), [-10.0, 0.0, 10.0, 20.0, 30.0]);
+    assert_eq!(tick_precision(100.0), 0);
+    assert_eq!(tick_precision(0.05), 2);
+}
@@ -0,0 +1,193 @@
+use std::{cell::RefCell, mem, path::Path, rc::Rc};
+
+use anyhow::{Context, anyhow};
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FLOAT, INT, Scope};
+
+use crate::{
+    object::{ObjectPrototype, ObjectSoa},
+    physics::PhysicsEngine,
+    vector2::Vector2,
+};
+
+// A user script with a `step(sim)` function, called before every simulation step. The script can read and modify
+// positions and velocities, add and remove objects and change the global gravity:
+//
+//     fn step(sim) {
+//         sim.set_gravity(0.0, 100.0 * sin(sim.time));
+//         if sim.object_count > 1000 { sim.remove(0); }
+//     }
+//
+// Added and removed objects are applied after `step` returns, so the object indices don't change during the call.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Rc<RefCell<ScriptState>>,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<Simulation>("Simulation")
+            .register_get("time", |sim: &mut Simulation| FLOAT::from(sim.0.borrow().time))
+            .register_get("dt", |sim: &mut Simulation| FLOAT::from(sim.0.borrow().dt))
+            .register_get("object_count", |sim: &mut Simulation| to_int(sim.0.borrow().objects.len()))
+            .register_get("planet_count", |sim: &mut Simulation| to_int(sim.0.borrow().objects.planet_count))
+            .register_fn("gravity", |sim: &mut Simulation| to_array(sim.0.borrow().global_gravity))
+            .register_fn("set_gravity", |sim: &mut Simulation, x: FLOAT, y: FLOAT| {
+                sim.0.borrow_mut().global_gravity = to_vector(x, y);
+            })
+            .register_fn("position", |sim: &mut Simulation, object_index: INT| -> ScriptResult<Array> {
+                let state = sim.0.borrow();
+                Ok(to_array(state.objects.positions[state.object_index(object_index)?]))
+            })
+            .register_fn(
+                "set_position",
+                |sim: &mut Simulation, object_index: INT, x: FLOAT, y: FLOAT| -> ScriptResult<()> {
+                    let mut state = sim.0.borrow_mut();
+                    let object_index = state.object_index(object_index)?;
+                    state.objects.positions[object_index] = to_vector(x, y);
+                    Ok(())
+                },
+            )
+            .register_fn("velocity", |sim: &mut Simulation, object_index: INT| -> ScriptResult<Array> {
+                let state = sim.0.borrow();
+                Ok(to_array(state.objects.velocities[state.object_index(object_index)?]))
+            })
+            .register_fn(
+                "set_velocity",
+                |sim: &mut Simulation, object_index: INT, x: FLOAT, y: FLOAT| -> ScriptResult<()> {
+                    let mut state = sim.0.borrow_mut();
+                    let object_index = state.object_index(object_index)?;
+                    state.objects.velocities[object_index] = to_vector(x, y);
+                    Ok(())
+                },
+            )
+            .register_fn("radius", |sim: &mut Simulation, object_index: INT| -> ScriptResult<FLOAT> {
+                let state = sim.0.borrow();
+                Ok(FLOAT::from(state.objects.radii[state.object_index(object_index)?]))
+            })
+            .register_fn("mass", |sim: &mut Simulation, object_index: INT| -> ScriptResult<FLOAT> {
+                let state = sim.0.borrow();
+                Ok(FLOAT::from(state.objects.masses[state.object_index(object_index)?]))
+            })
+            .register_fn("is_planet", |sim: &mut Simulation, object_index: INT| -> ScriptResult<bool> {
+                let state = sim.0.borrow();
+                Ok(state.objects.is_planet[state.object_index(object_index)?])
+            })
+            .register_fn("is_frozen", |sim: &mut Simulation, object_index: INT| -> ScriptResult<bool> {
+                let state = sim.0.borrow();
+                Ok(state.objects.is_frozen[state.object_index(object_index)?])
+            })
+            .register_fn("set_frozen", |sim: &mut Simulation, object_index: INT, is_frozen: bool| -> ScriptResult<()> {
+                let mut state = sim.0.borrow_mut();
+                let object_index = state.object_index(object_index)?;
+                state.objects.set_frozen(object_index, is_frozen);
+                Ok(())
+            })
+            .register_fn(
+                "add",
+                |sim: &mut Simulation, x: FLOAT, y: FLOAT, vx: FLOAT, vy: FLOAT, radius: FLOAT, mass: FLOAT| {
+                    sim.0.borrow_mut().added.push(ObjectPrototype {
+                        velocity: to_vector(vx, vy),
+                        radius: radius as f32,
+                        mass: mass as f32,
+                        ..ObjectPrototype::new(to_vector(x, y))
+                    });
+                },
+            )
+            .register_fn("remove", |sim: &mut Simulation, object_index: INT| -> ScriptResult<()> {
+                let mut state = sim.0.borrow_mut();
+                let object_index = state.object_index(object_index)?;
+                state.removed.push(object_index);
+                Ok(())
+            });
+
+        let source = std::fs::read_to_string(path).with_context(|| format!("read script \"{}\"", path.display()))?;
+        let ast = engine.compile(source).map_err(|e| anyhow!("compile script \"{}\": {e}", path.display()))?;
+        if !ast.iter_functions().any(|function| function.name == "step" && function.params.len() == 1) {
+            anyhow::bail!("script \"{}\" doesn't define \"fn step(sim)\"", path.display());
+        }
+        // Top-level statements run once and may initialize the global variables used by `step`
+        let mut scope = Scope::new();
+        engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| anyhow!("run script \"{}\": {e}", path.display()))?;
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            state: Rc::default(),
+        })
+    }
+
+    pub fn step(&mut self, physics: &mut PhysicsEngine) -> anyhow::Result<()> {
+        {
+            let mut state = self.state.borrow_mut();
+            state.time = physics.time();
+            state.dt = physics.last_dt();
+            state.global_gravity = physics.global_gravity();
+            // The vectors are moved, not copied, so their allocations (and the GPU buffers aliasing them) are kept
+            mem::swap(&mut state.objects, physics.objects_mut());
+        }
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false),
+            &mut self.scope,
+            &self.ast,
+            "step",
+            (Simulation(self.state.clone()),),
+        );
+
+        let mut state = self.state.borrow_mut();
+        mem::swap(&mut state.objects, physics.objects_mut());
+        physics.set_global_gravity(state.global_gravity);
+        let mut removed = mem::take(&mut state.removed);
+        removed.sort_unstable();
+        removed.dedup();
+        for &object_index in removed.iter().rev() {
+            physics.remove(object_index);
+        }
+        for object in state.added.drain(..) {
+            physics.add(object);
+        }
+        result.map(|_| ()).map_err(|e| anyhow!("script error: {e}"))
+    }
+}
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Clone)]
+struct Simulation(Rc<RefCell<ScriptState>>);
+
+#[derive(Default)]
+struct ScriptState {
+    time: f32,
+    dt: f32,
+    global_gravity: Vector2<f32>,
+    objects: ObjectSoa,
+    added: Vec<ObjectPrototype>,
+    removed: Vec<usize>,
+}
+
+impl ScriptState {
+    fn object_index(&self, object_index: INT) -> ScriptResult<usize> {
+        usize::try_from(object_index)
+            .ok()
+            .filter(|&object_index| object_index < self.objects.len())
+            .ok_or_else(|| format!("object index {object_index} is out of range").into())
+    }
+}
+
+fn to_int(value: usize) -> INT {
+    INT::try_from(value).unwrap_or(INT::MAX)
+}
+
+fn to_vector(x: FLOAT, y: FLOAT) -> Vector2<f32> {
+    Vector2::new(x as f32, y as f32)
+}
+
+fn to_array(vector: Vector2<f32>) -> Array {
+    vec![
+        Dynamic::from_float(vector.x.into()),
+        Dynamic::from_float(vector.y.into()),
+    ]
+}
@@ -9,7 +9,7 @@ use skrifa::{
 };
 use vello::{
     Glyph, Scene,
-    kurbo::Affine,
+    kurbo::{Affine, Stroke},
     peniko::{Blob, Brush, BrushRef, Fill, Font, StyleRef, color::palette},
 };
 
@@ -96,10 +96,41 @@ impl SimpleText {
             );
     }
 
+    // Width of the longest line and height of all lines, as drawn by `add`
+    #[must_use]
+    pub fn measure(&self, size: f32, text: &str) -> (f32, f32) {
+        let font_ref = to_font_ref(&self.roboto).unwrap();
+        let font_size = skrifa::instance::Size::new(size);
+        let var_loc = font_ref.axes().location(std::iter::empty::<(&str, f32)>());
+        let charmap = font_ref.charmap();
+        let metrics = font_ref.metrics(font_size, &var_loc);
+        let line_height = metrics.ascent - metrics.descent + metrics.leading;
+        let glyph_metrics = font_ref.glyph_metrics(font_size, &var_loc);
+        let width = text
+            .lines()
+            .map(|line| {
+                line.chars()
+                    .map(|ch| glyph_metrics.advance_width(charmap.map(ch).unwrap_or_default()).unwrap_or_default())
+                    .sum::<f32>()
+            })
+            .fold(0.0, f32::max);
+        #[allow(clippy::cast_precision_loss)]
+        let height = text.lines().count() as f32 * line_height;
+        (width, height)
+    }
+
     pub fn add(&mut self, scene: &mut Scene, size: f32, brush: Option<&Brush>, transform: Affine, text: &str) {
         let brush = brush.unwrap_or(&Brush::Solid(palette::css::WHITE));
         self.add_run(scene, size, brush, transform, None, Fill::NonZero, text);
     }
+
+    // The font has no bold face, so the glyphs are thickened with an outline
+    pub fn add_bold(&mut self, scene: &mut Scene, size: f32, brush: Option<&Brush>, transform: Affine, text: &str) {
+        let brush = brush.unwrap_or(&Brush::Solid(palette::css::WHITE));
+        self.add_run(scene, size, brush, transform, None, Fill::NonZero, text);
+        let stroke = Stroke::new(f64::from(size) * 0.06);
+        self.add_run(scene, size, brush, transform, None, &stroke, text);
+    }
 }
 
 fn to_font_ref(font: &Font) -> Option<FontRef<'_>> {
@@ -0,0 +1,335 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, bail};
+use peniko::Color;
+
+use crate::{
+    container::{self, ContainerReader, ContainerWriter, Field, Header, Layout},
+    object::{ObjectPrototype, ObjectSoa},
+    vector2::Vector2,
+};
+
+const MAGIC: &[u8; 4] = b"CSNP";
+// Versions before 5 aren't containers
+const VERSION: u32 = 5;
+
+// Object flags; version 1 only had the planet flag. Versions before 3 don't store materials, versions before 4 don't
+// store collision groups.
+const FLAG_PLANET: u8 = 1 << 0;
+const FLAG_FROZEN: u8 = 1 << 1;
+// The color field is only valid with this flag
+const FLAG_COLOR: u8 = 1 << 2;
+
+// Full simulation state: simulation time and every object, in a container of a single frame
+pub struct Snapshot {
+    pub time: f32,
+    pub objects: ObjectSoa,
+    pub object_state: ObjectState,
+}
+
+// State the engine keeps for each object besides the object itself. Each of the values is either empty, when the
+// engine doesn't track it, or has one entry per object, and only the tracked ones are stored.
+#[derive(Default)]
+pub struct ObjectState {
+    pub heat: Vec<f32>,
+    pub position_compensations: Vec<Vector2<f32>>,
+    pub velocity_compensations: Vec<Vector2<f32>>,
+}
+
+impl Snapshot {
+    pub fn write(
+        writer: &mut impl Write,
+        time: f32,
+        objects: &ObjectSoa,
+        object_state: &ObjectState,
+    ) -> anyhow::Result<()> {
+        let mut fields = vec![
+            Field::new("position", 8),
+            Field::new("velocity", 8),
+            Field::new("radius", 4),
+            Field::new("mass", 4),
+            Field::new("flags", 1),
+            Field::new("material", 4),
+            Field::new("collision_group", 4),
+            Field::new("color", 16),
+        ];
+        let ObjectState {
+            heat,
+            position_compensations,
+            velocity_compensations,
+        } = object_state;
+        for (name, size, len) in [
+            ("heat", 4, heat.len()),
+            ("position_compensation", 8, position_compensations.len()),
+            ("velocity_compensation", 8, velocity_compensations.len()),
+        ] {
+            match len {
+                0 => {}
+                len if len == objects.len() => fields.push(Field::new(name, size)),
+                len => bail!("{len} values of {name} for {} objects", objects.len()),
+            }
+        }
+        let header = Header {
+            frame: Layout::new(vec![Field::new("time", 4)]),
+            record: Layout::new(fields),
+        };
+        let mut records = Vec::with_capacity(objects.len() * header.record.size());
+        for object_index in 0..objects.len() {
+            let position = objects.positions[object_index];
+            let velocity = objects.velocities[object_index];
+            for value in [position.x, position.y, velocity.x, velocity.y] {
+                records.extend_from_slice(&value.to_le_bytes());
+            }
+            records.extend_from_slice(&objects.radii[object_index].to_le_bytes());
+            records.extend_from_slice(&objects.masses[object_index].to_le_bytes());
+            let color = objects.colors[object_index];
+            let flags = [
+                (objects.is_planet[object_index], FLAG_PLANET),
+                (objects.is_frozen[object_index], FLAG_FROZEN),
+                (color.is_some(), FLAG_COLOR),
+            ]
+            .into_iter()
+            .filter(|&(is_set, _)| is_set)
+            .fold(0, |flags, (_, flag)| flags | flag);
+            records.push(flags);
+            records.extend_from_slice(&objects.materials[object_index].to_le_bytes());
+            records.extend_from_slice(&objects.collision_groups[object_index].to_le_bytes());
+            for component in color.map_or([0.0; 4], |color| color.components) {
+                records.extend_from_slice(&component.to_le_bytes());
+            }
+            if let Some(heat) = heat.get(object_index) {
+                records.extend_from_slice(&heat.to_le_bytes());
+            }
+            for compensations in [position_compensations, velocity_compensations] {
+                if let Some(compensation) = compensations.get(object_index) {
+                    for value in [compensation.x, compensation.y] {
+                        records.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+            }
+        }
+        let mut container = ContainerWriter::new(writer, MAGIC, VERSION, header)?;
+        container.write_frame(&time.to_le_bytes(), &records)
+    }
+
+    pub fn read(reader: &mut impl Read) -> anyhow::Result<Self> {
+        match container::read_version(reader, MAGIC, "snapshot")? {
+            version @ 1..VERSION => Self::read_legacy(reader, version),
+            VERSION => Self::read_container(reader),
+            version => bail!("unsupported snapshot version {version}"),
+        }
+    }
+
+    fn read_container(reader: &mut impl Read) -> anyhow::Result<Self> {
+        let mut container = ContainerReader::new(reader)?;
+        let frame = container.next_frame()?.context("missing frame")?;
+        let time = f32::from_le_bytes(container.frame_values(&frame).require("time")?);
+        let mut objects = ObjectSoa::default();
+        let mut object_state = ObjectState::default();
+        for (object_index, record) in container.records(&frame).enumerate() {
+            let read_object = || -> anyhow::Result<ObjectPrototype> {
+                let flags = record.get("flags")?.map_or(0, |[flags]| flags);
+                let color = record
+                    .get::<16>("color")?
+                    .filter(|_| flags & FLAG_COLOR != 0)
+                    .map(|color| Color::new(container::f32s(&color)));
+                Ok(ObjectPrototype {
+                    velocity: vector2_from(record.require("velocity")?),
+                    radius: f32::from_le_bytes(record.require("radius")?),
+                    mass: f32::from_le_bytes(record.require("mass")?),
+                    color,
+                    is_planet: flags & FLAG_PLANET != 0,
+                    is_frozen: flags & FLAG_FROZEN != 0,
+                    material: record.get("material")?.map_or(0, u32::from_le_bytes),
+                    collision_group: record.get("collision_group")?.map_or(0, u32::from_le_bytes),
+                    ..ObjectPrototype::new(vector2_from(record.require("position")?))
+                })
+            };
+            let object = read_object().with_context(|| format!("read object {object_index}"))?;
+            add_object(&mut objects, object_index, object)?;
+            let mut read_state = || -> anyhow::Result<()> {
+                if let Some(heat) = record.get("heat")? {
+                    object_state.heat.push(f32::from_le_bytes(heat));
+                }
+                if let Some(compensation) = record.get("position_compensation")? {
+                    object_state.position_compensations.push(vector2_from(compensation));
+                }
+                if let Some(compensation) = record.get("velocity_compensation")? {
+                    object_state.velocity_compensations.push(vector2_from(compensation));
+                }
+                Ok(())
+            };
+            read_state().with_context(|| format!("read state of object {object_index}"))?;
+        }
+        Ok(Self {
+            time,
+            objects,
+            object_state,
+        })
+    }
+
+    fn read_legacy(reader: &mut impl Read, version: u32) -> anyhow::Result<Self> {
+        let time = read_f32(reader)?;
+        let object_count = usize::try_from(u64::from_le_bytes(read_bytes(reader)?))?;
+        let mut objects = ObjectSoa::default();
+        for object_index in 0..object_count {
+            let read_object = |reader: &mut _| -> anyhow::Result<ObjectPrototype> {
+                let position = Vector2::new(read_f32(reader)?, read_f32(reader)?);
+                let velocity = Vector2::new(read_f32(reader)?, read_f32(reader)?);
+                let radius = read_f32(reader)?;
+                let mass = read_f32(reader)?;
+                let [flags] = read_bytes(reader)?;
+                let material = if version >= 3 {
+                    u32::from_le_bytes(read_bytes(reader)?)
+                } else {
+                    0
+                };
+                let collision_group = if version >= 4 {
+                    u32::from_le_bytes(read_bytes(reader)?)
+                } else {
+                    0
+                };
+                let [has_color] = read_bytes(reader)?;
+                let color = if has_color == 0 {
+                    None
+                } else {
+                    Some(Color::new([
+                        read_f32(reader)?,
+                        read_f32(reader)?,
+                        read_f32(reader)?,
+                        read_f32(reader)?,
+                    ]))
+                };
+                Ok(ObjectPrototype {
+                    velocity,
+                    radius,
+                    mass,
+                    color,
+                    is_planet: flags & FLAG_PLANET != 0,
+                    is_frozen: flags & FLAG_FROZEN != 0,
+                    material,
+                    collision_group,
+                    ..ObjectPrototype::new(position)
+                })
+            };
+            let object = read_object(reader).with_context(|| format!("read object {object_index}"))?;
+            add_object(&mut objects, object_index, object)?;
+        }
+        Ok(Self {
+            time,
+            objects,
+            object_state: ObjectState::default(),
+        })
+    }
+
+    // Writes to a temporary file first, so a crash in the middle of saving doesn't destroy the previous snapshot
+    pub fn save(path: &Path, time: f32, objects: &ObjectSoa, object_state: &ObjectState) -> anyhow::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        {
+            let file = File::create(&temp_path).with_context(|| format!("create \"{}\"", temp_path.display()))?;
+            let mut writer = BufWriter::new(file);
+            Self::write(&mut writer, time, objects, object_state)?;
+            writer.into_inner()?.sync_all()?;
+        }
+        fs::rename(&temp_path, path).with_context(|| format!("rename to \"{}\"", path.display()))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("open \"{}\"", path.display()))?;
+        Self::read(&mut BufReader::new(file)).with_context(|| format!("load snapshot \"{}\"", path.display()))
+    }
+}
+
+fn add_object(objects: &mut ObjectSoa, object_index: usize, object: ObjectPrototype) -> anyhow::Result<()> {
+    if object.is_planet && object_index != objects.planet_count {
+        bail!("planet {object_index} follows non-planet objects");
+    }
+    objects.add(object);
+    Ok(())
+}
+
+fn vector2_from(bytes: [u8; 8]) -> Vector2<f32> {
+    let [x, y] = container::f32s(&bytes);
+    Vector2::new(x, y)
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_f32(reader: &mut impl Read) -> anyhow::Result<f32> {
+    Ok(f32::from_le_bytes(read_bytes(reader)?))
+}
+
+#[test]
+fn snapshot_roundtrip() {
+    let mut objects = ObjectSoa::default();
+    objects.add(ObjectPrototype {
+        mass: 1000.0,
+        is_planet: true,
+        ..ObjectPrototype::new(Vector2::new(1.0, 2.0))
+    });
+    objects.add(ObjectPrototype {
+        velocity: Vector2::new(-3.0, 4.0),
+        radius: 0.5,
+        color: Some(Color::new([0.1, 0.2, 0.3, 1.0])),
+        is_frozen: true,
+        material: 2,
+        collision_group: 3,
+        ..ObjectPrototype::new(Vector2::new(5.0, 6.0))
+    });
+
+    let mut buffer = Vec::new();
+    let object_state = ObjectState {
+        heat: vec![0.0, 2.5],
+        velocity_compensations: vec![Vector2::new(1e-7, 0.0), Vector2::new(0.0, -2e-7)],
+        ..ObjectState::default()
+    };
+    Snapshot::write(&mut buffer, 1.5, &objects, &object_state).unwrap();
+    let snapshot = Snapshot::read(&mut buffer.as_slice()).unwrap();
+    assert_eq!(snapshot.time, 1.5);
+    assert_eq!(snapshot.objects.len(), 2);
+    assert_eq!(snapshot.objects.planet_count, 1);
+    assert!(snapshot.objects.positions == objects.positions);
+    assert!(snapshot.objects.velocities == objects.velocities);
+    assert_eq!(snapshot.objects.radii, objects.radii);
+    assert_eq!(snapshot.objects.masses, objects.masses);
+    assert_eq!(snapshot.objects.colors, objects.colors);
+    assert_eq!(snapshot.objects.is_frozen, objects.is_frozen);
+    assert_eq!(snapshot.objects.materials, objects.materials);
+    assert_eq!(snapshot.objects.collision_groups, objects.collision_groups);
+    assert_eq!(snapshot.object_state.heat, object_state.heat);
+    assert!(snapshot.object_state.position_compensations.is_empty());
+    assert!(snapshot.object_state.velocity_compensations == object_state.velocity_compensations);
+}
+
+#[test]
+fn legacy_snapshot_is_loadable() {
+    // Version 4: no container, a color flag byte in front of the color
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&4_u32.to_le_bytes());
+    bytes.extend_from_slice(&2.5_f32.to_le_bytes());
+    bytes.extend_from_slice(&1_u64.to_le_bytes());
+    for value in [1.0_f32, 2.0, 3.0, 4.0, 0.5, 7.0] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.push(FLAG_FROZEN);
+    bytes.extend_from_slice(&1_u32.to_le_bytes());
+    bytes.extend_from_slice(&2_u32.to_le_bytes());
+    bytes.push(0);
+
+    let snapshot = Snapshot::read(&mut bytes.as_slice()).unwrap();
+    assert_eq!(snapshot.time, 2.5);
+    assert!(snapshot.objects.positions == [Vector2::new(1.0, 2.0)]);
+    assert!(snapshot.objects.velocities == [Vector2::new(3.0, 4.0)]);
+    assert_eq!((snapshot.objects.radii[0], snapshot.objects.masses[0]), (0.5, 7.0));
+    assert!(snapshot.objects.is_frozen[0]);
+    assert_eq!((snapshot.objects.materials[0], snapshot.objects.collision_groups[0]), (1, 2));
+    assert_eq!(snapshot.objects.colors[0], None);
+}
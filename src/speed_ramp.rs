@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+// Speed multiplier that eases into a new value over the ramp time, so that changing the speed doesn't jolt the
+// automatic time step. The multiplier changes geometrically, e.g. halfway from 1x to 4x is 2x.
+pub struct SpeedRamp {
+    start: f32,
+    target: f32,
+    ramp_time: Duration,
+    ramp_start: Instant,
+}
+
+impl SpeedRamp {
+    #[must_use]
+    pub fn new(multiplier: f32, ramp_time: Duration) -> Self {
+        Self {
+            start: multiplier,
+            target: multiplier,
+            ramp_time,
+            ramp_start: Instant::now(),
+        }
+    }
+
+    #[must_use]
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    // The ramp starts from the current multiplier, even if the previous ramp isn't over yet
+    pub fn set_target(&mut self, target: f32, now: Instant) {
+        self.start = self.multiplier(now);
+        self.target = target;
+        self.ramp_start = now;
+    }
+
+    #[must_use]
+    pub fn multiplier(&self, now: Instant) -> f32 {
+        let progress = if self.ramp_time.is_zero() {
+            1.0
+        } else {
+            (now.saturating_duration_since(self.ramp_start).as_secs_f32() / self.ramp_time.as_secs_f32()).min(1.0)
+        };
+        let eased = progress * progress * (3.0 - 2.0 * progress);
+        self.start * (self.target / self.start).powf(eased)
+    }
+}
+
+#[test]
+fn speed_ramp_eases_into_target() {
+    let ramp_time = Duration::from_millis(500);
+    let now = Instant::now();
+    let mut ramp = SpeedRamp::new(1.0, ramp_time);
+    ramp.set_target(4.0, now);
+    assert_eq!(ramp.multiplier(now), 1.0);
+    assert!((ramp.multiplier(now + ramp_time / 2) - 2.0).abs() < 1e-5);
+    assert_eq!(ramp.multiplier(now + ramp_time), 4.0);
+
+    // Changing the target mid-ramp continues from where the multiplier is
+    let later = now + ramp_time / 2;
+    ramp.set_target(0.5, later);
+    assert!((ramp.multiplier(later) - 2.0).abs() < 1e-5);
+    assert_eq!(ramp.multiplier(later + ramp_time * 2), 0.5);
+}
@@ -0,0 +1,128 @@
+use std::f32::consts::{PI, SQRT_2};
+
+use anyhow::ensure;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::{
+    bvh::AABB,
+    object::{ObjectPrototype, ObjectSoa},
+    vector2::Vector2,
+};
+
+// The same count always gives the same scene, so that runs on different machines are comparable
+pub const STRESS_SEED: u64 = 0x5EED;
+
+// Adds `count` particles at rest, spread evenly at random over the region without overlapping. The particles are
+// smaller than `max_radius` if that many don't fit otherwise.
+pub fn create_stress_scene(objects: &mut ObjectSoa, count: usize, region: AABB, max_radius: f32) -> anyhow::Result<()> {
+    // Poisson disk sampling places about 0.7 / min_distance^2 points per unit area, so the spacing is chosen to give
+    // more than needed, and the particles are a random subset of the points
+    const FILL_RATIO: f32 = 0.5;
+
+    let region_size = region.bottomright - region.topleft;
+    #[allow(clippy::cast_precision_loss)]
+    let radius = max_radius.min((FILL_RATIO * region_size.x * region_size.y / count.max(1) as f32).sqrt() / 2.0);
+    let centers = AABB {
+        topleft: region.topleft + radius,
+        bottomright: region.bottomright - radius,
+    };
+    let size = centers.bottomright - centers.topleft;
+    ensure!(size.x > 0.0 && size.y > 0.0, "particles of radius {radius} don't fit into the constraints");
+    #[allow(clippy::cast_precision_loss)]
+    let min_distance = (FILL_RATIO * size.x * size.y / count.max(1) as f32).sqrt().max(radius * 2.0);
+    let mut rng = StdRng::seed_from_u64(STRESS_SEED);
+    let points = poisson_disk_sample(centers, min_distance, &mut rng);
+    ensure!(
+        points.len() >= count,
+        "only {} particles of radius {radius} fit into the constraints, {count} requested",
+        points.len()
+    );
+    let mut selected = rand::seq::index::sample(&mut rng, points.len(), count).into_vec();
+    // In the order of generation, where neighbors are mostly close in memory too
+    selected.sort_unstable();
+    for position in selected.into_iter().map(|point_index| points[point_index]) {
+        objects.add(ObjectPrototype {
+            radius,
+            ..ObjectPrototype::new(position)
+        });
+    }
+    Ok(())
+}
+
+// Points filling the region, no closer to each other than `min_distance` (Bridson's algorithm)
+pub fn poisson_disk_sample(region: AABB, min_distance: f32, rng: &mut impl Rng) -> Vec<Vector2<f32>> {
+    // Candidates tried around an active point before it's retired
+    const CANDIDATE_COUNT: usize = 30;
+
+    let size = region.bottomright - region.topleft;
+    if size.x < 0.0 || size.y < 0.0 {
+        return Vec::new();
+    }
+    // A cell is small enough to hold at most one point
+    let cell_size = min_distance / SQRT_2;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let (width, height) = ((size.x / cell_size) as usize + 1, (size.y / cell_size) as usize + 1);
+    let mut grid: Vec<Option<usize>> = vec![None; width * height];
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let cell = |point: Vector2<f32>| {
+        let cell = (point - region.topleft) / cell_size;
+        ((cell.x as usize).min(width - 1), (cell.y as usize).min(height - 1))
+    };
+
+    let mut points = Vec::new();
+    let mut active = Vec::new();
+    let first = region.topleft + Vector2::new(rng.random::<f32>() * size.x, rng.random::<f32>() * size.y);
+    let (x, y) = cell(first);
+    grid[y * width + x] = Some(0);
+    points.push(first);
+    active.push(0);
+    while !active.is_empty() {
+        let active_index = rng.random_range(0..active.len());
+        let origin = points[active[active_index]];
+        let candidate = (0..CANDIDATE_COUNT).find_map(|_| {
+            let angle = rng.random::<f32>() * 2.0 * PI;
+            let distance = min_distance * (1.0 + rng.random::<f32>());
+            let candidate = origin + Vector2::new(angle.cos(), angle.sin()) * distance;
+            let inside = (region.topleft.x..=region.bottomright.x).contains(&candidate.x)
+                && (region.topleft.y..=region.bottomright.y).contains(&candidate.y);
+            if !inside {
+                return None;
+            }
+            let (x, y) = cell(candidate);
+            let neighbor_too_close = (y.saturating_sub(2)..(y + 3).min(height))
+                .flat_map(|y| (x.saturating_sub(2)..(x + 3).min(width)).map(move |x| (x, y)))
+                .filter_map(|(x, y)| grid[y * width + x])
+                .any(|point_index| (points[point_index] - candidate).magnitude() < min_distance);
+            (!neighbor_too_close).then_some((candidate, x, y))
+        });
+        match candidate {
+            Some((candidate, x, y)) => {
+                grid[y * width + x] = Some(points.len());
+                active.push(points.len());
+                points.push(candidate);
+            }
+            None => {
+                active.swap_remove(active_index);
+            }
+        }
+    }
+    points
+}
+
+#[test]
+fn poisson_disk_sample_is_spaced_and_reproducible() {
+    let region = AABB {
+        topleft: Vector2::new(10.0, 20.0),
+        bottomright: Vector2::new(210.0, 120.0),
+    };
+    let sample = |seed| poisson_disk_sample(region, 5.0, &mut StdRng::seed_from_u64(seed));
+    let points = sample(1);
+    assert!(points.len() > 400, "{}", points.len());
+    assert_eq!(points, sample(1));
+    assert_ne!(points, sample(2));
+    for (index, &point) in points.iter().enumerate() {
+        assert!(point.x >= region.topleft.x && point.x <= region.bottomright.x);
+        assert!(point.y >= region.topleft.y && point.y <= region.bottomright.y);
+        assert!(points[index + 1..].iter().all(|&other| (other - point).magnitude() >= 5.0));
+    }
+}
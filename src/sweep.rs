@@ -0,0 +1,357 @@
+use std::{fmt::Write as _, fs, path::Path, sync::mpsc, time::Instant};
+
+use anyhow::{Context, bail};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde_derive::Deserialize;
+
+use crate::{
+    boundary::Boundaries,
+    bvh::AABB,
+    material::CombineRule,
+    object::{ObjectPrototype, ObjectSoa},
+    pair_storage::PairStorageKind,
+    physics::{DtSource, DurationStat, GpuComputeOptions, PhysicsEngine, PhysicsSettings, SimulationMode, StepReport},
+    vector2::Vector2,
+};
+
+const BOX_SIZE: f32 = 1000.0;
+
+// Runs of a scene of particles falling in a box, one for every combination of the parameter values
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SweepSpec {
+    pub steps: usize,
+    #[serde(default = "default_dt")]
+    pub dt: f32,
+    #[serde(default)]
+    pub seed: u64,
+    // Steps between the rows of the per-run stats files
+    #[serde(default = "default_sample_period")]
+    pub sample_period: usize,
+    #[serde(default)]
+    pub parameters: SweepParameters,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SweepParameters {
+    #[serde(default = "default_object_count")]
+    pub object_count: SweepValues,
+    #[serde(default = "default_radius")]
+    pub radius: SweepValues,
+    #[serde(default = "default_restitution_coefficient")]
+    pub restitution_coefficient: SweepValues,
+    #[serde(default = "default_gravity")]
+    pub gravity: SweepValues,
+    // Largest initial speed of a particle, in a random direction
+    #[serde(default = "default_initial_speed")]
+    pub initial_speed: SweepValues,
+    #[serde(default = "default_position_correction_factor")]
+    pub position_correction_factor: SweepValues,
+}
+
+impl Default for SweepParameters {
+    fn default() -> Self {
+        Self {
+            object_count: default_object_count(),
+            radius: default_radius(),
+            restitution_coefficient: default_restitution_coefficient(),
+            gravity: default_gravity(),
+            initial_speed: default_initial_speed(),
+            position_correction_factor: default_position_correction_factor(),
+        }
+    }
+}
+
+// A single value, a list of values, or `{ from = 0.5, to = 1.0, step = 0.1 }` with both ends included
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SweepValues {
+    Single(f64),
+    List(Vec<f64>),
+    Range { from: f64, to: f64, step: f64 },
+}
+
+impl SweepValues {
+    pub fn values(&self) -> anyhow::Result<Vec<f64>> {
+        match *self {
+            SweepValues::Single(value) => Ok(vec![value]),
+            SweepValues::List(ref values) if values.is_empty() => bail!("empty list of values"),
+            SweepValues::List(ref values) => Ok(values.clone()),
+            SweepValues::Range { from, to, step } => {
+                if step <= 0.0 || to < from {
+                    bail!("range from {from} to {to} with step {step} is empty");
+                }
+                // The tolerance keeps the upper end despite the rounding errors of the step
+                let count = ((to - from) / step + 1e-9).floor() as usize + 1;
+                #[allow(clippy::cast_precision_loss)]
+                Ok((0..count).map(|i| from + i as f64 * step).collect())
+            }
+        }
+    }
+}
+
+fn default_dt() -> f32 {
+    0.001
+}
+
+fn default_sample_period() -> usize {
+    10
+}
+
+fn default_object_count() -> SweepValues {
+    SweepValues::Single(200.0)
+}
+
+fn default_radius() -> SweepValues {
+    SweepValues::Single(3.0)
+}
+
+fn default_restitution_coefficient() -> SweepValues {
+    SweepValues::Single(0.9)
+}
+
+fn default_gravity() -> SweepValues {
+    SweepValues::Single(200.0)
+}
+
+fn default_initial_speed() -> SweepValues {
+    SweepValues::Single(50.0)
+}
+
+fn default_position_correction_factor() -> SweepValues {
+    SweepValues::Single(0.8)
+}
+
+// Parameter values of a single run
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepRun {
+    pub object_count: usize,
+    pub radius: f32,
+    pub restitution_coefficient: f32,
+    pub gravity: f32,
+    pub initial_speed: f32,
+    pub position_correction_factor: f32,
+}
+
+impl SweepRun {
+    pub const CSV_HEADER: &str =
+        "object_count,radius,restitution_coefficient,gravity,initial_speed,position_correction_factor";
+
+    #[must_use]
+    pub fn csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{}",
+            self.object_count,
+            self.radius,
+            self.restitution_coefficient,
+            self.gravity,
+            self.initial_speed,
+            self.position_correction_factor
+        )
+    }
+}
+
+pub struct SweepResult {
+    pub samples: Vec<StepReport>,
+    pub final_kinetic_energy: f64,
+    pub mean_contact_count: f64,
+    pub mean_step_seconds: f64,
+    pub wall_seconds: f64,
+}
+
+impl SweepResult {
+    pub const CSV_HEADER: &str = "final_kinetic_energy,mean_contact_count,mean_step_seconds,wall_seconds";
+
+    #[must_use]
+    pub fn csv(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.final_kinetic_energy, self.mean_contact_count, self.mean_step_seconds, self.wall_seconds
+        )
+    }
+}
+
+impl SweepSpec {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("read \"{}\"", path.display()))?;
+        let spec: Self = toml::from_str(&text).with_context(|| format!("parse \"{}\"", path.display()))?;
+        if spec.steps == 0 || spec.sample_period == 0 || spec.dt <= 0.0 {
+            bail!("steps, sample_period and dt must be positive");
+        }
+        Ok(spec)
+    }
+
+    // Every combination of the parameter values, the last parameter changing fastest
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn runs(&self) -> anyhow::Result<Vec<SweepRun>> {
+        let parameters = &self.parameters;
+        let values = [
+            ("object_count", &parameters.object_count),
+            ("radius", &parameters.radius),
+            ("restitution_coefficient", &parameters.restitution_coefficient),
+            ("gravity", &parameters.gravity),
+            ("initial_speed", &parameters.initial_speed),
+            ("position_correction_factor", &parameters.position_correction_factor),
+        ]
+        .map(|(name, values)| values.values().with_context(|| format!("parameters.{name}")));
+        let mut runs = vec![Vec::new()];
+        for values in values {
+            let values = values?;
+            runs = runs
+                .into_iter()
+                .flat_map(|run| {
+                    values.iter().map(move |&value| {
+                        let mut run = run.clone();
+                        run.push(value);
+                        run
+                    })
+                })
+                .collect();
+        }
+        Ok(runs
+            .into_iter()
+            .map(|run| SweepRun {
+                object_count: run[0].round() as usize,
+                radius: run[1] as f32,
+                restitution_coefficient: run[2] as f32,
+                gravity: run[3] as f32,
+                initial_speed: run[4] as f32,
+                position_correction_factor: run[5] as f32,
+            })
+            .collect())
+    }
+
+    pub fn run(&self, run: &SweepRun) -> anyhow::Result<SweepResult> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let objects = create_objects(run, &mut rng)?;
+        let settings = PhysicsSettings {
+            dt: DtSource::Fixed(self.dt),
+            mode: SimulationMode::TimeStepped,
+            hybrid_max_cluster_size: 0,
+            constraints: AABB {
+                topleft: Vector2::new(0.0, 0.0),
+                bottomright: Vector2::new(BOX_SIZE, BOX_SIZE),
+            },
+            constraint_bouncing: true,
+            boundaries: Boundaries::default(),
+            restitution_coefficient: run.restitution_coefficient,
+            materials: Vec::new(),
+            restitution_combine: CombineRule::Max,
+            friction_combine: CombineRule::GeometricMean,
+            material_pairs: Vec::new(),
+            restitution_velocity_threshold: 1.0,
+            penetration_slop: 0.01,
+            position_correction_factor: run.position_correction_factor,
+            global_gravity: Vector2::new(0.0, run.gravity),
+            gravity_zones: Vec::new(),
+            gravitational_constant: 0.0,
+            wind: None,
+            thermostat: None,
+            fluid: None,
+            heat_conduction: None,
+            pair_cache_margin: None,
+            verlet_skin: None,
+            pair_storage: PairStorageKind::Vec,
+            compensated_summation: false,
+            gpu_kernel_timeout: None,
+            collision_budget: None,
+            thread_pool: None,
+            seed: Some(self.seed),
+            duration_stat_window: DurationStat::DEFAULT_WINDOW,
+        };
+        let mut physics = PhysicsEngine::new(objects, settings)?;
+        let (report_sender, report_receiver) = mpsc::channel();
+        physics.set_step_observer(Box::new(move |report| {
+            let _ = report_sender.send(*report);
+        }));
+
+        let start = Instant::now();
+        for _ in 0..self.steps {
+            physics.advance(1.0, GpuComputeOptions::default());
+        }
+        let wall_seconds = start.elapsed().as_secs_f64();
+        let reports = report_receiver.try_iter().collect::<Vec<_>>();
+        #[allow(clippy::cast_precision_loss)]
+        let step_count = reports.len().max(1) as f64;
+        #[allow(clippy::cast_precision_loss)]
+        let mean_contact_count = reports.iter().map(|report| report.contact_count as f64).sum::<f64>() / step_count;
+        let mean_step_seconds =
+            reports.iter().map(|report| report.total_duration.as_secs_f64()).sum::<f64>() / step_count;
+        Ok(SweepResult {
+            final_kinetic_energy: reports.last().map_or(0.0, |report| report.kinetic_energy),
+            mean_contact_count,
+            mean_step_seconds,
+            wall_seconds,
+            samples: reports.into_iter().filter(|report| report.step_count % self.sample_period == 0).collect(),
+        })
+    }
+}
+
+// Particles on a jittered grid, so that they start without overlapping
+fn create_objects(run: &SweepRun, rng: &mut StdRng) -> anyhow::Result<ObjectSoa> {
+    let spacing = run.radius * 2.5;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let columns = ((BOX_SIZE - spacing) / spacing) as usize;
+    if run.radius <= 0.0 || columns == 0 || run.object_count > columns * columns {
+        bail!("{} particles of radius {} don't fit the box", run.object_count, run.radius);
+    }
+    let mut objects = ObjectSoa::default();
+    for object_index in 0..run.object_count {
+        #[allow(clippy::cast_precision_loss)]
+        let cell = Vector2::new((object_index % columns) as f32, (object_index / columns) as f32);
+        let jitter = Vector2::new(rng.random_range(-0.1..0.1), rng.random_range(-0.1..0.1)) * run.radius;
+        let angle = rng.random_range(0.0..std::f32::consts::TAU);
+        let speed = rng.random_range(0.0..=run.initial_speed);
+        objects.add(ObjectPrototype {
+            velocity: Vector2::new(angle.cos(), angle.sin()) * speed,
+            radius: run.radius,
+            ..ObjectPrototype::new((cell + 1.0) * spacing + jitter)
+        });
+    }
+    Ok(objects)
+}
+
+// One row per sampled step
+pub fn format_samples(samples: &[StepReport]) -> String {
+    let mut csv = String::from("step,time,dt,object_count,contact_count,kinetic_energy,step_seconds\n");
+    for report in samples {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{}",
+            report.step_count,
+            report.time,
+            report.dt,
+            report.object_count,
+            report.contact_count,
+            report.kinetic_energy,
+            report.total_duration.as_secs_f64()
+        );
+    }
+    csv
+}
+
+#[test]
+fn sweep_expands_every_combination() {
+    let spec: SweepSpec = toml::from_str(
+        "steps = 10\n\
+         [parameters]\n\
+         restitution_coefficient = { from = 0.5, to = 1.0, step = 0.1 }\n\
+         object_count = [100, 200]\n",
+    )
+    .unwrap();
+    let runs = spec.runs().unwrap();
+    assert_eq!(runs.len(), 12);
+    assert_eq!(runs[0].object_count, 100);
+    assert_eq!(runs[0].restitution_coefficient, 0.5);
+    assert_eq!(runs[11].object_count, 200);
+    assert!((runs[11].restitution_coefficient - 1.0).abs() < 1e-6);
+    assert!(runs.iter().all(|run| run.radius == 3.0));
+
+    let empty = SweepValues::Range {
+        from: 1.0,
+        to: 0.5,
+        step: 0.1,
+    };
+    assert!(empty.values().is_err());
+}
@@ -0,0 +1,106 @@
+use std::f32::consts::TAU;
+
+use itertools::izip;
+use rand::Rng;
+
+use crate::vector2::Vector2;
+
+// Brownian agitation: random velocity kicks with a variance proportional to the temperature, optionally followed by
+// a Berendsen rescaling of the velocities towards the temperature. The temperature is the mean kinetic energy per
+// degree of freedom, i.e. kT.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thermostat {
+    pub temperature: f32,
+    // Strength of the kicks, per unit of time; kicks alone keep heating the objects until something removes energy
+    pub kick_rate: f32,
+    // Time constant of the rescaling, which holds the temperature; no rescaling if not set
+    pub relaxation_time: Option<f32>,
+}
+
+impl Thermostat {
+    // Frozen objects are left alone. Returns the temperature after the step.
+    pub fn apply(
+        &self,
+        velocities: &mut [Vector2<f32>],
+        masses: &[f32],
+        is_frozen: &[bool],
+        dt: f32,
+        rng: &mut impl Rng,
+    ) -> f32 {
+        if self.kick_rate > 0.0 {
+            let kick_variance = self.kick_rate * dt * self.temperature;
+            for (velocity, &mass, _) in izip!(&mut *velocities, masses, is_frozen).filter(|(_, _, frozen)| !**frozen) {
+                *velocity += gaussian(rng) * (kick_variance / mass).sqrt();
+            }
+        }
+
+        let temperature = temperature(velocities, masses, is_frozen);
+        match self.relaxation_time {
+            Some(relaxation_time) if temperature > 0.0 => {
+                let scale_squared = (1.0 + dt / relaxation_time * (self.temperature / temperature - 1.0)).max(0.0);
+                let scale = scale_squared.sqrt();
+                for (velocity, _) in velocities.iter_mut().zip(is_frozen).filter(|(_, frozen)| !**frozen) {
+                    *velocity *= scale;
+                }
+                temperature * scale_squared
+            }
+            _ => temperature,
+        }
+    }
+}
+
+// Mean kinetic energy per degree of freedom of the objects that aren't frozen
+#[must_use]
+pub fn temperature(velocities: &[Vector2<f32>], masses: &[f32], is_frozen: &[bool]) -> f32 {
+    let (kinetic_energy, count) = izip!(velocities, masses, is_frozen).filter(|(_, _, frozen)| !**frozen).fold(
+        (0.0, 0_usize),
+        |(kinetic_energy, count), (velocity, mass, _)| {
+            (kinetic_energy + 0.5 * mass * velocity.magnitude_squared(), count + 1)
+        },
+    );
+    // Two degrees of freedom per object
+    #[allow(clippy::cast_precision_loss)]
+    let degrees_of_freedom = (count * 2) as f32;
+    if count == 0 {
+        0.0
+    } else {
+        kinetic_energy / degrees_of_freedom
+    }
+}
+
+// Pair of independent standard normal values (Box-Muller)
+fn gaussian(rng: &mut impl Rng) -> Vector2<f32> {
+    let radius = (-2.0 * (1.0 - rng.random::<f32>()).ln()).sqrt();
+    let angle = TAU * rng.random::<f32>();
+    Vector2::new(radius * angle.cos(), radius * angle.sin())
+}
+
+#[test]
+fn thermostat_holds_temperature() {
+    use rand::{SeedableRng, rngs::StdRng};
+
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut velocities = vec![Vector2::default(); 1000];
+    let masses = vec![2.0; velocities.len()];
+    let mut is_frozen = vec![false; velocities.len()];
+    is_frozen[0] = true;
+
+    // Kicks heat the objects up, more or less by the expected amount
+    let heater = Thermostat {
+        temperature: 10.0,
+        kick_rate: 1.0,
+        relaxation_time: None,
+    };
+    let temperature = heater.apply(&mut velocities, &masses, &is_frozen, 0.5, &mut rng);
+    assert!((temperature - 2.5).abs() < 0.5, "temperature {temperature}");
+    assert!(velocities[0] == Vector2::default());
+
+    // Relaxation time equal to the time step rescales right to the temperature
+    let thermostat = Thermostat {
+        relaxation_time: Some(0.5),
+        ..heater
+    };
+    let temperature = thermostat.apply(&mut velocities, &masses, &is_frozen, 0.5, &mut rng);
+    assert!((temperature - 10.0).abs() < 1e-3, "temperature {temperature}");
+    assert!((self::temperature(&velocities, &masses, &is_frozen) - 10.0).abs() < 1e-3);
+}
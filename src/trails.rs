@@ -0,0 +1,131 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use anyhow::Context;
+
+use crate::{bvh::AABB, gamma::ColorMath, vector2::Vector2};
+
+const BYTES_PER_PIXEL: usize = 4;
+
+// Long-exposure accumulation image over the simulation region: every frame the image fades by `decay`, then the
+// particle positions are added on top, so moving particles leave light trails behind. The light adds up in the space of
+// `color_math` and is encoded to sRGB for the image.
+pub struct Trails {
+    region: AABB,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    decay: f32,
+    color_math: ColorMath,
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Trails {
+    #[must_use]
+    pub fn new(region: AABB, cell_size: f32, decay: f32, color_math: ColorMath) -> Self {
+        let size = region.bottomright - region.topleft;
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let (width, height) =
+            ((size.x / cell_size).ceil().max(1.0) as usize, (size.y / cell_size).ceil().max(1.0) as usize);
+        Self {
+            region,
+            cell_size,
+            width,
+            height,
+            decay,
+            color_math,
+            pixels: vec![[0.0; 3]; width * height],
+        }
+    }
+
+    #[must_use]
+    pub fn region(&self) -> AABB {
+        self.region
+    }
+
+    #[must_use]
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    #[must_use]
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill([0.0; 3]);
+    }
+
+    pub fn fade(&mut self) {
+        for pixel in &mut self.pixels {
+            for component in pixel {
+                *component *= self.decay;
+            }
+        }
+    }
+
+    // Adds the sRGB-encoded color scaled by the intensity; positions outside of the region are ignored
+    pub fn splat(&mut self, position: Vector2<f32>, color: [f32; 3], intensity: f32) {
+        let cell = (position - self.region.topleft) / self.cell_size;
+        if cell.x < 0.0 || cell.y < 0.0 {
+            return;
+        }
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let (i, j) = (cell.x as usize, cell.y as usize);
+        if i < self.width && j < self.height {
+            let pixel = &mut self.pixels[j * self.width + i];
+            for (component, value) in pixel.iter_mut().zip(color) {
+                *component += self.color_math.decode(value) * intensity;
+            }
+        }
+    }
+
+    // Saturated RGBA, with the alpha equal to the brightest component, so that faded trails become transparent
+    #[must_use]
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let mut image_data = Vec::with_capacity(self.pixels.len() * BYTES_PER_PIXEL);
+        for pixel in &self.pixels {
+            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+            let [r, g, b] = pixel.map(|component| (self.color_math.encode(component).clamp(0.0, 1.0) * 255.0) as u8);
+            image_data.extend([r, g, b, r.max(g).max(b)]);
+        }
+        image_data
+    }
+
+    pub fn save_png(&self, path: &Path) -> anyhow::Result<()> {
+        let file = File::create(path).with_context(|| format!("create \"{}\"", path.display()))?;
+        let mut encoder = png::Encoder::new(
+            BufWriter::new(file),
+            u32::try_from(self.width).context("image is too wide")?,
+            u32::try_from(self.height).context("image is too high")?,
+        );
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().with_context(|| format!("write \"{}\"", path.display()))?;
+        writer.write_image_data(&self.to_rgba8()).with_context(|| format!("write \"{}\"", path.display()))?;
+        writer.finish().with_context(|| format!("write \"{}\"", path.display()))
+    }
+}
+
+#[test]
+fn trails_fade_and_clip() {
+    let region = AABB {
+        topleft: Vector2::new(10.0, 10.0),
+        bottomright: Vector2::new(20.0, 15.0),
+    };
+    let mut trails = Trails::new(region, 2.0, 0.5, ColorMath::Legacy);
+    assert_eq!(trails.size(), (5, 3));
+    trails.splat(Vector2::new(13.0, 11.0), [1.0, 0.5, 0.0], 1.0);
+    trails.splat(Vector2::new(5.0, 11.0), [1.0, 1.0, 1.0], 1.0);
+    trails.splat(Vector2::new(30.0, 11.0), [1.0, 1.0, 1.0], 1.0);
+    trails.fade();
+    let image_data = trails.to_rgba8();
+    assert_eq!(&image_data[BYTES_PER_PIXEL..BYTES_PER_PIXEL * 2], &[127, 63, 0, 127]);
+    assert_eq!(image_data.iter().map(|&byte| u32::from(byte)).sum::<u32>(), 127 + 63 + 127);
+
+    // Half of the light is brighter than half of the encoded value
+    let mut trails = Trails::new(region, 2.0, 0.5, ColorMath::Linear);
+    trails.splat(Vector2::new(13.0, 11.0), [1.0, 0.0, 0.0], 1.0);
+    trails.fade();
+    assert_eq!(&trails.to_rgba8()[BYTES_PER_PIXEL..BYTES_PER_PIXEL * 2], &[187, 0, 0, 187]);
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    mem,
+};
+
+use crate::vector2::Vector2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrajectorySample {
+    pub time: f32,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+}
+
+// Positions and velocities of the selected objects, sampled every `period` steps. Only the latest `capacity` samples
+// of each object are kept. The objects are tracked by index, which follows them when other objects are inserted or
+// removed; the trajectory of a removed object is dropped.
+pub struct TrajectoryRecorder {
+    period: usize,
+    capacity: usize,
+    trajectories: BTreeMap<usize, VecDeque<TrajectorySample>>,
+}
+
+impl TrajectoryRecorder {
+    #[must_use]
+    pub fn new(object_indices: impl IntoIterator<Item = usize>, period: usize, capacity: usize) -> Self {
+        Self {
+            period: period.max(1),
+            capacity,
+            trajectories: object_indices.into_iter().map(|object_index| (object_index, VecDeque::new())).collect(),
+        }
+    }
+
+    pub fn record(&mut self, step_count: usize, time: f32, positions: &[Vector2<f32>], velocities: &[Vector2<f32>]) {
+        if !step_count.is_multiple_of(self.period) {
+            return;
+        }
+        for (&object_index, trajectory) in &mut self.trajectories {
+            if object_index >= positions.len() {
+                continue;
+            }
+            if trajectory.len() == self.capacity {
+                trajectory.pop_front();
+            }
+            if self.capacity > 0 {
+                trajectory.push_back(TrajectorySample {
+                    time,
+                    position: positions[object_index],
+                    velocity: velocities[object_index],
+                });
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn trajectory(&self, object_index: usize) -> Option<&VecDeque<TrajectorySample>> {
+        self.trajectories.get(&object_index)
+    }
+
+    pub fn object_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.trajectories.keys().copied()
+    }
+
+    pub fn object_inserted(&mut self, object_index: usize) {
+        self.shift_indices(object_index, |index| index + 1);
+    }
+
+    pub fn object_removed(&mut self, object_index: usize) {
+        self.trajectories.remove(&object_index);
+        self.shift_indices(object_index, |index| index - 1);
+    }
+
+    // After the objects were reordered or removed at once; `new_index` maps the old indices, to None for the removed
+    // objects
+    pub fn objects_moved(&mut self, new_index: impl Fn(usize) -> Option<usize>) {
+        self.trajectories = mem::take(&mut self.trajectories)
+            .into_iter()
+            .filter_map(|(object_index, trajectory)| Some((new_index(object_index)?, trajectory)))
+            .collect();
+    }
+
+    fn shift_indices(&mut self, from: usize, shift: impl Fn(usize) -> usize) {
+        let shifted = self.trajectories.split_off(&from);
+        self.trajectories
+            .extend(shifted.into_iter().map(|(object_index, trajectory)| (shift(object_index), trajectory)));
+    }
+
+    // One row per sample, grouped by object
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("object,time,x,y,vx,vy\n");
+        for (object_index, trajectory) in &self.trajectories {
+            for sample in trajectory {
+                let TrajectorySample {
+                    time,
+                    position,
+                    velocity,
+                } = sample;
+                let _ =
+                    writeln!(csv, "{object_index},{time},{},{},{},{}", position.x, position.y, velocity.x, velocity.y);
+            }
+        }
+        csv
+    }
+}
+
+#[test]
+fn trajectory_recorder_follows_objects() {
+    let positions = (0..4).map(|i| Vector2::new(i as f32, 0.0)).collect::<Vec<_>>();
+    let velocities = vec![Vector2::new(0.0, 1.0); positions.len()];
+    let mut recorder = TrajectoryRecorder::new([1, 3], 2, 2);
+    for step_count in 1..=6 {
+        #[allow(clippy::cast_precision_loss)]
+        recorder.record(step_count, step_count as f32, &positions, &velocities);
+    }
+    // Sampled at steps 2, 4 and 6, keeping the last two
+    let times = |recorder: &TrajectoryRecorder, object_index| {
+        recorder.trajectory(object_index).unwrap().iter().map(|sample| sample.time).collect::<Vec<_>>()
+    };
+    assert_eq!(times(&recorder, 1), [4.0, 6.0]);
+    assert_eq!(recorder.trajectory(3).unwrap()[0].position, Vector2::new(3.0, 0.0));
+
+    recorder.object_removed(1);
+    recorder.object_inserted(0);
+    assert_eq!(recorder.object_indices().collect::<Vec<_>>(), [3]);
+    assert_eq!(times(&recorder, 3), [4.0, 6.0]);
+    assert!(recorder.to_csv().starts_with("object,time,x,y,vx,vy\n3,4,3,0,0,1\n"));
+}
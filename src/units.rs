@@ -0,0 +1,136 @@
+use serde_derive::{Deserialize, Serialize};
+
+use crate::vector2::Vector2;
+
+// Maps physical units (meters, seconds, kilograms) to the units the engine works in, which are the render units:
+// pixels, engine seconds and engine mass units. The defaults make both systems identical. Every quantity of the
+// simulation, the demo, the scene files and the editor is physical; the window size, which is also the world bounds,
+// and the rendering and mouse settings stay in pixels.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Units {
+    #[serde(default = "default_scale")]
+    pub pixels_per_meter: f32,
+    // Physical seconds per engine second
+    #[serde(default = "default_scale")]
+    pub time_scale: f32,
+    // Kilograms per engine mass unit
+    #[serde(default = "default_scale")]
+    pub mass_scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            pixels_per_meter: 1.0,
+            time_scale: 1.0,
+            mass_scale: 1.0,
+        }
+    }
+}
+
+impl Units {
+    #[must_use]
+    pub fn length(&self, meters: f32) -> f32 {
+        meters * self.pixels_per_meter
+    }
+
+    #[must_use]
+    pub fn position(&self, meters: Vector2<f32>) -> Vector2<f32> {
+        meters * self.pixels_per_meter
+    }
+
+    #[must_use]
+    pub fn time(&self, seconds: f32) -> f32 {
+        seconds / self.time_scale
+    }
+
+    #[must_use]
+    pub fn mass(&self, kilograms: f32) -> f32 {
+        kilograms / self.mass_scale
+    }
+
+    #[must_use]
+    pub fn velocity(&self, meters_per_second: Vector2<f32>) -> Vector2<f32> {
+        meters_per_second * (self.pixels_per_meter * self.time_scale)
+    }
+
+    #[must_use]
+    pub fn speed(&self, meters_per_second: f32) -> f32 {
+        meters_per_second * (self.pixels_per_meter * self.time_scale)
+    }
+
+    #[must_use]
+    pub fn acceleration(&self, meters_per_second_squared: Vector2<f32>) -> Vector2<f32> {
+        meters_per_second_squared * (self.pixels_per_meter * self.time_scale * self.time_scale)
+    }
+
+    #[must_use]
+    pub fn acceleration_magnitude(&self, meters_per_second_squared: f32) -> f32 {
+        meters_per_second_squared * (self.pixels_per_meter * self.time_scale * self.time_scale)
+    }
+
+    #[must_use]
+    pub fn energy(&self, joules: f32) -> f32 {
+        self.mass(joules) * self.speed(1.0).powi(2)
+    }
+
+    #[must_use]
+    pub fn force(&self, newtons: f32) -> f32 {
+        self.mass(newtons) * self.acceleration_magnitude(1.0)
+    }
+
+    // Mass per unit of area
+    #[must_use]
+    pub fn area_density(&self, kilograms_per_square_meter: f32) -> f32 {
+        self.mass(kilograms_per_square_meter) / (self.pixels_per_meter * self.pixels_per_meter)
+    }
+
+    // Rate of an exponential decay, e.g. drag
+    #[must_use]
+    pub fn rate(&self, per_second: f32) -> f32 {
+        per_second * self.time_scale
+    }
+
+    // G is in m^3 kg^-1 s^-2
+    #[must_use]
+    pub fn gravitational_constant(&self, gravitational_constant: f32) -> f32 {
+        gravitational_constant * self.pixels_per_meter.powi(3) * self.mass_scale * self.time_scale * self.time_scale
+    }
+
+    // Engine time back to physical seconds, for display
+    #[must_use]
+    pub fn physical_time(&self, time: f32) -> f32 {
+        time * self.time_scale
+    }
+
+    // Engine length back to meters, for display
+    #[must_use]
+    pub fn physical_length(&self, length: f32) -> f32 {
+        length / self.pixels_per_meter
+    }
+
+    #[must_use]
+    pub fn physical_position(&self, position: Vector2<f32>) -> Vector2<f32> {
+        position / self.pixels_per_meter
+    }
+}
+
+#[test]
+fn free_fall_distance_is_unit_independent() {
+    // d = g * t^2 / 2 must describe the same fall in both unit systems
+    let units = Units {
+        pixels_per_meter: 50.0,
+        time_scale: 0.5,
+        mass_scale: 1000.0,
+    };
+    let (g, t) = (9.81, 2.0);
+    let physical_distance = g * t * t / 2.0;
+    let engine_time = units.time(t);
+    let engine_distance = units.acceleration(Vector2::new(0.0, g)).y * engine_time * engine_time / 2.0;
+    assert!((engine_distance - units.length(physical_distance)).abs() < 1e-3);
+}
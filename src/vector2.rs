@@ -7,8 +7,9 @@ use std::{
 
 use num_traits::Float;
 use serde::{
-    Deserialize,
+    Deserialize, Serialize,
     de::{Error, Visitor},
+    ser::SerializeTuple,
 };
 
 #[repr(C)]
@@ -249,6 +250,19 @@ impl<'de, T: Deserialize<'de>> Deserialize<'de> for Vector2<T> {
     }
 }
 
+// The same `[x, y]` form as accepted by `Deserialize`
+impl<T: Serialize> Serialize for Vector2<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&self.x)?;
+        tuple.serialize_element(&self.y)?;
+        tuple.end()
+    }
+}
+
 struct Vector2Visitor<T>(PhantomData<T>);
 
 impl<'de, T: Deserialize<'de>> Visitor<'de> for Vector2Visitor<T> {
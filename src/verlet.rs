@@ -0,0 +1,124 @@
+use rayon::{
+    ThreadPool,
+    iter::{IntoParallelIterator, ParallelExtend, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{bvh::Bvh, physics::NormalizedCollisionPair, vector2::Vector2};
+
+// Verlet lists: the pairs of objects closer than `skin` to touching, rebuilt from the BVH only once an object has
+// moved more than half the skin since the last build. Until then, two objects outside the lists can't have closed the
+// gap of the skin between them, so every touching pair is in the lists, and the BVH doesn't have to be updated at all.
+pub struct VerletLists {
+    skin: f32,
+    reference_positions: Vec<Vector2<f32>>,
+    pairs: Vec<NormalizedCollisionPair>,
+    rebuild_count: usize,
+}
+
+impl VerletLists {
+    #[must_use]
+    pub fn new(skin: f32) -> Self {
+        Self {
+            skin,
+            reference_positions: Vec::new(),
+            pairs: Vec::new(),
+            rebuild_count: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn skin(&self) -> f32 {
+        self.skin
+    }
+
+    #[must_use]
+    pub fn pairs(&self) -> &[NormalizedCollisionPair] {
+        &self.pairs
+    }
+
+    #[must_use]
+    pub fn rebuild_count(&self) -> usize {
+        self.rebuild_count
+    }
+
+    pub fn invalidate(&mut self) {
+        self.reference_positions.clear();
+    }
+
+    #[must_use]
+    pub fn needs_rebuild(&self, positions: &[Vector2<f32>]) -> bool {
+        let max_displacement_squared = (self.skin * 0.5) * (self.skin * 0.5);
+        self.reference_positions.len() != positions.len()
+            || self.reference_positions.iter().zip(positions).any(|(&reference_position, &position)| {
+                (position - reference_position).magnitude_squared() > max_displacement_squared
+            })
+    }
+
+    // The BVH must be up to date with the positions
+    pub fn rebuild(&mut self, bvh: &Bvh, thread_pool: &ThreadPool, positions: &[Vector2<f32>], radii: &[f32]) {
+        self.reference_positions.clear();
+        self.reference_positions.extend_from_slice(positions);
+        self.pairs.clear();
+        let skin = self.skin;
+        thread_pool.install(|| {
+            // Buffers are per rayon job rather than per object, which keeps the allocation count low
+            let pairs = (0..positions.len()).into_par_iter().fold(
+                || (Vec::new(), Vec::new()),
+                |(mut pairs, mut neighbors), object_index| {
+                    neighbors.clear();
+                    bvh.find_neighbors(object_index, skin, positions, radii, &mut neighbors);
+                    // Every pair is found from both objects
+                    pairs.extend(
+                        neighbors
+                            .iter()
+                            .filter(|&&neighbor| neighbor > object_index)
+                            .map(|&neighbor| NormalizedCollisionPair::new(object_index, neighbor)),
+                    );
+                    (pairs, neighbors)
+                },
+            );
+            self.pairs.par_extend(pairs.flat_map_iter(|(pairs, _)| pairs));
+            // In a reproducible order
+            self.pairs.par_sort_unstable();
+        });
+        self.rebuild_count += 1;
+    }
+}
+
+#[test]
+fn verlet_lists_keep_touching_pairs_until_rebuild() {
+    use crate::bvh::AABB;
+
+    let mut positions =
+        (0..100).map(|i| Vector2::new((i % 10) as f32 * 3.0, (i / 10) as f32 * 3.0)).collect::<Vec<_>>();
+    let radii = vec![1.4; positions.len()];
+    let thread_pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+    let constraints = AABB {
+        topleft: Vector2::new(0.0, 0.0),
+        bottomright: Vector2::new(30.0, 30.0),
+    };
+    let mut bvh = Bvh::default();
+    let mut lists = VerletLists::new(1.0);
+    for step in 0..20 {
+        for (i, position) in positions.iter_mut().enumerate() {
+            if i % 7 == step % 7 {
+                position.x += 0.1;
+            }
+        }
+        if lists.needs_rebuild(&positions) {
+            bvh.update(&positions, &radii, constraints);
+            lists.rebuild(&bvh, &thread_pool, &positions, &radii);
+        }
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                if (positions[i] - positions[j]).magnitude() < radii[i] + radii[j] {
+                    assert!(lists.pairs().contains(&NormalizedCollisionPair::new(i, j)));
+                }
+            }
+        }
+    }
+    // Every object moves by 0.3 at most, which is within half the skin
+    assert_eq!(lists.rebuild_count(), 1);
+    assert!(lists.pairs().windows(2).all(|pair| pair[0] < pair[1]));
+}
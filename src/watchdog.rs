@@ -0,0 +1,59 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+// Liveness signal of a thread running a loop: the thread beats every iteration and a watchdog checks how long ago the
+// last beat was. The first beat arms the watchdog, so that a slow startup isn't taken for a stall.
+pub struct Heartbeat {
+    start: Instant,
+    // Microseconds since the start, 0 before the first beat
+    last_beat: AtomicU64,
+}
+
+impl Heartbeat {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            last_beat: AtomicU64::new(0),
+        }
+    }
+
+    pub fn beat(&self) {
+        let micros = u64::try_from(self.start.elapsed().as_micros()).unwrap_or(u64::MAX).max(1);
+        self.last_beat.store(micros, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn since_last_beat(&self) -> Option<Duration> {
+        let last_beat = self.last_beat.load(Ordering::Relaxed);
+        (last_beat > 0).then(|| self.start.elapsed().saturating_sub(Duration::from_micros(last_beat)))
+    }
+
+    // Time since the last beat, if it's longer than the timeout
+    #[must_use]
+    pub fn stall(&self, timeout: Duration) -> Option<Duration> {
+        self.since_last_beat().filter(|&since_last_beat| since_last_beat > timeout)
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn heartbeat_detects_stall() {
+    let heartbeat = Heartbeat::new();
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(heartbeat.stall(Duration::from_millis(10)), None);
+
+    heartbeat.beat();
+    assert_eq!(heartbeat.stall(Duration::from_millis(10)), None);
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(heartbeat.stall(Duration::from_millis(10)).is_some());
+    heartbeat.beat();
+    assert_eq!(heartbeat.stall(Duration::from_millis(10)), None);
+}
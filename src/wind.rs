@@ -0,0 +1,111 @@
+use crate::vector2::Vector2;
+
+// Constant wind plus turbulence. The turbulence is the curl of a smooth noise potential, which makes it
+// divergence-free: it swirls the objects around without bunching them up or tearing them apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wind {
+    pub acceleration: Vector2<f32>,
+    pub turbulence_amplitude: f32,
+    // Size of the turbulence swirls
+    pub turbulence_scale: f32,
+    // How fast the turbulence changes, in noise cells per unit of time
+    pub turbulence_speed: f32,
+}
+
+impl Wind {
+    #[must_use]
+    pub fn acceleration_at(&self, position: Vector2<f32>, time: f32) -> Vector2<f32> {
+        const EPSILON: f32 = 1e-2;
+
+        if self.turbulence_amplitude == 0.0 {
+            return self.acceleration;
+        }
+        let point = position / self.turbulence_scale;
+        let z = time * self.turbulence_speed;
+        let potential = |x, y| gradient_noise(x, y, z);
+        let dx = (potential(point.x + EPSILON, point.y) - potential(point.x - EPSILON, point.y)) / (2.0 * EPSILON);
+        let dy = (potential(point.x, point.y + EPSILON) - potential(point.x, point.y - EPSILON)) / (2.0 * EPSILON);
+        self.acceleration + Vector2::new(dy, -dx) * self.turbulence_amplitude
+    }
+}
+
+// Perlin's improved noise, with a hash in place of the permutation table
+#[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+fn gradient_noise(x: f32, y: f32, z: f32) -> f32 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+    let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        gradient(hash(ix + dx, iy + dy, iz + dz), fx - dx as f32, fy - dy as f32, fz - dz as f32)
+    };
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+    lerp(
+        w,
+        lerp(v, lerp(u, corner(0, 0, 0), corner(1, 0, 0)), lerp(u, corner(0, 1, 0), corner(1, 1, 0))),
+        lerp(v, lerp(u, corner(0, 0, 1), corner(1, 0, 1)), lerp(u, corner(0, 1, 1), corner(1, 1, 1))),
+    )
+}
+
+#[allow(clippy::cast_sign_loss)]
+fn hash(x: i32, y: i32, z: i32) -> u32 {
+    let mut hash = (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0x5bd1_e995);
+    hash ^ (hash >> 15)
+}
+
+// One of the 12 directions to the edges of a cube, as in the improved noise
+fn gradient(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+#[test]
+fn wind_turbulence_is_divergence_free() {
+    const STEP: f32 = 0.01;
+
+    let wind = Wind {
+        acceleration: Vector2::new(1.0, 2.0),
+        turbulence_amplitude: 10.0,
+        turbulence_scale: 1.0,
+        turbulence_speed: 1.0,
+    };
+    let mut max_magnitude = 0.0_f32;
+    for i in 0..20 {
+        #[allow(clippy::cast_precision_loss)]
+        let position = Vector2::new(i as f32 * 0.37, i as f32 * 0.23 + 0.5);
+        let turbulence = |offset: Vector2<f32>| wind.acceleration_at(position + offset, 3.5) - wind.acceleration;
+        let divergence = (turbulence(Vector2::new(STEP, 0.0)).x - turbulence(Vector2::new(-STEP, 0.0)).x
+            + turbulence(Vector2::new(0.0, STEP)).y
+            - turbulence(Vector2::new(0.0, -STEP)).y)
+            / (2.0 * STEP);
+        assert!(divergence.abs() < 1.0, "divergence {divergence} at {position:?}");
+        max_magnitude = max_magnitude.max(turbulence(Vector2::default()).magnitude());
+    }
+    // The turbulence is actually there
+    assert!(max_magnitude > 1.0);
+
+    let calm = Wind {
+        turbulence_amplitude: 0.0,
+        ..wind
+    };
+    assert!(calm.acceleration_at(Vector2::new(5.0, 5.0), 1.0) == wind.acceleration);
+}